@@ -57,7 +57,9 @@ export default defineUserConfig({
         text: "Reference",
         children: [
           '/reference/repo.md',
-          '/reference/release.md'
+          '/reference/release.md',
+          '/reference/pages.md',
+          '/reference/file.md'
         ]
       },
       {
@@ -81,7 +83,9 @@ export default defineUserConfig({
           text: "Reference",
           children: [
             '/reference/repo.md',
-            '/reference/release.md'
+            '/reference/release.md',
+            '/reference/pages.md',
+          '/reference/file.md'
           ]
         },
         {
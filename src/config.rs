@@ -1,40 +1,302 @@
+use std::path::{Path, PathBuf};
+
 use serde::{Deserialize, Deserializer};
+use tracing_batteries::prelude::*;
 
-use crate::{errors, policy::BackupPolicy, Args};
+use crate::{errors, helpers::http::HttpConfig, policy::BackupPolicy, telemetry::SmtpConfig, Args};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, schemars::JsonSchema)]
 pub struct Config {
+    /// The cron expression describing when backups should be run. If omitted, backups are run once.
     #[serde(deserialize_with = "deserialize_cron")]
+    #[schemars(with = "Option<String>")]
     pub schedule: Option<croner::Cron>,
 
+    /// The maximum amount of time by which the start of each policy may be delayed,
+    /// to avoid every policy hitting the GitHub API at the same moment.
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    #[schemars(schema_with = "duration_schema")]
+    pub stagger: Option<std::time::Duration>,
+
+    /// The proxy and CA certificates which should be used when connecting to GitHub.
+    #[serde(default)]
+    pub http: HttpConfig,
+
+    /// The SMTP server through which a digest email should be sent after each backup run.
+    #[serde(default)]
+    pub smtp: Option<SmtpConfig>,
+
+    /// The backup policies which should be run.
     #[serde(default)]
     pub backups: Vec<BackupPolicy>,
 }
 
+impl Config {
+    /// Generates a JSON Schema describing the configuration file, for use by editors.
+    pub fn schema() -> schemars::schema::RootSchema {
+        schemars::schema_for!(Config)
+    }
+}
+
 impl TryFrom<&Args> for Config {
     type Error = errors::Error;
 
     fn try_from(value: &Args) -> Result<Self, Self::Error> {
-        let content = std::fs::read_to_string(&value.config).map_err(|e| {
+        let mut config = Config::load(&value.config)?;
+        config.select(&value.tags, &value.policies);
+        Ok(config)
+    }
+}
+
+impl Config {
+    /// Loads the configuration file at the given path, replacing any `$include` directives
+    /// with the content of the files they reference.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, errors::Error> {
+        let path = path.as_ref();
+        let content = read_config(path)?;
+
+        let mut includes = Includes::default();
+        let mut value = includes.resolve(parse_config(path, &content)?, path)?;
+        let expanded = expand_sources(&mut value, path)?;
+
+        // Deserializing from the original content lets us report the location of any errors in
+        // the file, which we lose once the content has been spliced together from several files.
+        let config = if includes.resolved || expanded {
+            serde_yaml::from_value(value)
+        } else {
+            serde_yaml::from_str(&content)
+        };
+
+        config.map_err(|e| {
             errors::user_with_internal(
-                &format!("Failed to read the config file {}.", &value.config),
-                "Make sure that the configuration file exists and can be ready by the process.",
+                "Failed to parse your configuration file, as it is not recognized as valid YAML.",
+                "Make sure that your configuration file is formatted correctly.",
                 e,
             )
-        })?;
-        let config: Config = serde_yaml::from_str(&content).map_err(|e| {
+        })
+    }
+}
+
+impl Config {
+    /// Restricts the backup policies which will be run to those matching any of the given tags or
+    /// policy names, warning about any which don't match a policy in the configuration file.
+    pub fn select(&mut self, tags: &[String], names: &[String]) {
+        for tag in tags {
+            if !self.backups.iter().any(|p| p.tags.contains(tag)) {
+                warn!("None of your backup policies have the tag '{tag}', so it will be ignored.");
+            }
+        }
+
+        for name in names {
+            if !self.backups.iter().any(|p| p.to_string() == *name) {
+                warn!("None of your backup policies are named '{name}' (policies are named using their kind and source, for example 'github/repo/users/octocat'), so it will be ignored.");
+            }
+        }
+
+        self.backups.retain(|p| p.is_selected(tags, names));
+    }
+}
+
+fn read_config(path: &Path) -> Result<String, errors::Error> {
+    std::fs::read_to_string(path).map_err(|e| {
+        errors::user_with_internal(
+            &format!("Failed to read the config file {}.", path.display()),
+            "Make sure that the configuration file exists and can be ready by the process.",
+            e,
+        )
+    })
+}
+
+fn parse_config(path: &Path, content: &str) -> Result<serde_yaml::Value, errors::Error> {
+    serde_yaml::from_str(content).map_err(|e| {
+        errors::user_with_internal(
+            &format!(
+                "Failed to parse the config file {}, as it is not recognized as valid YAML.",
+                path.display()
+            ),
+            "Make sure that your configuration file is formatted correctly.",
+            e,
+        )
+    })
+}
+
+/// Expands each backup policy whose `from` field lists several collections into a separate
+/// policy for each of them (sharing the rest of its settings), returning whether any policies
+/// were expanded.
+fn expand_sources(value: &mut serde_yaml::Value, file: &Path) -> Result<bool, errors::Error> {
+    let Some(serde_yaml::Value::Sequence(backups)) = value.get_mut("backups") else {
+        return Ok(false);
+    };
+
+    if !backups
+        .iter()
+        .any(|policy| matches!(policy.get("from"), Some(serde_yaml::Value::Sequence(..))))
+    {
+        return Ok(false);
+    }
+
+    let mut expanded = Vec::with_capacity(backups.len());
+    for policy in backups.drain(..) {
+        let Some(serde_yaml::Value::Sequence(sources)) = policy.get("from") else {
+            expanded.push(policy);
+            continue;
+        };
+
+        if sources.is_empty() {
+            return Err(errors::user(
+                &format!(
+                    "One of the backup policies in the config file {} has an empty list of sources in its 'from' field.",
+                    file.display()
+                ),
+                "Make sure that you list at least one collection to back up, like 'from: [orgs/a, users/b]'.",
+            ));
+        }
+
+        for source in sources.clone() {
+            let mut policy = policy.clone();
+            policy["from"] = source;
+            expanded.push(policy);
+        }
+    }
+
+    *backups = expanded;
+    Ok(true)
+}
+
+/// The key used to include the content of another file within a configuration file.
+const INCLUDE_KEY: &str = "$include";
+
+/// Resolves the `$include` directives in a configuration file.
+///
+/// A mapping containing an `$include` key is replaced by the content of the referenced file
+/// (with any other keys in the mapping taking precedence over those in the file), while a list
+/// item which only contains an `$include` key is replaced by each of the items in the file.
+#[derive(Default)]
+struct Includes {
+    /// The files which are currently being included, used to detect cycles.
+    stack: Vec<PathBuf>,
+    resolved: bool,
+}
+
+impl Includes {
+    fn resolve(
+        &mut self,
+        value: serde_yaml::Value,
+        file: &Path,
+    ) -> Result<serde_yaml::Value, errors::Error> {
+        self.stack
+            .push(file.canonicalize().unwrap_or_else(|_| file.to_path_buf()));
+        let value = self.resolve_value(value, file);
+        self.stack.pop();
+        value
+    }
+
+    fn resolve_value(
+        &mut self,
+        value: serde_yaml::Value,
+        file: &Path,
+    ) -> Result<serde_yaml::Value, errors::Error> {
+        match value {
+            serde_yaml::Value::Mapping(mut mapping) => {
+                let include = mapping.remove(INCLUDE_KEY);
+
+                let mut resolved = serde_yaml::Mapping::new();
+                for (key, value) in mapping {
+                    resolved.insert(key, self.resolve_value(value, file)?);
+                }
+
+                let Some(include) = include else {
+                    return Ok(serde_yaml::Value::Mapping(resolved));
+                };
+
+                match self.include(include, file)? {
+                    included if resolved.is_empty() => Ok(included),
+                    serde_yaml::Value::Mapping(mut included) => {
+                        included.extend(resolved);
+                        Ok(serde_yaml::Value::Mapping(included))
+                    }
+                    _ => Err(errors::user(
+                        &format!("The '{INCLUDE_KEY}' directive in the config file {} includes a file which does not contain a mapping, so it cannot be combined with the other keys alongside it.", file.display()),
+                        "Make sure that the files you include alongside other keys contain a mapping, or move the other keys into the included file.",
+                    )),
+                }
+            }
+            serde_yaml::Value::Sequence(items) => {
+                let mut resolved = Vec::with_capacity(items.len());
+                for item in items {
+                    let splice = matches!(&item, serde_yaml::Value::Mapping(m) if m.len() == 1 && m.contains_key(INCLUDE_KEY));
+                    match self.resolve_value(item, file)? {
+                        serde_yaml::Value::Sequence(included) if splice => {
+                            resolved.extend(included)
+                        }
+                        item => resolved.push(item),
+                    }
+                }
+
+                Ok(serde_yaml::Value::Sequence(resolved))
+            }
+            serde_yaml::Value::Tagged(mut tagged) => {
+                tagged.value = self.resolve_value(tagged.value, file)?;
+                Ok(serde_yaml::Value::Tagged(tagged))
+            }
+            value => Ok(value),
+        }
+    }
+
+    fn include(
+        &mut self,
+        include: serde_yaml::Value,
+        file: &Path,
+    ) -> Result<serde_yaml::Value, errors::Error> {
+        let Some(target) = include.as_str() else {
+            return Err(errors::user(
+                &format!(
+                    "The '{INCLUDE_KEY}' directive in the config file {} must be the path to another file.",
+                    file.display()
+                ),
+                &format!("Make sure that each '{INCLUDE_KEY}' directive in your configuration file is followed by a file path, like '{INCLUDE_KEY}: policies.yaml'."),
+            ));
+        };
+
+        // Relative paths are resolved against the directory of the file which includes them, so
+        // that a set of config files can be moved around together.
+        let path = file.parent().unwrap_or(Path::new("")).join(target);
+        let canonical = path.canonicalize().map_err(|e| {
             errors::user_with_internal(
-                "Failed to parse your configuration file, as it is not recognized as valid YAML.",
-                "Make sure that your configuration file is formatted correctly.",
+                &format!(
+                    "Failed to read the config file {}, which is included by {}.",
+                    path.display(),
+                    file.display()
+                ),
+                "Make sure that the included file exists and can be read by the process.",
                 e,
             )
         })?;
 
-        Ok(config)
+        if self.stack.contains(&canonical) {
+            let chain = self
+                .stack
+                .iter()
+                .skip_while(|p| **p != canonical)
+                .chain(std::iter::once(&canonical))
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+
+            return Err(errors::user(
+                &format!("Your configuration files include one another in a loop ({chain})."),
+                &format!("Remove one of the '{INCLUDE_KEY}' directives so that no file ends up including itself."),
+            ));
+        }
+
+        self.resolved = true;
+        let content = read_config(&path)?;
+        let value = parse_config(&path, &content)?;
+        self.resolve(value, &path)
     }
 }
 
-fn deserialize_cron<'de, D>(deserializer: D) -> Result<Option<croner::Cron>, D::Error>
+pub(crate) fn deserialize_cron<'de, D>(deserializer: D) -> Result<Option<croner::Cron>, D::Error>
 where
     D: Deserializer<'de>,
 {
@@ -49,6 +311,82 @@ where
     Ok(None)
 }
 
+fn deserialize_duration<'de, D>(deserializer: D) -> Result<Option<std::time::Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    if let Some(s) = Deserialize::deserialize(deserializer)? {
+        let s: String = s;
+        return parse_duration(&s)
+            .map(Some)
+            .map_err(serde::de::Error::custom);
+    }
+
+    Ok(None)
+}
+
+fn duration_schema(_gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+    schemars::schema::SchemaObject {
+        instance_type: Some(
+            vec![
+                schemars::schema::InstanceType::String,
+                schemars::schema::InstanceType::Integer,
+                schemars::schema::InstanceType::Null,
+            ]
+            .into(),
+        ),
+        ..Default::default()
+    }
+    .into()
+}
+
+/// Parses a duration like `90`, `30s`, `5m`, `1h30m` or `7d` (where a number without a unit is
+/// interpreted as a number of seconds).
+pub fn parse_duration(s: &str) -> Result<std::time::Duration, String> {
+    let s = s.trim();
+    if let Ok(seconds) = s.parse::<u64>() {
+        return Ok(std::time::Duration::from_secs(seconds));
+    }
+
+    let mut total: u64 = 0;
+    let mut digits = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+
+        let unit = match c {
+            'd' => 86400,
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(format!("'{s}' is not a valid duration (unknown unit '{c}'), use a duration like '30s', '5m' or '1h30m'.")),
+        };
+
+        let value: u64 = digits.parse().map_err(|_| {
+            format!("'{s}' is not a valid duration, use a duration like '30s', '5m' or '1h30m'.")
+        })?;
+        total = value
+            .checked_mul(unit)
+            .and_then(|seconds| total.checked_add(seconds))
+            .ok_or_else(|| {
+                format!(
+                    "'{s}' is too long to be used as a duration, use a shorter duration like '7d'."
+                )
+            })?;
+        digits.clear();
+    }
+
+    if !digits.is_empty() || s.is_empty() {
+        return Err(format!(
+            "'{s}' is not a valid duration, use a duration like '30s', '5m' or '1h30m'."
+        ));
+    }
+
+    Ok(std::time::Duration::from_secs(total))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -63,6 +401,40 @@ mod tests {
         assert!(config.schedule.is_some());
     }
 
+    #[rstest]
+    #[case("90", Some(90))]
+    #[case("30s", Some(30))]
+    #[case("5m", Some(300))]
+    #[case("1h30m", Some(5400))]
+    #[case("7d", Some(604800))]
+    #[case("", None)]
+    #[case("5", Some(5))]
+    #[case("5 minutes", None)]
+    #[case("m", None)]
+    #[case("10x", None)]
+    #[case("99999999999999999d", None)]
+    #[case("18446744073709551615s1s", None)]
+    fn deserialize_stagger(#[case] value: &str, #[case] seconds: Option<u64>) {
+        let config: Result<Config, _> =
+            serde_yaml::from_str(&format!("schedule: '0 * * * *'\nstagger: '{}'", value));
+        match seconds {
+            Some(seconds) => assert_eq!(
+                config.expect("the config to be valid").stagger,
+                Some(std::time::Duration::from_secs(seconds))
+            ),
+            None => assert!(config.is_err(), "'{value}' should not be a valid duration"),
+        }
+    }
+
+    #[test]
+    fn deserialize_stagger_seconds() {
+        let config: Config = serde_yaml::from_str("schedule: '0 * * * *'\nstagger: 300").unwrap();
+        assert_eq!(config.stagger, Some(std::time::Duration::from_secs(300)));
+
+        let config: Config = serde_yaml::from_str("schedule: '0 * * * *'").unwrap();
+        assert_eq!(config.stagger, None);
+    }
+
     #[test]
     #[cfg_attr(feature = "pure_tests", ignore)]
     fn deserialize_example_config() {
@@ -82,4 +454,296 @@ mod tests {
         assert!(config.schedule.is_some());
         assert!(config.backups.iter().len() > 0);
     }
+
+    #[test]
+    fn load_nested_includes() {
+        let dir = tempfile::tempdir().expect("a temporary directory");
+        std::fs::create_dir(dir.path().join("policies")).unwrap();
+        std::fs::write(
+            dir.path().join("config.yaml"),
+            r#"
+schedule: "0 * * * *"
+http:
+  $include: http.yaml
+backups:
+  - $include: policies/repos.yaml
+  - kind: github/release
+    from: users/notheotherben
+    to: /backups/releases
+"#,
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("http.yaml"), "proxy: http://proxy:3128\n").unwrap();
+        std::fs::write(
+            dir.path().join("policies").join("repos.yaml"),
+            r#"
+- kind: github/repo
+  from: users/notheotherben
+  to: /backups/repos
+  $include: common.yaml
+- kind: github/repo
+  from: orgs/SierraSoftworks
+  to: /backups/repos
+  credentials: !Token "abc"
+"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("policies").join("common.yaml"),
+            "from: users/ignored\nfilter: '!repo.fork'\n",
+        )
+        .unwrap();
+
+        let config = Config::load(dir.path().join("config.yaml")).expect("the config to load");
+        assert!(config.schedule.is_some());
+        assert_eq!(config.backups.len(), 3);
+        assert_eq!(config.backups[0].from.as_str(), "users/notheotherben");
+        assert_eq!(config.backups[0].filter.raw(), "!repo.fork");
+        assert_eq!(config.backups[1].from.as_str(), "orgs/SierraSoftworks");
+        assert_eq!(config.backups[2].kind.as_str(), "github/release");
+    }
+
+    #[test]
+    fn load_included_properties() {
+        let dir = tempfile::tempdir().expect("a temporary directory");
+        std::fs::write(
+            dir.path().join("config.yaml"),
+            "schedule: null\nbackups:\n  - $include: repos.yaml\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("repos.yaml"),
+            r#"
+kind: github/repo
+from: users/notheotherben
+to: /backups/repos
+properties:
+  mirror: true
+  limit: 3
+  query: "type=owner"
+"#,
+        )
+        .unwrap();
+
+        let config = Config::load(dir.path().join("config.yaml")).expect("the config to load");
+        let policy = &config.backups[0];
+        assert_eq!(policy.property::<bool>("mirror").unwrap(), Some(true));
+        assert_eq!(policy.property::<usize>("limit").unwrap(), Some(3));
+        assert_eq!(
+            policy.properties.get("query").map(String::as_str),
+            Some("type=owner")
+        );
+    }
+
+    #[rstest]
+    #[case(false)]
+    #[case(true)]
+    fn load_shared_credentials(#[case] include: bool) {
+        let dir = tempfile::tempdir().expect("a temporary directory");
+        std::fs::write(dir.path().join("http.yaml"), "proxy: http://proxy:3128\n").unwrap();
+        std::fs::write(
+            dir.path().join("config.yaml"),
+            format!(
+                r#"
+schedule: "0 * * * *"
+{}
+backups:
+  - kind: github/repo
+    from: users/notheotherben
+    to: /backups/repos
+    credentials: &creds !Token "secret"
+  - kind: github/release
+    from: users/notheotherben
+    to: /backups/releases
+    credentials: *creds
+"#,
+                if include {
+                    "http: { $include: http.yaml }"
+                } else {
+                    ""
+                }
+            ),
+        )
+        .unwrap();
+
+        // Anchors must survive any processing of the config file, since they are commonly used to
+        // share credentials between policies.
+        let config = Config::load(dir.path().join("config.yaml")).expect("the config to load");
+        assert_eq!(config.backups.len(), 2);
+        for policy in config.backups.iter() {
+            assert_eq!(
+                policy.credentials,
+                crate::entities::Credentials::token("secret")
+            );
+        }
+    }
+
+    #[test]
+    fn load_multiple_sources() {
+        let dir = tempfile::tempdir().expect("a temporary directory");
+        std::fs::write(
+            dir.path().join("config.yaml"),
+            r#"
+schedule: "0 * * * *"
+backups:
+  - kind: github/repo
+    from: [orgs/a, orgs/b, users/c]
+    to: /backups/repos
+    filter: "!repo.fork"
+    properties:
+      mirror: true
+  - kind: github/release
+    from: users/notheotherben
+    to: /backups/releases
+"#,
+        )
+        .unwrap();
+
+        let config = Config::load(dir.path().join("config.yaml")).expect("the config to load");
+        let sources = config
+            .backups
+            .iter()
+            .map(|policy| format!("{policy}"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sources,
+            vec![
+                "github/repo/orgs/a",
+                "github/repo/orgs/b",
+                "github/repo/users/c",
+                "github/release/users/notheotherben",
+            ]
+        );
+
+        for policy in config.backups.iter().take(3) {
+            assert_eq!(policy.to, PathBuf::from("/backups/repos"));
+            assert_eq!(policy.filter.raw(), "!repo.fork");
+            assert_eq!(
+                policy.properties.get("mirror").map(String::as_str),
+                Some("true")
+            );
+        }
+    }
+
+    #[rstest]
+    #[case(&[], &[], &["github/repo/orgs/a", "github/repo/orgs/b", "github/release/users/c"])]
+    #[case(&["nightly"], &[], &["github/repo/orgs/a", "github/repo/orgs/b"])]
+    #[case(&["releases"], &[], &["github/release/users/c"])]
+    #[case(&["nightly", "releases"], &[], &["github/repo/orgs/a", "github/repo/orgs/b", "github/release/users/c"])]
+    #[case(&[], &["github/repo/orgs/b"], &["github/repo/orgs/b"])]
+    #[case(&["releases"], &["github/repo/orgs/a"], &["github/repo/orgs/a", "github/release/users/c"])]
+    #[case(&["unknown"], &[], &[])]
+    fn select_policies(#[case] tags: &[&str], #[case] names: &[&str], #[case] expected: &[&str]) {
+        let dir = tempfile::tempdir().expect("a temporary directory");
+        std::fs::write(
+            dir.path().join("config.yaml"),
+            r#"
+schedule: "0 * * * *"
+backups:
+  - kind: github/repo
+    from: [orgs/a, orgs/b]
+    to: /backups/repos
+    tags: [nightly]
+  - kind: github/release
+    from: users/c
+    to: /backups/releases
+    tags: [releases, weekly]
+"#,
+        )
+        .unwrap();
+
+        let mut config = Config::load(dir.path().join("config.yaml")).expect("the config to load");
+        config.select(
+            &tags.iter().map(|t| t.to_string()).collect::<Vec<_>>(),
+            &names.iter().map(|n| n.to_string()).collect::<Vec<_>>(),
+        );
+
+        let selected = config
+            .backups
+            .iter()
+            .map(|policy| policy.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(selected, expected);
+    }
+
+    #[test]
+    fn load_empty_sources() {
+        let dir = tempfile::tempdir().expect("a temporary directory");
+        std::fs::write(
+            dir.path().join("config.yaml"),
+            "schedule: null\nbackups:\n  - kind: github/repo\n    from: []\n",
+        )
+        .unwrap();
+
+        let err = Config::load(dir.path().join("config.yaml"))
+            .err()
+            .expect("the config to be rejected");
+        assert!(err.to_string().contains("empty list of sources"), "{err}");
+    }
+
+    #[rstest]
+    #[case("- $include: a.yaml\n", "loop")]
+    #[case("- $include: b.yaml\n", "loop")]
+    #[case("- $include: missing.yaml\n", "missing.yaml")]
+    #[case("- $include: [c.yaml]\n", "must be the path")]
+    fn load_invalid_includes(#[case] content: &str, #[case] error: &str) {
+        let dir = tempfile::tempdir().expect("a temporary directory");
+        std::fs::write(
+            dir.path().join("a.yaml"),
+            "backups:\n  - $include: b.yaml\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("b.yaml"), content).unwrap();
+
+        let err = Config::load(dir.path().join("a.yaml"))
+            .err()
+            .expect("the config to be rejected");
+        assert!(err.to_string().contains(error), "{err}");
+    }
+
+    #[test]
+    fn schema_describes_example_config() {
+        let schema = serde_json::to_value(Config::schema()).unwrap();
+        let example: serde_yaml::Value = serde_yaml::from_str(
+            &std::fs::read_to_string(
+                std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                    .join("examples")
+                    .join("config.yaml"),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        for key in example.as_mapping().unwrap().keys() {
+            let key = key.as_str().unwrap();
+            assert!(
+                schema["properties"].get(key).is_some(),
+                "the schema should describe the '{key}' field"
+            );
+        }
+
+        let policy = &schema["definitions"]["BackupPolicy"];
+        let kinds: Vec<_> = ["GitHubArtifactKind", "HttpArtifactKind"]
+            .iter()
+            .flat_map(|kind| schema["definitions"][kind]["enum"].as_array().unwrap())
+            .collect();
+        for backup in example["backups"].as_sequence().unwrap() {
+            for key in backup.as_mapping().unwrap().keys() {
+                let key = key.as_str().unwrap();
+                assert!(
+                    policy["properties"].get(key).is_some(),
+                    "the schema should describe the policy's '{key}' field"
+                );
+            }
+
+            assert!(kinds.contains(&&serde_json::to_value(backup["kind"].as_str()).unwrap()));
+        }
+
+        assert!(
+            kinds.contains(&&serde_json::json!("http/file")),
+            "the schema should describe the kinds which aren't backed up from GitHub"
+        );
+
+        assert!(schema["definitions"]["Credentials"]["anyOf"].is_array());
+    }
 }
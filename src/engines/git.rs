@@ -10,48 +10,163 @@ use gix::{
 use tracing_batteries::prelude::*;
 
 use crate::{
-    entities::{Credentials, GitRepo},
-    errors, BackupEntity,
+    entities::{Credentials, GitRepo, GitRepoFormat},
+    errors,
+    helpers::safe_walk::{self, EntryKind},
+    BackupEntity,
 };
 
-use super::{BackupEngine, BackupState};
+use super::{BackupDetails, BackupEngine, BackupState, BackupStore, SkipReason};
 
-#[derive(Clone)]
-pub struct GitEngine;
+/// The refspecs used to fetch updates when no custom refspecs have been configured.
+const DEFAULT_REFSPECS: &[&str] = &["+refs/heads/*:refs/remotes/origin/*"];
+
+/// The refspecs used to fetch updates for a mirror, which include every ref on the remote
+/// (such as GitHub's `refs/pull/*` refs and `refs/notes/*`), not only its branches and tags.
+const MIRROR_REFSPECS: &[&str] = &["+refs/*:refs/*"];
+
+/// The refspecs used to fetch the branches and tags which are included in a bundle.
+const BUNDLE_REFSPECS: &[&str] = &["+refs/heads/*:refs/heads/*", "+refs/tags/*:refs/tags/*"];
+
+/// The (lowercase) messages which gix uses to report that a remote rejected our credentials.
+const AUTHENTICATION_FAILURES: &[&str] = &["authentication", "unauthorized", "credentials"];
+
+/// The signature which identifies a (version 2) git bundle.
+const BUNDLE_SIGNATURE: &str = "# v2 git bundle";
+
+/// The name of the file, kept in each repository's backup directory, which records where the
+/// backup came from and when it was last synced.
+const METADATA_FILE_NAME: &str = ".gitbackup-meta";
+
+/// The directory, within a working tree backup's git directory, in which its attachments are
+/// stored so that they don't appear as untracked files in the working tree.
+const WORKTREE_ATTACHMENTS_DIR: &str = "github-backup";
+
+/// The contents of a repository's [`METADATA_FILE_NAME`] file.
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+struct BackupMetadata {
+    name: String,
+    source: String,
+    kind: Option<String>,
+    last_sync: chrono::DateTime<chrono::Utc>,
+    head: Option<String>,
+    /// When the repository was last repacked (or cloned, which leaves it fully packed).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_repack: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Clone, Default)]
+pub struct GitEngine {
+    config_overrides: Vec<String>,
+}
 
 #[async_trait::async_trait]
 impl BackupEngine<GitRepo> for GitEngine {
     #[allow(clippy::blocks_in_conditions)]
-    #[tracing::instrument(skip(self, target, entity), res, err, entity=%entity)]
+    #[tracing::instrument(skip(self, store, target, entity), res, err, entity=%entity)]
     async fn backup<P: AsRef<Path> + Send>(
         &self,
         entity: &GitRepo,
+        store: &dyn BackupStore,
         target: P,
         cancel: &AtomicBool,
     ) -> Result<BackupState, crate::Error> {
+        if entity.format == GitRepoFormat::Bundle {
+            return self.bundle(entity, store, target.as_ref(), cancel).await;
+        }
+
         let target_path = target.as_ref().join(entity.target_path());
-        self.ensure_directory(&target_path)?;
+        self.ensure_directory(store, &target_path).await?;
 
-        if target_path.join(".git").exists() {
+        let state = if store.exists(&target_path.join(".git")).await {
             trace!(
                 "Git directory exists at {}/.git, using fetch mode.",
                 target_path.display()
             );
-            self.fetch(entity, &target_path, cancel)
+            match Self::with_refreshed_credentials(entity, |repo| {
+                self.fetch(repo, &target_path, cancel)
+            }) {
+                Ok(state) => state,
+                Err(e) if !cancel.load(std::sync::atomic::Ordering::Relaxed) => {
+                    // Only a corrupted backup is replaced, since removing a healthy backup when the
+                    // remote is unavailable (or our credentials are invalid) would only lose data.
+                    let Some(corruption) = self.find_corruption(&target_path) else {
+                        return Err(e);
+                    };
+
+                    warn!(
+                        "The backup of {} at {} appears to be corrupted ({corruption}), so it will be replaced with a fresh clone.",
+                        entity.clone_url,
+                        target_path.display()
+                    );
+
+                    store.remove_dir_all(&target_path).await.map_err(|e| {
+                        errors::user_with_internal(
+                            &format!(
+                                "Unable to remove the corrupted backup of '{}' at '{}'.",
+                                entity.clone_url,
+                                target_path.display()
+                            ),
+                            "Make sure that you have permission to write to the backup directory, or remove this directory manually.",
+                            e,
+                        )
+                    })?;
+
+                    self.ensure_directory(store, &target_path).await?;
+                    self.clone(entity, &target_path, cancel)?
+                }
+                Err(e) => return Err(e),
+            }
         } else {
             trace!(
                 "No Git directory found at {}/.git, using clone mode.",
                 target_path.display()
             );
-            self.clone(entity, &target_path, cancel)
+            self.clone(entity, &target_path, cancel)?
+        };
+
+        let last_repack = Self::read_metadata(store, &target_path)
+            .await
+            .and_then(|metadata| metadata.last_repack);
+        let (state, last_repack) = self.repack_if_due(entity, &target_path, state, last_repack);
+
+        self.write_attachments(store, entity, &target_path).await?;
+
+        let head = gix::open(&target_path)
+            .ok()
+            .and_then(|repository| Some(repository.head_id().ok()?.to_hex().to_string()));
+        self.write_metadata(store, entity, &target_path, head, last_repack)
+            .await;
+
+        Ok(state)
+    }
+
+    fn backup_path(&self, entity: &GitRepo, target: &Path) -> std::path::PathBuf {
+        let path = target.join(entity.target_path());
+        if entity.format != GitRepoFormat::Bundle {
+            return path;
         }
+
+        let mut bundle_path = path.into_os_string();
+        bundle_path.push(".bundle");
+        bundle_path.into()
     }
 }
 
 impl GitEngine {
-    fn ensure_directory(&self, path: &Path) -> Result<(), errors::Error> {
+    /// Applies additional Git configuration (for example, `http.proxy=...`) whenever repositories
+    /// are cloned or fetched.
+    pub fn with_config_overrides(self, config_overrides: Vec<String>) -> Self {
+        Self { config_overrides }
+    }
+
+    async fn ensure_directory(
+        &self,
+        store: &dyn BackupStore,
+        path: &Path,
+    ) -> Result<(), errors::Error> {
         trace!("Ensuring directory exists: {}", path.display());
-        std::fs::create_dir_all(path).map_err(|e| {
+        store.create_dir_all(path).await.map_err(|e| {
             errors::user_with_internal(
                 &format!("Unable to create backup directory '{}'", path.display()),
                 "Make sure that you have permission to create the directory.",
@@ -60,7 +175,565 @@ impl GitEngine {
         })
     }
 
-    #[tracing::instrument(skip(self, repo, target, cancel), err)]
+    async fn write_attachments(
+        &self,
+        store: &dyn BackupStore,
+        repo: &GitRepo,
+        target: &Path,
+    ) -> Result<(), errors::Error> {
+        let target = match repo.worktree {
+            true => target.join(".git").join(WORKTREE_ATTACHMENTS_DIR),
+            false => target.to_path_buf(),
+        };
+
+        if repo.worktree && !repo.attachments.is_empty() {
+            self.ensure_directory(store, &target).await?;
+        }
+
+        for attachment in repo.attachments.iter() {
+            let path = target.join(&attachment.name);
+            trace!("Writing attachment {}", path.display());
+            store.write(&path, &attachment.content).await.map_err(|e| {
+                errors::user_with_internal(
+                    &format!(
+                        "Unable to write the '{}' file for repository '{}'.",
+                        path.display(),
+                        repo.name()
+                    ),
+                    "Make sure that you have permission to write to the backup directory.",
+                    e,
+                )
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Records where a repository's backup came from and when it was last synced, so that the
+    /// backup can still be identified if it is moved elsewhere. Failing to write this file does
+    /// not fail the backup itself.
+    async fn write_metadata(
+        &self,
+        store: &dyn BackupStore,
+        repo: &GitRepo,
+        target: &Path,
+        head: Option<String>,
+        last_repack: Option<chrono::DateTime<chrono::Utc>>,
+    ) {
+        let path = target.join(METADATA_FILE_NAME);
+        let metadata = BackupMetadata {
+            name: repo.name().to_string(),
+            source: repo.clone_url.clone(),
+            kind: repo.artifact_kind.clone(),
+            last_sync: chrono::Utc::now(),
+            head,
+            last_repack,
+        };
+
+        let content = match serde_json::to_vec_pretty(&metadata) {
+            Ok(content) => content,
+            Err(e) => {
+                warn!(
+                    "Unable to serialize the backup metadata for '{}': {e}",
+                    repo.name()
+                );
+                return;
+            }
+        };
+
+        trace!("Writing backup metadata {}", path.display());
+        if let Err(e) = store.write(&path, &content).await {
+            warn!(
+                "Unable to write the backup metadata file '{}' for repository '{}': {e}",
+                path.display(),
+                repo.name()
+            );
+        }
+    }
+
+    /// Reads the metadata written by a previous backup of a repository, if there is any.
+    async fn read_metadata(store: &dyn BackupStore, target: &Path) -> Option<BackupMetadata> {
+        let path = target.join(METADATA_FILE_NAME);
+        if !store.exists(&path).await {
+            return None;
+        }
+
+        serde_json::from_str(&store.read_to_string(&path).await.ok()?).ok()
+    }
+
+    /// Repacks the repository if the policy has enabled the `repack` property and it hasn't been
+    /// repacked within the configured interval, returning the (possibly annotated) backup state and
+    /// the time at which the repository was last repacked.
+    ///
+    /// Repacking is a maintenance task, so failing to repack a repository does not fail its backup.
+    fn repack_if_due(
+        &self,
+        repo: &GitRepo,
+        target: &Path,
+        state: BackupState,
+        last_repack: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> (BackupState, Option<chrono::DateTime<chrono::Utc>>) {
+        let Some(interval) = repo.repack else {
+            return (state, last_repack);
+        };
+
+        let now = chrono::Utc::now();
+        match state {
+            // A fresh clone stores all of its objects in a single pack, so there is nothing to repack.
+            BackupState::New(_) => (state, Some(now)),
+            BackupState::Skipped(_) => (state, last_repack),
+            _ if last_repack
+                .is_some_and(|last| (now - last).to_std().unwrap_or_default() < interval) =>
+            {
+                (state, last_repack)
+            }
+            state => match self.repack(repo, target) {
+                Ok(reclaimed) => {
+                    let note = format!("reclaimed {reclaimed} bytes by repacking");
+                    let annotate = |details: BackupDetails| BackupDetails {
+                        description: Some(match details.description {
+                            Some(description) => format!("{description}, {note}"),
+                            None => note.clone(),
+                        }),
+                        ..details
+                    };
+
+                    let state = match state {
+                        BackupState::Updated(detail) => BackupState::Updated(annotate(detail)),
+                        BackupState::Unchanged(detail) => BackupState::Unchanged(annotate(detail)),
+                        state => state,
+                    };
+
+                    (state, Some(now))
+                }
+                Err(e) => {
+                    warn!("Unable to repack the backup of '{}': {}", repo.name(), e);
+                    (state, last_repack)
+                }
+            },
+        }
+    }
+
+    /// Consolidates the loose objects and packs in a repository into a single pack, returning the
+    /// number of bytes of disk space which were reclaimed.
+    ///
+    /// gix is not yet able to repack repositories, so this relies on `git` being installed.
+    #[tracing::instrument(skip(self, repo, target), err)]
+    fn repack(&self, repo: &GitRepo, target: &Path) -> Result<u64, errors::Error> {
+        let objects = target.join(".git").join("objects");
+        let size = || -> u64 {
+            safe_walk::walk(&objects, |_| true)
+                .map(|entries| {
+                    entries
+                        .iter()
+                        .filter(|e| e.kind == EntryKind::File)
+                        .map(|e| e.size)
+                        .sum()
+                })
+                .unwrap_or_default()
+        };
+
+        let before = size();
+        trace!("Repacking repository {}", target.display());
+        let output = std::process::Command::new("git")
+            .arg("--git-dir")
+            .arg(target.join(".git"))
+            .args(["gc", "--quiet", "--prune=now"])
+            .output()
+            .map_err(|e| {
+                errors::user_with_internal(
+                    &format!("Unable to run 'git gc' to repack the repository '{}'.", repo.name()),
+                    "Make sure that git is installed and available on your PATH, or remove the 'repack' property from your backup policy.",
+                    e,
+                )
+            })?;
+
+        if !output.status.success() {
+            return Err(errors::user(
+                &format!(
+                    "Running 'git gc' to repack the repository '{}' failed: {}",
+                    repo.name(),
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+                "Make sure that the backup is a valid git repository and that you have permission to write to it.",
+            ));
+        }
+
+        let reclaimed = before.saturating_sub(size());
+        debug!("Repacked {}, reclaiming {} bytes", repo.name(), reclaimed);
+        Ok(reclaimed)
+    }
+
+    /// Backs up a repository as a single `.bundle` file, rather than as a bare repository.
+    ///
+    /// The repository is cloned into a temporary directory, from which the bundle is assembled
+    /// (since a fresh clone stores all of its objects in a single pack, that pack can be used as
+    /// the bundle's content directly). The temporary clone is removed once the bundle has been
+    /// written, and the bundle is only replaced if the refs it contains have changed.
+    async fn bundle(
+        &self,
+        repo: &GitRepo,
+        store: &dyn BackupStore,
+        target: &Path,
+        cancel: &AtomicBool,
+    ) -> Result<BackupState, errors::Error> {
+        let bundle_path = self.backup_path(repo, target);
+
+        if let Some(parent) = bundle_path.parent() {
+            self.ensure_directory(store, parent).await?;
+        }
+
+        let mut clone_path = bundle_path.clone().into_os_string();
+        clone_path.push(".tmp.git");
+        let clone_path = std::path::PathBuf::from(clone_path);
+
+        let result = self
+            .write_bundle(repo, store, &bundle_path, &clone_path, cancel)
+            .await;
+
+        trace!("Removing temporary clone at {}", clone_path.display());
+        if let Err(e) = store.remove_dir_all(&clone_path).await {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!(
+                    "Unable to remove the temporary clone at '{}': {}",
+                    clone_path.display(),
+                    e
+                );
+            }
+        }
+
+        let state = result?;
+
+        if !repo.attachments.is_empty() {
+            let target_path = target.join(repo.target_path());
+            self.ensure_directory(store, &target_path).await?;
+            self.write_attachments(store, repo, &target_path).await?;
+        }
+
+        Ok(state)
+    }
+
+    async fn write_bundle(
+        &self,
+        repo: &GitRepo,
+        store: &dyn BackupStore,
+        bundle_path: &Path,
+        clone_path: &Path,
+        cancel: &AtomicBool,
+    ) -> Result<BackupState, errors::Error> {
+        if store.exists(clone_path).await {
+            store.remove_dir_all(clone_path).await.map_err(|e| {
+                errors::user_with_internal(
+                    &format!(
+                        "Unable to remove the previous temporary clone at '{}'.",
+                        clone_path.display()
+                    ),
+                    "Make sure that you have permission to write to the backup directory.",
+                    e,
+                )
+            })?;
+        }
+
+        // Listing the remote's refs is far cheaper than cloning the repository, so we only clone it
+        // when they no longer match the refs recorded in the existing bundle.
+        let existing = Self::read_bundle_header(store, bundle_path).await;
+        if let Some(existing) = existing.as_deref() {
+            let remote = self.remote_bundle_header(repo, clone_path);
+            store.remove_dir_all(clone_path).await.ok();
+
+            if let Some((_, head)) = remote.filter(|(header, _)| header == existing) {
+                trace!("The bundle for {} is already up to date", repo.clone_url);
+                return Ok(BackupState::Unchanged(
+                    BackupDetails::new(format!("at {head} (bundle)")).with_git_head(head),
+                ));
+            }
+        }
+
+        trace!(
+            "Cloning repository {} into {} to create a bundle",
+            repo.clone_url,
+            clone_path.display()
+        );
+        let mut fetch = gix::prepare_clone_bare(repo.clone_url.as_str(), clone_path).map_err(|e| errors::system_with_internal(
+            &format!("Failed to clone the repository {}.", &repo.clone_url),
+            "Please make sure that the target directory is writable and that the repository is accessible.",
+            e,
+        ))?
+        .with_in_memory_config_overrides(self.config_overrides.iter().map(String::as_str));
+
+        match repo.credentials.for_url(&repo.clone_url) {
+            Credentials::None => {}
+            creds => {
+                fetch = fetch.configure_connection(move |c| {
+                    Self::authenticate_connection(c, &creds);
+                    Ok(())
+                });
+            }
+        }
+
+        let refspecs = if repo.mirror {
+            MIRROR_REFSPECS
+        } else {
+            BUNDLE_REFSPECS
+        };
+        fetch = fetch.configure_remote(move |r| {
+            Ok(r.with_refspecs(refspecs.iter().copied(), gix::remote::Direction::Fetch)?)
+        });
+
+        let (repository, _outcome) = fetch.fetch_only(Discard, cancel).map_err(|e| Self::remote_error(repo, e, |e| errors::system_with_internal(
+            &format!("Unable to clone remote repository '{}'", repo.clone_url),
+            "Make sure that your internet connectivity is working correctly, and that your local git configuration is able to clone this repo.",
+            e)))?;
+
+        if Self::has_no_refs(&repository) {
+            return Ok(Self::skip_empty(repo));
+        }
+
+        let head_id = repository.head_id().map_err(|e| errors::user_with_internal(
+            &format!("The repository '{}' did not have a valid HEAD, which may indicate that there is something wrong with the source repository.", &repo.clone_url),
+            "Make sure that the remote repository is valid.",
+            e))?;
+        let head = head_id.to_hex().to_string();
+
+        let refs = Self::bundle_refs(repo, &repository)?;
+        let header = Self::bundle_header(&refs, Some(&head));
+
+        if existing.as_deref() == Some(header.as_str()) {
+            return Ok(BackupState::Unchanged(
+                BackupDetails::new(format!("at {head} (bundle)")).with_git_head(head),
+            ));
+        }
+
+        let pack_path = Self::bundle_pack(&repository)?;
+
+        let mut temp_path = bundle_path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        let temp_path = std::path::PathBuf::from(temp_path);
+
+        trace!("Writing bundle to {}", temp_path.display());
+        let write_err = |e: std::io::Error| {
+            errors::user_with_internal(
+                &format!(
+                    "Unable to write the bundle for repository '{}' to '{}'.",
+                    repo.name(),
+                    bundle_path.display()
+                ),
+                "Make sure that you have permission to write to the backup directory.",
+                e,
+            )
+        };
+
+        {
+            use tokio::io::AsyncWriteExt;
+
+            let mut file = store.create(&temp_path).await.map_err(write_err)?;
+            file.write_all(header.as_bytes()).await.map_err(write_err)?;
+
+            let mut pack = tokio::fs::File::open(&pack_path).await.map_err(write_err)?;
+            tokio::io::copy(&mut pack, &mut file)
+                .await
+                .map_err(write_err)?;
+            file.shutdown().await.map_err(write_err)?;
+        }
+
+        store
+            .rename(&temp_path, bundle_path)
+            .await
+            .map_err(write_err)?;
+
+        if existing.is_some() {
+            Ok(BackupState::Updated(
+                BackupDetails::new(format!("{head} (bundle)")).with_git_head(head),
+            ))
+        } else {
+            Ok(BackupState::New(
+                BackupDetails::new(format!("at {head} (bundle)")).with_git_head(head),
+            ))
+        }
+    }
+
+    /// Lists the refs (and the objects they point to) which should be included in a bundle,
+    /// sorted by name so that the bundle's header is stable between backups.
+    fn bundle_refs(
+        repo: &GitRepo,
+        repository: &gix::Repository,
+    ) -> Result<Vec<(String, String)>, errors::Error> {
+        let references = repository.references().map_err(|e| {
+            errors::system_with_internal(
+                &format!(
+                    "Unable to list the refs in the clone of repository '{}'.",
+                    repo.name()
+                ),
+                "Make sure that the git repository has been correctly initialized.",
+                e,
+            )
+        })?;
+
+        let all = references.all().map_err(|e| {
+            errors::system_with_internal(
+                &format!(
+                    "Unable to list the refs in the clone of repository '{}'.",
+                    repo.name()
+                ),
+                "Make sure that the git repository has been correctly initialized.",
+                e,
+            )
+        })?;
+
+        let mut refs: Vec<(String, String)> = all
+            .flatten()
+            .map(|r| (r.name().as_bstr().to_string(), r.id().to_hex().to_string()))
+            .filter(|(name, _)| !name.starts_with("refs/remotes/"))
+            .collect();
+
+        refs.sort();
+        Ok(refs)
+    }
+
+    /// Lists the refs on the remote (without fetching any objects) and builds the header that a
+    /// bundle of them would have, along with the remote's HEAD. The empty repository at
+    /// `scratch_path` is only used to connect to the remote, and returns `None` if the refs
+    /// couldn't be listed (in which case the repository is cloned as usual).
+    fn remote_bundle_header(
+        &self,
+        repo: &GitRepo,
+        scratch_path: &Path,
+    ) -> Option<(String, String)> {
+        trace!("Listing the refs of remote repository {}", repo.clone_url);
+        let repository = gix::ThreadSafeRepository::init_opts(
+            scratch_path,
+            gix::create::Kind::Bare,
+            Default::default(),
+            gix::open::Options::default()
+                .config_overrides(self.config_overrides.iter().map(String::as_str)),
+        )
+        .inspect_err(|e| trace!("Unable to create {}: {e}", scratch_path.display()))
+        .ok()?
+        .to_thread_local();
+
+        let refspecs = if repo.mirror {
+            MIRROR_REFSPECS
+        } else {
+            BUNDLE_REFSPECS
+        };
+        let remote = repository
+            .remote_at(repo.clone_url.as_str())
+            .ok()?
+            .with_fetch_tags(Tags::None)
+            .with_refspecs(
+                refspecs.iter().copied().chain(["HEAD"]),
+                gix::remote::Direction::Fetch,
+            )
+            .ok()?;
+
+        let mut connection = remote
+            .connect(gix::remote::Direction::Fetch)
+            .inspect_err(|e| trace!("Unable to connect to {}: {e}", repo.clone_url))
+            .ok()?;
+        Self::authenticate_connection(&mut connection, &repo.credentials.for_url(&repo.clone_url));
+
+        let (ref_map, _) = connection
+            .ref_map(Discard, Default::default())
+            .inspect_err(|e| trace!("Unable to list the refs of {}: {e}", repo.clone_url))
+            .ok()?;
+
+        let head = ref_map
+            .mappings
+            .iter()
+            .find(|m| m.remote.as_name().is_some_and(|name| name == "HEAD"))
+            .and_then(|m| m.remote.as_id())
+            .map(|id| id.to_hex().to_string())?;
+
+        let mut refs: Vec<(String, String)> = ref_map
+            .mappings
+            .iter()
+            .filter_map(|m| {
+                Some((
+                    m.local.as_ref()?.to_string(),
+                    m.remote.as_id()?.to_hex().to_string(),
+                ))
+            })
+            .filter(|(name, _)| !name.starts_with("refs/remotes/"))
+            .collect();
+        refs.sort();
+        refs.dedup();
+
+        Some((Self::bundle_header(&refs, Some(&head)), head))
+    }
+
+    /// Builds the header of a bundle, which lists each of the refs that it contains.
+    fn bundle_header(refs: &[(String, String)], head: Option<&str>) -> String {
+        let mut header = format!("{BUNDLE_SIGNATURE}\n");
+        for (name, id) in refs {
+            header.push_str(&format!("{id} {name}\n"));
+        }
+
+        if let Some(head) = head {
+            header.push_str(&format!("{head} HEAD\n"));
+        }
+
+        header.push('\n');
+        header
+    }
+
+    /// Reads the header of an existing bundle, returning `None` if it doesn't exist or isn't a valid bundle.
+    async fn read_bundle_header(store: &dyn BackupStore, path: &Path) -> Option<String> {
+        use tokio::io::AsyncBufReadExt;
+
+        if !store.exists(path).await {
+            return None;
+        }
+
+        let mut reader = tokio::io::BufReader::new(store.open(path).await.ok()?);
+        let mut header = String::new();
+        loop {
+            let mut line = Vec::new();
+            if reader.read_until(b'\n', &mut line).await.ok()? == 0 {
+                return None;
+            }
+
+            header.push_str(std::str::from_utf8(&line).ok()?);
+            if line == b"\n" {
+                break;
+            }
+        }
+
+        header.starts_with(BUNDLE_SIGNATURE).then_some(header)
+    }
+
+    /// Finds the pack which holds all of the objects in a freshly cloned repository.
+    fn bundle_pack(repository: &gix::Repository) -> Result<std::path::PathBuf, errors::Error> {
+        let pack_dir = repository.path().join("objects").join("pack");
+        let packs = std::fs::read_dir(&pack_dir)
+            .map_err(|e| {
+                errors::system_with_internal(
+                    &format!(
+                        "Unable to read the pack directory '{}'.",
+                        pack_dir.display()
+                    ),
+                    "Make sure that the git repository has been correctly initialized.",
+                    e,
+                )
+            })?
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|e| e == "pack"))
+            .collect::<Vec<_>>();
+
+        match packs.as_slice() {
+            [pack] => Ok(pack.clone()),
+            _ => Err(errors::system(
+                &format!(
+                    "Expected the clone at '{}' to contain a single pack, but found {}.",
+                    repository.path().display(),
+                    packs.len()
+                ),
+                "Please report this issue to us on GitHub.",
+            )),
+        }
+    }
+
+    #[tracing::instrument(skip(self, repo, target, cancel), err, fields(objects, bytes))]
     fn clone(
         &self,
         repo: &GitRepo,
@@ -76,12 +749,12 @@ impl GitEngine {
             &format!("Failed to clone the repository {}.", &repo.clone_url),
             "Please make sure that the target directory is writable and that the repository is accessible.",
             e,
-        ))?;
+        ))?
+        .with_in_memory_config_overrides(self.config_overrides.iter().map(String::as_str));
 
-        match &repo.credentials {
+        match repo.credentials.for_url(&repo.clone_url) {
             Credentials::None => {}
             creds => {
-                let creds = creds.clone();
                 fetch = fetch.configure_connection(move |c| {
                     Self::authenticate_connection(c, &creds);
                     Ok(())
@@ -89,34 +762,68 @@ impl GitEngine {
             }
         }
 
+        if repo.mirror {
+            trace!("Configuring clone to fetch all refs (mirror mode)");
+            fetch = fetch.configure_remote(|r| {
+                Ok(r.with_refspecs(
+                    MIRROR_REFSPECS.iter().copied(),
+                    gix::remote::Direction::Fetch,
+                )?)
+            });
+        }
+
         trace!("Running clone in bare mode (not checking out files)");
-        let (repository, _outcome) = fetch.fetch_only(Discard, cancel).map_err(|e| errors::system_with_internal(
+        let (repository, outcome) = fetch.fetch_only(Discard, cancel).map_err(|e| Self::remote_error(repo, e, |e| errors::system_with_internal(
             &format!("Unable to clone remote repository '{}'", repo.clone_url),
             "Make sure that your internet connectivity is working correctly, and that your local git configuration is able to clone this repo.",
-            e))?;
+            e)))?;
 
         trace!("Configure fallback committer information");
         self.ensure_committer(&repository)?;
 
         trace!("Configuring core.bare for Git repository");
         self.update_config(&repository, |c| {
-            c.set_raw_value(&gix::config::tree::Core::BARE, "true").map_err(|e| errors::system_with_internal(
-                &format!("Unable to set the 'core.bare' configuration option for repository '{}'", repo.name()),
-                "Make sure that the git repository has been correctly initialized and run `git config core.bare true` to configure it correctly.",
-                e))?;
+            Self::configure_bare(repo, c)?;
+
+            if repo.mirror {
+                c.set_raw_value_by("remote", Some("origin".into()), "mirror", "true").map_err(|e| errors::system_with_internal(
+                    &format!("Unable to set the 'remote.origin.mirror' configuration option for repository '{}'", repo.name()),
+                    "Make sure that the git repository has been correctly initialized and run `git config remote.origin.mirror true` to configure it correctly.",
+                    e))?;
+            }
 
             Ok(())
         })?;
 
+        if Self::has_no_refs(&repository) {
+            return Ok(Self::skip_empty(repo));
+        }
+
+        self.configure_sparse(repo, &repository)?;
+
+        if repo.worktree {
+            self.checkout(repo, &repository, target)?;
+        }
+
         let head_id = repository.head_id().map_err(|e| errors::user_with_internal(
             &format!("The repository '{}' did not have a valid HEAD, which may indicate that there is something wrong with the source repository.", &repo.clone_url),
             "Make sure that the remote repository is valid.",
             e))?;
 
-        Ok(BackupState::New(Some(format!("at {}", head_id.to_hex()))))
+        let stats = TransferStats::record(&outcome);
+        Ok(BackupState::New(
+            BackupDetails::new(Self::describe(
+                repo,
+                stats
+                    .map(|stats| format!("at {}, received {stats}", head_id.to_hex()))
+                    .unwrap_or_else(|| format!("at {}", head_id.to_hex())),
+            ))
+            .with_git_head(head_id.to_hex().to_string())
+            .with_bytes(stats.map(|stats| stats.bytes)),
+        ))
     }
 
-    #[tracing::instrument(skip(self, repo, target, cancel), err)]
+    #[tracing::instrument(skip(self, repo, target, cancel), err, fields(objects, bytes))]
     fn fetch(
         &self,
         repo: &GitRepo,
@@ -124,7 +831,12 @@ impl GitEngine {
         cancel: &AtomicBool,
     ) -> Result<BackupState, errors::Error> {
         trace!("Opening repository {}", target.display());
-        let repository = gix::open(target).map_err(|e| {
+        let repository = gix::open_opts(
+            target,
+            gix::open::Options::default()
+                .config_overrides(self.config_overrides.iter().map(String::as_str)),
+        )
+        .map_err(|e| {
             errors::user_with_internal(
                 &format!(
                     "Failed to open the repository '{}' at '{}'",
@@ -137,11 +849,11 @@ impl GitEngine {
         })?;
 
         self.ensure_committer(&repository)?;
+        self.configure_sparse(repo, &repository)?;
+        self.update_config(&repository, |c| Self::configure_bare(repo, c))?;
 
         let original_head = repository.head_id().ok();
 
-        let default_refspecs = vec!["+refs/heads/*:refs/remotes/origin/*".to_string()];
-
         trace!(
             "Configuring fetch operation for repository {}",
             target.display()
@@ -159,7 +871,7 @@ impl GitEngine {
         })?
             .with_fetch_tags(Tags::All)
             .with_refspecs(
-              repo.refspecs.as_ref().unwrap_or(&default_refspecs)
+              Self::fetch_refspecs(repo)
                 .iter()
                 .map(|s| gix::bstr::BString::from(s.as_str()))
                 .collect::<Vec<gix::bstr::BString>>(),
@@ -188,52 +900,283 @@ impl GitEngine {
             )
         })?;
 
-        Self::authenticate_connection(&mut connection, &repo.credentials);
+        Self::authenticate_connection(&mut connection, &repo.credentials.for_url(&repo.clone_url));
 
         trace!(
             "Running fetch operation for remote repository {}",
             repo.clone_url
         );
-        connection
+        let outcome = connection
             .prepare_fetch(Discard, Default::default())
             .map_err(|e| {
-                errors::user_with_internal(
-                    &format!(
-                        "Unable to prepare fetch from remote git repository '{}'",
-                        &repo.clone_url
-                    ),
-                    "Make sure that the repository is available and correctly configured.",
-                    e,
-                )
+                Self::remote_error(repo, e, |e| {
+                    errors::user_with_internal(
+                        &format!(
+                            "Unable to prepare fetch from remote git repository '{}'",
+                            &repo.clone_url
+                        ),
+                        "Make sure that the repository is available and correctly configured.",
+                        e,
+                    )
+                })
             })?
             .with_write_packed_refs_only(true)
             .receive(Discard, cancel)
             .map_err(|e| {
-                errors::user_with_internal(
-                    &format!(
-                        "Unable to fetch from remote git repository '{}'",
-                        &&repo.clone_url
-                    ),
-                    "Make sure that the repository is available and correctly configured.",
-                    e,
-                )
+                Self::remote_error(repo, e, |e| {
+                    errors::user_with_internal(
+                        &format!(
+                            "Unable to fetch from remote git repository '{}'",
+                            &&repo.clone_url
+                        ),
+                        "Make sure that the repository is available and correctly configured.",
+                        e,
+                    )
+                })
             })?;
 
-        let head_id = repository.head_id().map_err(|e| errors::user_with_internal(
-            &format!("The repository '{}' did not have a valid HEAD, which may indicate that there is something wrong with the source repository.", &repo.clone_url),
-            "Make sure that the remote repository is valid.",
-            e))?;
+        if Self::has_no_refs(&repository) {
+            return Ok(Self::skip_empty(repo));
+        }
+
+        if repo.mirror {
+            self.prune(repo, &repository, &outcome)?;
+        }
+
+        if repo.worktree {
+            self.checkout(repo, &repository, target)?;
+        }
+
+        let head_id = repository.head_id().map_err(|e| errors::user_with_internal(
+            &format!("The repository '{}' did not have a valid HEAD, which may indicate that there is something wrong with the source repository.", &repo.clone_url),
+            "Make sure that the remote repository is valid.",
+            e))?;
 
         if let Some(original_head) = original_head {
             if original_head == head_id {
-                return Ok(BackupState::Unchanged(Some(format!(
-                    "at {}",
-                    head_id.to_hex()
-                ))));
+                return Ok(BackupState::Unchanged(
+                    BackupDetails::new(Self::describe(repo, format!("at {}", head_id.to_hex())))
+                        .with_git_head(head_id.to_hex().to_string()),
+                ));
+            }
+        }
+
+        let stats = TransferStats::record(&outcome);
+        Ok(BackupState::Updated(
+            BackupDetails::new(Self::describe(
+                repo,
+                stats
+                    .map(|stats| format!("{}, received {stats}", head_id.to_hex()))
+                    .unwrap_or_else(|| format!("{}", head_id.to_hex())),
+            ))
+            .with_git_head(head_id.to_hex().to_string())
+            .with_bytes(stats.map(|stats| stats.bytes)),
+        ))
+    }
+
+    /// Determines whether a repository has no refs at all, which is the case when the remote
+    /// declares a default branch but has never had any commits pushed to it (so its HEAD can't
+    /// be resolved).
+    fn has_no_refs(repository: &gix::Repository) -> bool {
+        let Ok(references) = repository.references() else {
+            return false;
+        };
+
+        let Ok(mut all) = references.all() else {
+            return false;
+        };
+
+        all.next().is_none()
+    }
+
+    fn skip_empty(repo: &GitRepo) -> BackupState {
+        info!(
+            "The repository '{}' does not have any commits on its default branch, so there is nothing to back up.",
+            repo.clone_url
+        );
+        BackupState::Skipped(SkipReason::Empty)
+    }
+
+    /// Checks whether the local copy of a repository has been corrupted (for example, by an
+    /// interrupted pack write), returning a description of the problem if it has.
+    ///
+    /// This is used to decide whether a failed fetch can be repaired by cloning the repository
+    /// again, so only problems with the repository itself are reported; refs which point to
+    /// other (missing) refs are left for the next fetch to resolve.
+    fn find_corruption(&self, target: &Path) -> Option<String> {
+        let repository = match gix::open_opts(
+            target,
+            gix::open::Options::default()
+                .config_overrides(self.config_overrides.iter().map(String::as_str)),
+        ) {
+            Ok(repository) => repository,
+            Err(e) => return Some(format!("unable to open the repository: {e}")),
+        };
+
+        let references = match repository.references() {
+            Ok(references) => references,
+            Err(e) => return Some(format!("unable to read its refs: {e}")),
+        };
+
+        let all = match references.all() {
+            Ok(all) => all,
+            Err(e) => return Some(format!("unable to read its refs: {e}")),
+        };
+
+        for reference in all {
+            let reference = match reference {
+                Ok(reference) => reference,
+                Err(e) => return Some(format!("unable to read its refs: {e}")),
+            };
+
+            let Some(id) = reference.try_id() else {
+                continue;
+            };
+
+            if let Err(e) = repository.find_object(id.detach()) {
+                return Some(format!(
+                    "the ref '{}' points to an unreadable object {}: {e}",
+                    reference.name().as_bstr(),
+                    id.to_hex()
+                ));
+            }
+        }
+
+        None
+    }
+
+    /// The refspecs which should be used when fetching updates for a repository.
+    fn fetch_refspecs(repo: &GitRepo) -> Vec<String> {
+        if repo.mirror {
+            MIRROR_REFSPECS.iter().map(|s| s.to_string()).collect()
+        } else {
+            repo.refspecs
+                .clone()
+                .unwrap_or_else(|| DEFAULT_REFSPECS.iter().map(|s| s.to_string()).collect())
+        }
+    }
+
+    /// Adds the backup mode to the description of a repository's backup state, so that it is
+    /// clear when a repository has been backed up as a mirror.
+    fn describe(repo: &GitRepo, detail: String) -> String {
+        if repo.mirror {
+            format!("{detail} (mirror)")
+        } else {
+            detail
+        }
+    }
+
+    /// Removes any local refs which no longer exist on the remote, since gix does not yet
+    /// support pruning refs as part of a fetch.
+    fn prune(
+        &self,
+        repo: &GitRepo,
+        repository: &gix::Repository,
+        outcome: &gix::remote::fetch::Outcome,
+    ) -> Result<(), errors::Error> {
+        let remote_refs: std::collections::HashSet<String> = outcome
+            .ref_map
+            .mappings
+            .iter()
+            .filter_map(|m| m.local.as_ref().map(|l| l.to_string()))
+            .collect();
+
+        let references = repository.references().map_err(|e| {
+            errors::system_with_internal(
+                &format!(
+                    "Unable to list the refs in the backup of repository '{}'.",
+                    repo.name()
+                ),
+                "Make sure that the git repository has been correctly initialized.",
+                e,
+            )
+        })?;
+
+        let all = references.all().map_err(|e| {
+            errors::system_with_internal(
+                &format!(
+                    "Unable to list the refs in the backup of repository '{}'.",
+                    repo.name()
+                ),
+                "Make sure that the git repository has been correctly initialized.",
+                e,
+            )
+        })?;
+
+        for reference in all.flatten() {
+            let name = reference.name().as_bstr().to_string();
+            if remote_refs.contains(&name) {
+                continue;
             }
+
+            trace!("Pruning ref {} which no longer exists on the remote", name);
+            reference.delete().map_err(|e| {
+                errors::system_with_internal(
+                    &format!(
+                        "Unable to remove the ref '{}' from the backup of repository '{}'.",
+                        name,
+                        repo.name()
+                    ),
+                    "Make sure that you have permission to write to the backup directory.",
+                    e,
+                )
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Reports a failed git operation as an authentication error if the remote rejected our
+    /// credentials (which gix only describes in its error messages), so that it is reported with
+    /// the correct exit code, or using `otherwise` if it failed for any other reason.
+    fn remote_error<E: std::error::Error + Send + Sync + 'static>(
+        repo: &GitRepo,
+        error: E,
+        otherwise: impl FnOnce(E) -> errors::Error,
+    ) -> errors::Error {
+        let rejected =
+            std::iter::successors(Some(&error as &(dyn std::error::Error + 'static)), |e| {
+                e.source()
+            })
+            .any(|e| {
+                let message = e.to_string().to_ascii_lowercase();
+                AUTHENTICATION_FAILURES.iter().any(|m| message.contains(m))
+            });
+
+        if rejected {
+            errors::authentication_with_internal(
+                &format!(
+                    "The credentials used to access the git repository '{}' were rejected.",
+                    repo.clone_url
+                ),
+                "Make sure that your credentials are valid, have not expired, and grant access to this repository.",
+                error,
+            )
+        } else {
+            otherwise(error)
         }
+    }
+
+    /// Runs a git operation, retrying it once with refreshed credentials if the remote rejected
+    /// ours (for example, because a token which is read from a file has since been rotated).
+    fn with_refreshed_credentials<T>(
+        repo: &GitRepo,
+        operation: impl Fn(&GitRepo) -> Result<T, errors::Error>,
+    ) -> Result<T, errors::Error> {
+        match operation(repo) {
+            Err(e) if errors::ErrorCategory::of(&e) == errors::ErrorCategory::Authentication => {
+                let Some(credentials) = repo.credentials.refresh() else {
+                    return Err(e);
+                };
 
-        Ok(BackupState::Updated(Some(format!("{}", head_id.to_hex()))))
+                debug!(
+                    "Retrying {} with refreshed credentials, since the remote rejected the previous ones",
+                    repo.clone_url
+                );
+                operation(&repo.clone().with_credentials(credentials))
+            }
+            result => result,
+        }
     }
 
     fn authenticate_connection<T: Transport>(
@@ -247,20 +1190,7 @@ impl GitEngine {
                 let creds = creds.clone();
                 connection.set_credentials(move |a| match a {
                     Action::Get(ctx) => Ok(Some(gix::credentials::protocol::Outcome {
-                        identity: match &creds {
-                            Credentials::None => Account {
-                                username: "".into(),
-                                password: "".into(),
-                            },
-                            Credentials::Token(token) => Account {
-                                username: token.clone(),
-                                password: "".into(),
-                            },
-                            Credentials::UsernamePassword { username, password } => Account {
-                                username: username.clone(),
-                                password: password.clone(),
-                            },
-                        },
+                        identity: Self::account(&creds),
                         next: ctx.into(),
                     })),
                     _ => Ok(None),
@@ -269,6 +1199,237 @@ impl GitEngine {
         }
     }
 
+    fn account(creds: &Credentials) -> Account {
+        match creds {
+            Credentials::None => Account {
+                username: "".into(),
+                password: "".into(),
+            },
+            Credentials::Token { value: token, .. } => Account {
+                username: token.clone(),
+                password: "".into(),
+            },
+            Credentials::UsernamePassword {
+                username, password, ..
+            } => Account {
+                username: username.clone(),
+                password: password.clone(),
+            },
+            // Sources resolve a chain of credentials to the ones which can access the repository
+            // before it is backed up, so we only fall back to the first of them here.
+            Credentials::Chain(chain) => Self::account(chain.first().unwrap_or(&Credentials::None)),
+            // Per-host credentials are resolved using the repository's clone URL before connecting.
+            Credentials::PerHost(_) => Self::account(&Credentials::None),
+        }
+    }
+
+    /// Backups are stored as bare repositories, unless they have a working tree which is kept
+    /// up to date with the default branch.
+    fn configure_bare(
+        repo: &GitRepo,
+        config: &mut gix::config::File<'_>,
+    ) -> Result<(), errors::Error> {
+        let bare = if repo.worktree { "false" } else { "true" };
+        config.set_raw_value(&gix::config::tree::Core::BARE, bare).map_err(|e| errors::system_with_internal(
+            &format!("Unable to set the 'core.bare' configuration option for repository '{}'", repo.name()),
+            &format!("Make sure that the git repository has been correctly initialized and run `git config core.bare {bare}` to configure it correctly."),
+            e))?;
+
+        Ok(())
+    }
+
+    /// Checks out the latest commit on the repository's default branch into its working tree, so
+    /// that the backup can be browsed directly.
+    ///
+    /// The working tree is only ever updated by GitHub Backup, so it is hard-reset to match the
+    /// remote: any local changes are overwritten and files which no longer exist are removed.
+    fn checkout(
+        &self,
+        repo: &GitRepo,
+        repository: &gix::Repository,
+        target: &Path,
+    ) -> Result<(), errors::Error> {
+        let Some(branch) = repository
+            .head_name()
+            .map_err(|e| Self::checkout_error(repo, target, e))?
+        else {
+            debug!(
+                "The repository '{}' does not have a default branch, so no working tree will be checked out.",
+                repo.name()
+            );
+            return Ok(());
+        };
+
+        // Fetches only update the remote-tracking branches (unless we are mirroring), so we move
+        // the local default branch to the remote's latest commit before checking it out.
+        let remote_branch = format!("refs/remotes/origin/{}", branch.shorten());
+        let commit_id = match repository.find_reference(remote_branch.as_str()) {
+            Ok(mut reference) if !repo.mirror => reference
+                .peel_to_id_in_place()
+                .map_err(|e| Self::checkout_error(repo, target, e))?
+                .detach(),
+            _ => repository
+                .head_id()
+                .map_err(|e| Self::checkout_error(repo, target, e))?
+                .detach(),
+        };
+
+        repository
+            .reference(
+                branch.as_bstr(),
+                commit_id,
+                gix::refs::transaction::PreviousValue::Any,
+                "github-backup: reset to the remote default branch",
+            )
+            .map_err(|e| Self::checkout_error(repo, target, e))?;
+
+        let tree = repository
+            .find_commit(commit_id)
+            .map_err(|e| Self::checkout_error(repo, target, e))?
+            .tree()
+            .map_err(|e| Self::checkout_error(repo, target, e))?;
+
+        let mut recorder = gix::traverse::tree::Recorder::default();
+        tree.traverse()
+            .breadthfirst(&mut recorder)
+            .map_err(|e| Self::checkout_error(repo, target, e))?;
+
+        trace!(
+            "Checking out {} files from {} into {}",
+            recorder.records.len(),
+            commit_id,
+            target.display()
+        );
+
+        let mut files = std::collections::HashSet::new();
+        for entry in recorder.records.iter() {
+            let relative = entry.filepath.to_string();
+            let path = worktree_path(target, &relative)
+                .map_err(|e| Self::checkout_error(repo, target, e))?;
+
+            if entry.mode.is_tree() {
+                if std::fs::symlink_metadata(&path).is_ok_and(|m| !m.is_dir()) {
+                    remove_path(&path).map_err(|e| Self::checkout_error(repo, target, e))?;
+                }
+
+                continue;
+            } else if entry.mode.is_commit() {
+                // Submodules are backed up separately (if at all), so we leave them empty.
+                continue;
+            }
+
+            let content = repository
+                .find_object(entry.oid)
+                .map_err(|e| Self::checkout_error(repo, target, e))?
+                .detach()
+                .data;
+
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)
+                    .map_err(|e| Self::checkout_error(repo, target, e))?;
+            }
+
+            write_worktree_file(
+                &path,
+                &content,
+                entry.mode.is_link(),
+                entry.mode.is_executable(),
+            )
+            .map_err(|e| Self::checkout_error(repo, target, e))?;
+            files.insert(std::path::PathBuf::from(relative));
+        }
+
+        remove_stale_files(target, &files).map_err(|e| Self::checkout_error(repo, target, e))?;
+
+        let mut index = repository
+            .index_from_tree(&tree.id)
+            .map_err(|e| Self::checkout_error(repo, target, e))?;
+        index
+            .write(Default::default())
+            .map_err(|e| Self::checkout_error(repo, target, e))?;
+
+        Ok(())
+    }
+
+    fn checkout_error<E: std::error::Error + Send + Sync + 'static>(
+        repo: &GitRepo,
+        target: &Path,
+        error: E,
+    ) -> errors::Error {
+        errors::system_with_internal(
+            &format!(
+                "Unable to check out the default branch of repository '{}' into '{}'.",
+                repo.name(),
+                target.display()
+            ),
+            "Make sure that you have permission to write to the backup directory, and remove the working tree files if they have become corrupted.",
+            error,
+        )
+    }
+
+    /// Records the sparse-checkout patterns for a repository.
+    ///
+    /// Since backups are stored as bare repositories (and gix does not yet support partial clone
+    /// filters), the full history is still fetched. The patterns are written to the repository's
+    /// sparse-checkout configuration so that they are applied when the backup is restored.
+    fn configure_sparse(
+        &self,
+        repo: &GitRepo,
+        repository: &gix::Repository,
+    ) -> Result<(), errors::Error> {
+        let Some(patterns) = repo.sparse.as_ref() else {
+            return Ok(());
+        };
+
+        debug!(
+            "Recording sparse-checkout patterns for {}; the full repository history will still be backed up.",
+            repo.name()
+        );
+
+        self.write_sparse_patterns(repository.path(), patterns)?;
+
+        self.update_config(repository, |c| {
+            c.set_raw_value(&gix::config::tree::Core::SPARSE_CHECKOUT, "true").map_err(|e| errors::system_with_internal(
+                &format!("Unable to set the 'core.sparseCheckout' configuration option for repository '{}'", repo.name()),
+                "Make sure that the git repository has been correctly initialized and run `git config core.sparseCheckout true` to configure it correctly.",
+                e))?;
+
+            Ok(())
+        })
+    }
+
+    fn write_sparse_patterns(
+        &self,
+        git_dir: &Path,
+        patterns: &[String],
+    ) -> Result<(), errors::Error> {
+        // The sparse-checkout patterns live within the git directory, which is managed
+        // by gix directly on the local filesystem rather than through the backup store.
+        let info_dir = git_dir.join("info");
+        std::fs::create_dir_all(&info_dir).map_err(|e| {
+            errors::user_with_internal(
+                &format!("Unable to create backup directory '{}'", info_dir.display()),
+                "Make sure that you have permission to create the directory.",
+                e,
+            )
+        })?;
+
+        let path = info_dir.join("sparse-checkout");
+        let mut content = patterns.join("\n");
+        content.push('\n');
+
+        std::fs::write(&path, content).map_err(|e| {
+            errors::user_with_internal(
+                &format!(
+                    "Unable to write the sparse-checkout patterns to '{}'.",
+                    path.display()
+                ),
+                "Make sure that you have permission to write to the backup directory.",
+                e,
+            )
+        })
+    }
+
     fn ensure_committer(&self, repo: &gix::Repository) -> Result<(), errors::Error> {
         if repo.committer().is_none() {
             self.update_config(repo, |cfg| {
@@ -335,22 +1496,1119 @@ impl GitEngine {
     }
 }
 
+/// Describes the data which was received from the remote while cloning or fetching a repository.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TransferStats {
+    objects: u64,
+    bytes: u64,
+}
+
+impl TransferStats {
+    /// Extracts the transfer statistics from the outcome of a fetch and records them on the current
+    /// tracing span, returning `None` if no pack was received (for example, when nothing has changed).
+    fn record(outcome: &gix::remote::fetch::Outcome) -> Option<Self> {
+        let stats = match &outcome.status {
+            gix::remote::fetch::Status::Change {
+                write_pack_bundle, ..
+            } => Self {
+                objects: write_pack_bundle.index.num_objects as u64,
+                bytes: write_pack_bundle
+                    .data_path
+                    .as_ref()
+                    .and_then(|path| std::fs::metadata(path).ok())
+                    .map(|metadata| metadata.len())
+                    .unwrap_or_default(),
+            },
+            _ => return None,
+        };
+
+        let span = tracing::Span::current();
+        span.record("objects", stats.objects);
+        span.record("bytes", stats.bytes);
+        debug!(
+            "Received {} objects in a {} byte pack",
+            stats.objects, stats.bytes
+        );
+
+        Some(stats)
+    }
+}
+
+impl Display for TransferStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} objects ({} bytes)", self.objects, self.bytes)
+    }
+}
+
 impl Display for GitEngine {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "git")
     }
 }
 
+/// Writes a file in a repository's working tree, leaving it untouched if it already has the
+/// expected content so that its modification time reflects when it last changed.
+fn write_worktree_file(
+    path: &Path,
+    content: &[u8],
+    symlink: bool,
+    executable: bool,
+) -> std::io::Result<()> {
+    let metadata = std::fs::symlink_metadata(path).ok();
+
+    #[cfg(unix)]
+    if symlink {
+        let destination = std::path::PathBuf::from(String::from_utf8_lossy(content).as_ref());
+        if metadata.is_some() {
+            if std::fs::read_link(path).ok().as_ref() == Some(&destination) {
+                return Ok(());
+            }
+
+            remove_path(path)?;
+        }
+
+        return std::os::unix::fs::symlink(destination, path);
+    }
+
+    match metadata {
+        Some(metadata) if metadata.is_file() => {
+            if std::fs::read(path)? != content {
+                std::fs::write(path, content)?;
+            }
+        }
+        Some(_) => {
+            remove_path(path)?;
+            std::fs::write(path, content)?;
+        }
+        None => std::fs::write(path, content)?,
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut permissions = std::fs::metadata(path)?.permissions();
+        let mode = if executable {
+            permissions.mode() | 0o111
+        } else {
+            permissions.mode() & !0o111
+        };
+
+        if mode != permissions.mode() {
+            permissions.set_mode(mode);
+            std::fs::set_permissions(path, permissions)?;
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = (symlink, executable);
+
+    Ok(())
+}
+
+/// Removes any files from a repository's working tree which aren't part of the checked out
+/// commit, along with any directories which are left empty. The `.git` directory and our metadata
+/// file are never touched, and symlinks are removed (rather than followed) so that nothing outside
+/// of the tree is deleted.
+fn remove_stale_files(
+    root: &Path,
+    files: &std::collections::HashSet<std::path::PathBuf>,
+) -> std::io::Result<()> {
+    let git_dir = Path::new(".git");
+    let entries = safe_walk::walk(root, |relative| relative != git_dir)?;
+
+    // Directories are listed before their children, so by working backwards we empty each
+    // directory before deciding whether it should be removed.
+    for entry in entries.iter().rev() {
+        if entry.relative == git_dir || entry.relative == Path::new(METADATA_FILE_NAME) {
+            continue;
+        }
+
+        match entry.kind {
+            EntryKind::Directory if std::fs::read_dir(&entry.path)?.next().is_none() => {
+                std::fs::remove_dir(&entry.path)?;
+            }
+            EntryKind::Directory => {}
+            _ if !files.contains(&entry.relative) => {
+                trace!("Removing {} from the working tree", entry.path.display());
+                std::fs::remove_file(&entry.path)?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolves the path at which a file from a commit's tree should be written, rejecting any which
+/// would be written into the `.git` directory or outside of the working tree.
+fn worktree_path(root: &Path, relative: &str) -> std::io::Result<std::path::PathBuf> {
+    let path = Path::new(relative);
+    let safe = path.components().all(|component| match component {
+        std::path::Component::Normal(name) => !name.eq_ignore_ascii_case(".git"),
+        _ => false,
+    });
+
+    if !safe || relative.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("the repository contains a file with an unsafe path '{relative}'"),
+        ));
+    }
+
+    Ok(root.join(path))
+}
+
+fn remove_path(path: &Path) -> std::io::Result<()> {
+    if std::fs::symlink_metadata(path)?.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::{engines::FileSystemStore, entities::Attachment};
+    use rstest::rstest;
+
     use super::*;
 
+    /// Runs `git` with the given arguments in `dir`, using a fixed identity for any commits.
+    fn git(dir: &Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .env("GIT_AUTHOR_NAME", "Test")
+            .env("GIT_AUTHOR_EMAIL", "test@example.com")
+            .env("GIT_COMMITTER_NAME", "Test")
+            .env("GIT_COMMITTER_EMAIL", "test@example.com")
+            .status()
+            .expect("git to be available");
+        assert!(status.success(), "git {args:?} failed");
+    }
+
+    /// Creates a repository to back up, with a single commit (adding a README) on its `main` branch.
+    fn init_source_repo() -> tempfile::TempDir {
+        let source_dir = tempfile::tempdir().expect("a temporary directory");
+        git(source_dir.path(), &["init", "--initial-branch", "main"]);
+        std::fs::write(source_dir.path().join("README.md"), "# Test\n").unwrap();
+        git(source_dir.path(), &["add", "."]);
+        git(source_dir.path(), &["commit", "-m", "Initial commit"]);
+        source_dir
+    }
+
+    #[tokio::test]
+    async fn test_write_attachments() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+
+        let repo = GitRepo::new(
+            "SierraSoftworks/grey",
+            "https://github.com/sierrasoftworks/grey.git",
+            None,
+        )
+        .with_attachments(vec![
+            Attachment::json("settings.json", &serde_json::json!({ "name": "grey" })).unwrap(),
+            Attachment::json("protection.json", &serde_json::Value::Null).unwrap(),
+        ]);
+
+        GitEngine::default()
+            .write_attachments(&FileSystemStore, &repo, temp_dir.path())
+            .await
+            .expect("the attachments to be written");
+
+        assert_eq!(
+            std::fs::read_to_string(temp_dir.path().join("protection.json")).unwrap(),
+            "null"
+        );
+        assert!(
+            std::fs::read_to_string(temp_dir.path().join("settings.json"))
+                .unwrap()
+                .contains("\"grey\"")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_metadata() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+
+        let repo = GitRepo::new(
+            "SierraSoftworks/grey",
+            "https://github.com/sierrasoftworks/grey.git",
+            None,
+        )
+        .with_artifact_kind(Some("github/repo".to_string()));
+
+        let before = chrono::Utc::now();
+        GitEngine::default()
+            .write_metadata(
+                &FileSystemStore,
+                &repo,
+                temp_dir.path(),
+                Some("0123456789abcdef0123456789abcdef01234567".to_string()),
+                None,
+            )
+            .await;
+
+        let metadata: BackupMetadata = serde_json::from_str(
+            &std::fs::read_to_string(temp_dir.path().join(METADATA_FILE_NAME)).unwrap(),
+        )
+        .expect("the metadata to be valid JSON");
+        assert_eq!(metadata.name, "SierraSoftworks/grey");
+        assert_eq!(
+            metadata.source,
+            "https://github.com/sierrasoftworks/grey.git"
+        );
+        assert_eq!(metadata.kind.as_deref(), Some("github/repo"));
+        assert_eq!(
+            metadata.head.as_deref(),
+            Some("0123456789abcdef0123456789abcdef01234567")
+        );
+        assert!(metadata.last_sync >= before);
+
+        // Failing to write the metadata should never fail the backup.
+        GitEngine::default()
+            .write_metadata(
+                &FileSystemStore,
+                &repo,
+                &temp_dir.path().join("missing"),
+                None,
+                None,
+            )
+            .await;
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_remove_stale_files() {
+        let root = tempfile::tempdir().expect("a temporary directory");
+        let outside = tempfile::tempdir().expect("a temporary directory");
+        std::fs::create_dir_all(outside.path().join("docs")).unwrap();
+        std::fs::write(outside.path().join("docs").join("secret.md"), "Secret\n").unwrap();
+
+        std::fs::create_dir_all(root.path().join(".git")).unwrap();
+        std::fs::write(
+            root.path().join(".git").join("HEAD"),
+            "ref: refs/heads/main\n",
+        )
+        .unwrap();
+        std::fs::write(root.path().join("README.md"), "# Test\n").unwrap();
+        std::fs::write(root.path().join(METADATA_FILE_NAME), "{}").unwrap();
+        std::fs::create_dir_all(root.path().join("old").join("nested")).unwrap();
+        std::fs::write(
+            root.path().join("old").join("nested").join("file.md"),
+            "Old\n",
+        )
+        .unwrap();
+        std::os::unix::fs::symlink(outside.path(), root.path().join("outside")).unwrap();
+
+        let files = std::collections::HashSet::from([std::path::PathBuf::from("README.md")]);
+        remove_stale_files(root.path(), &files).expect("stale files to be removed");
+
+        assert!(root.path().join("README.md").exists());
+        assert!(root.path().join(".git").join("HEAD").exists());
+        assert!(
+            root.path().join(METADATA_FILE_NAME).exists(),
+            "the backup's metadata should be kept"
+        );
+        assert!(!root.path().join("old").exists());
+        assert!(
+            std::fs::symlink_metadata(root.path().join("outside")).is_err(),
+            "the stale symlink should be removed"
+        );
+        assert!(
+            outside.path().join("docs").join("secret.md").exists(),
+            "files outside of the working tree should never be removed"
+        );
+    }
+
+    #[test]
+    fn test_with_refreshed_credentials() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let token_path = temp_dir.path().join("token");
+        std::fs::write(&token_path, "expired").unwrap();
+
+        let credentials: Credentials =
+            serde_yaml::from_str(&format!("!TokenFile {}", token_path.display())).unwrap();
+        let repo = GitRepo::new("test/fixture", "https://github.com/test/fixture.git", None)
+            .with_credentials(credentials);
+
+        let attempt = |repo: &GitRepo| match &repo.credentials {
+            Credentials::Token { value, .. } if value == "rotated" => Ok(value.clone()),
+            _ => Err(errors::authentication(
+                errors::AUTHENTICATION_FAILED,
+                "Check your token.",
+            )),
+        };
+
+        GitEngine::with_refreshed_credentials(&repo, attempt)
+            .expect_err("credentials which haven't changed should not be retried");
+
+        std::fs::write(&token_path, "rotated").unwrap();
+        assert_eq!(
+            GitEngine::with_refreshed_credentials(&repo, attempt).unwrap(),
+            "rotated",
+            "the operation should be retried with the refreshed credentials"
+        );
+    }
+
+    #[rstest]
+    #[case("Authentication failed for 'https://github.com/org/repo.git'", true)]
+    #[case("Received HTTP status 401 Unauthorized", true)]
+    #[case(
+        "Credentials provided for \"https://github.com\" were not accepted by the remote",
+        true
+    )]
+    #[case("Could not resolve host: github.com", false)]
+    fn test_remote_error(#[case] message: &str, #[case] authentication: bool) {
+        let repo = GitRepo::new("org/repo", "https://github.com/org/repo.git", None);
+        let error = GitEngine::remote_error(&repo, std::io::Error::other(message), |e| {
+            errors::system_with_internal("Unable to clone the repository.", "Try again.", e)
+        });
+
+        assert_eq!(
+            errors::ErrorCategory::of(&error) == errors::ErrorCategory::Authentication,
+            authentication,
+            "'{message}' was categorized as {:?}",
+            errors::ErrorCategory::of(&error)
+        );
+    }
+
+    #[rstest]
+    #[case("README.md", true)]
+    #[case("docs/nested/file.md", true)]
+    #[case(".github/workflows/ci.yml", true)]
+    #[case(".gitignore", true)]
+    #[case(".git/config", false)]
+    #[case("docs/.GIT/hooks/post-checkout", false)]
+    #[case("../outside.md", false)]
+    #[case("docs/../../outside.md", false)]
+    #[case("/etc/passwd", false)]
+    #[case("", false)]
+    fn test_worktree_path(#[case] relative: &str, #[case] safe: bool) {
+        let root = Path::new("/backups/repo");
+        match worktree_path(root, relative) {
+            Ok(path) => {
+                assert!(safe, "'{relative}' should have been rejected");
+                assert_eq!(path, root.join(relative));
+            }
+            Err(e) => assert!(!safe, "'{relative}' should be allowed, got {e}"),
+        }
+    }
+
+    #[test]
+    fn test_write_sparse_patterns() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+
+        GitEngine::default()
+            .write_sparse_patterns(temp_dir.path(), &["docs/".to_string(), "*.md".to_string()])
+            .expect("the patterns to be written");
+        assert_eq!(
+            std::fs::read_to_string(temp_dir.path().join("info").join("sparse-checkout")).unwrap(),
+            "docs/\n*.md\n"
+        );
+
+        GitEngine::default()
+            .write_sparse_patterns(temp_dir.path(), &["src/".to_string()])
+            .expect("the patterns to be updated");
+        assert_eq!(
+            std::fs::read_to_string(temp_dir.path().join("info").join("sparse-checkout")).unwrap(),
+            "src/\n"
+        );
+    }
+
+    #[test]
+    fn test_fetch_refspecs() {
+        let repo = GitRepo::new(
+            "SierraSoftworks/grey",
+            "https://github.com/sierrasoftworks/grey.git",
+            None,
+        );
+        assert_eq!(
+            GitEngine::fetch_refspecs(&repo),
+            vec!["+refs/heads/*:refs/remotes/origin/*".to_string()]
+        );
+        assert_eq!(GitEngine::describe(&repo, "at abc".to_string()), "at abc");
+
+        let repo = repo.with_mirror(true);
+        assert_eq!(
+            GitEngine::fetch_refspecs(&repo),
+            vec!["+refs/*:refs/*".to_string()]
+        );
+        assert_eq!(
+            GitEngine::describe(&repo, "at abc".to_string()),
+            "at abc (mirror)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bundle_header() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let path = temp_dir.path().join("repo.bundle");
+
+        let header = GitEngine::bundle_header(
+            &[
+                ("refs/heads/main".to_string(), "aaaa".to_string()),
+                ("refs/tags/v1.0.0".to_string(), "bbbb".to_string()),
+            ],
+            Some("aaaa"),
+        );
+        assert_eq!(
+            header,
+            "# v2 git bundle\naaaa refs/heads/main\nbbbb refs/tags/v1.0.0\naaaa HEAD\n\n"
+        );
+
+        assert_eq!(
+            GitEngine::read_bundle_header(&FileSystemStore, &path).await,
+            None
+        );
+
+        let mut content = header.clone().into_bytes();
+        content.extend_from_slice(b"PACK\0\0\0\x02\n\nbinary content");
+        std::fs::write(&path, content).unwrap();
+        assert_eq!(
+            GitEngine::read_bundle_header(&FileSystemStore, &path).await,
+            Some(header)
+        );
+
+        std::fs::write(&path, "not a bundle\n\n").unwrap();
+        assert_eq!(
+            GitEngine::read_bundle_header(&FileSystemStore, &path).await,
+            None
+        );
+    }
+
+    #[test]
+    fn test_transfer_stats_display() {
+        let stats = TransferStats {
+            objects: 3,
+            bytes: 1024,
+        };
+
+        assert_eq!(stats.to_string(), "3 objects (1024 bytes)");
+    }
+
+    #[tokio::test]
+    #[cfg_attr(feature = "pure_tests", ignore)]
+    async fn test_backup_transfer_stats() {
+        let source_dir = init_source_repo();
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let agent = GitEngine::default();
+        let cancel = AtomicBool::new(false);
+
+        let repo = GitRepo::new(
+            "test/fixture",
+            format!("file://{}", source_dir.path().display()),
+            None,
+        );
+
+        let state = agent
+            .backup(&repo, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("initial backup to succeed");
+
+        let head = String::from_utf8(
+            std::process::Command::new("git")
+                .args(["rev-parse", "HEAD"])
+                .current_dir(source_dir.path())
+                .output()
+                .expect("git to be available")
+                .stdout,
+        )
+        .unwrap();
+
+        match &state {
+            BackupState::New(details) => {
+                let detail = details.description.as_deref().unwrap_or_default();
+                assert!(
+                    detail.contains("received 3 objects (") && detail.contains(" bytes)"),
+                    "the detail should include the transfer statistics, but was '{detail}'"
+                );
+                assert_eq!(details.git_head.as_deref(), Some(head.trim()));
+                assert!(details.bytes.is_some_and(|bytes| bytes > 0));
+            }
+            state => panic!("expected a new backup, got {state:?}"),
+        }
+
+        let state = agent
+            .backup(&repo, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("second backup to succeed");
+        assert!(
+            matches!(&state, BackupState::Unchanged(_))
+                && !state.detail().unwrap_or_default().contains("received"),
+            "no transfer statistics should be reported when nothing was received, got {state:?}"
+        );
+        assert_eq!(
+            state.details().and_then(|d| d.git_head.as_deref()),
+            Some(head.trim())
+        );
+        assert_eq!(state.details().and_then(|d| d.bytes), None);
+    }
+
+    #[tokio::test]
+    #[cfg_attr(feature = "pure_tests", ignore)]
+    async fn test_backup_branches() {
+        let source_dir = init_source_repo();
+        git(source_dir.path(), &["branch", "release/1.0"]);
+        git(source_dir.path(), &["branch", "feature/x"]);
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let agent = GitEngine::default();
+        let cancel = AtomicBool::new(false);
+
+        let repo = GitRepo::new(
+            "test/fixture",
+            format!("file://{}", source_dir.path().display()),
+            Some(
+                crate::helpers::refspecs::from_branches("main, release/*")
+                    .expect("the branches to be valid"),
+            ),
+        );
+
+        agent
+            .backup(&repo, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("backup to succeed");
+
+        let mut refs: Vec<String> = gix::open(temp_dir.path().join(repo.target_path()))
+            .expect("the repository to be opened")
+            .references()
+            .expect("the refs to be listed")
+            .all()
+            .expect("the refs to be listed")
+            .flatten()
+            .map(|r| r.name().as_bstr().to_string())
+            .filter(|name| name.starts_with("refs/remotes/"))
+            .collect();
+        refs.sort();
+
+        assert_eq!(
+            refs,
+            vec![
+                "refs/remotes/origin/main".to_string(),
+                "refs/remotes/origin/release/1.0".to_string(),
+            ],
+            "only the selected branches should be fetched"
+        );
+    }
+
+    #[tokio::test]
+    #[cfg_attr(feature = "pure_tests", ignore)]
+    async fn test_backup_metadata() {
+        let source_dir = init_source_repo();
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let agent = GitEngine::default();
+        let cancel = AtomicBool::new(false);
+
+        let clone_url = format!("file://{}", source_dir.path().display());
+        let repo = GitRepo::new("test/fixture", clone_url.as_str(), None)
+            .with_artifact_kind(Some("github/repo".to_string()));
+
+        agent
+            .backup(&repo, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("backup to succeed");
+
+        let target = temp_dir.path().join(repo.target_path());
+        let read_metadata = || -> BackupMetadata {
+            serde_json::from_str(
+                &std::fs::read_to_string(target.join(METADATA_FILE_NAME))
+                    .expect("the metadata file to be written"),
+            )
+            .expect("the metadata to be valid JSON")
+        };
+
+        let head = gix::open(&target)
+            .expect("the repository to be opened")
+            .head_id()
+            .expect("the repository to have a HEAD")
+            .to_hex()
+            .to_string();
+
+        let first = read_metadata();
+        assert_eq!(first.name, "test/fixture");
+        assert_eq!(first.source, clone_url);
+        assert_eq!(first.kind.as_deref(), Some("github/repo"));
+        assert_eq!(first.head.as_deref(), Some(head.as_str()));
+
+        std::fs::write(source_dir.path().join("README.md"), "# Updated\n").unwrap();
+        git(source_dir.path(), &["commit", "-am", "Update README"]);
+
+        agent
+            .backup(&repo, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("backup to succeed");
+
+        let second = read_metadata();
+        assert!(second.last_sync >= first.last_sync);
+        assert_ne!(
+            second.head, first.head,
+            "the metadata should be updated with the new HEAD on every backup"
+        );
+    }
+
+    #[tokio::test]
+    #[cfg_attr(feature = "pure_tests", ignore)]
+    async fn test_backup_worktree() {
+        let source_dir = tempfile::tempdir().expect("a temporary directory");
+
+        git(source_dir.path(), &["init", "--initial-branch", "main"]);
+        std::fs::write(source_dir.path().join("README.md"), "# Test\n").unwrap();
+        std::fs::create_dir(source_dir.path().join("docs")).unwrap();
+        std::fs::write(source_dir.path().join("docs").join("old.md"), "Old\n").unwrap();
+        git(source_dir.path(), &["add", "."]);
+        git(source_dir.path(), &["commit", "-m", "Initial commit"]);
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let agent = GitEngine::default();
+        let cancel = AtomicBool::new(false);
+
+        let repo = GitRepo::new(
+            "test/fixture",
+            format!("file://{}", source_dir.path().display()),
+            None,
+        )
+        .with_worktree(true)
+        .with_attachments(vec![Attachment {
+            name: "settings.json".to_string(),
+            content: b"{}".to_vec(),
+        }]);
+        let target = temp_dir.path().join(repo.target_path());
+
+        let state = agent
+            .backup(&repo, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("initial backup to succeed");
+        assert!(matches!(state, BackupState::New(..)), "got {state:?}");
+        assert_eq!(
+            std::fs::read_to_string(target.join("README.md")).unwrap(),
+            "# Test\n"
+        );
+        assert!(target.join("docs").join("old.md").exists());
+        assert!(
+            target
+                .join(".git")
+                .join(WORKTREE_ATTACHMENTS_DIR)
+                .join("settings.json")
+                .exists(),
+            "attachments should be stored within the git directory"
+        );
+        assert!(!target.join("settings.json").exists());
+
+        // Local changes to the working tree should be overwritten by the next backup.
+        std::fs::write(target.join("README.md"), "Local changes\n").unwrap();
+
+        std::fs::write(source_dir.path().join("README.md"), "# Updated\n").unwrap();
+        std::fs::write(source_dir.path().join("CHANGELOG.md"), "v1.0.0\n").unwrap();
+        git(source_dir.path(), &["rm", "-r", "docs"]);
+        git(source_dir.path(), &["add", "."]);
+        git(source_dir.path(), &["commit", "-m", "Update the docs"]);
+
+        let state = agent
+            .backup(&repo, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("second backup to succeed");
+        assert!(matches!(state, BackupState::Updated(..)), "got {state:?}");
+        assert_eq!(
+            std::fs::read_to_string(target.join("README.md")).unwrap(),
+            "# Updated\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(target.join("CHANGELOG.md")).unwrap(),
+            "v1.0.0\n"
+        );
+        assert!(
+            !target.join("docs").exists(),
+            "files which were removed from the repository should be removed from the working tree"
+        );
+
+        let status = std::process::Command::new("git")
+            .args(["status", "--porcelain"])
+            .current_dir(&target)
+            .output()
+            .expect("git to be available");
+        assert!(status.status.success(), "the backup should not be bare");
+        assert_eq!(
+            String::from_utf8_lossy(&status.stdout),
+            "",
+            "the working tree should match the default branch"
+        );
+    }
+
+    #[tokio::test]
+    #[cfg_attr(feature = "pure_tests", ignore)]
+    async fn test_backup_corrupted() {
+        let source_dir = init_source_repo();
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let agent = GitEngine::default();
+        let cancel = AtomicBool::new(false);
+
+        let repo = GitRepo::new(
+            "test/fixture",
+            format!("file://{}", source_dir.path().display()),
+            None,
+        );
+        let target = temp_dir.path().join(repo.target_path());
+
+        let state = agent
+            .backup(&repo, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("initial backup to succeed");
+        assert!(matches!(state, BackupState::New(..)), "got {state:?}");
+
+        // A fetch which fails because the remote is unavailable should leave the backup intact.
+        let unavailable = GitRepo::new(
+            "test/fixture",
+            format!("file://{}", temp_dir.path().join("missing").display()),
+            None,
+        );
+        agent
+            .backup(&unavailable, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect_err("the backup of an unavailable repository to fail");
+        assert!(
+            target.join(".git").exists(),
+            "the existing backup should not be removed"
+        );
+
+        // Simulate an interrupted pack write by removing all of the objects in the backup.
+        let objects = target.join(".git").join("objects");
+        std::fs::remove_dir_all(&objects).unwrap();
+        std::fs::create_dir_all(objects.join("pack")).unwrap();
+
+        let state = agent
+            .backup(&repo, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("the corrupted backup to be replaced");
+        assert!(matches!(state, BackupState::New(..)), "got {state:?}");
+
+        let status = std::process::Command::new("git")
+            .args(["fsck", "--full"])
+            .current_dir(&target)
+            .status()
+            .expect("git to be available");
+        assert!(status.success(), "the replaced backup should be valid");
+    }
+
+    #[tokio::test]
+    #[cfg_attr(feature = "pure_tests", ignore)]
+    async fn test_backup_no_commits() {
+        let source_dir = tempfile::tempdir().expect("a temporary directory");
+
+        // The repository declares a default branch, but it has no commits (and so no refs).
+        git(source_dir.path(), &["init", "--initial-branch", "main"]);
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let agent = GitEngine::default();
+        let cancel = AtomicBool::new(false);
+
+        let repo = GitRepo::new(
+            "test/empty",
+            format!("file://{}", source_dir.path().display()),
+            None,
+        );
+
+        let state = agent
+            .backup(&repo, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("the clone of an empty repository to succeed");
+        assert_eq!(state, BackupState::Skipped(SkipReason::Empty));
+
+        let state = agent
+            .backup(&repo, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("the fetch of an empty repository to succeed");
+        assert_eq!(state, BackupState::Skipped(SkipReason::Empty));
+
+        let bundle = repo.clone().with_format(GitRepoFormat::Bundle);
+        let state = agent
+            .backup(&bundle, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("the bundle of an empty repository to succeed");
+        assert_eq!(state, BackupState::Skipped(SkipReason::Empty));
+        assert!(!temp_dir.path().join("test").join("empty.bundle").exists());
+
+        // Once something is pushed, the existing (empty) clone is updated as usual.
+        std::fs::write(source_dir.path().join("README.md"), "# Test\n").unwrap();
+        git(source_dir.path(), &["add", "."]);
+        git(source_dir.path(), &["commit", "-m", "Initial commit"]);
+
+        let state = agent
+            .backup(&repo, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("the fetch to succeed once the repository has commits");
+        assert!(matches!(state, BackupState::Updated(..)), "got {state:?}");
+    }
+
+    #[rstest]
+    #[case(false)]
+    #[case(true)]
+    #[tokio::test]
+    #[cfg_attr(feature = "pure_tests", ignore)]
+    async fn test_backup_repack(#[case] worktree: bool) {
+        let source_dir = init_source_repo();
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let agent = GitEngine::default();
+        let cancel = AtomicBool::new(false);
+
+        let repo = GitRepo::new(
+            "test/fixture",
+            format!("file://{}", source_dir.path().display()),
+            None,
+        )
+        .with_repack(Some(std::time::Duration::ZERO))
+        .with_worktree(worktree);
+        let target = temp_dir.path().join(repo.target_path());
+
+        let state = agent
+            .backup(&repo, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("initial backup to succeed");
+        assert!(matches!(state, BackupState::New(..)), "got {state:?}");
+
+        // Simulate the loose objects left behind by fetching many small updates.
+        for i in 0..10 {
+            let file = temp_dir.path().join(format!("loose-{i}.txt"));
+            std::fs::write(&file, format!("loose object {i}\n")).unwrap();
+            git(
+                &target,
+                &["hash-object", "-w", file.to_string_lossy().as_ref()],
+            );
+        }
+
+        let loose_objects = || {
+            std::fs::read_dir(target.join(".git").join("objects"))
+                .unwrap()
+                .flatten()
+                .filter(|e| e.file_name().len() == 2)
+                .map(|e| std::fs::read_dir(e.path()).unwrap().count())
+                .sum::<usize>()
+        };
+        assert!(loose_objects() >= 10);
+
+        let state = agent
+            .backup(&repo, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("the backup to succeed");
+        assert!(
+            matches!(&state, BackupState::Unchanged(_))
+                && state.detail().unwrap_or_default().contains("by repacking"),
+            "got {state:?}"
+        );
+        assert_eq!(
+            loose_objects(),
+            0,
+            "the loose objects should have been removed"
+        );
+
+        // Once repacked, the repository isn't repacked again until the interval has elapsed.
+        let repo = repo.with_repack(Some(std::time::Duration::from_secs(3600)));
+        let state = agent
+            .backup(&repo, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("the backup to succeed");
+        assert!(
+            matches!(&state, BackupState::Unchanged(_))
+                && !state.detail().unwrap_or_default().contains("by repacking"),
+            "got {state:?}"
+        );
+    }
+
+    #[tokio::test]
+    #[cfg_attr(feature = "pure_tests", ignore)]
+    async fn test_backup_bundle() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+
+        let agent = GitEngine::default();
+        let cancel = AtomicBool::new(false);
+
+        let repo = GitRepo::new(
+            "SierraSoftworks/grey",
+            "https://github.com/sierrasoftworks/grey.git",
+            None,
+        )
+        .with_format(GitRepoFormat::Bundle);
+
+        let state1 = agent
+            .backup(&repo, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("initial backup to succeed");
+        assert!(
+            matches!(state1, BackupState::New(..)),
+            "the bundle should have been created initially"
+        );
+
+        let bundle_path = temp_dir.path().join("SierraSoftworks").join("grey.bundle");
+        let content = std::fs::read(&bundle_path).expect("the bundle to have been written");
+        assert!(content.starts_with(b"# v2 git bundle\n"));
+        assert!(
+            !temp_dir.path().join(repo.target_path()).exists(),
+            "no repository should be left alongside the bundle"
+        );
+        assert!(!temp_dir
+            .path()
+            .join("SierraSoftworks")
+            .join("grey.bundle.tmp.git")
+            .exists());
+
+        let state2 = agent
+            .backup(&repo, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("subsequent backup to succeed");
+        assert!(
+            matches!(state2, BackupState::Unchanged(..)),
+            "the bundle should not have changed between backups"
+        );
+    }
+
+    #[tokio::test]
+    #[cfg_attr(feature = "pure_tests", ignore)]
+    async fn test_backup_bundle_unchanged_refs() {
+        let source_dir = init_source_repo();
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let agent = GitEngine::default();
+        let cancel = AtomicBool::new(false);
+
+        let repo = GitRepo::new(
+            "test/fixture",
+            format!("file://{}", source_dir.path().display()),
+            None,
+        )
+        .with_format(GitRepoFormat::Bundle);
+
+        let state = agent
+            .backup(&repo, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("initial backup to succeed");
+        assert!(matches!(state, BackupState::New(..)), "got {state:?}");
+
+        // Without its objects the source can no longer be cloned, but its refs can still be listed.
+        let objects = source_dir.path().join(".git").join("objects");
+        for entry in std::fs::read_dir(&objects).unwrap().flatten() {
+            if entry.file_name().len() == 2 {
+                std::fs::remove_dir_all(entry.path()).unwrap();
+            }
+        }
+
+        let state = agent
+            .backup(&repo, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("the refs to be compared without cloning the repository");
+        assert!(matches!(state, BackupState::Unchanged(..)), "got {state:?}");
+        assert!(!temp_dir
+            .path()
+            .join("test")
+            .join("fixture.bundle.tmp.git")
+            .exists());
+    }
+
+    #[tokio::test]
+    #[cfg_attr(feature = "pure_tests", ignore)]
+    async fn test_backup_mirror() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+
+        let agent = GitEngine::default();
+        let cancel = AtomicBool::new(false);
+
+        let repo = GitRepo::new(
+            "SierraSoftworks/grey",
+            "https://github.com/sierrasoftworks/grey.git",
+            None,
+        )
+        .with_mirror(true);
+
+        let target = temp_dir.path().join(repo.target_path());
+        let pull_refs = || {
+            gix::open(&target)
+                .expect("the repository to be opened")
+                .references()
+                .expect("the refs to be listed")
+                .all()
+                .expect("the refs to be listed")
+                .flatten()
+                .filter(|r| r.name().as_bstr().to_string().starts_with("refs/pull/"))
+                .count()
+        };
+
+        let state = agent
+            .backup(&repo, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("initial backup to succeed (clone)");
+        assert!(
+            matches!(&state, BackupState::New(_))
+                && state.detail().unwrap_or_default().ends_with("(mirror)"),
+            "the backup state should indicate that a mirror was created"
+        );
+        assert!(
+            pull_refs() > 0,
+            "the mirror should include the pull request refs"
+        );
+
+        agent
+            .backup(&repo, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("subsequent backup to succeed (fetch)");
+        assert!(
+            pull_refs() > 0,
+            "the pull request refs should be retained after a fetch"
+        );
+    }
+
+    #[tokio::test]
+    #[cfg_attr(feature = "pure_tests", ignore)]
+    async fn test_backup_sparse() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+
+        let agent = GitEngine::default();
+        let cancel = AtomicBool::new(false);
+
+        let repo = GitRepo::new(
+            "SierraSoftworks/grey",
+            "https://github.com/sierrasoftworks/grey.git",
+            None,
+        )
+        .with_sparse(Some(vec!["docs/".to_string()]));
+
+        let git_dir = temp_dir.path().join(repo.target_path()).join(".git");
+
+        agent
+            .backup(&repo, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("initial backup to succeed (clone)");
+
+        assert_eq!(
+            std::fs::read_to_string(git_dir.join("info").join("sparse-checkout")).unwrap(),
+            "docs/\n"
+        );
+        assert!(std::fs::read_to_string(git_dir.join("config"))
+            .unwrap()
+            .contains("sparseCheckout = true"));
+
+        let repo = repo.with_sparse(Some(vec!["src/".to_string()]));
+        agent
+            .backup(&repo, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("subsequent backup to succeed (fetch)");
+
+        assert_eq!(
+            std::fs::read_to_string(git_dir.join("info").join("sparse-checkout")).unwrap(),
+            "src/\n",
+            "the sparse-checkout patterns should be updated on fetch"
+        );
+    }
+
     #[tokio::test]
     #[cfg_attr(feature = "pure_tests", ignore)]
     async fn test_backup() {
         let temp_dir = tempfile::tempdir().expect("a temporary directory");
 
-        let agent = GitEngine;
+        let agent = GitEngine::default();
         let cancel = AtomicBool::new(false);
 
         let repo = GitRepo::new(
@@ -360,7 +2618,7 @@ mod tests {
         );
 
         let state1 = agent
-            .backup(&repo, temp_dir.path(), &cancel)
+            .backup(&repo, &FileSystemStore, temp_dir.path(), &cancel)
             .await
             .expect("initial backup to succeed (clone)");
         assert!(
@@ -378,7 +2636,7 @@ mod tests {
         );
 
         let state2 = agent
-            .backup(&repo, temp_dir.path(), &cancel)
+            .backup(&repo, &FileSystemStore, temp_dir.path(), &cancel)
             .await
             .expect("subsequent backup to succeed (fetch)");
 
@@ -1,10 +1,11 @@
 use std::{
-    path::Path,
+    collections::HashMap,
+    path::{Path, PathBuf},
     sync::{atomic::AtomicBool, Arc},
 };
 
 use sha2::Digest;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tracing_batteries::prelude::*;
 
 use crate::{
@@ -12,22 +13,166 @@ use crate::{
     errors, BackupEntity,
 };
 
-use super::{BackupEngine, BackupState};
+use super::{
+    sha_index::ShaIndex, sidecars, BackupDetails, BackupEngine, BackupState, BackupStore,
+    SkipReason,
+};
 
 #[derive(Clone)]
 pub struct HttpFileEngine {
     client: Arc<reqwest::Client>,
+    sha_indices: Arc<tokio::sync::Mutex<HashMap<PathBuf, Arc<ShaIndex>>>>,
 }
 
 impl HttpFileEngine {
+    #[allow(dead_code)]
     pub fn new() -> Self {
+        Self::with_client(reqwest::Client::new())
+    }
+
+    pub fn with_client(client: reqwest::Client) -> Self {
         Self {
-            client: Arc::new(reqwest::Client::new()),
+            client: Arc::new(client),
+            sha_indices: Default::default(),
+        }
+    }
+
+    fn request(
+        &self,
+        entity: &HttpFile,
+        credentials: &Credentials,
+        etag: Option<&str>,
+    ) -> reqwest::RequestBuilder {
+        let req = self
+            .client
+            .get(entity.url.as_str())
+            .header("User-Agent", "SierraSoftworks/github-backup");
+
+        let req = if let Some(etag) = etag {
+            req.header(reqwest::header::IF_NONE_MATCH, etag)
+        } else {
+            req
+        };
+
+        let req = if let Some(content_type) = &entity.content_type {
+            req.header("Accept", content_type)
+        } else {
+            req
+        };
+
+        Self::authenticate(req, credentials)
+    }
+
+    fn authenticate(
+        req: reqwest::RequestBuilder,
+        credentials: &Credentials,
+    ) -> reqwest::RequestBuilder {
+        match credentials {
+            Credentials::None => req,
+            Credentials::Token { value: token, .. } => req.bearer_auth(token),
+            Credentials::UsernamePassword {
+                username, password, ..
+            } => req.basic_auth(username, Some(password)),
+            Credentials::Chain(chain) => {
+                Self::authenticate(req, chain.first().unwrap_or(&Credentials::None))
+            }
+            // Per-host credentials are resolved by `send` before any request is made.
+            Credentials::PerHost(_) => req,
         }
     }
 
-    fn ensure_directory(&self, path: &Path) -> Result<(), errors::Error> {
-        std::fs::create_dir_all(path).map_err(|e| {
+    /// Sends the request for a file, trying each set of credentials in a chain in turn until
+    /// one of them is able to access it. If none of them can, the error names every set of
+    /// credentials which was tried.
+    async fn send(
+        &self,
+        entity: &HttpFile,
+        etag: Option<&str>,
+    ) -> Result<reqwest::Response, errors::Error> {
+        let credentials = entity.credentials.for_url(&entity.url);
+        let Credentials::Chain(chain) = &credentials else {
+            return self.send_with(entity, &credentials, etag).await;
+        };
+
+        let mut status = None;
+        for (i, chained) in chain.iter().enumerate() {
+            let resp = self.send_with(entity, chained, etag).await?;
+            if !matches!(
+                resp.status(),
+                reqwest::StatusCode::FORBIDDEN | reqwest::StatusCode::NOT_FOUND
+            ) {
+                return Ok(resp);
+            }
+
+            status = Some(resp.status());
+            if i + 1 < chain.len() {
+                debug!(
+                    "Got an HTTP {} status code when fetching '{}' with credentials #{}, falling back to the next credentials.",
+                    resp.status(),
+                    entity.url,
+                    i + 1
+                );
+            }
+        }
+
+        let status = status
+            .map(|s| format!(" (the last responded with a {s} status code)"))
+            .unwrap_or_default();
+        Err(errors::user(
+            &format!(
+                "None of your credentials were able to download '{}'{status}, after trying: {credentials}.",
+                entity.url
+            ),
+            "Make sure that at least one of the credentials in your list has been granted access to this file.",
+        ))
+    }
+
+    async fn send_with(
+        &self,
+        entity: &HttpFile,
+        credentials: &Credentials,
+        etag: Option<&str>,
+    ) -> Result<reqwest::Response, errors::Error> {
+        let resp = self.request(entity, credentials, etag).send().await?;
+
+        // Tokens which are rotated by another process may expire part way through a long backup
+        // run, so we try once more with the replacement token (if there is one).
+        if resp.status() == reqwest::StatusCode::UNAUTHORIZED {
+            if let Some(credentials) = credentials.refresh() {
+                info!(
+                    "Retrying the download of '{}' with refreshed credentials.",
+                    entity.url
+                );
+                return Ok(self.request(entity, &credentials, etag).send().await?);
+            }
+        }
+
+        Ok(resp)
+    }
+
+    /// Retrieves the SHA-256 index for the given backup directory, loading it from the store
+    /// the first time it is used during a run.
+    async fn sha_index(
+        &self,
+        store: &dyn BackupStore,
+        target: &Path,
+    ) -> Result<Arc<ShaIndex>, errors::Error> {
+        let mut indices = self.sha_indices.lock().await;
+        if let Some(index) = indices.get(target) {
+            return Ok(index.clone());
+        }
+
+        let index = Arc::new(ShaIndex::load(store, target).await?);
+        indices.insert(target.to_path_buf(), index.clone());
+        Ok(index)
+    }
+
+    async fn ensure_directory(
+        &self,
+        store: &dyn BackupStore,
+        path: &Path,
+    ) -> Result<(), errors::Error> {
+        store.create_dir_all(path).await.map_err(|e| {
             errors::user_with_internal(
                 &format!("Unable to create backup directory '{}'", path.display()),
                 "Make sure that you have permission to create the directory.",
@@ -36,78 +181,480 @@ impl HttpFileEngine {
         })
     }
 
-    fn get_last_modified(&self, path: &Path) -> Option<chrono::DateTime<chrono::Utc>> {
-        path.metadata()
-            .and_then(|m| m.modified())
+    async fn get_existing_sha256(
+        &self,
+        store: &dyn BackupStore,
+        path: &Path,
+        index: Option<(&ShaIndex, &str)>,
+    ) -> Option<String> {
+        // An index entry (or sidecar) for a backup which has since been deleted must not stop us
+        // from downloading the file again.
+        if !store.exists(path).await {
+            if let Some((index, key)) = index {
+                index.remove(key);
+            }
+
+            return None;
+        }
+
+        if let Some(sha256) = index.and_then(|(index, key)| index.get(key)) {
+            return Some(sha256);
+        }
+
+        let sha_path = sidecars::sha256_path(path);
+
+        store
+            .read_to_string(&sha_path)
+            .await
+            .map(|s| s.trim().to_owned())
             .ok()
-            .map(chrono::DateTime::from)
     }
 
-    async fn get_existing_sha256(&self, path: &Path) -> Option<String> {
-        let sha_path = path.with_extension(
+    fn etag_path(&self, path: &Path) -> PathBuf {
+        path.with_extension(
             format!(
-                "{}.sha256",
+                "{}.etag",
                 path.extension().unwrap_or_default().to_string_lossy()
             )
             .trim_start_matches('.'),
-        );
+        )
+    }
 
-        tokio::fs::read_to_string(sha_path)
+    /// Retrieves the ETag which the server returned when the existing backup was downloaded, so
+    /// that we can ask the server to skip sending the file again if it hasn't changed.
+    async fn get_existing_etag(&self, store: &dyn BackupStore, path: &Path) -> Option<String> {
+        if !store.exists(path).await {
+            return None;
+        }
+
+        store
+            .read_to_string(&self.etag_path(path))
             .await
             .map(|s| s.trim().to_owned())
             .ok()
+            .filter(|etag| !etag.is_empty())
+    }
+
+    /// Records the ETag returned by the server alongside the backup, removing any stale ETag if the
+    /// server no longer provides one.
+    async fn update_etag(
+        &self,
+        store: &dyn BackupStore,
+        path: &Path,
+        etag: Option<&str>,
+    ) -> Result<(), errors::Error> {
+        let etag_path = self.etag_path(path);
+        match etag {
+            Some(etag) => store.write(&etag_path, etag.as_bytes()).await.map_err(|e| {
+                errors::user_with_internal(
+                    &format!(
+                        "Unable to write the ETag file for backup file '{}'.",
+                        path.display()
+                    ),
+                    "Make sure that you have permission to write to this file/directory and try again.",
+                    e,
+                )
+            }),
+            None if store.exists(&etag_path).await => {
+                store.remove(&etag_path).await.map_err(|e| {
+                    errors::user_with_internal(
+                        &format!(
+                            "Unable to remove the outdated ETag file for backup file '{}'.",
+                            path.display()
+                        ),
+                        "Make sure that you have write (and delete) permission on the backup directory and try again.",
+                        e,
+                    )
+                })
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Determines where a file should be downloaded to before it is moved into place, which is
+    /// alongside the backup unless the policy specifies a `temp_dir`.
+    async fn temp_path(
+        &self,
+        store: &dyn BackupStore,
+        entity: &HttpFile,
+        target_path: &Path,
+    ) -> Result<PathBuf, errors::Error> {
+        let Some(temp_dir) = &entity.temp_dir else {
+            return Ok(target_path.with_extension(
+                format!(
+                    "{}.tmp",
+                    target_path
+                        .extension()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                )
+                .trim_start_matches('.'),
+            ));
+        };
+
+        self.ensure_directory(store, temp_dir).await?;
+
+        // Files from many different backup directories share the same temporary directory, so
+        // we name them after their destination to avoid conflicts.
+        let name = sha2::Sha256::digest(target_path.to_string_lossy().as_bytes());
+        Ok(temp_dir.join(format!("{name:x}.tmp")))
+    }
+
+    /// Moves a downloaded file into place, copying it if the temporary directory is on a different
+    /// device to the backup (where it cannot be renamed).
+    async fn move_into_place(
+        &self,
+        store: &dyn BackupStore,
+        from: &Path,
+        to: &Path,
+    ) -> std::io::Result<()> {
+        let result = match store.rename(from, to).await {
+            Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+                debug!(
+                    "Copying '{}' to '{}' since they are on different devices.",
+                    from.display(),
+                    to.display()
+                );
+
+                match self.copy(store, from, to).await {
+                    Ok(()) => {
+                        self.remove_temp_file(store, from).await;
+                        Ok(())
+                    }
+                    Err(e) => {
+                        store.remove(to).await.unwrap_or_default();
+                        Err(e)
+                    }
+                }
+            }
+            result => result,
+        };
+
+        if result.is_err() {
+            self.remove_temp_file(store, from).await;
+        }
+
+        result
+    }
+
+    /// Sets the modification time of a backed up file to the time at which it was last modified
+    /// upstream, so that the next run can tell that it is up to date without downloading it again.
+    /// Only the file itself is checked by future runs, so its sidecars keep their own timestamps.
+    async fn preserve_modified(&self, store: &dyn BackupStore, entity: &HttpFile, path: &Path) {
+        if let Some(last_modified) = entity.last_modified {
+            if let Err(e) = store.set_modified(path, last_modified).await {
+                warn!(
+                    "Unable to set the modification time of backup file '{}': {}",
+                    path.display(),
+                    e
+                );
+            }
+        }
+    }
+
+    /// Reads a backup file back from the store once it has been written, ensuring that its content
+    /// matches the checksum of the data which was downloaded.
+    async fn verify_written(
+        &self,
+        store: &dyn BackupStore,
+        path: &Path,
+        expected: &str,
+    ) -> Result<(), errors::Error> {
+        let read_error = |e| {
+            errors::user_with_internal(
+                &format!(
+                    "Unable to read the backup file '{}' to verify its content after writing it.",
+                    path.display()
+                ),
+                "Make sure that you have permission to read this file/directory and try again.",
+                e,
+            )
+        };
+
+        let mut reader = store.open(path).await.map_err(read_error)?;
+        let mut shasum = sha2::Sha256::new();
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let n = reader.read(&mut buffer).await.map_err(read_error)?;
+            if n == 0 {
+                break;
+            }
+
+            shasum.update(&buffer[..n]);
+        }
+
+        let actual = format!("{:x}", shasum.finalize());
+        if actual != expected {
+            return Err(errors::user(
+                &format!(
+                    "The backup file '{}' does not match the content which was downloaded (expected sha256:{expected}, but read back sha256:{actual}), so it has been removed.",
+                    path.display()
+                ),
+                "Make sure that the disk holding your backups is healthy and has enough free space, then run the backup again.",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Removes a backup file which failed verification, along with its checksum, so that it is
+    /// downloaded again by the next backup run.
+    async fn remove_unverified(
+        &self,
+        store: &dyn BackupStore,
+        path: &Path,
+        sha_index: Option<(&ShaIndex, &str)>,
+    ) {
+        let sha_path = sidecars::sha256_path(path);
+        for path in [path, sha_path.as_path()] {
+            if store.exists(path).await {
+                if let Err(e) = store.remove(path).await {
+                    tracing::error!(
+                        "Failed to remove unverified backup file '{}': {}",
+                        path.display(),
+                        e
+                    );
+                }
+            }
+        }
+
+        if let Some((index, key)) = sha_index {
+            index.remove(key);
+        }
+    }
+
+    async fn copy(&self, store: &dyn BackupStore, from: &Path, to: &Path) -> std::io::Result<()> {
+        let mut reader = store.open(from).await?;
+        let mut writer = store.create(to).await?;
+        tokio::io::copy(&mut reader, &mut writer).await?;
+        writer.shutdown().await
+    }
+
+    async fn remove_temp_file(&self, store: &dyn BackupStore, path: &Path) {
+        store.remove(path).await.unwrap_or_else(|e| {
+            tracing::error!(
+                "Failed to remove temporary backup file '{}': {}",
+                path.display(),
+                e
+            );
+        });
+    }
+
+    /// Whether the file is larger than the policy's `max_bytes` limit allows, in which case its
+    /// backup is skipped.
+    fn exceeds_max_bytes(&self, entity: &HttpFile, size: u64) -> bool {
+        match entity.max_bytes {
+            Some(max_bytes) if size > max_bytes => {
+                warn!(
+                    "Skipping the backup of '{}', since it is {} bytes in size, which exceeds the max_bytes limit of {} bytes configured for this backup.",
+                    entity.url, size, max_bytes
+                );
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Ensures that we haven't been served an HTML page (for example, a login or error page from a CDN)
+    /// when we asked for a different type of content, since saving it would silently replace the file.
+    fn check_content_type(
+        &self,
+        entity: &HttpFile,
+        resp: &reqwest::Response,
+    ) -> Result<(), errors::Error> {
+        let Some(accept) = &entity.content_type else {
+            return Ok(());
+        };
+
+        if accept.contains("text/html") || accept.contains("*/*") {
+            return Ok(());
+        }
+
+        match resp
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+        {
+            Some(content_type) if content_type.trim_start().starts_with("text/html") => Err(errors::user(
+                &format!(
+                    "The file '{}' was served as an HTML page ('{}'), rather than the '{}' content which was requested.",
+                    entity.url, content_type, accept
+                ),
+                "This usually means that the download was redirected to a login or error page. Make sure that you can access the URL, or set the 'accept' property on your backup policy to request a different media type.",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether the file is smaller than the policy's `min_bytes` limit allows (which usually means
+    /// that we were served an error page), in which case its backup is skipped.
+    fn below_min_bytes(&self, entity: &HttpFile, size: u64) -> bool {
+        match entity.min_bytes {
+            Some(min_bytes) if size < min_bytes => {
+                warn!(
+                    "Skipping the backup of '{}', since it is only {} bytes in size, which is below the min_bytes limit of {} bytes configured for this backup.",
+                    entity.url, size, min_bytes
+                );
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The file extensions used for the content types which release assets are commonly uploaded with.
+const CONTENT_TYPE_EXTENSIONS: &[(&str, &str)] = &[
+    ("application/zip", "zip"),
+    ("application/x-zip-compressed", "zip"),
+    ("application/gzip", "gz"),
+    ("application/x-gzip", "gz"),
+    ("application/x-tar", "tar"),
+    ("application/x-xz", "xz"),
+    ("application/x-bzip2", "bz2"),
+    ("application/zstd", "zst"),
+    ("application/x-7z-compressed", "7z"),
+    ("application/java-archive", "jar"),
+    ("application/vnd.debian.binary-package", "deb"),
+    ("application/x-debian-package", "deb"),
+    ("application/x-rpm", "rpm"),
+    ("application/x-redhat-package-manager", "rpm"),
+    ("application/vnd.android.package-archive", "apk"),
+    ("application/x-msdownload", "exe"),
+    ("application/vnd.microsoft.portable-executable", "exe"),
+    ("application/x-msi", "msi"),
+    ("application/x-apple-diskimage", "dmg"),
+    ("application/x-iso9660-image", "iso"),
+    ("application/pgp-signature", "asc"),
+    ("application/pdf", "pdf"),
+    ("application/json", "json"),
+    ("text/plain", "txt"),
+];
+
+impl HttpFileEngine {
+    /// The path (relative to the policy's `to` directory) at which a file is stored, which has an
+    /// extension matching its declared content type appended when `ext_from_content_type` is enabled
+    /// and its name doesn't already have a sensible extension.
+    fn target_key(entity: &HttpFile) -> PathBuf {
+        let path = entity.target_path();
+        if !entity.ext_from_content_type {
+            return path;
+        }
+
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if has_sensible_extension(&name) {
+            return path;
+        }
+
+        match entity.metadata.get("asset.content_type") {
+            crate::FilterValue::String(content_type) => {
+                match extension_for_content_type(&content_type) {
+                    Some(ext) => path.with_file_name(format!("{name}.{ext}")),
+                    None => path,
+                }
+            }
+            _ => path,
+        }
+    }
+}
+
+fn extension_for_content_type(content_type: &str) -> Option<&'static str> {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+
+    CONTENT_TYPE_EXTENSIONS
+        .iter()
+        .find(|(m, _)| *m == mime)
+        .map(|(_, ext)| *ext)
+}
+
+/// Determines whether a file name ends with something that looks like a file extension (rather
+/// than, for example, the last part of a version number like `v1.2`).
+fn has_sensible_extension(name: &str) -> bool {
+    match name.rsplit_once('.') {
+        Some((stem, ext)) => {
+            !stem.is_empty()
+                && (1..=8).contains(&ext.len())
+                && ext.chars().all(|c| c.is_ascii_alphanumeric())
+                && ext.chars().any(|c| c.is_ascii_alphabetic())
+        }
+        None => false,
     }
 }
 
 #[async_trait::async_trait]
 impl BackupEngine<HttpFile> for HttpFileEngine {
-    #[tracing::instrument(skip(self, entity, cancel, target), entity=%entity)]
+    fn backup_path(&self, entity: &HttpFile, target: &Path) -> PathBuf {
+        target.join(Self::target_key(entity))
+    }
+
+    #[tracing::instrument(skip(self, entity, store, cancel, target), entity=%entity, fields(bytes_transferred = tracing::field::Empty))]
     async fn backup<P: AsRef<Path> + Send>(
         &self,
         entity: &HttpFile,
+        store: &dyn BackupStore,
         target: P,
         cancel: &AtomicBool,
     ) -> Result<BackupState, crate::Error> {
-        let target_path = target.as_ref().join(entity.target_path());
+        let key = Self::target_key(entity).to_string_lossy().to_string();
+        let target_path = target.as_ref().join(&key);
+        let sha_index = if entity.sha_index {
+            Some(self.sha_index(store, target.as_ref()).await?)
+        } else {
+            None
+        };
+        let sha_index = sha_index.as_deref().map(|index| (index, key.as_str()));
+
         if let Some(parent) = target_path.parent() {
-            self.ensure_directory(parent)?;
+            self.ensure_directory(store, parent).await?;
         }
 
         if let Some(origin_last_modified) = entity.last_modified {
-            if let Some(target_last_modified) = self.get_last_modified(&target_path) {
+            if let Some(target_last_modified) = store.modified(&target_path).await {
                 if target_last_modified >= origin_last_modified {
-                    return Ok(BackupState::Unchanged(Some(format!(
-                        "since {}",
-                        target_last_modified.format("%Y-%m-%dT%H:%M:%S")
-                    ))));
+                    return Ok(BackupState::Unchanged(
+                        BackupDetails::new(format!(
+                            "since {}",
+                            target_last_modified.format("%Y-%m-%dT%H:%M:%S")
+                        ))
+                        .with_last_modified(Some(target_last_modified)),
+                    ));
                 }
             }
         }
 
-        let req = self
-            .client
-            .get(entity.url.as_str())
-            .header("User-Agent", "SierraSoftworks/github-backup");
-
-        let req = if let Some(content_type) = &entity.content_type {
-            req.header("Accept", content_type)
-        } else {
-            req
-        };
+        if cancel.load(std::sync::atomic::Ordering::Relaxed) {
+            return Ok(BackupState::Skipped(SkipReason::Cancelled));
+        }
 
-        let req = match &entity.credentials {
-            Credentials::None => req,
-            Credentials::Token(token) => req.bearer_auth(token),
-            Credentials::UsernamePassword { username, password } => {
-                req.basic_auth(username, Some(password))
-            }
-        };
+        let etag = self.get_existing_etag(store, &target_path).await;
+        let mut resp = self.send(entity, etag.as_deref()).await?;
 
-        if cancel.load(std::sync::atomic::Ordering::Relaxed) {
-            return Ok(BackupState::Skipped);
+        // Servers which support conditional requests will tell us when our copy is up to date, so
+        // that we don't need to download the file again to compare its checksum.
+        if resp.status() == reqwest::StatusCode::NOT_MODIFIED {
+            return Ok(BackupState::Unchanged(
+                BackupDetails::new(format!("at etag {}", etag.unwrap_or_default()))
+                    .with_last_modified(entity.last_modified),
+            ));
         }
 
-        let mut resp = req.send().await?;
+        if resp.status() == reqwest::StatusCode::UNAUTHORIZED {
+            return Err(errors::authentication_with_internal(
+                &format!(
+                    "The credentials used to fetch '{}' were rejected.",
+                    entity.url.as_str(),
+                ),
+                "Make sure that your credentials are valid and have not expired.",
+                errors::ResponseError::with_body(resp).await,
+            ));
+        }
 
         if !resp.status().is_success() {
             return Err(errors::user_with_internal(
@@ -121,25 +668,30 @@ impl BackupEngine<HttpFile> for HttpFileEngine {
             ));
         }
 
+        self.check_content_type(entity, &resp)?;
+
+        let new_etag = resp
+            .headers()
+            .get(reqwest::header::ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string());
+
+        if let Some(content_length) = resp.content_length() {
+            if self.exceeds_max_bytes(entity, content_length)
+                || self.below_min_bytes(entity, content_length)
+            {
+                return Ok(BackupState::Skipped(SkipReason::SizeLimit));
+            }
+        }
+
         if cancel.load(std::sync::atomic::Ordering::Relaxed) {
-            return Ok(BackupState::Skipped);
+            return Ok(BackupState::Skipped(SkipReason::Cancelled));
         }
 
-        let temp_path = target_path.with_extension(
-            format!(
-                "{}.tmp",
-                target_path
-                    .extension()
-                    .unwrap_or_default()
-                    .to_string_lossy()
-            )
-            .trim_start_matches('.'),
-        );
+        let temp_path = self.temp_path(store, entity, &target_path).await?;
 
-        let mut file = tokio::fs::File::create(temp_path.as_path())
-            .await
-            .map_err(|e| {
-                errors::user_with_internal(
+        let mut file = store.create(temp_path.as_path()).await.map_err(|e| {
+            errors::user_with_internal(
                 &format!(
                     "Unable to create temporary backup file '{}'.",
                     temp_path.as_path().display()
@@ -147,23 +699,23 @@ impl BackupEngine<HttpFile> for HttpFileEngine {
                 "Make sure that you have permission to write to this file/directory and try again.",
                 e,
             )
-            })?;
+        })?;
 
         let mut shasum = sha2::Sha256::new();
+        let mut size: u64 = 0;
 
         while let Some(chunk) = resp.chunk().await? {
             if cancel.load(std::sync::atomic::Ordering::Relaxed) {
                 drop(file);
-                tokio::fs::remove_file(&temp_path)
-                    .await
-                    .unwrap_or_else(|e| {
-                        tracing::error!(
-                            "Failed to remove temporary backup file '{}': {}",
-                            temp_path.display(),
-                            e
-                        );
-                    });
-                return Ok(BackupState::Skipped);
+                self.remove_temp_file(store, &temp_path).await;
+                return Ok(BackupState::Skipped(SkipReason::Cancelled));
+            }
+
+            size += chunk.len() as u64;
+            if self.exceeds_max_bytes(entity, size) {
+                drop(file);
+                self.remove_temp_file(store, &temp_path).await;
+                return Ok(BackupState::Skipped(SkipReason::SizeLimit));
             }
 
             match file.write_all(&chunk).await {
@@ -172,15 +724,7 @@ impl BackupEngine<HttpFile> for HttpFileEngine {
                 }
                 Err(e) => {
                     drop(file);
-                    tokio::fs::remove_file(&temp_path)
-                        .await
-                        .unwrap_or_else(|e| {
-                            tracing::error!(
-                                "Failed to remove temporary backup file '{}': {}",
-                                temp_path.display(),
-                                e
-                            );
-                        });
+                    self.remove_temp_file(store, &temp_path).await;
                     return Err(errors::user_with_internal(
                       &format!("Failed to write to temporary backup file '{}'.", temp_path.display()),
                       "Make sure that you have permission to write to this file/directory and try again.",
@@ -191,58 +735,91 @@ impl BackupEngine<HttpFile> for HttpFileEngine {
         }
 
         drop(file);
+        tracing::Span::current().record("bytes_transferred", size);
+
+        if self.below_min_bytes(entity, size) {
+            self.remove_temp_file(store, &temp_path).await;
+            return Ok(BackupState::Skipped(SkipReason::SizeLimit));
+        }
 
         let shasum = shasum.finalize();
-        if let Some(existing_sha256) = self.get_existing_sha256(&target_path).await {
+        if let Some(existing_sha256) = self
+            .get_existing_sha256(store, &target_path, sha_index)
+            .await
+        {
             if existing_sha256 == format!("{:x}", shasum) {
-                tokio::fs::remove_file(&temp_path).await.map_err(|e| errors::user_with_internal(
+                if let Some((index, key)) = sha_index {
+                    index.insert(key, &existing_sha256);
+                }
+
+                store.remove(&temp_path).await.map_err(|e| errors::user_with_internal(
               &format!("Unable to remove temporary backup file '{}' after verifying that it is a duplicate of the existing file.", temp_path.display()),
               "Make sure that you have write (and delete) permission on the backup directory and try again.",
               e))?;
-                return Ok(BackupState::Unchanged(Some(format!(
-                    "at sha256@{shasum:x}"
-                ))));
+                self.update_etag(store, &target_path, new_etag.as_deref())
+                    .await?;
+                self.preserve_modified(store, entity, &target_path).await;
+                return Ok(BackupState::Unchanged(
+                    BackupDetails::new(format!("at sha256@{shasum:x}"))
+                        .with_sha256(format!("{shasum:x}"))
+                        .with_bytes(Some(size))
+                        .with_last_modified(entity.last_modified),
+                ));
             }
         }
 
-        let state = if target_path.exists() {
-            tokio::fs::remove_file(&target_path).await.map_err(|e| errors::user_with_internal(
+        let details = BackupDetails::new(
+            entity
+                .last_modified
+                .map(|m| format!("at {}", m.format("%Y-%m-%dT%H:%M:%S")))
+                .unwrap_or_else(|| format!("at sha256:{shasum:x}")),
+        )
+        .with_sha256(format!("{shasum:x}"))
+        .with_bytes(Some(size))
+        .with_last_modified(entity.last_modified);
+
+        let state = if store.exists(&target_path).await {
+            store.remove(&target_path).await.map_err(|e| errors::user_with_internal(
               &format!("Unable to remove original backup file '{}' prior to replacement with new file.", target_path.display()),
               "Make sure that you have write (and delete) permission on the backup directory and try again.",
               e))?;
-            BackupState::Updated(
-                entity
-                    .last_modified
-                    .map(|m| format!("at {}", m.format("%Y-%m-%dT%H:%M:%S")))
-                    .or(Some(format!("at sha256:{shasum:x}"))),
-            )
+            BackupState::Updated(details)
         } else {
-            BackupState::New(
-                entity
-                    .last_modified
-                    .map(|m| format!("at {}", m.format("%Y-%m-%dT%H:%M:%S")))
-                    .or(Some(format!("at sha256:{shasum:x}"))),
-            )
+            BackupState::New(details)
         };
 
-        tokio::fs::rename(&temp_path, &target_path).await.map_err(|e| errors::user_with_internal(
-          &format!("Unable to move temporary backup file '{}' to final location '{}'.", temp_path.display(), target_path.display()),
-          "Make sure that you have permission to write to this file/directory and try again.",
-          e))?;
-
-        tokio::fs::write(
-            target_path.with_extension(format!(
-                "{}.sha256",
-                target_path
-                    .extension()
-                    .unwrap_or_default()
-                    .to_string_lossy()
-            )),
-            format!("{:x}", shasum),
-        )
-        .await
-        .map_err(|e| {
-            errors::user_with_internal(
+        self.move_into_place(store, &temp_path, &target_path)
+            .await
+            .map_err(|e| {
+                errors::user_with_internal(
+                    &format!(
+                        "Unable to move temporary backup file '{}' to final location '{}'.",
+                        temp_path.display(),
+                        target_path.display()
+                    ),
+                    "Make sure that you have permission to write to this file/directory and try again.",
+                    e,
+                )
+            })?;
+
+        if entity.verify_after_write {
+            if let Err(e) = self
+                .verify_written(store, &target_path, &format!("{:x}", shasum))
+                .await
+            {
+                self.remove_unverified(store, &target_path, sha_index).await;
+                return Err(e);
+            }
+        }
+
+        store
+            .write(
+                &sidecars::sha256_path(&target_path),
+                format!("{:x}", shasum).as_bytes(),
+            )
+            .await
+            .map_err(|e| {
+                errors::user_with_internal(
                 &format!(
                     "Unable to write SHA-256 checksum file for backup file '{}'.",
                     target_path.display()
@@ -250,14 +827,34 @@ impl BackupEngine<HttpFile> for HttpFileEngine {
                 "Make sure that you have permission to write to this file/directory and try again.",
                 e,
             )
-        })?;
+            })?;
+
+        self.update_etag(store, &target_path, new_etag.as_deref())
+            .await?;
+        self.preserve_modified(store, entity, &target_path).await;
+
+        if let Some((index, key)) = sha_index {
+            index.insert(key, &format!("{:x}", shasum));
+        }
 
         Ok(state)
     }
+
+    async fn finish(&self, store: &dyn BackupStore, target: &Path) -> Result<(), crate::Error> {
+        let index = self.sha_indices.lock().await.remove(target);
+        match index {
+            Some(index) => index.flush(store, target).await,
+            None => Ok(()),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use rstest::rstest;
+
+    use crate::engines::{FaultyStore, FileSystemStore};
+
     use super::*;
 
     #[tokio::test]
@@ -275,14 +872,24 @@ mod tests {
             metadata: Default::default(),
             last_modified: None,
             content_type: None,
+            min_bytes: None,
+            max_bytes: None,
+            sha_index: false,
+            path: None,
+            fingerprint: None,
+            temp_dir: None,
+            ext_from_content_type: false,
+            verify_after_write: false,
         };
 
         let state = engine
-            .backup(&entity, temp_dir.path(), &cancel)
+            .backup(&entity, &FileSystemStore, temp_dir.path(), &cancel)
             .await
             .expect("backup to succeed");
 
-        assert!(matches!(state, BackupState::New(Some(msg)) if msg.starts_with("at sha256:")));
+        assert!(matches!(state, BackupState::New(_)), "got {state:?}");
+        assert!(state.detail().unwrap_or_default().starts_with("at sha256:"));
+        assert_eq!(state.details().and_then(|d| d.bytes), Some(1024));
 
         assert!(
             temp_dir.path().join(entity.target_path()).exists(),
@@ -290,11 +897,12 @@ mod tests {
         );
 
         let state = engine
-            .backup(&entity, temp_dir.path(), &cancel)
+            .backup(&entity, &FileSystemStore, temp_dir.path(), &cancel)
             .await
             .expect("backup to succeed");
 
-        assert!(matches!(state, BackupState::Updated(Some(msg)) if msg.starts_with("at sha256:")));
+        assert!(matches!(state, BackupState::Updated(_)), "got {state:?}");
+        assert!(state.detail().unwrap_or_default().starts_with("at sha256:"));
     }
 
     #[tokio::test]
@@ -312,19 +920,31 @@ mod tests {
             metadata: Default::default(),
             last_modified: Some(chrono::Utc::now()),
             content_type: None,
+            min_bytes: None,
+            max_bytes: None,
+            sha_index: false,
+            path: None,
+            fingerprint: None,
+            temp_dir: None,
+            ext_from_content_type: false,
+            verify_after_write: false,
         };
 
         let state = engine
-            .backup(&entity, temp_dir.path(), &cancel)
+            .backup(&entity, &FileSystemStore, temp_dir.path(), &cancel)
             .await
             .expect("backup to succeed");
 
         assert_eq!(
-            state,
-            BackupState::New(Some(format!(
-                "at {}",
+            state.to_string(),
+            format!(
+                "new at {}",
                 entity.last_modified.unwrap().format("%Y-%m-%dT%H:%M:%S")
-            )))
+            )
+        );
+        assert_eq!(
+            state.details().and_then(|d| d.last_modified),
+            entity.last_modified
         );
 
         assert!(
@@ -342,16 +962,558 @@ mod tests {
             .into();
 
         let state = engine
-            .backup(&entity, temp_dir.path(), &cancel)
+            .backup(&entity, &FileSystemStore, temp_dir.path(), &cancel)
             .await
             .expect("backup to succeed");
 
         assert_eq!(
-            state,
-            BackupState::Unchanged(Some(format!(
-                "since {}",
+            state.to_string(),
+            format!(
+                "unchanged since {}",
                 backup_modified.format("%Y-%m-%dT%H:%M:%S")
-            )))
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_backup_unauthorized() {
+        use axum::{http::StatusCode, routing::get, Router};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let url = format!("http://{}/asset", listener.local_addr().unwrap());
+
+        let app = Router::new().route("/asset", get(|| async { StatusCode::UNAUTHORIZED }));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let cancel = AtomicBool::new(false);
+
+        let err = HttpFileEngine::new()
+            .backup(
+                &HttpFile::new("test.bin", url).with_credentials(Credentials::token("expired")),
+                &FileSystemStore,
+                temp_dir.path(),
+                &cancel,
+            )
+            .await
+            .expect_err("the backup to fail");
+        assert_eq!(
+            errors::ErrorCategory::of(&err),
+            errors::ErrorCategory::Authentication
+        );
+    }
+
+    #[tokio::test]
+    async fn test_backup_cancelled() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+
+        let engine = HttpFileEngine::new();
+        let cancel = AtomicBool::new(true);
+
+        let entity = HttpFile::new("test.bin", "http://127.0.0.1:1/test.bin");
+
+        let state = engine
+            .backup(&entity, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("backup to be skipped");
+
+        assert_eq!(state, BackupState::Skipped(SkipReason::Cancelled));
+        assert_eq!(state.to_string(), "skipped (cancelled)");
+    }
+
+    #[rstest]
+    #[case(Some("application/octet-stream"), true)]
+    #[case(Some("text/html"), false)]
+    #[case(None, false)]
+    #[tokio::test]
+    async fn test_backup_html_response(#[case] accept: Option<&str>, #[case] fails: bool) {
+        use axum::{response::Html, routing::get, Router};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let url = format!("http://{}/asset", listener.local_addr().unwrap());
+
+        let app = Router::new().route(
+            "/asset",
+            get(|| async { Html("<html><body>Please sign in</body></html>") }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+
+        let engine = HttpFileEngine::new();
+        let cancel = AtomicBool::new(false);
+
+        let entity =
+            HttpFile::new("test.bin", url).with_content_type(accept.map(|a| a.to_string()));
+
+        let result = engine
+            .backup(&entity, &FileSystemStore, temp_dir.path(), &cancel)
+            .await;
+
+        if fails {
+            let err = result.expect_err("backup to fail");
+            assert!(
+                err.to_string().contains("HTML page"),
+                "the error should explain that an HTML page was returned"
+            );
+            assert!(
+                !temp_dir.path().join(entity.target_path()).exists(),
+                "the file should not exist"
+            );
+        } else {
+            result.expect("backup to succeed");
+            assert!(temp_dir.path().join(entity.target_path()).exists());
+        }
+    }
+
+    #[tokio::test]
+    #[cfg_attr(feature = "pure_tests", ignore)]
+    async fn test_backup_exceeding_content_length() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+
+        let engine = HttpFileEngine::new();
+        let cancel = AtomicBool::new(false);
+
+        let entity =
+            HttpFile::new("test.bin", "https://httpbin.org/bytes/1024").with_max_bytes(Some(512));
+
+        let state = engine
+            .backup(&entity, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("the backup to be skipped");
+
+        assert_eq!(state, BackupState::Skipped(SkipReason::SizeLimit));
+        assert!(
+            !temp_dir.path().join(entity.target_path()).exists(),
+            "the file should not exist"
+        );
+    }
+
+    #[tokio::test]
+    #[cfg_attr(feature = "pure_tests", ignore)]
+    async fn test_backup_exceeding_streamed_length() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+
+        let engine = HttpFileEngine::new();
+        let cancel = AtomicBool::new(false);
+
+        let entity = HttpFile::new(
+            "test.bin",
+            "https://httpbin.org/stream-bytes/2048?chunk_size=256",
+        )
+        .with_max_bytes(Some(512));
+
+        let state = engine
+            .backup(&entity, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("the backup to be skipped");
+
+        assert_eq!(state, BackupState::Skipped(SkipReason::SizeLimit));
+        assert!(
+            !temp_dir.path().join("test.bin").exists(),
+            "the file should not exist"
+        );
+        assert!(
+            !temp_dir.path().join("test.bin.tmp").exists(),
+            "the temporary file should have been removed"
+        );
+    }
+
+    #[rstest]
+    #[case(true)]
+    #[case(false)]
+    #[tokio::test]
+    async fn test_backup_etag(#[case] conditional: bool) {
+        use axum::{
+            http::{header, HeaderMap, StatusCode},
+            response::IntoResponse,
+            routing::get,
+            Router,
+        };
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let downloads = Arc::new(AtomicUsize::new(0));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let url = format!("http://{}/asset", listener.local_addr().unwrap());
+
+        let app = Router::new().route(
+            "/asset",
+            get({
+                let downloads = downloads.clone();
+                move |headers: HeaderMap| async move {
+                    if !conditional {
+                        downloads.fetch_add(1, Ordering::Relaxed);
+                        return (StatusCode::OK, "content").into_response();
+                    }
+
+                    if headers
+                        .get(header::IF_NONE_MATCH)
+                        .is_some_and(|v| v == "\"v1\"")
+                    {
+                        return StatusCode::NOT_MODIFIED.into_response();
+                    }
+
+                    downloads.fetch_add(1, Ordering::Relaxed);
+                    (StatusCode::OK, [(header::ETAG, "\"v1\"")], "content").into_response()
+                }
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+
+        let engine = HttpFileEngine::new();
+        let cancel = AtomicBool::new(false);
+
+        let entity = HttpFile::new("test.bin", url);
+
+        let state = engine
+            .backup(&entity, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("backup to succeed");
+        assert!(matches!(state, BackupState::New(..)));
+
+        let etag_path = temp_dir.path().join("test.bin.etag");
+        if conditional {
+            assert_eq!(
+                std::fs::read_to_string(&etag_path).expect("the ETag to be stored"),
+                "\"v1\""
+            );
+        } else {
+            assert!(!etag_path.exists(), "no ETag should be stored");
+        }
+
+        let state = engine
+            .backup(&entity, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("backup to succeed");
+
+        if conditional {
+            assert_eq!(state.to_string(), "unchanged at etag \"v1\"");
+            assert_eq!(
+                downloads.load(Ordering::Relaxed),
+                1,
+                "the file should not be downloaded again"
+            );
+        } else {
+            assert!(
+                matches!(&state, BackupState::Unchanged(_))
+                    && state.detail().unwrap_or_default().starts_with("at sha256@"),
+                "the file should be compared with the existing backup, got {state:?}"
+            );
+            assert_eq!(downloads.load(Ordering::Relaxed), 2);
+        }
+
+        assert_eq!(
+            std::fs::read_to_string(temp_dir.path().join("test.bin")).unwrap(),
+            "content"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_backup_sha_index_deleted_file() {
+        use axum::{routing::get, Router};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let url = format!("http://{}/asset", listener.local_addr().unwrap());
+
+        let app = Router::new().route("/asset", get(|| async { "content" }));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+
+        let engine = HttpFileEngine::new();
+        let cancel = AtomicBool::new(false);
+
+        let entity = HttpFile::new("test.bin", url).with_sha_index(true);
+
+        let state = engine
+            .backup(&entity, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("backup to succeed");
+        assert!(matches!(state, BackupState::New(..)), "got {state:?}");
+
+        let target = temp_dir.path().join("test.bin");
+        std::fs::remove_file(&target).expect("the backup to be deleted");
+
+        let state = engine
+            .backup(&entity, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("backup to succeed");
+        assert!(
+            matches!(state, BackupState::New(..)),
+            "a deleted backup should be restored, got {state:?}"
+        );
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "content");
+    }
+
+    #[tokio::test]
+    async fn test_backup_preserves_last_modified() {
+        use axum::{routing::get, Router};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let downloads = Arc::new(AtomicUsize::new(0));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let url = format!("http://{}/asset", listener.local_addr().unwrap());
+
+        let app = Router::new().route(
+            "/asset",
+            get({
+                let downloads = downloads.clone();
+                move || async move {
+                    downloads.fetch_add(1, Ordering::Relaxed);
+                    "content"
+                }
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+
+        let engine = HttpFileEngine::new();
+        let cancel = AtomicBool::new(false);
+
+        let last_modified = "2024-01-01T12:00:00Z"
+            .parse::<chrono::DateTime<chrono::Utc>>()
+            .unwrap();
+        let entity = HttpFile::new("test.bin", url).with_last_modified(Some(last_modified));
+
+        let state = engine
+            .backup(&entity, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("backup to succeed");
+        assert!(matches!(state, BackupState::New(..)));
+
+        let modified: chrono::DateTime<chrono::Utc> = temp_dir
+            .path()
+            .join("test.bin")
+            .metadata()
+            .expect("metadata")
+            .modified()
+            .expect("modified")
+            .into();
+        assert_eq!(
+            modified, last_modified,
+            "the file's modification time should match the entity's"
+        );
+
+        let state = engine
+            .backup(&entity, &FileSystemStore, temp_dir.path(), &cancel)
+            .await
+            .expect("backup to succeed");
+        assert_eq!(state.to_string(), "unchanged since 2024-01-01T12:00:00");
+        assert_eq!(
+            downloads.load(Ordering::Relaxed),
+            1,
+            "the file should not be downloaded again"
+        );
+    }
+
+    #[rstest]
+    #[case(None, false)]
+    #[case(Some(2048), false)]
+    #[case(None, true)]
+    #[tokio::test]
+    async fn test_backup_temp_dir(#[case] min_bytes: Option<u64>, #[case] cross_device: bool) {
+        use axum::{routing::get, Router};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let url = format!("http://{}/asset", listener.local_addr().unwrap());
+
+        // Streaming the response omits the Content-Length header, so that the file is downloaded
+        // before the min_bytes check is applied.
+        let app = Router::new().route(
+            "/asset",
+            get(|| async {
+                axum::body::Body::from_stream(futures::stream::once(async {
+                    Ok::<_, std::io::Error>("content")
+                }))
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let download_dir = tempfile::tempdir().expect("a temporary directory");
+
+        let engine = HttpFileEngine::new();
+        let cancel = AtomicBool::new(false);
+
+        let entity = HttpFile::new("test.bin", url)
+            .with_min_bytes(min_bytes)
+            .with_temp_dir(Some(download_dir.path().join("downloads")));
+
+        // Renames fail when moving files between devices, so they must be copied instead.
+        let cross_device_store =
+            FaultyStore::new(|_, _| Err(std::io::ErrorKind::CrossesDevices.into()));
+        let store: &dyn BackupStore = if cross_device {
+            &cross_device_store
+        } else {
+            &FileSystemStore
+        };
+        let result = engine
+            .backup(&entity, store, temp_dir.path(), &cancel)
+            .await;
+
+        if min_bytes.is_some() {
+            let state = result.expect("the backup to be skipped");
+            assert_eq!(state, BackupState::Skipped(SkipReason::SizeLimit));
+            assert!(!temp_dir.path().join("test.bin").exists());
+        } else {
+            let state = result.expect("the backup to succeed");
+            assert!(matches!(state, BackupState::New(..)), "got {state:?}");
+            assert_eq!(
+                std::fs::read_to_string(temp_dir.path().join("test.bin")).unwrap(),
+                "content"
+            );
+        }
+
+        assert!(
+            !temp_dir.path().join("test.bin.tmp").exists(),
+            "the download should not be written alongside the backup"
+        );
+        assert_eq!(
+            std::fs::read_dir(download_dir.path().join("downloads"))
+                .expect("the temporary directory to be created")
+                .count(),
+            0,
+            "the temporary file should have been removed"
+        );
+    }
+
+    #[rstest]
+    #[case(false, false, true)]
+    #[case(false, true, true)]
+    #[case(true, false, true)]
+    #[case(true, true, false)]
+    #[tokio::test]
+    async fn test_backup_verify_after_write(
+        #[case] corrupt: bool,
+        #[case] verify: bool,
+        #[case] succeeds: bool,
+    ) {
+        use axum::{routing::get, Router};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let url = format!("http://{}/asset", listener.local_addr().unwrap());
+
+        let app = Router::new().route("/asset", get(|| async { "content" }));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let target_path = temp_dir.path().join("test.bin");
+        let sha_path = sidecars::sha256_path(&target_path);
+
+        // An existing backup (and its checksum) is replaced by the new download.
+        std::fs::write(&target_path, "original").unwrap();
+        std::fs::write(&sha_path, "0000").unwrap();
+
+        let engine = HttpFileEngine::new();
+        let cancel = AtomicBool::new(false);
+        let entity = HttpFile::new("test.bin", url).with_verify_after_write(verify);
+
+        // Files are corrupted as they are moved into place, as a failing disk might do.
+        let corrupting_store = FaultyStore::new(|from, to| {
+            std::fs::rename(from, to)?;
+            std::fs::write(to, b"cont\0\0\0")
+        });
+        let store: &dyn BackupStore = if corrupt {
+            &corrupting_store
+        } else {
+            &FileSystemStore
+        };
+        let result = engine
+            .backup(&entity, store, temp_dir.path(), &cancel)
+            .await;
+
+        if succeeds {
+            let state = result.expect("the backup to succeed");
+            assert!(matches!(state, BackupState::Updated(..)), "got {state:?}");
+            assert!(target_path.exists());
+            assert_ne!(std::fs::read_to_string(&sha_path).unwrap(), "0000");
+
+            let details = state.details().expect("the backup to have details");
+            assert_eq!(details.bytes, Some("content".len() as u64));
+            assert_eq!(
+                details.sha256.as_deref(),
+                Some(std::fs::read_to_string(&sha_path).unwrap().as_str())
+            );
+            assert_eq!(details.git_head, None);
+        } else {
+            let err = result.expect_err("the corruption to be detected");
+            assert!(
+                err.to_string().contains("does not match the content"),
+                "{err}"
+            );
+            assert!(
+                !target_path.exists(),
+                "the corrupted file should have been removed"
+            );
+            assert!(
+                !sha_path.exists(),
+                "the outdated checksum should have been removed"
+            );
+        }
+    }
+
+    #[rstest]
+    #[case(
+        "tool-linux-amd64",
+        "application/gzip",
+        true,
+        "r/v1/tool-linux-amd64.gz"
+    )]
+    #[case(
+        "tool-windows",
+        "application/x-msdownload",
+        true,
+        "r/v1/tool-windows.exe"
+    )]
+    #[case("checksums", "text/plain; charset=utf-8", true, "r/v1/checksums.txt")]
+    #[case("tool-v1.2", "application/zip", true, "r/v1/tool-v1.2.zip")]
+    #[case(
+        "tool-linux-amd64",
+        "application/octet-stream",
+        true,
+        "r/v1/tool-linux-amd64"
+    )]
+    #[case("tool.tar.gz", "application/zip", true, "r/v1/tool.tar.gz")]
+    #[case("tool.exe", "application/x-msdownload", true, "r/v1/tool.exe")]
+    #[case(
+        "tool.AppImage",
+        "application/octet-stream",
+        true,
+        "r/v1/tool.AppImage"
+    )]
+    #[case("tool-linux-amd64", "application/gzip", false, "r/v1/tool-linux-amd64")]
+    fn test_ext_from_content_type(
+        #[case] name: &str,
+        #[case] content_type: &str,
+        #[case] enabled: bool,
+        #[case] expected: &str,
+    ) {
+        let entity = HttpFile::new(format!("r/v1/{name}"), "https://example.com/asset")
+            .with_ext_from_content_type(enabled)
+            .with_metadata("asset.content_type", content_type);
+
+        assert_eq!(
+            HttpFileEngine::new().backup_path(&entity, Path::new("/backups")),
+            Path::new("/backups").join(expected)
         );
     }
 }
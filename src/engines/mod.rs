@@ -1,20 +1,78 @@
 mod git;
 mod http_file;
+mod sha_index;
+mod sidecars;
+mod store;
 
 pub use git::GitEngine;
 pub use http_file::HttpFileEngine;
+pub use sidecars::repair_sidecars;
+pub use store::{BackupStore, FileSystemStore, RetryingStore, DEFAULT_FS_RETRIES};
+
+#[cfg(test)]
+pub use store::FaultyStore;
 
 use crate::BackupEntity;
 use std::fmt::Display;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::AtomicBool;
 
 #[derive(Debug, Eq, PartialEq)]
 pub enum BackupState {
-    Skipped,
-    New(Option<String>),
-    Updated(Option<String>),
-    Unchanged(Option<String>),
+    Skipped(SkipReason),
+    New(BackupDetails),
+    Updated(BackupDetails),
+    Unchanged(BackupDetails),
+}
+
+/// Describes what was backed up, both for people (through its `description`) and for code which
+/// needs to inspect the outcome of a backup without parsing that description.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BackupDetails {
+    /// A short description of the backup, such as the commit or checksum which was backed up.
+    pub description: Option<String>,
+    /// The commit at the HEAD of the repository which was backed up.
+    pub git_head: Option<String>,
+    /// The SHA-256 checksum of the file which was backed up.
+    pub sha256: Option<String>,
+    /// The number of bytes which were transferred to back up the item.
+    pub bytes: Option<u64>,
+    /// The time at which the item was last modified at its source.
+    pub last_modified: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl BackupDetails {
+    pub fn new<S: Into<String>>(description: S) -> Self {
+        Self {
+            description: Some(description.into()),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_git_head<S: Into<String>>(self, git_head: S) -> Self {
+        Self {
+            git_head: Some(git_head.into()),
+            ..self
+        }
+    }
+
+    pub fn with_sha256<S: Into<String>>(self, sha256: S) -> Self {
+        Self {
+            sha256: Some(sha256.into()),
+            ..self
+        }
+    }
+
+    pub fn with_bytes(self, bytes: Option<u64>) -> Self {
+        Self { bytes, ..self }
+    }
+
+    pub fn with_last_modified(self, last_modified: Option<chrono::DateTime<chrono::Utc>>) -> Self {
+        Self {
+            last_modified,
+            ..self
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -22,21 +80,102 @@ pub trait BackupEngine<E: BackupEntity> {
     async fn backup<P: AsRef<Path> + Send>(
         &self,
         entity: &E,
+        store: &dyn BackupStore,
         target: P,
         cancel: &AtomicBool,
     ) -> Result<BackupState, crate::Error>;
+
+    /// The path within `target` at which the backup of the given entity is stored.
+    fn backup_path(&self, entity: &E, target: &Path) -> PathBuf {
+        target.join(entity.target_path())
+    }
+
+    /// Called once every entity for a backup policy has been backed up to `target`, allowing
+    /// the engine to persist any state which it has accumulated during the run.
+    async fn finish(&self, _store: &dyn BackupStore, _target: &Path) -> Result<(), crate::Error> {
+        Ok(())
+    }
+}
+
+/// The reason for which an entity was not backed up.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SkipReason {
+    /// The entity did not match the policy's filter.
+    Filtered,
+    /// The entity would have been backed up, had this not been a dry run.
+    DryRun,
+    /// The backup was cancelled before the entity could be backed up.
+    Cancelled,
+    /// The entity is an empty repository, which has nothing to back up.
+    Empty,
+    /// The entity is larger (or smaller) than the policy's `max_bytes` (or `min_bytes`) limit allows.
+    SizeLimit,
+}
+
+impl SkipReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SkipReason::Filtered => "filtered",
+            SkipReason::DryRun => "dry_run",
+            SkipReason::Cancelled => "cancelled",
+            SkipReason::Empty => "empty",
+            SkipReason::SizeLimit => "size_limit",
+        }
+    }
+}
+
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::DryRun => write!(f, "dry run"),
+            SkipReason::SizeLimit => write!(f, "size limit"),
+            reason => write!(f, "{}", reason.as_str()),
+        }
+    }
+}
+
+impl BackupState {
+    /// A low-cardinality name for this state, suitable for use as a metric label or span attribute.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BackupState::Skipped(_) => "skipped",
+            BackupState::New(_) => "new",
+            BackupState::Updated(_) => "updated",
+            BackupState::Unchanged(_) => "unchanged",
+        }
+    }
+
+    /// The detail describing this state, such as the commit or checksum which was backed up, or
+    /// the reason that the entity was skipped.
+    pub fn detail(&self) -> Option<&str> {
+        match self {
+            BackupState::Skipped(reason) => Some(reason.as_str()),
+            BackupState::New(details)
+            | BackupState::Updated(details)
+            | BackupState::Unchanged(details) => details.description.as_deref(),
+        }
+    }
+
+    /// The typed details of an entity which was backed up, or `None` if it was skipped.
+    pub fn details(&self) -> Option<&BackupDetails> {
+        match self {
+            BackupState::Skipped(_) => None,
+            BackupState::New(details)
+            | BackupState::Updated(details)
+            | BackupState::Unchanged(details) => Some(details),
+        }
+    }
 }
 
 impl Display for BackupState {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            BackupState::Skipped => write!(f, "skipped"),
-            BackupState::New(Some(s)) => write!(f, "new {}", s),
-            BackupState::Updated(Some(s)) => write!(f, "updated {}", s),
-            BackupState::Unchanged(Some(s)) => write!(f, "unchanged {}", s),
-            BackupState::New(None) => write!(f, "new"),
-            BackupState::Updated(None) => write!(f, "updated"),
-            BackupState::Unchanged(None) => write!(f, "unchanged"),
+            BackupState::Skipped(SkipReason::DryRun) => write!(f, "would back up"),
+            BackupState::Skipped(reason) => write!(f, "skipped ({})", reason),
+            state => match state.detail() {
+                Some(detail) => write!(f, "{} {}", state.as_str(), detail),
+                None => write!(f, "{}", state.as_str()),
+            },
         }
     }
 }
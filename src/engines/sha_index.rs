@@ -0,0 +1,213 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::errors;
+
+use super::BackupStore;
+
+/// The name of the file, within a policy's `to` directory, in which the SHA-256 index is stored.
+pub const SHA_INDEX_FILE_NAME: &str = ".github-backup-sha256.json";
+
+/// An in-memory index of the SHA-256 checksums of the files which have been backed up to a
+/// directory, keyed by their path relative to that directory.
+///
+/// Reading a single index is considerably faster than reading a `.sha256` sidecar file for each
+/// of (potentially) tens of thousands of files, so the index is loaded once, consulted and updated
+/// by each backup task, and then written back to the store once the run has completed.
+#[derive(Debug, Default)]
+pub struct ShaIndex {
+    entries: Mutex<BTreeMap<String, String>>,
+    dirty: Mutex<bool>,
+}
+
+impl ShaIndex {
+    pub fn path_for(target: &Path) -> PathBuf {
+        target.join(SHA_INDEX_FILE_NAME)
+    }
+
+    /// Loads the index for the given directory, returning an empty index if one does not exist yet.
+    pub async fn load(store: &dyn BackupStore, target: &Path) -> Result<Self, errors::Error> {
+        let path = Self::path_for(target);
+        if !store.exists(&path).await {
+            return Ok(Self::default());
+        }
+
+        let content = store.read_to_string(&path).await.map_err(|e| {
+            errors::user_with_internal(
+                &format!("Unable to read the SHA-256 index '{}'.", path.display()),
+                "Make sure that you have permission to read this file.",
+                e,
+            )
+        })?;
+
+        let entries = serde_json::from_str(&content).map_err(|e| {
+            errors::user_with_internal(
+                &format!("The SHA-256 index '{}' could not be parsed.", path.display()),
+                "Remove the index file to have it recreated from the checksum files on the next backup run.",
+                e,
+            )
+        })?;
+
+        Ok(Self {
+            entries: Mutex::new(entries),
+            dirty: Mutex::new(false),
+        })
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.entries.lock().unwrap().get(key).cloned()
+    }
+
+    pub fn insert(&self, key: &str, sha256: &str) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.get(key).map(|s| s.as_str()) != Some(sha256) {
+            entries.insert(key.to_string(), sha256.to_string());
+            *self.dirty.lock().unwrap() = true;
+        }
+    }
+
+    pub fn remove(&self, key: &str) {
+        if self.entries.lock().unwrap().remove(key).is_some() {
+            *self.dirty.lock().unwrap() = true;
+        }
+    }
+
+    /// Writes the index back to the store (if it has changed since it was loaded), replacing the
+    /// existing index atomically.
+    pub async fn flush(&self, store: &dyn BackupStore, target: &Path) -> Result<(), errors::Error> {
+        if !std::mem::take(&mut *self.dirty.lock().unwrap()) {
+            return Ok(());
+        }
+
+        let content = serde_json::to_vec_pretty(&*self.entries.lock().unwrap()).map_err(|e| {
+            errors::system_with_internal(
+                "Unable to serialize the SHA-256 index.",
+                "Please report this issue to us on GitHub.",
+                e,
+            )
+        })?;
+
+        let path = Self::path_for(target);
+        let temp_path = path.with_extension("json.tmp");
+        store.create_dir_all(target).await.map_err(|e| {
+            errors::user_with_internal(
+                &format!("Unable to create backup directory '{}'", target.display()),
+                "Make sure that you have permission to create the directory.",
+                e,
+            )
+        })?;
+
+        store.write(&temp_path, &content).await.map_err(|e| {
+            errors::user_with_internal(
+                &format!(
+                    "Unable to write the SHA-256 index '{}'.",
+                    temp_path.display()
+                ),
+                "Make sure that you have permission to write to the backup directory.",
+                e,
+            )
+        })?;
+
+        store.rename(&temp_path, &path).await.map_err(|e| {
+            errors::user_with_internal(
+                &format!("Unable to replace the SHA-256 index '{}'.", path.display()),
+                "Make sure that you have permission to write to the backup directory.",
+                e,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engines::{BackupStore, FileSystemStore};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_load_and_flush() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+
+        let index = ShaIndex::load(&FileSystemStore, temp_dir.path())
+            .await
+            .expect("a missing index to load");
+        assert_eq!(index.get("a/b.bin"), None);
+
+        index
+            .flush(&FileSystemStore, temp_dir.path())
+            .await
+            .expect("the index to be flushed");
+        assert!(
+            !ShaIndex::path_for(temp_dir.path()).exists(),
+            "an unchanged index should not be written"
+        );
+
+        index.insert("a/b.bin", "abc123");
+        index
+            .flush(&FileSystemStore, temp_dir.path())
+            .await
+            .expect("the index to be flushed");
+
+        let index = ShaIndex::load(&FileSystemStore, temp_dir.path())
+            .await
+            .expect("the index to load");
+        assert_eq!(index.get("a/b.bin"), Some("abc123".to_string()));
+        assert!(
+            !temp_dir
+                .path()
+                .join(".github-backup-sha256.json.tmp")
+                .exists(),
+            "the temporary index file should have been removed"
+        );
+    }
+
+    /// Compares the time taken to look up the checksums of many small files using their `.sha256`
+    /// sidecar files against a single index. Run with `cargo test --release bench_sha_index -- --ignored`.
+    #[tokio::test]
+    #[ignore]
+    async fn bench_sha_index() {
+        const FILES: usize = 10_000;
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let index = ShaIndex::default();
+        for i in 0..FILES {
+            let key = format!("repo/v{}/asset-{i}.bin", i % 100);
+            let sidecar = temp_dir.path().join(format!("{key}.sha256"));
+            std::fs::create_dir_all(sidecar.parent().unwrap()).unwrap();
+            std::fs::write(&sidecar, format!("{i:064x}")).unwrap();
+            index.insert(&key, &format!("{i:064x}"));
+        }
+        index
+            .flush(&FileSystemStore, temp_dir.path())
+            .await
+            .unwrap();
+
+        let started_at = std::time::Instant::now();
+        for i in 0..FILES {
+            let key = format!("repo/v{}/asset-{i}.bin", i % 100);
+            FileSystemStore
+                .read_to_string(&temp_dir.path().join(format!("{key}.sha256")))
+                .await
+                .expect("the sidecar to be read");
+        }
+        let sidecars = started_at.elapsed();
+
+        let started_at = std::time::Instant::now();
+        let index = ShaIndex::load(&FileSystemStore, temp_dir.path())
+            .await
+            .expect("the index to load");
+        for i in 0..FILES {
+            let key = format!("repo/v{}/asset-{i}.bin", i % 100);
+            index.get(&key).expect("the checksum to be indexed");
+        }
+        let indexed = started_at.elapsed();
+
+        assert!(
+            indexed < sidecars,
+            "looking up {FILES} checksums in the index ({indexed:?}) should be faster than reading their sidecar files ({sidecars:?})"
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use sha2::Digest;
+use tokio::io::AsyncReadExt;
+use tracing_batteries::prelude::*;
+
+use crate::{errors, helpers::safe_walk};
+
+/// The file extensions used for the metadata which is stored alongside downloaded files, rather
+/// than for the files themselves.
+const SIDECAR_EXTENSIONS: &[&str] = &["sha256", "etag", "tmp"];
+
+/// The path of the `.sha256` sidecar file which holds the checksum of the file at `path`.
+pub fn sha256_path(path: &Path) -> PathBuf {
+    path.with_extension(
+        format!(
+            "{}.sha256",
+            path.extension().unwrap_or_default().to_string_lossy()
+        )
+        .trim_start_matches('.'),
+    )
+}
+
+/// The outcome of repairing the checksum sidecars within a backup directory.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SidecarRepair {
+    /// The number of sidecars which were missing (or invalid) and have been written.
+    pub created: usize,
+    /// The number of files which already had a sidecar matching their content.
+    pub valid: usize,
+    /// The number of files whose sidecar holds a different checksum to their content, which are
+    /// reported (rather than repaired) since either the file or its sidecar may be damaged.
+    pub mismatched: usize,
+}
+
+/// Writes a `.sha256` sidecar for every downloaded file within `root` which doesn't already have a
+/// valid one, so that backups made before sidecars were introduced don't need to be downloaded again
+/// to determine whether they have changed. Existing sidecars are checked against the content of
+/// their files.
+pub async fn repair_sidecars(
+    root: &Path,
+    cancel: &AtomicBool,
+) -> Result<SidecarRepair, errors::Error> {
+    let mut repair = SidecarRepair::default();
+    if !root.exists() {
+        return Ok(repair);
+    }
+
+    let entries = safe_walk::walk(root, |_| true).map_err(|e| {
+        errors::user_with_internal(
+            &format!("Unable to read the backup directory '{}'.", root.display()),
+            "Make sure that you have permission to read the backup directory and try again.",
+            e,
+        )
+    })?;
+
+    for entry in entries {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+
+        if entry.kind != safe_walk::EntryKind::File || !is_backup_file(&entry.path) {
+            continue;
+        }
+
+        let checksum = sha256(&entry.path).await.map_err(|e| {
+            errors::user_with_internal(
+                &format!(
+                    "Unable to calculate the checksum of backup file '{}'.",
+                    entry.path.display()
+                ),
+                "Make sure that you have permission to read this file and try again.",
+                e,
+            )
+        })?;
+
+        let sidecar = sha256_path(&entry.path);
+        match read_sha256(&sidecar).await {
+            Some(existing) if existing == checksum => {
+                repair.valid += 1;
+                continue;
+            }
+            Some(existing) => {
+                warn!(
+                    "The checksum file for '{}' holds {existing}, but the file's content has a checksum of {checksum}. Either the file or its checksum file may be damaged.",
+                    entry.path.display()
+                );
+                repair.mismatched += 1;
+                continue;
+            }
+            None => {}
+        }
+
+        tokio::fs::write(&sidecar, checksum.as_bytes())
+            .await
+            .map_err(|e| {
+                errors::user_with_internal(
+                &format!(
+                    "Unable to write SHA-256 checksum file for backup file '{}'.",
+                    entry.path.display()
+                ),
+                "Make sure that you have permission to write to this file/directory and try again.",
+                e,
+            )
+            })?;
+
+        debug!("Wrote the missing checksum for '{}'", entry.path.display());
+        repair.created += 1;
+    }
+
+    Ok(repair)
+}
+
+/// Determines whether a file is one which was downloaded by a backup, rather than one of the
+/// sidecars or state files that GitHub Backup keeps alongside them.
+fn is_backup_file(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    if name.starts_with(".github-backup-") {
+        return false;
+    }
+
+    !matches!(
+        path.extension().map(|e| e.to_string_lossy()),
+        Some(ext) if SIDECAR_EXTENSIONS.contains(&ext.as_ref())
+    )
+}
+
+/// Reads the checksum held by a sidecar, returning `None` if it is missing or isn't a valid
+/// SHA-256 checksum.
+async fn read_sha256(path: &Path) -> Option<String> {
+    let content = tokio::fs::read_to_string(path).await.ok()?;
+    let content = content.trim().to_ascii_lowercase();
+    (content.len() == 64 && content.chars().all(|c| c.is_ascii_hexdigit())).then_some(content)
+}
+
+async fn sha256(path: &Path) -> std::io::Result<String> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut shasum = sha2::Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+
+        shasum.update(&buffer[..read]);
+    }
+
+    Ok(format!("{:x}", shasum.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha256_path() {
+        assert_eq!(
+            sha256_path(Path::new("a/b/file.tar.gz")),
+            Path::new("a/b/file.tar.gz.sha256")
+        );
+        assert_eq!(
+            sha256_path(Path::new("a/b/file")),
+            Path::new("a/b/file.sha256")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_repair_sidecars() {
+        let root = tempfile::tempdir().expect("a temporary directory");
+        let release = root
+            .path()
+            .join("octocat")
+            .join("hello-world")
+            .join("v1.0.0");
+        std::fs::create_dir_all(&release).unwrap();
+
+        // A file which was backed up before sidecars were written
+        std::fs::write(release.join("missing.bin"), "abc").unwrap();
+
+        // A file with a valid sidecar, which should be left alone
+        let abc = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        std::fs::write(release.join("valid.bin"), "abc").unwrap();
+        std::fs::write(release.join("valid.bin.sha256"), abc).unwrap();
+
+        // A file whose sidecar doesn't match its content, which should be reported but left alone
+        std::fs::write(release.join("damaged.bin"), "abd").unwrap();
+        std::fs::write(release.join("damaged.bin.sha256"), abc).unwrap();
+
+        // A file whose sidecar was truncated
+        std::fs::write(release.join("invalid"), "abc").unwrap();
+        std::fs::write(release.join("invalid.sha256"), "ba78").unwrap();
+
+        // Sidecars and state files which shouldn't have sidecars of their own
+        std::fs::write(release.join("missing.bin.etag"), "\"etag\"").unwrap();
+        std::fs::write(root.path().join(".github-backup-state.json"), "{}").unwrap();
+
+        let cancel = AtomicBool::new(false);
+        let repair = repair_sidecars(root.path(), &cancel)
+            .await
+            .expect("the repair to succeed");
+        assert_eq!(
+            repair,
+            SidecarRepair {
+                created: 2,
+                valid: 1,
+                mismatched: 1,
+            }
+        );
+
+        assert_eq!(
+            std::fs::read_to_string(release.join("missing.bin.sha256")).unwrap(),
+            abc
+        );
+        assert_eq!(
+            std::fs::read_to_string(release.join("invalid.sha256")).unwrap(),
+            abc
+        );
+        assert_eq!(
+            std::fs::read_to_string(release.join("damaged.bin.sha256")).unwrap(),
+            abc
+        );
+        assert!(!release.join("missing.bin.etag.sha256").exists());
+        assert!(!root
+            .path()
+            .join(".github-backup-state.json.sha256")
+            .exists());
+
+        let repair = repair_sidecars(root.path(), &cancel)
+            .await
+            .expect("the repair to succeed");
+        assert_eq!(
+            repair,
+            SidecarRepair {
+                created: 0,
+                valid: 3,
+                mismatched: 1,
+            },
+            "a second repair shouldn't need to write anything"
+        );
+    }
+}
@@ -0,0 +1,562 @@
+use std::{path::Path, sync::Arc, time::Duration};
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use tracing_batteries::prelude::*;
+
+use crate::helpers::{
+    safe_walk::{self, EntryKind},
+    Clock, SystemClock,
+};
+
+/// A destination into which backups are written.
+///
+/// Engines perform all of their reads and writes against the backup destination
+/// through this trait, allowing backups to be stored somewhere other than the local
+/// filesystem (for example, in an object store). Errors are returned as
+/// [`std::io::Error`]s so that engines can describe them in the context of the
+/// operation being performed.
+#[async_trait::async_trait]
+pub trait BackupStore: Send + Sync {
+    /// Ensures that a directory (and all of its parents) exists.
+    async fn create_dir_all(&self, path: &Path) -> std::io::Result<()>;
+
+    /// Determines whether an entry exists at the given path.
+    async fn exists(&self, path: &Path) -> bool;
+
+    /// Retrieves the time at which the entry at the given path was last modified, if known.
+    async fn modified(&self, path: &Path) -> Option<chrono::DateTime<chrono::Utc>>;
+
+    /// Sets the time at which the file at the given path was last modified.
+    async fn set_modified(
+        &self,
+        path: &Path,
+        modified: chrono::DateTime<chrono::Utc>,
+    ) -> std::io::Result<()>;
+
+    /// Calculates the total size (in bytes) of the entry at the given path, including everything
+    /// within it if it is a directory, or `None` if it does not exist.
+    async fn size(&self, path: &Path) -> Option<u64>;
+
+    async fn read_to_string(&self, path: &Path) -> std::io::Result<String>;
+
+    /// Opens the file at the given path, returning a reader for its content.
+    async fn open(&self, path: &Path) -> std::io::Result<Box<dyn AsyncRead + Send + Unpin>>;
+
+    async fn write(&self, path: &Path, content: &[u8]) -> std::io::Result<()>;
+
+    /// Creates (or truncates) the file at the given path, returning a writer for its content.
+    async fn create(&self, path: &Path) -> std::io::Result<Box<dyn AsyncWrite + Send + Unpin>>;
+
+    async fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()>;
+
+    async fn remove(&self, path: &Path) -> std::io::Result<()>;
+
+    /// Removes the directory at the given path, along with everything inside it.
+    async fn remove_dir_all(&self, path: &Path) -> std::io::Result<()>;
+}
+
+/// A [`BackupStore`] which writes backups to the local filesystem.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FileSystemStore;
+
+#[async_trait::async_trait]
+impl BackupStore for FileSystemStore {
+    async fn create_dir_all(&self, path: &Path) -> std::io::Result<()> {
+        tokio::fs::create_dir_all(path).await
+    }
+
+    async fn exists(&self, path: &Path) -> bool {
+        tokio::fs::try_exists(path).await.unwrap_or_default()
+    }
+
+    async fn modified(&self, path: &Path) -> Option<chrono::DateTime<chrono::Utc>> {
+        tokio::fs::metadata(path)
+            .await
+            .and_then(|m| m.modified())
+            .ok()
+            .map(chrono::DateTime::from)
+    }
+
+    async fn set_modified(
+        &self,
+        path: &Path,
+        modified: chrono::DateTime<chrono::Utc>,
+    ) -> std::io::Result<()> {
+        let path = path.to_path_buf();
+        let modified = filetime::FileTime::from_system_time(modified.into());
+        tokio::task::spawn_blocking(move || filetime::set_file_mtime(path, modified)).await?
+    }
+
+    async fn size(&self, path: &Path) -> Option<u64> {
+        let metadata = tokio::fs::symlink_metadata(path).await.ok()?;
+        if !metadata.is_dir() {
+            return Some(metadata.len());
+        }
+
+        let path = path.to_path_buf();
+        let entries = tokio::task::spawn_blocking(move || safe_walk::walk(&path, |_| true))
+            .await
+            .ok()?
+            .ok()?;
+
+        Some(
+            entries
+                .iter()
+                .filter(|e| e.kind != EntryKind::Directory)
+                .map(|e| e.size)
+                .sum(),
+        )
+    }
+
+    async fn read_to_string(&self, path: &Path) -> std::io::Result<String> {
+        tokio::fs::read_to_string(path).await
+    }
+
+    async fn open(&self, path: &Path) -> std::io::Result<Box<dyn AsyncRead + Send + Unpin>> {
+        Ok(Box::new(tokio::fs::File::open(path).await?))
+    }
+
+    async fn write(&self, path: &Path, content: &[u8]) -> std::io::Result<()> {
+        tokio::fs::write(path, content).await
+    }
+
+    async fn create(&self, path: &Path) -> std::io::Result<Box<dyn AsyncWrite + Send + Unpin>> {
+        Ok(Box::new(tokio::fs::File::create(path).await?))
+    }
+
+    async fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        tokio::fs::rename(from, to).await
+    }
+
+    async fn remove(&self, path: &Path) -> std::io::Result<()> {
+        tokio::fs::remove_file(path).await
+    }
+
+    async fn remove_dir_all(&self, path: &Path) -> std::io::Result<()> {
+        tokio::fs::remove_dir_all(path).await
+    }
+}
+
+/// The number of times a filesystem operation which fails with a transient error is retried (by
+/// default) before the error is reported.
+pub const DEFAULT_FS_RETRIES: usize = 3;
+
+/// How long to wait before the first retry of a failed filesystem operation, which is doubled for
+/// each subsequent retry.
+const DEFAULT_FS_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The (platform specific) OS error codes which indicate a transient failure that is likely to
+/// succeed if retried, in addition to those covered by [`is_transient`]'s error kinds.
+#[cfg(target_os = "linux")]
+const TRANSIENT_OS_ERRORS: &[i32] = &[
+    16,  // EBUSY
+    116, // ESTALE
+];
+#[cfg(target_os = "macos")]
+const TRANSIENT_OS_ERRORS: &[i32] = &[
+    16, // EBUSY
+    70, // ESTALE
+];
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+const TRANSIENT_OS_ERRORS: &[i32] = &[];
+
+/// A [`BackupStore`] which retries the operations that modify its destination (creating
+/// directories, renaming files and removing them) when they fail with a transient error, as
+/// networked filesystems (like NFS and SMB) occasionally do. Errors which aren't transient (like
+/// a lack of permission) are returned immediately.
+#[derive(Clone)]
+pub struct RetryingStore {
+    inner: Arc<dyn BackupStore>,
+    retries: usize,
+    backoff: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl RetryingStore {
+    pub fn new(inner: Arc<dyn BackupStore>, retries: usize) -> Self {
+        Self {
+            inner,
+            retries,
+            backoff: DEFAULT_FS_RETRY_BACKOFF,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_backoff(self, backoff: Duration) -> Self {
+        Self { backoff, ..self }
+    }
+
+    #[cfg(test)]
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+
+    async fn retry<'a, T, F, Fut>(
+        &'a self,
+        operation: &str,
+        path: &Path,
+        f: F,
+    ) -> std::io::Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: std::future::Future<Output = std::io::Result<T>> + 'a,
+    {
+        let mut backoff = self.backoff;
+        let mut attempt = 0;
+        loop {
+            match f().await {
+                Err(e) if attempt < self.retries && is_transient(&e) => {
+                    attempt += 1;
+                    debug!(
+                        "Unable to {operation} '{}' ({e}), retrying in {:?} (attempt {attempt} of {})",
+                        path.display(),
+                        backoff,
+                        self.retries
+                    );
+                    self.clock.sleep(backoff).await;
+                    backoff *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Determines whether a filesystem error is transient, and so is likely to succeed if retried.
+fn is_transient(error: &std::io::Error) -> bool {
+    matches!(
+        error.kind(),
+        std::io::ErrorKind::Interrupted
+            | std::io::ErrorKind::WouldBlock
+            | std::io::ErrorKind::TimedOut
+    ) || error
+        .raw_os_error()
+        .is_some_and(|code| TRANSIENT_OS_ERRORS.contains(&code))
+}
+
+#[async_trait::async_trait]
+impl BackupStore for RetryingStore {
+    async fn create_dir_all(&self, path: &Path) -> std::io::Result<()> {
+        self.retry("create the directory", path, || {
+            self.inner.create_dir_all(path)
+        })
+        .await
+    }
+
+    async fn exists(&self, path: &Path) -> bool {
+        self.inner.exists(path).await
+    }
+
+    async fn modified(&self, path: &Path) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.inner.modified(path).await
+    }
+
+    async fn set_modified(
+        &self,
+        path: &Path,
+        modified: chrono::DateTime<chrono::Utc>,
+    ) -> std::io::Result<()> {
+        self.inner.set_modified(path, modified).await
+    }
+
+    async fn size(&self, path: &Path) -> Option<u64> {
+        self.inner.size(path).await
+    }
+
+    async fn read_to_string(&self, path: &Path) -> std::io::Result<String> {
+        self.inner.read_to_string(path).await
+    }
+
+    async fn open(&self, path: &Path) -> std::io::Result<Box<dyn AsyncRead + Send + Unpin>> {
+        self.inner.open(path).await
+    }
+
+    async fn write(&self, path: &Path, content: &[u8]) -> std::io::Result<()> {
+        self.inner.write(path, content).await
+    }
+
+    async fn create(&self, path: &Path) -> std::io::Result<Box<dyn AsyncWrite + Send + Unpin>> {
+        self.inner.create(path).await
+    }
+
+    async fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        self.retry("rename", from, || self.inner.rename(from, to))
+            .await
+    }
+
+    async fn remove(&self, path: &Path) -> std::io::Result<()> {
+        self.retry("remove", path, || self.inner.remove(path)).await
+    }
+
+    async fn remove_dir_all(&self, path: &Path) -> std::io::Result<()> {
+        self.retry("remove", path, || self.inner.remove_dir_all(path))
+            .await
+    }
+}
+
+/// A [`BackupStore`] which passes everything through to the filesystem, except for renames which
+/// are handled by the given function, allowing tests to simulate failing (or corrupting) disks.
+#[cfg(test)]
+pub struct FaultyStore {
+    rename: Box<FaultyRename>,
+}
+
+#[cfg(test)]
+type FaultyRename = dyn Fn(&Path, &Path) -> std::io::Result<()> + Send + Sync;
+
+#[cfg(test)]
+impl FaultyStore {
+    pub fn new<F>(rename: F) -> Self
+    where
+        F: Fn(&Path, &Path) -> std::io::Result<()> + Send + Sync + 'static,
+    {
+        Self {
+            rename: Box::new(rename),
+        }
+    }
+}
+
+#[cfg(test)]
+#[async_trait::async_trait]
+impl BackupStore for FaultyStore {
+    async fn create_dir_all(&self, path: &Path) -> std::io::Result<()> {
+        FileSystemStore.create_dir_all(path).await
+    }
+
+    async fn exists(&self, path: &Path) -> bool {
+        FileSystemStore.exists(path).await
+    }
+
+    async fn modified(&self, path: &Path) -> Option<chrono::DateTime<chrono::Utc>> {
+        FileSystemStore.modified(path).await
+    }
+
+    async fn set_modified(
+        &self,
+        path: &Path,
+        modified: chrono::DateTime<chrono::Utc>,
+    ) -> std::io::Result<()> {
+        FileSystemStore.set_modified(path, modified).await
+    }
+
+    async fn size(&self, path: &Path) -> Option<u64> {
+        FileSystemStore.size(path).await
+    }
+
+    async fn read_to_string(&self, path: &Path) -> std::io::Result<String> {
+        FileSystemStore.read_to_string(path).await
+    }
+
+    async fn open(&self, path: &Path) -> std::io::Result<Box<dyn AsyncRead + Send + Unpin>> {
+        FileSystemStore.open(path).await
+    }
+
+    async fn write(&self, path: &Path, content: &[u8]) -> std::io::Result<()> {
+        FileSystemStore.write(path, content).await
+    }
+
+    async fn create(&self, path: &Path) -> std::io::Result<Box<dyn AsyncWrite + Send + Unpin>> {
+        FileSystemStore.create(path).await
+    }
+
+    async fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        (self.rename)(from, to)
+    }
+
+    async fn remove(&self, path: &Path) -> std::io::Result<()> {
+        FileSystemStore.remove(path).await
+    }
+
+    async fn remove_dir_all(&self, path: &Path) -> std::io::Result<()> {
+        FileSystemStore.remove_dir_all(path).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::helpers::clock::MockClock;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_filesystem_store() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let store = FileSystemStore;
+
+        let dir = temp_dir.path().join("a").join("b");
+        store
+            .create_dir_all(&dir)
+            .await
+            .expect("directory to be created");
+
+        let temp_path = dir.join("file.txt.tmp");
+        let path = dir.join("file.txt");
+        assert!(!store.exists(&path).await);
+        assert!(store.modified(&path).await.is_none());
+
+        let mut file = store.create(&temp_path).await.expect("file to be created");
+        file.write_all(b"hello").await.unwrap();
+        file.shutdown().await.unwrap();
+        drop(file);
+
+        store
+            .rename(&temp_path, &path)
+            .await
+            .expect("file to be renamed");
+        assert!(!store.exists(&temp_path).await);
+        assert!(store.exists(&path).await);
+        assert!(store.modified(&path).await.is_some());
+
+        let modified = "2024-01-01T12:00:00Z"
+            .parse::<chrono::DateTime<chrono::Utc>>()
+            .unwrap();
+        store
+            .set_modified(&path, modified)
+            .await
+            .expect("modification time to be set");
+        assert_eq!(store.modified(&path).await, Some(modified));
+        assert_eq!(store.read_to_string(&path).await.unwrap(), "hello");
+
+        let mut content = Vec::new();
+        store
+            .open(&path)
+            .await
+            .expect("file to be opened")
+            .read_to_end(&mut content)
+            .await
+            .unwrap();
+        assert_eq!(content, b"hello");
+
+        store.write(&path, b"world").await.unwrap();
+        assert_eq!(store.read_to_string(&path).await.unwrap(), "world");
+        assert_eq!(store.size(&path).await, Some(5));
+
+        store.write(&dir.join("other.txt"), b"abc").await.unwrap();
+        assert_eq!(store.size(&dir).await, Some(8));
+        assert_eq!(store.size(temp_dir.path()).await, Some(8));
+        assert_eq!(store.size(&dir.join("missing.txt")).await, None);
+        store.remove(&dir.join("other.txt")).await.unwrap();
+
+        store.remove(&path).await.expect("file to be removed");
+        assert!(!store.exists(&path).await);
+
+        store
+            .remove_dir_all(&temp_dir.path().join("a"))
+            .await
+            .expect("directory to be removed");
+        assert!(!store.exists(&dir).await);
+    }
+
+    /// A [`FaultyStore`] whose renames fail with the given errors before they are passed through
+    /// to the filesystem, along with the number of renames which were attempted.
+    fn flaky_store(failures: &[std::io::ErrorKind]) -> (FaultyStore, Arc<AtomicUsize>) {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let failures = std::sync::Mutex::new(failures.iter().rev().copied().collect::<Vec<_>>());
+        let store = FaultyStore::new({
+            let attempts = attempts.clone();
+            move |from, to| {
+                attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                match failures.lock().unwrap().pop() {
+                    Some(kind) => Err(kind.into()),
+                    None => std::fs::rename(from, to),
+                }
+            }
+        });
+
+        (store, attempts)
+    }
+
+    #[rstest::rstest]
+    #[case(&[], 0, true, 1)]
+    #[case(&[std::io::ErrorKind::Interrupted], 3, true, 2)]
+    #[case(&[std::io::ErrorKind::Interrupted, std::io::ErrorKind::TimedOut], 3, true, 3)]
+    #[case(&[std::io::ErrorKind::Interrupted], 0, false, 1)]
+    #[case(&[std::io::ErrorKind::Interrupted; 4], 3, false, 4)]
+    #[case(&[std::io::ErrorKind::PermissionDenied], 3, false, 1)]
+    #[tokio::test]
+    async fn test_retrying_store(
+        #[case] failures: &[std::io::ErrorKind],
+        #[case] retries: usize,
+        #[case] succeeds: bool,
+        #[case] attempts: usize,
+    ) {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let from = temp_dir.path().join("file.txt.tmp");
+        let to = temp_dir.path().join("file.txt");
+        std::fs::write(&from, "hello").unwrap();
+
+        let (flaky, rename_attempts) = flaky_store(failures);
+        let store = RetryingStore::new(Arc::new(flaky), retries).with_backoff(Duration::ZERO);
+
+        assert_eq!(store.rename(&from, &to).await.is_ok(), succeeds);
+        assert_eq!(store.exists(&to).await, succeeds);
+        assert_eq!(
+            rename_attempts.load(std::sync::atomic::Ordering::SeqCst),
+            attempts
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retrying_store_backoff() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let from = temp_dir.path().join("file.txt.tmp");
+        let to = temp_dir.path().join("file.txt");
+        std::fs::write(&from, "hello").unwrap();
+
+        let clock = Arc::new(MockClock::default());
+        let (flaky, _) = flaky_store(&[std::io::ErrorKind::Interrupted; 3]);
+        let store = RetryingStore::new(Arc::new(flaky), 3)
+            .with_backoff(Duration::from_millis(100))
+            .with_clock(clock.clone());
+
+        store
+            .rename(&from, &to)
+            .await
+            .expect("the rename to succeed once retried");
+        assert_eq!(
+            clock.slept(),
+            vec![
+                Duration::from_millis(100),
+                Duration::from_millis(200),
+                Duration::from_millis(400)
+            ]
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[rstest::rstest]
+    #[case(std::io::Error::from_raw_os_error(4), true)] // EINTR
+    #[case(std::io::Error::from_raw_os_error(116), true)] // ESTALE
+    #[case(std::io::Error::from_raw_os_error(16), true)] // EBUSY
+    #[case(std::io::Error::from_raw_os_error(13), false)] // EACCES
+    #[case(std::io::Error::from_raw_os_error(2), false)] // ENOENT
+    fn test_is_transient(#[case] error: std::io::Error, #[case] transient: bool) {
+        assert_eq!(is_transient(&error), transient);
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_size_ignores_symlink_targets() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let outside = tempfile::tempdir().expect("a temporary directory");
+        std::fs::write(outside.path().join("large.bin"), vec![0u8; 4096]).unwrap();
+
+        std::fs::write(temp_dir.path().join("file.txt"), "hello").unwrap();
+        std::os::unix::fs::symlink(outside.path(), temp_dir.path().join("link")).unwrap();
+
+        let size = FileSystemStore
+            .size(temp_dir.path())
+            .await
+            .expect("the size to be known");
+        assert!(
+            size < 4096,
+            "the content of symlinked directories should not be counted (got {size} bytes)"
+        );
+    }
+}
@@ -1,25 +1,527 @@
-use std::fmt::{Debug, Display};
+use std::{
+    fmt::{Debug, Display},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
 
 use serde::Deserialize;
+use tracing_batteries::prelude::*;
+
+use crate::errors;
 
 #[allow(dead_code)]
-#[derive(Default, Clone, Deserialize, PartialEq)]
+#[derive(Default, Clone, Deserialize)]
+#[serde(try_from = "CredentialsConfig")]
 pub enum Credentials {
     #[default]
     None,
-    Token(String),
+    /// A token, along with the source it can be read from again to refresh it (if it was loaded
+    /// from a token file or keyring).
+    Token {
+        value: String,
+        source: Option<Arc<Refresher>>,
+    },
+    /// A username and password, along with the source that the username can be read from again to
+    /// refresh it (if it is a token which is used for basic authentication).
     UsernamePassword {
         username: String,
         password: String,
+        source: Option<Arc<Refresher>>,
     },
+    /// A list of credentials which are tried in order, falling back to the next when a request
+    /// is rejected with a `403 Forbidden` or `404 Not Found` response.
+    Chain(Vec<Credentials>),
+    /// A list of host patterns and the credentials which should be used for requests to hosts
+    /// matching them, resolved using [`Credentials::for_url`] when each request is made. The
+    /// first matching pattern is used, and requests to hosts which don't match any of them are
+    /// sent without credentials.
+    PerHost(Vec<(String, Credentials)>),
+}
+
+/// Credentials may either be provided as a single [`CredentialsSource`], or as a list of them
+/// which are tried in order.
+enum CredentialsConfig {
+    Single(CredentialsSource),
+    Chain(Vec<CredentialsSource>),
+}
+
+impl<'de> Deserialize<'de> for CredentialsConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de::value::{EnumAccessDeserializer, MapAccessDeserializer, StrDeserializer};
+
+        struct ConfigVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for ConfigVisitor {
+            type Value = CredentialsConfig;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "credentials, or a list of credentials")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                CredentialsSource::deserialize(StrDeserializer::new(v))
+                    .map(CredentialsConfig::Single)
+            }
+
+            fn visit_enum<A: serde::de::EnumAccess<'de>>(
+                self,
+                data: A,
+            ) -> Result<Self::Value, A::Error> {
+                CredentialsSource::deserialize(EnumAccessDeserializer::new(data))
+                    .map(CredentialsConfig::Single)
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                map: A,
+            ) -> Result<Self::Value, A::Error> {
+                CredentialsSource::deserialize(MapAccessDeserializer::new(map))
+                    .map(CredentialsConfig::Single)
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<Self::Value, A::Error> {
+                let mut chain = Vec::new();
+                while let Some(source) = seq.next_element::<CredentialsSource>()? {
+                    chain.push(source);
+                }
+
+                Ok(CredentialsConfig::Chain(chain))
+            }
+        }
+
+        deserializer.deserialize_any(ConfigVisitor)
+    }
+}
+
+/// The forms in which credentials may be provided in a configuration file, some of
+/// which are resolved into their in-memory [`Credentials`] form when the config is loaded.
+#[derive(Clone, Deserialize, schemars::JsonSchema)]
+enum CredentialsSource {
+    None,
+    Token(String),
+    UsernamePassword { username: String, password: String },
+    TokenFile(PathBuf),
+    Keyring { service: String, account: String },
+    PerHost(Vec<HostCredentials>),
+}
+
+/// The credentials which should be used for requests to hosts matching the given pattern.
+#[derive(Clone, Deserialize, schemars::JsonSchema)]
+struct HostCredentials {
+    /// The host which these credentials should be used for (like `github.com`, which also matches
+    /// its subdomains), `*.` followed by a domain to only match its subdomains, or `*` to match
+    /// any host.
+    host: String,
+    credentials: Credentials,
+}
+
+impl schemars::JsonSchema for Credentials {
+    fn schema_name() -> String {
+        "Credentials".to_string()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        // Most editors drop YAML tags like `!Token` before validating a document, leaving only
+        // the tagged value, so we accept those values as well as the single-key map form.
+        let tagged_values = serde_json::json!([
+            { "type": "string" },
+            {
+                "type": "object",
+                "required": ["username", "password"],
+                "properties": {
+                    "username": { "type": "string" },
+                    "password": { "type": "string" }
+                }
+            },
+            {
+                "type": "object",
+                "required": ["service", "account"],
+                "properties": {
+                    "service": { "type": "string" },
+                    "account": { "type": "string" }
+                }
+            }
+        ]);
+
+        let mut any_of = vec![gen.subschema_for::<CredentialsSource>()];
+        any_of.extend(
+            serde_json::from_value::<Vec<schemars::schema::Schema>>(tagged_values)
+                .unwrap_or_default(),
+        );
+
+        let single = schemars::schema::SchemaObject {
+            subschemas: Some(Box::new(schemars::schema::SubschemaValidation {
+                any_of: Some(any_of.clone()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        any_of.push(
+            schemars::schema::SchemaObject {
+                instance_type: Some(schemars::schema::InstanceType::Array.into()),
+                array: Some(Box::new(schemars::schema::ArrayValidation {
+                    items: Some(schemars::schema::Schema::from(single).into()),
+                    ..Default::default()
+                })),
+                ..Default::default()
+            }
+            .into(),
+        );
+
+        schemars::schema::SchemaObject {
+            subschemas: Some(Box::new(schemars::schema::SubschemaValidation {
+                any_of: Some(any_of),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+/// Reads a token again from the source it was loaded from (like a token file which is rotated
+/// by another process), so that it can be refreshed if it expires part way through a backup run.
+pub struct Refresher {
+    source: CredentialsSource,
+    latest: Mutex<String>,
+}
+
+impl Refresher {
+    fn new(token: &str, source: CredentialsSource) -> Arc<Self> {
+        Arc::new(Refresher {
+            source,
+            latest: Mutex::new(token.to_string()),
+        })
+    }
+
+    fn refresh(&self, token: &str) -> Option<String> {
+        let mut latest = self.latest.lock().unwrap();
+        if *latest != token {
+            // Another request has already refreshed this token, so we can use its replacement.
+            return Some(latest.clone());
+        }
+
+        match Credentials::resolve(self.source.clone()) {
+            Ok(Credentials::Token { value, .. }) if value != *latest => {
+                *latest = value.clone();
+                Some(value)
+            }
+            Ok(_) => None,
+            Err(e) => {
+                warn!("Unable to refresh your credentials: {}", e);
+                None
+            }
+        }
+    }
+}
+
+impl Display for Refresher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.source {
+            CredentialsSource::TokenFile(path) => write!(f, "{}", path.display()),
+            CredentialsSource::Keyring { service, account } => {
+                write!(f, "keyring entry {service}/{account}")
+            }
+            _ => write!(f, "your configuration file"),
+        }
+    }
+}
+
+impl Credentials {
+    /// A token which was not loaded from a source that can be refreshed.
+    pub fn token<S: Into<String>>(value: S) -> Self {
+        Credentials::Token {
+            value: value.into(),
+            source: None,
+        }
+    }
+
+    /// A username and password which were not loaded from a source that can be refreshed.
+    pub fn username_password<U: Into<String>, P: Into<String>>(username: U, password: P) -> Self {
+        Credentials::UsernamePassword {
+            username: username.into(),
+            password: password.into(),
+            source: None,
+        }
+    }
+
+    /// Selects the credentials which should be used for a request to the given URL, resolving
+    /// any per-host credentials using the URL's host.
+    pub fn for_url(&self, url: &str) -> Credentials {
+        match self {
+            Credentials::PerHost(hosts) => {
+                let Some(host) = reqwest::Url::parse(url)
+                    .ok()
+                    .and_then(|u| u.host_str().map(|h| h.to_ascii_lowercase()))
+                else {
+                    return Credentials::None;
+                };
+
+                hosts
+                    .iter()
+                    .find(|(pattern, _)| Self::host_matches(pattern, &host))
+                    .map(|(_, creds)| creds.for_url(url))
+                    .unwrap_or_default()
+            }
+            Credentials::Chain(chain) => {
+                Credentials::Chain(chain.iter().map(|c| c.for_url(url)).collect())
+            }
+            creds => creds.clone(),
+        }
+    }
+
+    fn host_matches(pattern: &str, host: &str) -> bool {
+        let pattern = pattern.trim().to_ascii_lowercase();
+        match pattern.strip_prefix('*') {
+            Some("") => true,
+            Some(suffix) => suffix.starts_with('.') && host.ends_with(suffix),
+            None => host == pattern || host.ends_with(&format!(".{pattern}")),
+        }
+    }
+
+    fn validate_host_pattern(pattern: &str) -> Result<(), errors::Error> {
+        let domain = match pattern.trim() {
+            "*" => return Ok(()),
+            pattern => pattern.strip_prefix("*.").unwrap_or(pattern),
+        };
+
+        if domain.is_empty()
+            || !domain
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+        {
+            return Err(errors::user(
+                &format!("The host pattern '{pattern}' in your per-host credentials is not valid."),
+                "Use a hostname (like 'github.com', which also matches its subdomains), '*.' followed by a domain (to only match its subdomains), or '*' to match any host.",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Attempts to obtain replacements for these credentials after they have been rejected, by
+    /// reading them again from the token file or keyring that they were loaded from.
+    ///
+    /// Returns `None` if the credentials can't be refreshed (for example, because they were written
+    /// directly into the config file) or haven't changed, in which case the original error should
+    /// be reported.
+    pub fn refresh(&self) -> Option<Credentials> {
+        match self {
+            Credentials::None
+            | Credentials::Token { source: None, .. }
+            | Credentials::UsernamePassword { source: None, .. } => None,
+            Credentials::Token {
+                value,
+                source: Some(source),
+            } => source.refresh(value).map(|value| Credentials::Token {
+                value,
+                source: Some(source.clone()),
+            }),
+            Credentials::Chain(chain) => {
+                let refreshed = chain.iter().map(|c| c.refresh()).collect::<Vec<_>>();
+                if refreshed.iter().all(|c| c.is_none()) {
+                    return None;
+                }
+
+                Some(Credentials::Chain(
+                    chain
+                        .iter()
+                        .zip(refreshed)
+                        .map(|(c, refreshed)| refreshed.unwrap_or_else(|| c.clone()))
+                        .collect(),
+                ))
+            }
+            Credentials::PerHost(hosts) => {
+                let refreshed = hosts.iter().map(|(_, c)| c.refresh()).collect::<Vec<_>>();
+                if refreshed.iter().all(|c| c.is_none()) {
+                    return None;
+                }
+
+                Some(Credentials::PerHost(
+                    hosts
+                        .iter()
+                        .zip(refreshed)
+                        .map(|((host, c), refreshed)| {
+                            (host.clone(), refreshed.unwrap_or_else(|| c.clone()))
+                        })
+                        .collect(),
+                ))
+            }
+            Credentials::UsernamePassword {
+                username,
+                password,
+                source: Some(source),
+            } => source
+                .refresh(username)
+                .map(|username| Credentials::UsernamePassword {
+                    username,
+                    password: password.clone(),
+                    source: Some(source.clone()),
+                }),
+        }
+    }
+
+    fn resolve(source: CredentialsSource) -> Result<Self, errors::Error> {
+        match source {
+            CredentialsSource::None => Ok(Credentials::None),
+            CredentialsSource::Token(token) => Ok(Credentials::token(token)),
+            CredentialsSource::UsernamePassword { username, password } => {
+                Ok(Credentials::username_password(username, password))
+            }
+            CredentialsSource::TokenFile(path) => {
+                let token = std::fs::read_to_string(&path).map_err(|e| {
+                    errors::user_with_internal(
+                        &format!(
+                            "Unable to read the token file '{}' referenced in your credentials.",
+                            path.display()
+                        ),
+                        "Make sure that the token file exists and that you have permission to read it.",
+                        e,
+                    )
+                })?;
+
+                match token.trim() {
+                    "" => Err(errors::user(
+                        &format!(
+                            "The token file '{}' referenced in your credentials is empty.",
+                            path.display()
+                        ),
+                        "Make sure that you have written your access token into the token file.",
+                    )),
+                    token => Ok(Credentials::token(token)),
+                }
+            }
+            CredentialsSource::Keyring { service, account } => {
+                let token = keyring::Entry::new(&service, &account)
+                    .and_then(|entry| entry.get_password())
+                    .map_err(|e| {
+                        errors::user_with_internal(
+                            &format!(
+                                "Unable to read the credentials for account '{account}' of service '{service}' from your OS keyring."
+                            ),
+                            "Make sure that the credentials have been stored in your keyring and that GitHub Backup has permission to access them.",
+                            e,
+                        )
+                    })?;
+
+                Ok(Credentials::token(token))
+            }
+            CredentialsSource::PerHost(hosts) => {
+                for entry in hosts.iter() {
+                    Self::validate_host_pattern(&entry.host)?;
+                }
+
+                Ok(Credentials::PerHost(
+                    hosts
+                        .into_iter()
+                        .map(|entry| (entry.host, entry.credentials))
+                        .collect(),
+                ))
+            }
+        }
+    }
+}
+
+impl TryFrom<CredentialsConfig> for Credentials {
+    type Error = errors::Error;
+
+    fn try_from(config: CredentialsConfig) -> Result<Self, Self::Error> {
+        match config {
+            CredentialsConfig::Single(source) => Credentials::try_from(source),
+            CredentialsConfig::Chain(sources) if sources.is_empty() => Err(errors::user(
+                "Your list of credentials is empty.",
+                "Provide at least one set of credentials in the list, or remove the 'credentials' field to make requests without them.",
+            )),
+            CredentialsConfig::Chain(sources) => sources
+                .into_iter()
+                .map(Credentials::try_from)
+                .collect::<Result<Vec<_>, _>>()
+                .map(Credentials::Chain),
+        }
+    }
+}
+
+impl TryFrom<CredentialsSource> for Credentials {
+    type Error = errors::Error;
+
+    fn try_from(source: CredentialsSource) -> Result<Self, Self::Error> {
+        match (&source, Credentials::resolve(source.clone())?) {
+            (
+                CredentialsSource::TokenFile(..) | CredentialsSource::Keyring { .. },
+                Credentials::Token { value, .. },
+            ) => Ok(Credentials::Token {
+                source: Some(Refresher::new(&value, source)),
+                value,
+            }),
+            (_, credentials) => Ok(credentials),
+        }
+    }
+}
+
+/// Credentials are compared without their sources, since those only determine how they are
+/// refreshed.
+impl PartialEq for Credentials {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Credentials::None, Credentials::None) => true,
+            (Credentials::Token { value: a, .. }, Credentials::Token { value: b, .. }) => a == b,
+            (
+                Credentials::UsernamePassword {
+                    username: a_username,
+                    password: a_password,
+                    ..
+                },
+                Credentials::UsernamePassword {
+                    username: b_username,
+                    password: b_password,
+                    ..
+                },
+            ) => a_username == b_username && a_password == b_password,
+            (Credentials::Chain(a), Credentials::Chain(b)) => a == b,
+            (Credentials::PerHost(a), Credentials::PerHost(b)) => a == b,
+            _ => false,
+        }
+    }
 }
 
 impl Display for Credentials {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Credentials::None => write!(f, "No credentials"),
-            Credentials::Token(..) => write!(f, "Token"),
+            Credentials::Token {
+                source: Some(source),
+                ..
+            } => write!(f, "Token from {source}"),
+            Credentials::Token { .. } => write!(f, "Token"),
+            Credentials::UsernamePassword {
+                source: Some(source),
+                ..
+            } => write!(f, "Username+Password from {source}"),
             Credentials::UsernamePassword { .. } => write!(f, "Username+Password"),
+            Credentials::Chain(chain) => write!(
+                f,
+                "{}",
+                chain
+                    .iter()
+                    .map(|c| c.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", then ")
+            ),
+            Credentials::PerHost(hosts) => write!(
+                f,
+                "{}",
+                hosts
+                    .iter()
+                    .map(|(host, c)| format!("{c} for {host}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
         }
     }
 }
@@ -28,8 +530,10 @@ impl Debug for Credentials {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Credentials::None => write!(f, "None"),
-            Credentials::Token(..) => write!(f, "Token"),
+            Credentials::Token { .. } => write!(f, "Token"),
             Credentials::UsernamePassword { .. } => write!(f, "UsernamePassword"),
+            Credentials::Chain(chain) => write!(f, "Chain({chain:?})"),
+            Credentials::PerHost(hosts) => write!(f, "PerHost({hosts:?})"),
         }
     }
 }
@@ -41,17 +545,205 @@ mod tests {
 
     #[rstest]
     #[case::none(Credentials::None, "No credentials")]
-    #[case::token(Credentials::Token("token".to_string()), "Token")]
-    #[case::username_password(Credentials::UsernamePassword { username: "admin".to_string(), password: "pass".to_string() }, "Username+Password")]
+    #[case::token(Credentials::token("token"), "Token")]
+    #[case::username_password(Credentials::username_password("admin", "pass"), "Username+Password")]
+    #[case::chain(Credentials::Chain(vec![Credentials::token("token"), Credentials::None]), "Token, then No credentials")]
+    #[case::per_host(Credentials::PerHost(vec![("github.com".to_string(), Credentials::token("token")), ("*".to_string(), Credentials::None)]), "Token for github.com, No credentials for *")]
     fn test_display(#[case] credentials: Credentials, #[case] expected: &str) {
         assert_eq!(format!("{}", credentials), expected);
     }
 
+    #[test]
+    fn test_display_source() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let path = temp_dir.path().join("token");
+        std::fs::write(&path, "token").unwrap();
+
+        let credentials: Credentials =
+            serde_yaml::from_str(&format!("[!TokenFile {}, None]", path.display()))
+                .expect("the credentials to be loaded");
+        assert_eq!(
+            credentials.to_string(),
+            format!("Token from {}, then No credentials", path.display())
+        );
+    }
+
+    #[test]
+    fn test_deserialize_empty_chain() {
+        serde_yaml::from_str::<Credentials>("[]").expect_err("an empty list to be rejected");
+    }
+
     #[rstest]
     #[case::none(Credentials::None, "None")]
-    #[case::token(Credentials::Token("token".to_string()), "Token")]
-    #[case::username_password(Credentials::UsernamePassword { username: "admin".to_string(), password: "pass".to_string() }, "UsernamePassword")]
+    #[case::token(Credentials::token("token"), "Token")]
+    #[case::username_password(Credentials::username_password("admin", "pass"), "UsernamePassword")]
+    #[case::chain(Credentials::Chain(vec![Credentials::token("token"), Credentials::None]), "Chain([Token, None])")]
+    #[case::per_host(Credentials::PerHost(vec![("github.com".to_string(), Credentials::token("token"))]), "PerHost([(\"github.com\", Token)])")]
     fn test_debug(#[case] credentials: Credentials, #[case] expected: &str) {
         assert_eq!(format!("{:?}", credentials), expected);
     }
+
+    #[rstest]
+    #[case::tagged("!Token abc", Credentials::token("abc"))]
+    #[case::map("{ Token: abc }", Credentials::token("abc"))]
+    #[case::unit("None", Credentials::None)]
+    #[case::chain(
+        "[!Token abc, !UsernamePassword { username: admin, password: pass }]",
+        Credentials::Chain(vec![
+            Credentials::token("abc"),
+            Credentials::username_password("admin", "pass"),
+        ])
+    )]
+    #[case::per_host(
+        "!PerHost [{ host: github.com, credentials: !Token abc }, { host: '*.example.com', credentials: [!Token def, None] }]",
+        Credentials::PerHost(vec![
+            ("github.com".to_string(), Credentials::token("abc")),
+            ("*.example.com".to_string(), Credentials::Chain(vec![Credentials::token("def"), Credentials::None])),
+        ])
+    )]
+    fn test_deserialize(#[case] yaml: &str, #[case] expected: Credentials) {
+        let credentials: Credentials =
+            serde_yaml::from_str(yaml).expect("the credentials to be loaded");
+        assert_eq!(credentials, expected);
+    }
+
+    #[rstest]
+    #[case::empty("''")]
+    #[case::wildcard_suffix("'github.*'")]
+    #[case::url("'https://github.com'")]
+    #[case::port("'github.com:443'")]
+    fn test_deserialize_invalid_host(#[case] host: &str) {
+        serde_yaml::from_str::<Credentials>(&format!(
+            "!PerHost [{{ host: {host}, credentials: !Token abc }}]"
+        ))
+        .expect_err("the credentials should fail to load");
+    }
+
+    #[rstest]
+    #[case::exact("https://api.github.com/user", Credentials::token("api"))]
+    #[case::subdomain("https://uploads.github.com/file", Credentials::token("public"))]
+    #[case::case_insensitive("https://GitHub.com/org/repo.git", Credentials::token("public"))]
+    #[case::wildcard(
+        "https://ghes.example.com/api/v3/user",
+        Credentials::token("enterprise")
+    )]
+    #[case::wildcard_root("https://example.com/file", Credentials::None)]
+    #[case::suffix_only("https://notgithub.com/file", Credentials::None)]
+    #[case::no_match("https://gitlab.com/org/repo.git", Credentials::None)]
+    #[case::invalid_url("not a url", Credentials::None)]
+    fn test_for_url(#[case] url: &str, #[case] expected: Credentials) {
+        let credentials = Credentials::PerHost(vec![
+            ("api.github.com".to_string(), Credentials::token("api")),
+            ("github.com".to_string(), Credentials::token("public")),
+            (
+                "*.example.com".to_string(),
+                Credentials::token("enterprise"),
+            ),
+        ]);
+
+        assert_eq!(credentials.for_url(url), expected);
+        assert_eq!(
+            Credentials::token("token").for_url(url),
+            Credentials::token("token"),
+            "credentials which aren't per-host should be used for every host"
+        );
+    }
+
+    #[test]
+    fn test_deserialize_token_file() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let token_path = temp_dir.path().join("token");
+        std::fs::write(&token_path, "my_token\n").expect("the token file to be written");
+
+        let credentials: Credentials =
+            serde_yaml::from_str(&format!("!TokenFile {}", token_path.display()))
+                .expect("the credentials to be loaded");
+        assert_eq!(credentials, Credentials::token("my_token"));
+    }
+
+    #[rstest]
+    #[case::missing("missing", None)]
+    #[case::empty("empty", Some(""))]
+    fn test_deserialize_token_file_errors(#[case] name: &str, #[case] content: Option<&str>) {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let token_path = temp_dir.path().join(name);
+        if let Some(content) = content {
+            std::fs::write(&token_path, content).expect("the token file to be written");
+        }
+
+        let err =
+            serde_yaml::from_str::<Credentials>(&format!("!TokenFile {}", token_path.display()))
+                .expect_err("the credentials should fail to load");
+        assert!(
+            err.to_string().contains(&token_path.display().to_string()),
+            "the error should reference the token file"
+        );
+    }
+
+    #[test]
+    fn test_refresh_token_file() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let token_path = temp_dir.path().join("token");
+        std::fs::write(&token_path, "refresh_token_1").expect("the token file to be written");
+
+        let credentials: Credentials =
+            serde_yaml::from_str(&format!("!TokenFile {}", token_path.display()))
+                .expect("the credentials to be loaded");
+        assert_eq!(
+            credentials.refresh(),
+            None,
+            "credentials which haven't changed should not be refreshed"
+        );
+
+        std::fs::write(&token_path, "refresh_token_2").expect("the token file to be written");
+        assert_eq!(
+            credentials.refresh(),
+            Some(Credentials::token("refresh_token_2"))
+        );
+        assert_eq!(
+            credentials.refresh().and_then(|c| c.refresh()),
+            None,
+            "refreshed credentials should be refreshed from the same source"
+        );
+
+        let Credentials::Token { source, .. } = &credentials else {
+            panic!("the credentials should be a token");
+        };
+        assert_eq!(
+            Credentials::UsernamePassword {
+                username: "refresh_token_1".to_string(),
+                password: "".to_string(),
+                source: source.clone(),
+            }
+            .refresh(),
+            Some(Credentials::username_password("refresh_token_2", "")),
+            "tokens used as a username should also be refreshed"
+        );
+        assert_eq!(
+            Credentials::username_password("refresh_token_1", "").refresh(),
+            None,
+            "credentials which weren't loaded from the token file should not be refreshed"
+        );
+    }
+
+    #[test]
+    fn test_refresh_static_token() {
+        let credentials: Credentials =
+            serde_yaml::from_str("!Token static_token").expect("the credentials to be loaded");
+        assert_eq!(credentials.refresh(), None);
+        assert_eq!(Credentials::None.refresh(), None);
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let token_path = temp_dir.path().join("token");
+        std::fs::write(&token_path, "static_token").expect("the token file to be written");
+        let _file_credentials: Credentials =
+            serde_yaml::from_str(&format!("!TokenFile {}", token_path.display()))
+                .expect("the credentials to be loaded");
+        std::fs::write(&token_path, "rotated_token").expect("the token file to be written");
+        assert_eq!(
+            credentials.refresh(),
+            None,
+            "static tokens should never be replaced by tokens from other sources"
+        );
+    }
 }
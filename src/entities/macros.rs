@@ -7,6 +7,8 @@ macro_rules! entity {
             pub name: String,
             $(pub $rfield: $rtype,)*
             $(pub $field: $type,)*
+            pub path: Option<std::path::PathBuf>,
+            pub fingerprint: Option<String>,
             pub metadata: $crate::entities::Metadata,
         }
 
@@ -18,6 +20,8 @@ macro_rules! entity {
                     name: name.into(),
                     $($rfield: $rfield.into(),)*
                     $($field: Default::default(),)*
+                    path: None,
+                    fingerprint: None,
                     metadata: Default::default(),
                 }
             }
@@ -29,6 +33,20 @@ macro_rules! entity {
             }
             )*
 
+            /// Stores the entity's backup at the given path (relative to the policy's `to` directory),
+            /// rather than at a path derived from its name.
+            pub fn with_path<P: Into<std::path::PathBuf>>(mut self, path: P) -> Self {
+                self.path = Some(path.into());
+                self
+            }
+
+            /// Identifies the current version of the entity (using data provided by its source), allowing
+            /// its backup to be skipped when it hasn't changed since it was last backed up.
+            pub fn with_fingerprint<F: Into<String>>(mut self, fingerprint: F) -> Self {
+                self.fingerprint = Some(fingerprint.into());
+                self
+            }
+
             pub fn with_metadata<V: Into<FilterValue>>(mut self, key: &'static str, value: V) -> Self {
                 self.metadata.insert(key, value.into());
                 self
@@ -44,6 +62,22 @@ macro_rules! entity {
             fn name(&self) -> &str {
                 &self.name
             }
+
+            fn target_path(&self) -> std::path::PathBuf {
+                self.path.clone().unwrap_or_else(|| self.name.as_str().into())
+            }
+
+            fn fingerprint(&self) -> Option<&str> {
+                self.fingerprint.as_deref()
+            }
+
+            fn metadata(&self) -> &$crate::entities::Metadata {
+                &self.metadata
+            }
+
+            fn metadata_mut(&mut self) -> &mut $crate::entities::Metadata {
+                &mut self.metadata
+            }
         }
 
         impl crate::Filterable for $name {
@@ -71,13 +105,13 @@ mod tests {
     #[test]
     fn test_entity() {
         let entity = TestEntity::new("test", "http://example.com")
-            .with_credentials(Credentials::Token("test".to_string()))
+            .with_credentials(Credentials::token("test"))
             .with_metadata("test", "test")
             .with_metadata("test2", 1);
 
         assert_eq!(entity.name, "test");
         assert_eq!(entity.url, "http://example.com");
-        assert_eq!(entity.credentials, Credentials::Token("test".to_string()));
+        assert_eq!(entity.credentials, Credentials::token("test"));
 
         assert_eq!(entity.get("test"), FilterValue::String("test".to_string()));
         assert_eq!(entity.get("test2"), FilterValue::Number(1_f64));
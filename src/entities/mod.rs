@@ -13,6 +13,20 @@ pub trait BackupEntity: std::fmt::Display + Filterable {
     fn target_path(&self) -> std::path::PathBuf {
         self.name().into()
     }
+
+    /// Identifies the current version of the entity, if its source is able to provide one. When
+    /// an entity's fingerprint matches the one recorded after its last successful backup, the
+    /// backup can be skipped without contacting the remote.
+    fn fingerprint(&self) -> Option<&str> {
+        None
+    }
+
+    /// Provides access to the entity's metadata, which is used when evaluating filters.
+    fn metadata(&self) -> &Metadata;
+
+    /// Provides access to the entity's metadata, so that it can be enriched after the entity
+    /// has been loaded from its source.
+    fn metadata_mut(&mut self) -> &mut Metadata;
 }
 
 #[derive(Default, Clone, Debug)]
@@ -29,18 +43,139 @@ impl Metadata {
             .cloned()
             .unwrap_or(FilterValue::Null)
     }
+
+    /// Iterates over the entries in this metadata, ordered by their keys.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &FilterValue)> {
+        let mut entries = self
+            .0
+            .iter()
+            .map(|(k, v)| (k.into_inner(), v))
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|(k, _)| k.to_ascii_lowercase());
+        entries.into_iter()
+    }
+
+    /// Describes each of the properties in this metadata (and their values), one per line, to
+    /// help when writing filters.
+    pub fn dump(&self) -> String {
+        self.iter()
+            .map(|(key, value)| format!("  - {key} = {value}\n"))
+            .collect()
+    }
 }
 
 pub trait MetadataSource {
     fn inject_metadata(&self, metadata: &mut Metadata);
 }
 
+/// An additional file which should be written alongside an entity's backup.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Attachment {
+    pub name: String,
+    pub content: Vec<u8>,
+}
+
+impl Attachment {
+    pub fn json<N: Into<String>, T: serde::Serialize>(
+        name: N,
+        value: &T,
+    ) -> Result<Self, crate::Error> {
+        let name = name.into();
+        let content = serde_json::to_vec_pretty(value).map_err(|e| {
+            crate::errors::system_with_internal(
+                &format!("Unable to serialize the '{name}' attachment as JSON."),
+                "Please report this issue to us on GitHub.",
+                e,
+            )
+        })?;
+
+        Ok(Self { name, content })
+    }
+}
+
 entity!(HttpFile(url: U => String) {
     with_credentials => credentials: Credentials,
     with_last_modified => last_modified: Option<chrono::DateTime<chrono::Utc>>,
     with_content_type => content_type: Option<String>,
+    with_min_bytes => min_bytes: Option<u64>,
+    with_max_bytes => max_bytes: Option<u64>,
+    with_sha_index => sha_index: bool,
+    with_temp_dir => temp_dir: Option<std::path::PathBuf>,
+    with_ext_from_content_type => ext_from_content_type: bool,
+    with_verify_after_write => verify_after_write: bool,
 });
 
+/// The format in which a Git repository's backup is stored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GitRepoFormat {
+    /// A bare repository which is updated in place on each backup.
+    #[default]
+    Repository,
+    /// A single, portable `.bundle` file containing all of the repository's refs.
+    Bundle,
+}
+
+impl std::str::FromStr for GitRepoFormat {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "repository" => Ok(GitRepoFormat::Repository),
+            "bundle" => Ok(GitRepoFormat::Bundle),
+            _ => Err(crate::errors::user(
+                &format!("The backup format '{s}' is not supported."),
+                "Use either 'repository' or 'bundle' as the backup format.",
+            )),
+        }
+    }
+}
+
 entity!(GitRepo(clone_url: U => String, refspecs: R => Option<Vec<String>>) {
     with_credentials => credentials: Credentials,
+    with_attachments => attachments: Vec<Attachment>,
+    with_sparse => sparse: Option<Vec<String>>,
+    with_mirror => mirror: bool,
+    with_format => format: GitRepoFormat,
+    with_worktree => worktree: bool,
+    with_repack => repack: Option<std::time::Duration>,
+    with_artifact_kind => artifact_kind: Option<String>,
+    with_page_guard => page_guard: Option<crate::helpers::checkpoint::PageGuard>,
 });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::github::GitHubRepo;
+
+    #[test]
+    fn test_metadata_dump() {
+        let repos: Vec<GitHubRepo> =
+            serde_json::from_str(include_str!("../../tests/data/github.repos.0.json")).unwrap();
+        let repo = GitRepo::new(
+            repos[0].full_name.as_str(),
+            repos[0].clone_url.as_str(),
+            None,
+        )
+        .with_metadata_source(&repos[0]);
+
+        let keys = repo.metadata().iter().map(|(k, _)| k).collect::<Vec<_>>();
+        let mut sorted = keys.clone();
+        sorted.sort_by_key(|k| k.to_ascii_lowercase());
+        assert_eq!(keys, sorted, "the metadata should be ordered by key");
+
+        for key in [
+            "repo.name",
+            "repo.fullname",
+            "repo.owner",
+            "repo.fork",
+            "repo.private",
+        ] {
+            assert!(keys.contains(&key), "the metadata should include {key}");
+        }
+
+        let dump = repo.metadata().dump();
+        assert_eq!(dump.lines().count(), keys.len());
+        assert!(dump.contains(&format!("  - repo.fullname = \"{}\"\n", repos[0].full_name)));
+        assert!(dump.contains(&format!("  - repo.fork = {}\n", repos[0].fork)));
+    }
+}
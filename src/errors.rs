@@ -4,11 +4,124 @@ use std::convert;
 
 use reqwest::StatusCode;
 
+pub(crate) const AUTHENTICATION_FAILED: &str =
+    "The access token you have provided was rejected by the GitHub API.";
+pub(crate) const CONNECTION_FAILED: &str =
+    "We could not connect to the remote server to make a web request.";
+pub(crate) const REQUEST_TIMED_OUT: &str = "We timed out making a web request.";
+
+/// A coarse classification of errors, used to report the outcome of a run to automation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorCategory {
+    Authentication,
+    Network,
+    User,
+    System,
+}
+
+impl ErrorCategory {
+    /// Determines the category of an error, using the category it (or one of its causes) was
+    /// constructed with, or whether it is a user or system error otherwise.
+    pub fn of(err: &Error) -> Self {
+        std::iter::successors(Some(err as &(dyn std::error::Error + 'static)), |e| {
+            e.source()
+        })
+        .find_map(|e| e.downcast_ref::<Categorized>())
+        .map(|c| c.category)
+        .unwrap_or(if err.is_user() {
+            ErrorCategory::User
+        } else {
+            ErrorCategory::System
+        })
+    }
+}
+
+/// The internal error attached to errors which should be reported with a specific [`ErrorCategory`],
+/// wrapping the error (if any) which caused them.
+#[derive(Debug)]
+pub struct Categorized {
+    category: ErrorCategory,
+    internal: Option<Box<dyn std::error::Error + Send + Sync>>,
+}
+
+impl Categorized {
+    pub fn new(category: ErrorCategory) -> Self {
+        Self {
+            category,
+            internal: None,
+        }
+    }
+
+    pub fn with_internal<T: Into<Box<dyn std::error::Error + Send + Sync>>>(
+        self,
+        internal: T,
+    ) -> Self {
+        Self {
+            internal: Some(internal.into()),
+            ..self
+        }
+    }
+}
+
+impl std::fmt::Display for Categorized {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.internal, self.category) {
+            (Some(internal), _) => write!(f, "{internal}"),
+            (None, ErrorCategory::Authentication) => write!(f, "Authentication failed"),
+            (None, ErrorCategory::Network) => write!(f, "Network request failed"),
+            (None, _) => write!(f, "Request failed"),
+        }
+    }
+}
+
+impl std::error::Error for Categorized {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.internal.as_deref().map(|e| e as _)
+    }
+}
+
+/// Builds an error for credentials which were rejected by a remote service.
+pub fn authentication(description: &str, advice: &str) -> Error {
+    user_with_internal(
+        description,
+        advice,
+        Categorized::new(ErrorCategory::Authentication),
+    )
+}
+
+/// Builds an error for credentials which were rejected by a remote service, along with the
+/// error which was reported when they were rejected.
+pub fn authentication_with_internal<T: Into<Box<dyn std::error::Error + Send + Sync>>>(
+    description: &str,
+    advice: &str,
+    internal: T,
+) -> Error {
+    user_with_internal(
+        description,
+        advice,
+        Categorized::new(ErrorCategory::Authentication).with_internal(internal),
+    )
+}
+
+/// Builds an error for a network failure which prevented us from reaching a remote service.
+pub fn network_with_internal<T: Into<Box<dyn std::error::Error + Send + Sync>>>(
+    description: &str,
+    advice: &str,
+    internal: T,
+) -> Error {
+    user_with_internal(
+        description,
+        advice,
+        Categorized::new(ErrorCategory::Network).with_internal(internal),
+    )
+}
+
 impl convert::From<reqwest::Error> for Error {
     fn from(err: reqwest::Error) -> Self {
         if err.is_connect() {
-            user_with_internal(
-                "We could not connect to the remote server to make a web request.",
+            network_with_internal(
+                CONNECTION_FAILED,
                 "Make sure that your internet connection is working correctly and the service is not blocked by your firewall.",
                 err)
         } else if err.is_decode() {
@@ -23,9 +136,9 @@ impl convert::From<reqwest::Error> for Error {
                 err)
         } else if err.is_timeout() {
             system_with_internal(
-                "We timed out making a web request.",
+                REQUEST_TIMED_OUT,
                 "This is likely due to a problem with the remote server or your internet connection, please try again later and report the problem to us on GitHub if the issue persists.", 
-                err)
+                Categorized::new(ErrorCategory::Network).with_internal(err))
         } else {
             system_with_internal(
                 "An internal error occurred which we could not recover from.",
@@ -41,7 +154,7 @@ impl convert::From<reqwest::Response> for Error {
             StatusCode::NOT_FOUND => user(
                 "We received a 404 Not Found response when sending a web request.",
                 "Please check that you're using the correct options and try again. If the problem persists, please open an issue with us on GitHub."),
-            StatusCode::UNAUTHORIZED => user(
+            StatusCode::UNAUTHORIZED => authentication(
                 "We received an 401 Unauthorized response when sending a web request.",
                 "This probably means that you have not configured your access tokens correctly, please check your configuration and try again."),
             StatusCode::FORBIDDEN => user(
@@ -0,0 +1,169 @@
+use crate::{
+    errors::{Error, ErrorCategory},
+    statistics::SummaryStatistics,
+};
+
+/// The exit codes which GitHub Backup uses to report the outcome of a run.
+///
+/// | Code | Meaning                                                     |
+/// |------|-------------------------------------------------------------|
+/// | 0    | All backups completed successfully.                         |
+/// | 1    | An unexpected (internal) error prevented the run.           |
+/// | 2    | The run completed, but some backups failed.                 |
+/// | 3    | Authentication with the remote service failed.              |
+/// | 4    | The configuration (or command line arguments) are invalid.  |
+/// | 5    | A network error prevented backups from completing.          |
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitCode {
+    Success = 0,
+    Failure = 1,
+    PartialSuccess = 2,
+    AuthenticationFailed = 3,
+    InvalidConfig = 4,
+    NetworkError = 5,
+}
+
+impl ExitCode {
+    /// Determines the exit code for an error which prevented the run from completing.
+    pub fn from_error(error: &Error) -> Self {
+        match ErrorCategory::of(error) {
+            ErrorCategory::Authentication => ExitCode::AuthenticationFailed,
+            ErrorCategory::Network => ExitCode::NetworkError,
+            ErrorCategory::User => ExitCode::InvalidConfig,
+            ErrorCategory::System => ExitCode::Failure,
+        }
+    }
+
+    /// Determines the exit code for a command line which couldn't be parsed. Requests for the
+    /// help or version information are reported through the same path, but are successful.
+    pub fn from_usage_error(error: &clap::Error) -> Self {
+        match error.kind() {
+            clap::error::ErrorKind::DisplayHelp | clap::error::ErrorKind::DisplayVersion => {
+                ExitCode::Success
+            }
+            _ => ExitCode::InvalidConfig,
+        }
+    }
+
+    /// Determines the exit code for a run which completed, based on the errors it encountered.
+    ///
+    /// Authentication failures take precedence, since they usually affect every backup, followed
+    /// by runs in which every error was a network error. Any other errors indicate partial success.
+    pub fn from_statistics(stats: &SummaryStatistics) -> Self {
+        match stats.errors() {
+            0 => ExitCode::Success,
+            _ if stats.authentication_errors() > 0 => ExitCode::AuthenticationFailed,
+            errors if stats.network_errors() == errors => ExitCode::NetworkError,
+            _ => ExitCode::PartialSuccess,
+        }
+    }
+
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::errors;
+
+    use super::*;
+
+    #[rstest]
+    #[case(
+        errors::authentication(errors::AUTHENTICATION_FAILED, "Check your token."),
+        ExitCode::AuthenticationFailed
+    )]
+    #[case(
+        errors::authentication("The download was rejected.", "Check your token."),
+        ExitCode::AuthenticationFailed
+    )]
+    #[case(
+        errors::user_with_cause(
+            "The backup failed.",
+            "Try again.",
+            errors::authentication(errors::AUTHENTICATION_FAILED, "Check your token.")
+        ),
+        ExitCode::AuthenticationFailed
+    )]
+    #[case(
+        errors::user(errors::AUTHENTICATION_FAILED, "Check your token."),
+        ExitCode::InvalidConfig
+    )]
+    #[case(
+        errors::network_with_internal(
+            errors::CONNECTION_FAILED,
+            "Check your connection.",
+            "refused"
+        ),
+        ExitCode::NetworkError
+    )]
+    #[case(
+        errors::system_with_internal(
+            errors::REQUEST_TIMED_OUT,
+            "Try again later.",
+            errors::Categorized::new(ErrorCategory::Network)
+        ),
+        ExitCode::NetworkError
+    )]
+    #[case(
+        errors::user("Failed to read the config file.", "Check the path."),
+        ExitCode::InvalidConfig
+    )]
+    #[case(errors::system("Something broke.", "Report it."), ExitCode::Failure)]
+    fn test_from_error(#[case] error: Error, #[case] expected: ExitCode) {
+        assert_eq!(ExitCode::from_error(&error), expected);
+    }
+
+    #[rstest]
+    #[case(vec![], ExitCode::Success)]
+    #[case(vec![errors::user("Something failed.", "Try again.")], ExitCode::PartialSuccess)]
+    #[case(vec![errors::network_with_internal(errors::CONNECTION_FAILED, "Check your connection.", "refused")], ExitCode::NetworkError)]
+    #[case(vec![
+        errors::network_with_internal(errors::CONNECTION_FAILED, "Check your connection.", "refused"),
+        errors::user("Something failed.", "Try again."),
+    ], ExitCode::PartialSuccess)]
+    #[case(vec![
+        errors::network_with_internal(errors::CONNECTION_FAILED, "Check your connection.", "refused"),
+        errors::authentication(errors::AUTHENTICATION_FAILED, "Check your token."),
+    ], ExitCode::AuthenticationFailed)]
+    fn test_from_statistics(#[case] errors: Vec<Error>, #[case] expected: ExitCode) {
+        let stats = SummaryStatistics::default();
+        for error in errors.iter() {
+            stats.record_error(error);
+        }
+
+        assert_eq!(ExitCode::from_statistics(&stats), expected);
+    }
+
+    #[rstest]
+    #[case(&["github-backup", "--help"], ExitCode::Success)]
+    #[case(&["github-backup", "--version"], ExitCode::Success)]
+    #[case(&["github-backup", "--not-a-flag"], ExitCode::InvalidConfig)]
+    #[case(&["github-backup", "--concurrency", "many"], ExitCode::InvalidConfig)]
+    fn test_from_usage_error(#[case] args: &[&str], #[case] expected: ExitCode) {
+        let error = clap::Command::new("github-backup")
+            .version("1.0.0")
+            .arg(
+                clap::Arg::new("concurrency")
+                    .long("concurrency")
+                    .value_parser(clap::value_parser!(usize)),
+            )
+            .try_get_matches_from(args)
+            .expect_err("the arguments should not be parsed");
+
+        assert_eq!(ExitCode::from_usage_error(&error), expected);
+    }
+
+    #[test]
+    fn test_codes() {
+        assert_eq!(ExitCode::Success.code(), 0);
+        assert_eq!(ExitCode::Failure.code(), 1);
+        assert_eq!(ExitCode::PartialSuccess.code(), 2);
+        assert_eq!(ExitCode::AuthenticationFailed.code(), 3);
+        assert_eq!(ExitCode::InvalidConfig.code(), 4);
+        assert_eq!(ExitCode::NetworkError.code(), 5);
+    }
+}
@@ -12,6 +12,8 @@ pub enum Expr<'a> {
     Binary(Box<Expr<'a>>, Token<'a>, Box<Expr<'a>>),
     Logical(Box<Expr<'a>>, Token<'a>, Box<Expr<'a>>),
     Unary(Token<'a>, Box<Expr<'a>>),
+    Call(Token<'a>, Vec<Expr<'a>>),
+    Between(Box<Expr<'a>>, Token<'a>, Box<Expr<'a>>, Box<Expr<'a>>),
 }
 
 pub trait ExprVisitor<T> {
@@ -22,6 +24,10 @@ pub trait ExprVisitor<T> {
             Expr::Binary(left, operator, right) => self.visit_binary(left, operator, right),
             Expr::Logical(left, operator, right) => self.visit_logical(left, operator, right),
             Expr::Unary(operator, right) => self.visit_unary(operator, right),
+            Expr::Call(function, args) => self.visit_call(function, args),
+            Expr::Between(value, operator, low, high) => {
+                self.visit_between(value, operator, low, high)
+            }
         }
     }
 
@@ -30,6 +36,8 @@ pub trait ExprVisitor<T> {
     fn visit_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) -> T;
     fn visit_logical(&mut self, left: &Expr, operator: &Token, right: &Expr) -> T;
     fn visit_unary(&mut self, operator: &Token, right: &Expr) -> T;
+    fn visit_call(&mut self, function: &Token, args: &[Expr]) -> T;
+    fn visit_between(&mut self, value: &Expr, operator: &Token, low: &Expr, high: &Expr) -> T;
 }
 
 impl Display for Expr<'_> {
@@ -78,6 +86,31 @@ impl<'a, 'b> ExprVisitor<std::fmt::Result> for ExprPrinter<'a, 'b> {
         write!(self.0, "{}", operator.lexeme())?;
         self.visit_expr(right)
     }
+
+    fn visit_call(&mut self, function: &Token, args: &[Expr]) -> std::fmt::Result {
+        write!(self.0, "({}", function.lexeme())?;
+        for arg in args {
+            write!(self.0, " ")?;
+            self.visit_expr(arg)?;
+        }
+        write!(self.0, ")")
+    }
+
+    fn visit_between(
+        &mut self,
+        value: &Expr,
+        operator: &Token,
+        low: &Expr,
+        high: &Expr,
+    ) -> std::fmt::Result {
+        write!(self.0, "({operator} ")?;
+        self.visit_expr(value)?;
+        write!(self.0, " ")?;
+        self.visit_expr(low)?;
+        write!(self.0, " ")?;
+        self.visit_expr(high)?;
+        write!(self.0, ")")
+    }
 }
 
 #[cfg(test)]
@@ -107,6 +140,25 @@ mod tests {
         ),
         "(&& \"value\" (property test))"
     )]
+    #[case(
+        Expr::Call(
+            Token::Property(Loc::new(1, 1), "matches_any"),
+            vec![
+                Expr::Property("test"),
+                Expr::Literal(vec!["^a".into(), "b$".into()].into()),
+            ],
+        ),
+        "(matches_any (property test) [\"^a\", \"b$\"])"
+    )]
+    #[case(
+        Expr::Between(
+            Box::new(Expr::Property("test")),
+            Token::Between(Loc::new(1, 6)),
+            Box::new(Expr::Literal(1.0.into())),
+            Box::new(Expr::Literal(5.0.into())),
+        ),
+        "(between (property test) 1 5)"
+    )]
     fn expression_visualization(#[case] expr: Expr<'_>, #[case] view: &str) {
         assert_eq!(view, format!("{expr}"));
     }
@@ -1,3 +1,7 @@
+use std::collections::HashMap;
+
+use crate::errors;
+
 use super::{
     expr::{Expr, ExprVisitor},
     token::Token,
@@ -6,11 +10,54 @@ use super::{
 
 pub struct FilterContext<'a, T: Filterable> {
     target: &'a T,
+    strict: bool,
+    error: Option<errors::Error>,
+    /// Property values retrieved during this evaluation, so that filters which reference
+    /// the same property several times only retrieve it from the target once.
+    properties: HashMap<String, FilterValue>,
 }
 
 impl<'a, T: Filterable> FilterContext<'a, T> {
     pub fn new(target: &'a T) -> Self {
-        Self { target }
+        Self {
+            target,
+            strict: false,
+            error: None,
+            properties: HashMap::new(),
+        }
+    }
+
+    /// Enables strict mode, in which type mismatches that would otherwise
+    /// silently evaluate to `false` are reported as errors.
+    pub fn with_strict(self, strict: bool) -> Self {
+        Self { strict, ..self }
+    }
+
+    /// Retrieves the first error encountered while evaluating the filter, if any.
+    pub fn take_error(&mut self) -> Option<errors::Error> {
+        self.error.take()
+    }
+
+    fn check_containment(&mut self, container: &FilterValue, item: &FilterValue, operator: &Token) {
+        if !self.strict || self.error.is_some() || !container.is_type_mismatch(item) {
+            return;
+        }
+
+        self.error = Some(errors::user(
+            &format!(
+                "The '{}' operator at {} cannot check whether a {} ({}) contains a {} ({}), so it will never match.",
+                operator.lexeme(),
+                operator.location(),
+                container.type_name(),
+                container,
+                item.type_name(),
+                item,
+            ),
+            &format!(
+                "Make sure that both sides of the '{}' operator have compatible types, or run without --strict-filters to ignore this mismatch.",
+                operator.lexeme()
+            ),
+        ));
     }
 }
 
@@ -20,7 +67,13 @@ impl<'a, T: Filterable> ExprVisitor<FilterValue> for FilterContext<'a, T> {
     }
 
     fn visit_property(&mut self, name: &str) -> FilterValue {
-        self.target.get(name).clone()
+        if let Some(value) = self.properties.get(name) {
+            return value.clone();
+        }
+
+        let value = self.target.get(name);
+        self.properties.insert(name.to_string(), value.clone());
+        value
     }
 
     fn visit_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) -> FilterValue {
@@ -29,8 +82,14 @@ impl<'a, T: Filterable> ExprVisitor<FilterValue> for FilterContext<'a, T> {
         match operator {
             Token::Equals(..) => (left == right).into(),
             Token::NotEquals(..) => (left != right).into(),
-            Token::Contains(..) => left.contains(&right).into(),
-            Token::In(..) => right.contains(&left).into(),
+            Token::Contains(..) => {
+                self.check_containment(&left, &right, operator);
+                left.contains(&right).into()
+            }
+            Token::In(..) => {
+                self.check_containment(&right, &left, operator);
+                right.contains(&left).into()
+            }
             Token::StartsWith(..) => left.startswith(&right).into(),
             Token::EndsWith(..) => left.endswith(&right).into(),
             Token::GreaterThan(..) => (left > right).into(),
@@ -64,9 +123,115 @@ impl<'a, T: Filterable> ExprVisitor<FilterValue> for FilterContext<'a, T> {
                     true.into()
                 }
             }
+            Token::Exists(..) => (right != FilterValue::Null).into(),
             token => unreachable!("Encountered an unexpected unary operator '{token}'"),
         }
     }
+
+    fn visit_call(&mut self, function: &Token, args: &[Expr]) -> FilterValue {
+        match (function.lexeme(), args) {
+            ("matches_any", [value, patterns]) => {
+                let value = self.visit_expr(value);
+                let FilterValue::String(value) = value else {
+                    if self.strict && self.error.is_none() {
+                        self.error = Some(errors::user(
+                            &format!(
+                                "The 'matches_any' function at {} can only match strings, but was given a {} ({}), so it will never match.",
+                                function.location(),
+                                value.type_name(),
+                                value,
+                            ),
+                            "Make sure that you pass a string property to 'matches_any', or run without --strict-filters to ignore this mismatch.",
+                        ));
+                    }
+
+                    return false.into();
+                };
+
+                let FilterValue::Tuple(patterns) = self.visit_expr(patterns) else {
+                    return false.into();
+                };
+
+                // The patterns are validated when the filter is parsed, so any which fail to
+                // compile here can safely be ignored.
+                patterns
+                    .iter()
+                    .filter_map(|pattern| match pattern {
+                        FilterValue::String(pattern) => regex::Regex::new(pattern).ok(),
+                        _ => None,
+                    })
+                    .any(|pattern| pattern.is_match(&value))
+                    .into()
+            }
+            ("semver", [value, constraint]) => {
+                let value = self.visit_expr(value);
+                let FilterValue::String(value) = value else {
+                    if self.strict && self.error.is_none() {
+                        self.error = Some(errors::user(
+                            &format!(
+                                "The 'semver' function at {} can only compare strings, but was given a {} ({}), so it will never match.",
+                                function.location(),
+                                value.type_name(),
+                                value,
+                            ),
+                            "Make sure that you pass a string property to 'semver', or run without --strict-filters to ignore this mismatch.",
+                        ));
+                    }
+
+                    return false.into();
+                };
+
+                let FilterValue::String(constraint) = self.visit_expr(constraint) else {
+                    return false.into();
+                };
+
+                // Tags are commonly prefixed with a 'v' (like v1.2.3), which isn't part of the version
+                // itself, while values which aren't valid versions simply never match.
+                let version = value
+                    .strip_prefix(['v', 'V'])
+                    .unwrap_or(&value)
+                    .parse::<semver::Version>();
+                match (version, semver::VersionReq::parse(&constraint)) {
+                    (Ok(version), Ok(constraint)) => constraint.matches(&version).into(),
+                    _ => false.into(),
+                }
+            }
+            (name, _) => unreachable!("Encountered an unexpected function '{name}'"),
+        }
+    }
+
+    fn visit_between(
+        &mut self,
+        value: &Expr,
+        operator: &Token,
+        low: &Expr,
+        high: &Expr,
+    ) -> FilterValue {
+        let value = self.visit_expr(value);
+        let low = self.visit_expr(low);
+        let high = self.visit_expr(high);
+
+        match value.between(&low, &high) {
+            Some(within) => within.into(),
+            None => {
+                if self.strict && self.error.is_none() && value != FilterValue::Null {
+                    self.error = Some(errors::user(
+                        &format!(
+                            "The '{}' operator at {} can only compare numbers or dates, but was asked whether {} is between {} and {}, so it will never match.",
+                            operator.lexeme(),
+                            operator.location(),
+                            value,
+                            low,
+                            high,
+                        ),
+                        "Make sure that the value and both bounds of your range are numbers (or dates), or run without --strict-filters to ignore this mismatch.",
+                    ));
+                }
+
+                false.into()
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -90,6 +255,41 @@ mod tests {
             let result = context.visit_expr(&expr);
             result.is_truthy()
         }
+
+        pub fn matches_strict(filter: &str) -> Result<bool, errors::Error> {
+            use crate::filter::parser::Parser;
+
+            let tokens = Scanner::new(filter);
+            let expr = Parser::parse(tokens).expect("parse the filter");
+            let mut context = FilterContext::new(&Self).with_strict(true);
+            let result = context.visit_expr(&expr);
+            match context.take_error() {
+                Some(err) => Err(err),
+                None => Ok(result.is_truthy()),
+            }
+        }
+    }
+
+    /// A [`Filterable`] which counts the number of times each of its properties is retrieved.
+    #[derive(Default)]
+    struct CountingFilterable {
+        gets: std::cell::RefCell<HashMap<String, usize>>,
+    }
+
+    impl Filterable for CountingFilterable {
+        fn get(&self, property: &str) -> FilterValue {
+            *self
+                .gets
+                .borrow_mut()
+                .entry(property.to_string())
+                .or_default() += 1;
+
+            match property {
+                "repo.fork" => false.into(),
+                "repo.name" => "github-backup".into(),
+                _ => FilterValue::Null,
+            }
+        }
     }
 
     impl Filterable for TestFilterable {
@@ -98,8 +298,21 @@ mod tests {
                 "boolean" => true.into(),
                 "string" => "Alice".into(),
                 "number" => 1.into(),
+                "date" => FilterValue::parse_date("2023-03-15T10:00:00Z")
+                    .unwrap()
+                    .into(),
                 "null" => FilterValue::Null,
                 "tuple" => vec![true.into(), false.into()].into(),
+                "tag" => "2023-01-01-nightly".into(),
+                "version" => "2.1.0".into(),
+                "prefixed_version" => "v1.0.5".into(),
+                "prerelease_version" => "3.0.0-beta.2".into(),
+                "size" => (5 * 1024 * 1024).into(),
+                "forks" => 3.into(),
+                "stargazers" => 5.into(),
+                "owner" => "Alice".into(),
+                "empty_string" => "".into(),
+                "empty_tuple" => FilterValue::Tuple(vec![]),
                 _ => FilterValue::Null,
             }
         }
@@ -177,6 +390,28 @@ mod tests {
         assert_eq!(TestFilterable::matches(filter), expected);
     }
 
+    #[rstest]
+    #[case("exists boolean", true)]
+    #[case("exists string", true)]
+    #[case("exists number", true)]
+    #[case("exists tuple", true)]
+    #[case("exists empty_string", true)]
+    #[case("exists empty_tuple", true)]
+    #[case("exists owner", true)]
+    #[case("exists unknown", false)]
+    #[case("has string", true)]
+    #[case("has unknown", false)]
+    #[case("!exists unknown", true)]
+    #[case("exists string && !has unknown", true)]
+    #[case("exists empty_string == (empty_string != null)", true)]
+    fn exists(#[case] filter: &str, #[case] expected: bool) {
+        assert_eq!(TestFilterable::matches(filter), expected);
+        assert_eq!(
+            TestFilterable::matches_strict(filter).expect("no errors in strict mode"),
+            expected
+        );
+    }
+
     #[rstest]
     #[case("string contains \"Ali\"", true)]
     #[case("string contains \"Bob\"", false)]
@@ -202,11 +437,211 @@ mod tests {
         assert_eq!(TestFilterable::matches(filter), expected);
     }
 
+    #[rstest]
+    #[case("matches_any(string, [\"^Al\"])", true)]
+    #[case("matches_any(string, [\"^Bob\", \"ice$\"])", true)]
+    #[case("matches_any(string, [\"^Bob\", \"^alice$\"])", false)]
+    #[case("matches_any(string, [\"(?i)^alice$\"])", true)]
+    #[case("matches_any(string, [])", false)]
+    #[case("matches_any(number, [\".*\"])", false)]
+    #[case("matches_any(null, [\".*\"])", false)]
+    #[case("!matches_any(string, [\"^Bob\"])", true)]
+    #[case("boolean && matches_any(string, [\"l\"])", true)]
+    fn matches_any(#[case] filter: &str, #[case] expected: bool) {
+        assert_eq!(TestFilterable::matches(filter), expected);
+    }
+
+    #[rstest]
+    #[case("semver(version, \">=2.0.0\")", true)]
+    #[case("semver(version, \">=2.0.0, <3.0.0\")", true)]
+    #[case("semver(version, \"^2.1\")", true)]
+    #[case("semver(version, \">=3.0.0\")", false)]
+    #[case("semver(prefixed_version, \"~1.0\")", true)]
+    #[case("semver(prefixed_version, \">=2.0.0\")", false)]
+    #[case("semver(prerelease_version, \">=2.0.0\")", false)]
+    #[case("semver(prerelease_version, \">=3.0.0-beta.1\")", true)]
+    #[case("semver(string, \">=0.0.0\")", false)]
+    #[case("semver(number, \">=0.0.0\")", false)]
+    #[case("!semver(string, \">=0.0.0\")", true)]
+    fn semver(#[case] filter: &str, #[case] expected: bool) {
+        assert_eq!(TestFilterable::matches(filter), expected);
+    }
+
+    #[rstest]
+    #[case("1KB == 1024", true)]
+    #[case("1.5KB == 1536", true)]
+    #[case("2B == 2", true)]
+    #[case("1kb == 1KiB", true)]
+    #[case("1GB == 1024MB", true)]
+    #[case("size == 5MB", true)]
+    #[case("size > 4MB", true)]
+    #[case("size > 5MB", false)]
+    #[case("size >= 5MB", true)]
+    #[case("size < 1.5GB", true)]
+    #[case("size <= 5120KB", true)]
+    #[case("size < 5119KB", false)]
+    #[case("size between 1MB and 10MB", true)]
+    #[case("null > 1MB", false)]
+    fn sizes(#[case] filter: &str, #[case] expected: bool) {
+        assert_eq!(TestFilterable::matches(filter), expected);
+    }
+
+    #[rstest]
+    #[case("stargazers > forks", true)]
+    #[case("stargazers < forks", false)]
+    #[case("forks >= forks", true)]
+    #[case("forks <= stargazers", true)]
+    #[case("stargazers == forks", false)]
+    #[case("stargazers != forks", true)]
+    #[case("string == owner", true)]
+    #[case("string != owner", false)]
+    #[case("string contains owner", true)]
+    #[case("string startswith owner", true)]
+    #[case("date == date", true)]
+    #[case("date > date", false)]
+    #[case("null == missing", true)]
+    #[case("number < null", false)]
+    fn property_comparisons(#[case] filter: &str, #[case] expected: bool) {
+        assert_eq!(TestFilterable::matches(filter), expected);
+    }
+
+    #[rstest]
+    #[case("number between 0 and 2", true)]
+    #[case("number between 1 and 2", true)]
+    #[case("number between 0 and 1", true)]
+    #[case("number between 1 and 1", true)]
+    #[case("number between 2 and 5", false)]
+    #[case("number between 0.5 and 0.9", false)]
+    #[case("number between 2 and 0", false)]
+    #[case("date between \"2023-01-01\" and \"2023-12-31\"", true)]
+    #[case(
+        "date between \"2023-03-15T10:00:00Z\" and \"2023-03-15T10:00:00Z\"",
+        true
+    )]
+    #[case("date between \"2024-01-01\" and \"2024-12-31\"", false)]
+    #[case("string between 0 and 10", false)]
+    #[case("number between \"a\" and \"z\"", false)]
+    #[case("null between 0 and 10", false)]
+    #[case("!(number between 2 and 5)", true)]
+    #[case("number between 0 and 2 && boolean", true)]
+    fn between(#[case] filter: &str, #[case] expected: bool) {
+        assert_eq!(TestFilterable::matches(filter), expected);
+    }
+
+    #[rstest]
+    #[case("number between 0 and 2", Some(true))]
+    #[case("null between 0 and 2", Some(false))]
+    #[case("string between 0 and 2", None)]
+    fn between_strict(#[case] filter: &str, #[case] expected: Option<bool>) {
+        match expected {
+            Some(expected) => assert_eq!(
+                TestFilterable::matches_strict(filter).expect("the filter to be evaluated"),
+                expected
+            ),
+            None => {
+                let err = TestFilterable::matches_strict(filter)
+                    .expect_err("the type mismatch to be reported");
+                assert!(
+                    err.to_string()
+                        .contains("can only compare numbers or dates"),
+                    "unexpected error: {err}"
+                );
+            }
+        }
+    }
+
+    #[rstest]
+    #[case("matches_any(string, [\"^Al\"])", Some(true))]
+    #[case("matches_any(string, [])", Some(false))]
+    #[case("matches_any(number, [\".*\"])", None)]
+    #[case("matches_any(tuple, [\".*\"])", None)]
+    fn matches_any_strict(#[case] filter: &str, #[case] expected: Option<bool>) {
+        match expected {
+            Some(expected) => assert_eq!(
+                TestFilterable::matches_strict(filter).expect("the filter to be evaluated"),
+                expected
+            ),
+            None => {
+                let err = TestFilterable::matches_strict(filter)
+                    .expect_err("the type mismatch to be reported");
+                assert!(
+                    err.to_string()
+                        .contains("'matches_any' function at line 1, column 1"),
+                    "the error should include the function's location: {err}"
+                );
+            }
+        }
+    }
+
+    #[rstest]
+    #[case("\"Ali\" in string", Some(true))]
+    #[case("true in tuple", Some(true))]
+    #[case("null in tuple", Some(false))]
+    #[case("string in null", Some(false))]
+    #[case("tuple contains true", Some(true))]
+    #[case("[80, 443] contains 80", Some(true))]
+    #[case("\"80\" in [80, 443]", None)]
+    #[case("[80, 443] contains \"80\"", None)]
+    #[case("number in 1", None)]
+    #[case("string contains 1", None)]
+    #[case("false && (string contains 1)", Some(false))]
+    fn in_strict(#[case] filter: &str, #[case] expected: Option<bool>) {
+        match expected {
+            Some(expected) => assert_eq!(
+                TestFilterable::matches_strict(filter).expect("the filter to be evaluated"),
+                expected
+            ),
+            None => {
+                let err = TestFilterable::matches_strict(filter)
+                    .expect_err("the type mismatch to be reported");
+                assert!(
+                    err.to_string().contains("line 1, column"),
+                    "the error should include the operator's location"
+                );
+                assert!(
+                    !TestFilterable::matches(filter),
+                    "lenient mode should evaluate the mismatch to false"
+                );
+            }
+        }
+    }
+
+    #[rstest]
+    #[case("date == \"2023-03-15T10:00:00Z\"", true)]
+    #[case("date == \"2023-03-15T12:00:00+02:00\"", true)]
+    #[case("date == \"2023-03-15\"", false)]
+    #[case("date != \"2023-03-15\"", true)]
+    #[case("date > \"2023-01-01\"", true)]
+    #[case("date > \"2023-03-15\"", true)]
+    #[case("date < \"2023-03-16\"", true)]
+    #[case("date >= \"2024-01-01\"", false)]
+    #[case("date <= \"2023-03-15 10:00:00\"", true)]
+    #[case("\"2023-01-01\" < \"2023-01-02\"", true)]
+    #[case("date == \"hello\"", false)]
+    #[case("date > null", false)]
+    #[case("string == \"2023-03-15\"", false)]
+    #[case("date startswith \"2023-03\"", true)]
+    #[case("tag startswith \"2023-01-01\"", true)]
+    #[case("tag contains \"2023-01\"", true)]
+    #[case("tag endswith \"01-nightly\"", true)]
+    #[case("tag == \"2023-01-01\"", false)]
+    #[case("tag > \"2022-12-31\"", true)]
+    fn dates(#[case] filter: &str, #[case] expected: bool) {
+        assert_eq!(TestFilterable::matches(filter), expected);
+    }
+
     #[rstest]
     #[case("string startswith \"Ali\"", true)]
     #[case("string startswith \"Bob\"", false)]
     #[case("string startswith null", false)]
     #[case("null startswith null", false)]
+    #[case("number startswith \"1\"", true)]
+    #[case("1024 startswith \"10\"", true)]
+    #[case("1024 startswith \"24\"", false)]
+    #[case("\"1024\" startswith 10", true)]
+    #[case("1.5 startswith \"1.\"", true)]
+    #[case("number startswith null", false)]
+    #[case("boolean startswith \"t\"", false)]
     fn startswith(#[case] filter: &str, #[case] expected: bool) {
         assert_eq!(TestFilterable::matches(filter), expected);
     }
@@ -216,10 +651,63 @@ mod tests {
     #[case("string endswith \"ob\"", false)]
     #[case("string endswith null", false)]
     #[case("null endswith null", false)]
+    #[case("1200 endswith \"00\"", true)]
+    #[case("1200 endswith \"12\"", false)]
+    #[case("\"v1200\" endswith 200", true)]
+    #[case("1200 endswith 0", true)]
+    #[case("1200.0 endswith \".0\"", false)]
+    #[case("null endswith \"null\"", false)]
     fn endswith(#[case] filter: &str, #[case] expected: bool) {
         assert_eq!(TestFilterable::matches(filter), expected);
     }
 
+    #[rstest]
+    #[case("repo.fork || !repo.fork", true, &[("repo.fork", 1)])]
+    #[case("!repo.fork && repo.fork == false && repo.name != null", true, &[("repo.fork", 1), ("repo.name", 1)])]
+    #[case("repo.fork && repo.name == \"github-backup\"", false, &[("repo.fork", 1)])]
+    #[case("(repo.name contains \"git\") && (repo.name startswith \"github\") && (repo.name endswith \"backup\")", true, &[("repo.name", 1)])]
+    fn property_caching(
+        #[case] filter: &str,
+        #[case] expected: bool,
+        #[case] gets: &[(&str, usize)],
+    ) {
+        use crate::filter::parser::Parser;
+
+        let expr = Parser::parse(Scanner::new(filter)).expect("parse the filter");
+        let target = CountingFilterable::default();
+        let result = FilterContext::new(&target).visit_expr(&expr);
+
+        assert_eq!(result.is_truthy(), expected);
+        assert_eq!(
+            *target.gets.borrow(),
+            gets.iter()
+                .map(|(k, v)| (k.to_string(), *v))
+                .collect::<HashMap<_, _>>(),
+            "each property should only be retrieved once per evaluation (and never if short-circuited)"
+        );
+    }
+
+    /// Each evaluation of a filter retrieves the properties it refers to exactly once, no matter
+    /// how many times they appear in the filter or how many times it has been evaluated before.
+    #[test]
+    fn repeated_property_access() {
+        use crate::filter::parser::Parser;
+
+        let expr = Parser::parse(Scanner::new(
+            "!repo.fork && (repo.fork == false || repo.fork == null) && !(repo.fork && repo.name == \"x\") && repo.name != \"\"",
+        ))
+        .expect("parse the filter");
+
+        let iterations = 100;
+        let target = CountingFilterable::default();
+        for _ in 0..iterations {
+            assert!(FilterContext::new(&target).visit_expr(&expr).is_truthy());
+        }
+
+        assert_eq!(target.gets.borrow()["repo.fork"], iterations);
+        assert_eq!(target.gets.borrow()["repo.name"], iterations);
+    }
+
     #[rstest]
     #[case("!boolean", false)]
     #[case("!string", false)]
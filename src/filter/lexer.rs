@@ -2,6 +2,28 @@ use crate::errors::{self, Error};
 
 use super::{location::Loc, token::Token};
 
+/// The units which may follow a number to describe a size, along with the number of bytes in each.
+/// Sizes use binary multiples, matching the units which GitHub reports repository sizes in.
+const SIZE_UNITS: &[(&str, u64)] = &[
+    ("b", 1),
+    ("kb", 1 << 10),
+    ("kib", 1 << 10),
+    ("mb", 1 << 20),
+    ("mib", 1 << 20),
+    ("gb", 1 << 30),
+    ("gib", 1 << 30),
+    ("tb", 1 << 40),
+    ("tib", 1 << 40),
+];
+
+/// The number of bytes represented by a size unit (like `MB`), ignoring its case.
+pub fn size_unit(unit: &str) -> Option<u64> {
+    SIZE_UNITS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(unit))
+        .map(|(_, bytes)| *bytes)
+}
+
 pub struct Scanner<'a> {
     source: &'a str,
     chars: std::iter::Peekable<std::str::CharIndices<'a>>,
@@ -92,10 +114,24 @@ impl<'a> Scanner<'a> {
             }
         }
 
-        Ok(Token::Number(
-            Loc::new(self.line, 1 + start - self.line_start),
-            &self.source[start..end + 1],
-        ))
+        let location = Loc::new(self.line, 1 + start - self.line_start);
+        let unit_length = self.advance_while_fn(|_, c| c.is_ascii_alphabetic());
+        if unit_length > 0 {
+            let unit = &self.source[end + 1..end + 1 + unit_length];
+            if size_unit(unit).is_none() {
+                return Err(errors::user(
+                    &format!("The number at {location} is followed by '{unit}', which is not a recognized size unit."),
+                    "Make sure that you use one of the supported size units (B, KB, MB, GB, or TB), like 'asset.size > 100MB'.",
+                ));
+            }
+
+            return Ok(Token::Size(
+                location,
+                &self.source[start..end + 1 + unit_length],
+            ));
+        }
+
+        Ok(Token::Number(location, &self.source[start..end + 1]))
     }
 
     fn read_identifier(&mut self, start: usize) -> Result<Token<'a>, Error> {
@@ -112,6 +148,8 @@ impl<'a> Scanner<'a> {
             "in" => Ok(Token::In(location)),
             "startswith" => Ok(Token::StartsWith(location)),
             "endswith" => Ok(Token::EndsWith(location)),
+            "between" => Ok(Token::Between(location)),
+            "exists" | "has" => Ok(Token::Exists(location)),
             lexeme => Ok(Token::Property(location, lexeme)),
         }
     }
@@ -330,6 +368,43 @@ mod tests {
         assert_sequence!("123.456", Token::Number(.., "123.456"));
     }
 
+    #[test]
+    fn test_size() {
+        assert_sequence!(
+            "5MB 1.5GB 100kb 2B 3TiB",
+            Token::Size(.., "5MB"),
+            Token::Size(.., "1.5GB"),
+            Token::Size(.., "100kb"),
+            Token::Size(.., "2B"),
+            Token::Size(.., "3TiB"),
+        );
+
+        assert_sequence!(
+            "asset.size > 100MB",
+            Token::Property(.., "asset.size"),
+            Token::GreaterThan(..),
+            Token::Size(
+                Loc {
+                    line: 1,
+                    column: 14
+                },
+                "100MB"
+            ),
+        );
+    }
+
+    #[test]
+    fn test_invalid_size() {
+        let err = Scanner::new("5XB")
+            .next()
+            .expect("a token")
+            .expect_err("the unit to be rejected");
+        assert!(
+            err.to_string().contains("not a recognized size unit"),
+            "{err}"
+        );
+    }
+
     #[test]
     fn test_identifiers() {
         assert_sequence!(
@@ -369,6 +444,22 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_exists() {
+        assert_sequence!(
+            "exists repo.homepage && !has release.body",
+            Token::Exists(Loc { line: 1, column: 1 }),
+            Token::Property(.., "repo.homepage"),
+            Token::And(..),
+            Token::Not(..),
+            Token::Exists(Loc {
+                line: 1,
+                column: 26
+            }),
+            Token::Property(.., "release.body"),
+        );
+    }
+
     #[test]
     fn test_location() {
         assert_sequence!(
@@ -16,6 +16,7 @@ pub struct Filter {
     #[allow(clippy::box_collection)]
     filter: Pin<Box<String>>,
     ast: Expr<'static>,
+    constant: Option<bool>,
 }
 
 impl Filter {
@@ -26,27 +27,124 @@ impl Filter {
 
         let tokens = crate::filter::lexer::Scanner::new(unsafe { filter_ptr.as_ref() });
         let ast = crate::filter::parser::Parser::parse(tokens.into_iter())?;
+        let constant = match &ast {
+            Expr::Literal(value) => Some(value.is_truthy()),
+            _ => None,
+        };
+
         Ok(Self {
             filter: pinned,
             ast,
+            constant,
         })
     }
 
+    /// Gets the result of this filter if it is a constant literal (like `true` or `false`), which
+    /// matches every object (or none of them) without needing to be evaluated.
+    pub fn constant(&self) -> Option<bool> {
+        self.constant
+    }
+
     pub fn matches<T: Filterable>(&self, target: &T) -> Result<bool, crate::Error> {
         Ok(FilterContext::new(target).visit_expr(&self.ast).is_truthy())
     }
 
+    /// Evaluates the filter against the target, returning an error if the filter
+    /// contains type mismatches which would otherwise silently evaluate to `false`.
+    pub fn matches_strict<T: Filterable>(&self, target: &T) -> Result<bool, crate::Error> {
+        let mut context = FilterContext::new(target).with_strict(true);
+        let result = context.visit_expr(&self.ast);
+        match context.take_error() {
+            Some(err) => Err(err),
+            None => Ok(result.is_truthy()),
+        }
+    }
+
+    /// Gets the names of the properties which are referenced by this filter.
+    pub fn properties(&self) -> Vec<String> {
+        let mut collector = PropertyCollector::default();
+        collector.visit_expr(&self.ast);
+        collector.0
+    }
+
+    /// Describes how this filter was parsed, along with the properties which are accessed (and
+    /// the result) when it is evaluated against an object which has no properties, to help when
+    /// debugging filters.
+    pub fn dump(&self) -> String {
+        let target = AccessRecorder::default();
+        let result = FilterContext::new(&target).visit_expr(&self.ast);
+
+        let mut dump = format!("Filter: {}\nAST:    {:?}\n", self.raw(), self.ast);
+        dump.push_str("Evaluated against an object with no properties:\n");
+        for property in target.0.borrow().iter() {
+            dump.push_str(&format!("  - accessed {property} (null)\n"));
+        }
+        dump.push_str(&format!("Result: {} ({})\n", result.is_truthy(), result));
+
+        dump
+    }
+
     /// Gets the raw filter expression which was used to construct this filter.
     pub fn raw(&self) -> &str {
         &self.filter
     }
 }
 
+/// A [`Filterable`] object which has no properties, but which records the properties that
+/// are accessed on it.
+#[derive(Default)]
+struct AccessRecorder(std::cell::RefCell<Vec<String>>);
+
+impl Filterable for AccessRecorder {
+    fn get(&self, key: &str) -> FilterValue {
+        self.0.borrow_mut().push(key.to_string());
+        FilterValue::Null
+    }
+}
+
+#[derive(Default)]
+struct PropertyCollector(Vec<String>);
+
+impl ExprVisitor<()> for PropertyCollector {
+    fn visit_literal(&mut self, _value: &FilterValue) {}
+
+    fn visit_property(&mut self, name: &str) {
+        self.0.push(name.to_string());
+    }
+
+    fn visit_binary(&mut self, left: &Expr, _operator: &token::Token, right: &Expr) {
+        self.visit_expr(left);
+        self.visit_expr(right);
+    }
+
+    fn visit_logical(&mut self, left: &Expr, _operator: &token::Token, right: &Expr) {
+        self.visit_expr(left);
+        self.visit_expr(right);
+    }
+
+    fn visit_unary(&mut self, _operator: &token::Token, right: &Expr) {
+        self.visit_expr(right);
+    }
+
+    fn visit_call(&mut self, _function: &token::Token, args: &[Expr]) {
+        for arg in args {
+            self.visit_expr(arg);
+        }
+    }
+
+    fn visit_between(&mut self, value: &Expr, _operator: &token::Token, low: &Expr, high: &Expr) {
+        self.visit_expr(value);
+        self.visit_expr(low);
+        self.visit_expr(high);
+    }
+}
+
 impl Default for Filter {
     fn default() -> Self {
         Self {
             filter: Box::pin("true".to_string()),
             ast: Expr::Literal(FilterValue::Bool(true)),
+            constant: Some(true),
         }
     }
 }
@@ -97,6 +195,28 @@ impl<'de> serde::Deserialize<'de> for Filter {
     }
 }
 
+impl schemars::JsonSchema for Filter {
+    fn schema_name() -> String {
+        "Filter".to_string()
+    }
+
+    fn json_schema(_gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        schemars::schema::SchemaObject {
+            instance_type: Some(schemars::schema::InstanceType::String.into()),
+            metadata: Some(Box::new(schemars::schema::Metadata {
+                description: Some(
+                    "A filter expression which determines whether an item should be backed up."
+                        .to_string(),
+                ),
+                examples: vec![serde_json::json!("repo.public && !repo.fork")],
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use rstest::rstest;
@@ -139,6 +259,52 @@ mod tests {
         }
     }
 
+    #[rstest]
+    #[case("true", &[])]
+    #[case("name == \"John Doe\"", &["name"])]
+    #[case("!backup.exists && (repo.size > 0 || \"x\" in repo.topics)", &["backup.exists", "repo.size", "repo.topics"])]
+    fn properties(#[case] filter: &str, #[case] expected: &[&str]) {
+        assert_eq!(
+            Filter::new(filter).expect("parse filter").properties(),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case("true", Some(true))]
+    #[case("false", Some(false))]
+    #[case("(false)", Some(false))]
+    #[case("\"\"", Some(false))]
+    #[case("!true", None)]
+    #[case("true && repo.fork", None)]
+    #[case("repo.fork", None)]
+    fn constant(#[case] filter: &str, #[case] expected: Option<bool>) {
+        assert_eq!(
+            Filter::new(filter).expect("parse filter").constant(),
+            expected
+        );
+    }
+
+    #[test]
+    fn constant_default() {
+        assert_eq!(Filter::default().constant(), Some(true));
+    }
+
+    #[test]
+    fn dump() {
+        let filter = Filter::new("!repo.fork && repo.name == \"test\" || repo.fork").unwrap();
+        assert_eq!(
+            filter.dump(),
+            r#"Filter: !repo.fork && repo.name == "test" || repo.fork
+AST:    (|| (&& !(property repo.fork) (== (property repo.name) "test")) (property repo.fork))
+Evaluated against an object with no properties:
+  - accessed repo.fork (null)
+  - accessed repo.name (null)
+Result: false (null)
+"#
+        );
+    }
+
     #[rstest]
     #[case("name == \"John Doe\"", true)]
     #[case("name != \"John Doe\"", false)]
@@ -198,6 +364,22 @@ mod tests {
         );
     }
 
+    #[rstest]
+    #[case("\"red\" in tags", Some(true))]
+    #[case("age in tags", None)]
+    #[case("tags contains 30", None)]
+    #[case("name contains 30", None)]
+    fn strict_filtering(#[case] filter: &str, #[case] matches: Option<bool>) {
+        let obj = TestObject::default();
+        let filter = Filter::new(filter).expect("parse filter");
+
+        assert_eq!(filter.matches_strict(&obj).ok(), matches);
+        assert_eq!(
+            filter.matches(&obj).expect("run filter"),
+            matches.unwrap_or_default()
+        );
+    }
+
     #[rstest]
     #[case("name == \"John Doe\" && age == 30", true)]
     #[case("name == \"John Doe\" && age == 31", false)]
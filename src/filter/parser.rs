@@ -76,7 +76,7 @@ impl<'a, I: Iterator<Item = Result<Token<'a>, Error>>> Parser<'a, I> {
     }
 
     fn comparison(&mut self) -> Result<Expr<'a>, Error> {
-        let mut expr = self.unary()?;
+        let mut expr = self.range()?;
 
         if matches!(
             self.tokens.peek(),
@@ -90,20 +90,63 @@ impl<'a, I: Iterator<Item = Result<Token<'a>, Error>>> Parser<'a, I> {
                 | Some(Ok(Token::SmallerEqual(..)))
         ) {
             let token = self.tokens.next().unwrap().unwrap();
-            let right = self.unary()?;
+            let right = self.range()?;
             expr = Expr::Binary(Box::new(expr), token, Box::new(right));
         }
 
         Ok(expr)
     }
 
+    fn range(&mut self) -> Result<Expr<'a>, Error> {
+        let expr = self.unary()?;
+
+        if !matches!(self.tokens.peek(), Some(Ok(Token::Between(..)))) {
+            return Ok(expr);
+        }
+
+        let token = self.tokens.next().unwrap().unwrap();
+        let low = self.unary()?;
+
+        // Since logical AND is written as '&&', the textual 'and' separating the bounds of the
+        // range is unambiguous (and is otherwise scanned as a property name).
+        match self.tokens.next() {
+            Some(Ok(Token::And(..) | Token::Property(.., "and"))) => {}
+            Some(Err(err)) => return Err(err),
+            _ => {
+                return Err(errors::user(
+                    &format!("The 'between' operator at {} expects its lower and upper bounds to be separated by 'and'.", token.location()),
+                    "Make sure that you write your range like repo.size between 100 and 5000.",
+                ))
+            }
+        }
+
+        let high = self.unary()?;
+        Ok(Expr::Between(
+            Box::new(expr),
+            token,
+            Box::new(low),
+            Box::new(high),
+        ))
+    }
+
     fn unary(&mut self) -> Result<Expr<'a>, Error> {
-        if matches!(self.tokens.peek(), Some(Ok(Token::Not(..)))) {
-            let token = self.tokens.next().unwrap().unwrap();
-            let right = self.unary()?;
-            Ok(Expr::Unary(token, Box::new(right)))
-        } else {
-            self.primary()
+        match self.tokens.peek() {
+            Some(Ok(Token::Not(..))) => {
+                let token = self.tokens.next().unwrap().unwrap();
+                let right = self.unary()?;
+                Ok(Expr::Unary(token, Box::new(right)))
+            }
+            Some(Ok(Token::Exists(..))) => {
+                let token = self.tokens.next().unwrap().unwrap();
+                match self.primary()? {
+                    right @ Expr::Property(..) => Ok(Expr::Unary(token, Box::new(right))),
+                    _ => Err(errors::user(
+                        &format!("The 'exists' operator at {} can only be used to check whether a property has a value.", token.location()),
+                        "Make sure that you follow 'exists' (or 'has') with the name of a property, like exists repo.homepage.",
+                    )),
+                }
+            }
+            _ => self.primary(),
         }
     }
 
@@ -143,7 +186,12 @@ impl<'a, I: Iterator<Item = Result<Token<'a>, Error>>> Parser<'a, I> {
               }
             }
             Some(Ok(Token::Property(..))) => {
-              if let Some(Ok(Token::Property(.., p))) = self.tokens.next() {
+              let token = self.tokens.next().unwrap().unwrap();
+              if matches!(self.tokens.peek(), Some(Ok(Token::LeftParen(..)))) {
+                return self.call(token);
+              }
+
+              if let Token::Property(.., p) = token {
                 Ok(Expr::Property(p))
               } else {
                 unreachable!()
@@ -158,6 +206,80 @@ impl<'a, I: Iterator<Item = Result<Token<'a>, Error>>> Parser<'a, I> {
         }
     }
 
+    fn call(&mut self, function: Token<'a>) -> Result<Expr<'a>, Error> {
+        let start = self.tokens.next().unwrap()?;
+        let mut args = Vec::new();
+        while !matches!(self.tokens.peek(), Some(Ok(Token::RightParen(..)))) {
+            args.push(self.or()?);
+            if matches!(self.tokens.peek(), Some(Ok(Token::Comma(..)))) {
+                self.tokens.next();
+            } else {
+                break;
+            }
+        }
+
+        if !matches!(self.tokens.next(), Some(Ok(Token::RightParen(..)))) {
+            return Err(errors::user(
+                &format!("When attempting to parse the arguments to '{}' starting at {}, we didn't find the closing ')' where we expected to.", function.lexeme(), start.location()),
+                "Make sure that you have balanced your parentheses correctly.",
+            ));
+        }
+
+        match function.lexeme() {
+            "matches_any" => match args.as_slice() {
+                [_, Expr::Literal(FilterValue::Tuple(patterns))] => {
+                    for pattern in patterns {
+                        let FilterValue::String(pattern) = pattern else {
+                            return Err(errors::user(
+                                &format!("The 'matches_any' function at {} was given the pattern {pattern}, which is not a string.", function.location()),
+                                "Make sure that each of the patterns you provide to 'matches_any' is a quoted regular expression, like matches_any(repo.name, [\"^infra-\", \"-prod$\"]).",
+                            ));
+                        };
+
+                        regex::Regex::new(pattern).map_err(|e| errors::user_with_internal(
+                            &format!("The regular expression \"{pattern}\" passed to 'matches_any' at {} is not valid.", function.location()),
+                            "Make sure that each of the patterns you provide to 'matches_any' is a valid regular expression.",
+                            e,
+                        ))?;
+                    }
+
+                    Ok(Expr::Call(function, args))
+                }
+                [_, patterns] => Err(errors::user(
+                    &format!("The 'matches_any' function at {} expects a list of patterns as its second argument, but was given {patterns}.", function.location()),
+                    "Make sure that you provide the patterns as a list, like matches_any(repo.name, [\"^infra-\", \"-prod$\"]).",
+                )),
+                _ => Err(errors::user(
+                    &format!("The 'matches_any' function at {} expects a value and a list of patterns, but was given {} arguments.", function.location(), args.len()),
+                    "Make sure that you call it like matches_any(repo.name, [\"^infra-\", \"-prod$\"]).",
+                )),
+            },
+            "semver" => match args.as_slice() {
+                [_, Expr::Literal(FilterValue::String(constraint))] => {
+                    semver::VersionReq::parse(constraint).map_err(|e| errors::user_with_internal(
+                        &format!("The version constraint \"{constraint}\" passed to 'semver' at {} is not valid.", function.location()),
+                        "Make sure that you provide a valid semantic version constraint, like semver(release.tag, \">=2.0.0, <3.0.0\").",
+                        e,
+                    ))?;
+
+                    Ok(Expr::Call(function, args))
+                }
+                [_, constraint] => Err(errors::user(
+                    &format!("The 'semver' function at {} expects a version constraint string as its second argument, but was given {constraint}.", function.location()),
+                    "Make sure that you provide the constraint as a quoted string, like semver(release.tag, \">=2.0.0, <3.0.0\").",
+                )),
+                _ => Err(errors::user(
+                    &format!("The 'semver' function at {} expects a version and a constraint, but was given {} arguments.", function.location(), args.len()),
+                    "Make sure that you call it like semver(release.tag, \">=2.0.0, <3.0.0\").",
+                )),
+            },
+            name => Err(errors::user(
+                &format!("Your filter expression calls an unknown function '{name}' at {}.", function.location()),
+                "Make sure that you have spelled the function name correctly. The supported functions are: matches_any, semver.",
+            )),
+        }
+    }
+
     fn literal(&mut self) -> Result<FilterValue, Error> {
         match self.tokens.next() {
             Some(Ok(Token::True(..))) => Ok(true.into()),
@@ -167,6 +289,16 @@ impl<'a, I: Iterator<Item = Result<Token<'a>, Error>>> Parser<'a, I> {
               "Please make sure that the number is well formatted. It should be in the form 123, or 123.45.",
               e,
             ))?)),
+            Some(Ok(Token::Size(loc, s))) => {
+                let split = s.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(s.len());
+                let (n, unit) = s.split_at(split);
+                let bytes = super::lexer::size_unit(unit).unwrap_or(1);
+                Ok(super::FilterValue::Number(n.parse::<f64>().map_err(|e| errors::user_with_internal(
+                  &format!("Failed to parse the size '{s}' which you provided at {}.", loc),
+                  "Please make sure that the size is well formatted. It should be in the form 100MB, or 1.5GB.",
+                  e,
+                ))? * bytes as f64))
+            },
             Some(Ok(Token::String(.., s))) => Ok(s.replace("\\\"", "\"").replace("\\\\", "\\").into()),
             Some(Ok(Token::Null(..))) => Ok(super::FilterValue::Null),
             Some(Ok(token)) => Err(errors::user(
@@ -196,6 +328,8 @@ mod tests {
     #[case("\"hello\"", "hello".into())]
     #[case("123", 123.0.into())]
     #[case("null", FilterValue::Null)]
+    #[case("5MB", (5.0 * 1024.0 * 1024.0).into())]
+    #[case("1.5KB", 1536.0.into())]
     #[case("[]", FilterValue::Tuple(vec![]))]
     #[case("[true]", FilterValue::Tuple(vec![true.into()]))]
     #[case("[\ntrue,\n]", FilterValue::Tuple(vec![true.into()]))]
@@ -209,10 +343,53 @@ mod tests {
         }
     }
 
+    #[rstest]
+    #[case("\"2023-01-01\"")]
+    #[case("\"2023-01-01T12:30:00Z\"")]
+    #[case("\"2023-01-01 12:30:00\"")]
+    #[case("\"v2023-01-01\"")]
+    fn parsing_date_literals(#[case] input: &str) {
+        let tokens = crate::filter::lexer::Scanner::new(input);
+        match Parser::parse(tokens.into_iter()) {
+            Ok(Expr::Literal(ast)) => assert_eq!(
+                ast.type_name(),
+                "string",
+                "date literals should remain strings until they are compared with a date"
+            ),
+            Ok(expr) => panic!("Expected a literal, got {:?}", expr),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
     #[rstest]
     #[case("!true", Expr::Unary(Token::Not(Loc::new(1, 1)), Box::new(Expr::Literal(true.into()))))]
     #[case("!false", Expr::Unary(Token::Not(Loc::new(1, 1)), Box::new(Expr::Literal(false.into()))))]
     #[case("!\"hello\"", Expr::Unary(Token::Not(Loc::new(1, 1)), Box::new(Expr::Literal("hello".into()))))]
+    #[case(
+        "exists repo.homepage",
+        Expr::Unary(
+            Token::Exists(Loc::new(1, 1)),
+            Box::new(Expr::Property("repo.homepage"))
+        )
+    )]
+    #[case(
+        "has repo.homepage",
+        Expr::Unary(
+            Token::Exists(Loc::new(1, 1)),
+            Box::new(Expr::Property("repo.homepage"))
+        )
+    )]
+    #[case(
+        "!exists repo.homepage",
+        Expr::Unary(
+            Token::Not(Loc::new(1, 1)),
+            Box::new(Expr::Unary(
+                Token::Exists(Loc::new(1, 2)),
+                Box::new(Expr::Property("repo.homepage"))
+            ))
+        )
+    )]
+    #[case("exists repo.homepage && true", Expr::Logical(Box::new(Expr::Unary(Token::Exists(Loc::new(1, 1)), Box::new(Expr::Property("repo.homepage")))), Token::And(Loc::new(1, 22)), Box::new(Expr::Literal(true.into()))))]
     fn parsing_unary_expressions(#[case] input: &str, #[case] ast: Expr) {
         let tokens = crate::filter::lexer::Scanner::new(input);
         match Parser::parse(tokens.into_iter()) {
@@ -230,6 +407,30 @@ mod tests {
     #[case("1 > 2", Expr::Binary(Box::new(Expr::Literal(1.0.into())), Token::GreaterThan(Loc::new(1, 2)), Box::new(Expr::Literal(2.0.into()))))]
     #[case("1 <= 2", Expr::Binary(Box::new(Expr::Literal(1.0.into())), Token::SmallerEqual(Loc::new(1, 3)), Box::new(Expr::Literal(2.0.into()))))]
     #[case("1 >= 2", Expr::Binary(Box::new(Expr::Literal(1.0.into())), Token::GreaterEqual(Loc::new(1, 3)), Box::new(Expr::Literal(2.0.into()))))]
+    #[case(
+        "repo.stargazers > repo.forks",
+        Expr::Binary(
+            Box::new(Expr::Property("repo.stargazers")),
+            Token::GreaterThan(Loc::new(1, 16)),
+            Box::new(Expr::Property("repo.forks"))
+        )
+    )]
+    #[case(
+        "repo.owner == repo.name",
+        Expr::Binary(
+            Box::new(Expr::Property("repo.owner")),
+            Token::Equals(Loc::new(1, 12)),
+            Box::new(Expr::Property("repo.name"))
+        )
+    )]
+    #[case(
+        "repo.name contains repo.owner",
+        Expr::Binary(
+            Box::new(Expr::Property("repo.name")),
+            Token::Contains(Loc::new(1, 11)),
+            Box::new(Expr::Property("repo.owner"))
+        )
+    )]
     fn parse_comparison_expressions(#[case] input: &str, #[case] ast: Expr) {
         let tokens = crate::filter::lexer::Scanner::new(input);
         match Parser::parse(tokens.into_iter()) {
@@ -250,6 +451,31 @@ mod tests {
         }
     }
 
+    #[rstest]
+    #[case("repo.size between 100 and 5000", Expr::Between(Box::new(Expr::Property("repo.size")), Token::Between(Loc::new(1, 11)), Box::new(Expr::Literal(100.0.into())), Box::new(Expr::Literal(5000.0.into()))))]
+    #[case("repo.size between 1 and 5 && true", Expr::Logical(Box::new(Expr::Between(Box::new(Expr::Property("repo.size")), Token::Between(Loc::new(1, 11)), Box::new(Expr::Literal(1.0.into())), Box::new(Expr::Literal(5.0.into())))), Token::And(Loc::new(1, 27)), Box::new(Expr::Literal(true.into()))))]
+    #[case("repo.size between 1 and 5 == false", Expr::Binary(Box::new(Expr::Between(Box::new(Expr::Property("repo.size")), Token::Between(Loc::new(1, 11)), Box::new(Expr::Literal(1.0.into())), Box::new(Expr::Literal(5.0.into())))), Token::Equals(Loc::new(1, 27)), Box::new(Expr::Literal(false.into()))))]
+    fn parsing_range_expressions(#[case] input: &str, #[case] ast: Expr) {
+        let tokens = crate::filter::lexer::Scanner::new(input);
+        match Parser::parse(tokens.into_iter()) {
+            Ok(expr) => assert_eq!(ast, expr, "Expected {ast} to be {expr}"),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[rstest]
+    #[case("matches_any(repo.name, [\"^infra-\", \"-prod$\"])", Expr::Call(Token::Property(Loc::new(1, 1), "matches_any"), vec![Expr::Property("repo.name"), Expr::Literal(vec!["^infra-".into(), "-prod$".into()].into())]))]
+    #[case("matches_any(repo.name, [])", Expr::Call(Token::Property(Loc::new(1, 1), "matches_any"), vec![Expr::Property("repo.name"), Expr::Literal(FilterValue::Tuple(vec![]))]))]
+    #[case("!matches_any(repo.name, [\"x\"])", Expr::Unary(Token::Not(Loc::new(1, 1)), Box::new(Expr::Call(Token::Property(Loc::new(1, 2), "matches_any"), vec![Expr::Property("repo.name"), Expr::Literal(vec!["x".into()].into())]))))]
+    #[case("semver(release.tag, \">=2.0.0, <3.0.0\")", Expr::Call(Token::Property(Loc::new(1, 1), "semver"), vec![Expr::Property("release.tag"), Expr::Literal(">=2.0.0, <3.0.0".into())]))]
+    fn parsing_function_calls(#[case] input: &str, #[case] ast: Expr) {
+        let tokens = crate::filter::lexer::Scanner::new(input);
+        match Parser::parse(tokens.into_iter()) {
+            Ok(expr) => assert_eq!(ast, expr, "Expected {ast} to be {expr}"),
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
     #[rstest]
     #[case(
         "true false",
@@ -271,6 +497,62 @@ mod tests {
         ")",
         "While parsing your filter, we found an unexpected ')' at line 1, column 1."
     )]
+    #[case(
+        "matches_any(repo.name, [\"^infra-\", \"(unclosed\"])",
+        "The regular expression \"(unclosed\" passed to 'matches_any' at line 1, column 1 is not valid."
+    )]
+    #[case(
+        "matches_any(repo.name, [1])",
+        "The 'matches_any' function at line 1, column 1 was given the pattern 1, which is not a string."
+    )]
+    #[case(
+        "matches_any(repo.name, \"^infra-\")",
+        "The 'matches_any' function at line 1, column 1 expects a list of patterns as its second argument"
+    )]
+    #[case(
+        "matches_any(repo.name)",
+        "The 'matches_any' function at line 1, column 1 expects a value and a list of patterns, but was given 1 arguments."
+    )]
+    #[case(
+        "semver(release.tag, \"not a constraint\")",
+        "The version constraint \"not a constraint\" passed to 'semver' at line 1, column 1 is not valid."
+    )]
+    #[case(
+        "semver(release.tag, 2)",
+        "The 'semver' function at line 1, column 1 expects a version constraint string as its second argument"
+    )]
+    #[case(
+        "semver(release.tag)",
+        "The 'semver' function at line 1, column 1 expects a version and a constraint, but was given 1 arguments."
+    )]
+    #[case(
+        "matches_all(repo.name, [])",
+        "Your filter expression calls an unknown function 'matches_all' at line 1, column 1."
+    )]
+    #[case(
+        "matches_any(repo.name, [\"x\"]",
+        "When attempting to parse the arguments to 'matches_any' starting at line 1, column 12, we didn't find the closing ')' where we expected to."
+    )]
+    #[case(
+        "repo.size between 1 5",
+        "The 'between' operator at line 1, column 11 expects its lower and upper bounds to be separated by 'and'."
+    )]
+    #[case(
+        "repo.size between 1 and",
+        "We reached the end of your filter expression while waiting for a [true, false, \"string\", number, (group), or property.name]."
+    )]
+    #[case(
+        "exists \"homepage\"",
+        "The 'exists' operator at line 1, column 1 can only be used to check whether a property has a value."
+    )]
+    #[case(
+        "has matches_any(repo.name, [])",
+        "The 'exists' operator at line 1, column 1 can only be used to check whether a property has a value."
+    )]
+    #[case(
+        "exists",
+        "We reached the end of your filter expression while waiting for a [true, false, \"string\", number, (group), or property.name]."
+    )]
     fn invalid_filters(#[case] input: &str, #[case] message: &str) {
         let tokens = crate::filter::lexer::Scanner::new(input);
         match Parser::parse(tokens.into_iter()) {
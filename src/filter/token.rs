@@ -17,6 +17,8 @@ pub enum Token<'a> {
     False(Loc),
     String(Loc, &'a str),
     Number(Loc, &'a str),
+    /// A number followed by a size unit (like `5MB`), which represents a number of bytes.
+    Size(Loc, &'a str),
 
     Equals(Loc),
     NotEquals(Loc),
@@ -28,8 +30,11 @@ pub enum Token<'a> {
     SmallerThan(Loc),
     GreaterEqual(Loc),
     SmallerEqual(Loc),
+    Between(Loc),
 
     Not(Loc),
+    /// Checks whether a property has a (non-null) value, written as either `exists` or `has`.
+    Exists(Loc),
     And(Loc),
     Or(Loc),
 }
@@ -50,6 +55,7 @@ impl Token<'_> {
             Token::False(..) => "false",
             Token::String(.., s) => s,
             Token::Number(.., s) => s,
+            Token::Size(.., s) => s,
 
             Token::Equals(..) => "==",
             Token::NotEquals(..) => "!=",
@@ -61,8 +67,10 @@ impl Token<'_> {
             Token::GreaterEqual(..) => ">=",
             Token::SmallerThan(..) => "<",
             Token::SmallerEqual(..) => "<=",
+            Token::Between(..) => "between",
 
             Token::Not(..) => "!",
+            Token::Exists(..) => "exists",
             Token::And(..) => "&&",
             Token::Or(..) => "||",
         }
@@ -83,6 +91,7 @@ impl Token<'_> {
             Token::False(loc) => *loc,
             Token::String(loc, ..) => *loc,
             Token::Number(loc, ..) => *loc,
+            Token::Size(loc, ..) => *loc,
 
             Token::Equals(loc) => *loc,
             Token::NotEquals(loc) => *loc,
@@ -94,8 +103,10 @@ impl Token<'_> {
             Token::SmallerThan(loc) => *loc,
             Token::GreaterEqual(loc) => *loc,
             Token::SmallerEqual(loc) => *loc,
+            Token::Between(loc) => *loc,
 
             Token::Not(loc) => *loc,
+            Token::Exists(loc) => *loc,
             Token::And(loc) => *loc,
             Token::Or(loc) => *loc,
         }
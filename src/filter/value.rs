@@ -33,6 +33,7 @@ pub enum FilterValue {
     Bool(bool),
     Number(f64),
     String(String),
+    Date(chrono::DateTime<chrono::Utc>),
     Tuple(Vec<FilterValue>),
 }
 
@@ -43,16 +44,97 @@ impl FilterValue {
             FilterValue::Bool(b) => *b,
             FilterValue::Number(n) => *n != 0.0,
             FilterValue::String(s) => !s.is_empty(),
+            FilterValue::Date(..) => true,
             FilterValue::Tuple(v) => !v.is_empty(),
         }
     }
 
+    /// Attempts to parse a string as a date, accepting RFC3339 timestamps (`2023-01-01T12:00:00Z`)
+    /// as well as plain dates (`2023-01-01`) and date-times (`2023-01-01 12:00:00`) in UTC.
+    pub fn parse_date(value: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+        if let Ok(date) = chrono::DateTime::parse_from_rfc3339(value) {
+            return Some(date.with_timezone(&chrono::Utc));
+        }
+
+        for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S"] {
+            if let Ok(date) = chrono::NaiveDateTime::parse_from_str(value, format) {
+                return Some(date.and_utc());
+            }
+        }
+
+        chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .ok()
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+            .map(|d| d.and_utc())
+    }
+
+    /// Retrieves the pair of dates to compare when at least one side of a comparison is a date
+    /// and the other is either a date, or a string which can be parsed as one.
+    fn as_dates(
+        &self,
+        other: &FilterValue,
+    ) -> Option<(chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>)> {
+        match (self, other) {
+            (FilterValue::Date(a), FilterValue::Date(b)) => Some((*a, *b)),
+            (FilterValue::Date(a), FilterValue::String(b)) => Self::parse_date(b).map(|b| (*a, b)),
+            (FilterValue::String(a), FilterValue::Date(b)) => Self::parse_date(a).map(|a| (a, *b)),
+            _ => None,
+        }
+    }
+
+    /// Determines whether this value falls within the inclusive range from `low` to `high`,
+    /// returning `None` if the values are not all numbers (or all dates) and so cannot be compared.
+    pub fn between(&self, low: &FilterValue, high: &FilterValue) -> Option<bool> {
+        if let (FilterValue::Number(value), FilterValue::Number(low), FilterValue::Number(high)) =
+            (self, low, high)
+        {
+            return Some(low <= value && value <= high);
+        }
+
+        let (low, value) = low.as_dates(self)?;
+        let (_, high) = self.as_dates(high)?;
+        Some(low <= value && value <= high)
+    }
+
+    /// The name of this value's type, as it should be presented to users.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            FilterValue::Null => "null",
+            FilterValue::Bool(..) => "boolean",
+            FilterValue::Number(..) => "number",
+            FilterValue::String(..) => "string",
+            FilterValue::Date(..) => "date",
+            FilterValue::Tuple(..) => "tuple",
+        }
+    }
+
+    /// Determines whether checking if this value contains `other` is a type mismatch
+    /// which can never succeed (e.g. looking for a string in a tuple of numbers).
+    ///
+    /// Null values on either side are never considered a mismatch, since they are
+    /// used to represent missing properties.
+    pub fn is_type_mismatch(&self, other: &FilterValue) -> bool {
+        match (self, other) {
+            (FilterValue::Null, _) | (_, FilterValue::Null) => false,
+            (FilterValue::Tuple(a), b) => {
+                !a.is_empty()
+                    && a.iter()
+                        .all(|ai| std::mem::discriminant(ai) != std::mem::discriminant(b))
+            }
+            (FilterValue::String(..), FilterValue::String(..) | FilterValue::Date(..)) => false,
+            _ => true,
+        }
+    }
+
     pub fn contains(&self, other: &FilterValue) -> bool {
         match (self, other) {
             (FilterValue::Tuple(a), b) => a.iter().any(|ai| ai == b),
             (FilterValue::String(a), FilterValue::String(b)) => {
                 a.to_lowercase().contains(&b.to_lowercase())
             }
+            (FilterValue::String(a), b @ FilterValue::Date(..)) => {
+                b.affix_str().is_some_and(|b| a.to_lowercase().contains(&b))
+            }
             _ => false,
         }
     }
@@ -60,26 +142,48 @@ impl FilterValue {
     pub fn startswith(&self, other: &FilterValue) -> bool {
         match (self, other) {
             (FilterValue::Tuple(a), b) => a.iter().any(|ai| ai == b),
-            (FilterValue::String(a), FilterValue::String(b)) => {
-                a.to_lowercase().starts_with(&b.to_lowercase())
-            }
-            _ => false,
+            (a, b) => match (a.affix_str(), b.affix_str()) {
+                (Some(a), Some(b)) => a.starts_with(&b),
+                _ => false,
+            },
         }
     }
 
     pub fn endswith(&self, other: &FilterValue) -> bool {
         match (self, other) {
             (FilterValue::Tuple(a), b) => a.iter().any(|ai| ai == b),
-            (FilterValue::String(a), FilterValue::String(b)) => {
-                a.to_lowercase().ends_with(&b.to_lowercase())
-            }
-            _ => false,
+            (a, b) => match (a.affix_str(), b.affix_str()) {
+                (Some(a), Some(b)) => a.ends_with(&b),
+                _ => false,
+            },
+        }
+    }
+
+    fn format_date(date: &chrono::DateTime<chrono::Utc>) -> String {
+        date.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true)
+    }
+
+    /// The (lowercase) string form of this value used for prefix and suffix matching.
+    ///
+    /// Numbers are coerced to the same representation used by their [`Display`]
+    /// implementation (e.g. `1024`, `1.5`) and dates to their RFC3339 form, while all other values
+    /// return [`None`] so that they never match.
+    fn affix_str(&self) -> Option<String> {
+        match self {
+            FilterValue::String(s) => Some(s.to_lowercase()),
+            FilterValue::Number(n) => Some(n.to_string()),
+            FilterValue::Date(d) => Some(Self::format_date(d).to_lowercase()),
+            _ => None,
         }
     }
 }
 
 impl PartialEq for FilterValue {
     fn eq(&self, other: &Self) -> bool {
+        if let Some((a, b)) = self.as_dates(other) {
+            return a == b;
+        }
+
         match (self, other) {
             (FilterValue::Null, FilterValue::Null) => true,
             (FilterValue::Bool(a), FilterValue::Bool(b)) => a == b,
@@ -95,6 +199,10 @@ impl PartialEq for FilterValue {
 
 impl PartialOrd for FilterValue {
     fn lt(&self, other: &Self) -> bool {
+        if let Some((a, b)) = self.as_dates(other) {
+            return a < b;
+        }
+
         match (self, other) {
             (FilterValue::Null, FilterValue::Null) => true,
             (FilterValue::Bool(a), FilterValue::Bool(b)) => a < b,
@@ -108,6 +216,10 @@ impl PartialOrd for FilterValue {
     }
 
     fn le(&self, other: &Self) -> bool {
+        if let Some((a, b)) = self.as_dates(other) {
+            return a <= b;
+        }
+
         match (self, other) {
             (FilterValue::Null, FilterValue::Null) => true,
             (FilterValue::Bool(a), FilterValue::Bool(b)) => a <= b,
@@ -121,6 +233,10 @@ impl PartialOrd for FilterValue {
     }
 
     fn gt(&self, other: &Self) -> bool {
+        if let Some((a, b)) = self.as_dates(other) {
+            return a > b;
+        }
+
         match (self, other) {
             (FilterValue::Null, FilterValue::Null) => true,
             (FilterValue::Bool(a), FilterValue::Bool(b)) => a > b,
@@ -134,6 +250,10 @@ impl PartialOrd for FilterValue {
     }
 
     fn ge(&self, other: &Self) -> bool {
+        if let Some((a, b)) = self.as_dates(other) {
+            return a >= b;
+        }
+
         match (self, other) {
             (FilterValue::Null, FilterValue::Null) => true,
             (FilterValue::Bool(a), FilterValue::Bool(b)) => a >= b,
@@ -147,6 +267,10 @@ impl PartialOrd for FilterValue {
     }
 
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if let Some((a, b)) = self.as_dates(other) {
+            return a.partial_cmp(&b);
+        }
+
         match (self, other) {
             (FilterValue::Null, FilterValue::Null) => Some(Ordering::Equal),
             (FilterValue::Bool(a), FilterValue::Bool(b)) => a.partial_cmp(b),
@@ -177,6 +301,7 @@ impl Display for FilterValue {
             FilterValue::String(s) => {
                 write!(f, "\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
             }
+            FilterValue::Date(d) => write!(f, "\"{}\"", Self::format_date(d)),
             FilterValue::Tuple(v) => {
                 write!(f, "[")?;
                 for (i, value) in v.iter().enumerate() {
@@ -224,6 +349,12 @@ number!(f64);
 number!(i64);
 number!(u64);
 
+impl From<chrono::DateTime<chrono::Utc>> for FilterValue {
+    fn from(d: chrono::DateTime<chrono::Utc>) -> Self {
+        FilterValue::Date(d)
+    }
+}
+
 impl From<&str> for FilterValue {
     fn from(s: &str) -> Self {
         FilterValue::String(s.to_string())
@@ -299,4 +430,38 @@ mod tests {
             FilterValue::String(String::from("abc"))
         );
     }
+
+    #[rstest]
+    #[case("2023-01-01T12:30:00Z", Some("2023-01-01T12:30:00Z"))]
+    #[case("2023-01-01T12:30:00+02:00", Some("2023-01-01T10:30:00Z"))]
+    #[case("2023-01-01T12:30:00", Some("2023-01-01T12:30:00Z"))]
+    #[case("2023-01-01 12:30:00", Some("2023-01-01T12:30:00Z"))]
+    #[case("2023-01-01", Some("2023-01-01T00:00:00Z"))]
+    #[case("2023-13-01", None)]
+    #[case("v2023-01-01", None)]
+    #[case("hello", None)]
+    fn test_parse_date(#[case] value: &str, #[case] expected: Option<&str>) {
+        assert_eq!(
+            FilterValue::parse_date(value),
+            expected.map(|e| e.parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_date_comparison() {
+        let date = |s: &str| FilterValue::Date(FilterValue::parse_date(s).unwrap());
+
+        assert!(date("2023-01-01") < date("2023-06-01"));
+        assert!(date("2023-06-01") > date("2023-01-01"));
+        assert!(date("2023-01-01") <= date("2023-01-01T00:00:00Z"));
+        assert_eq!(date("2023-01-01"), date("2023-01-01T00:00:00Z"));
+
+        assert_eq!(
+            date("2023-01-01"),
+            FilterValue::from("2023-01-01T00:00:00Z")
+        );
+        assert!(FilterValue::from("2022-12-31") < date("2023-01-01"));
+        assert_ne!(date("2023-01-01"), FilterValue::from("hello"));
+        assert_ne!(date("2023-01-01"), FilterValue::Null);
+    }
 }
@@ -0,0 +1,207 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// The number of backup tasks which may run at once (for each available CPU) when the
+/// concurrency limit is chosen automatically. Backups spend most of their time waiting on the
+/// network, so we allow several of them to run for each CPU.
+const TASKS_PER_CPU: usize = 4;
+
+/// How much slower than usual a backup task needs to be before we consider it a sign that too
+/// many tasks are running at once.
+const SLOW_FACTOR: u32 = 2;
+
+/// The weight given to each new observation when updating the typical latency of a backup task.
+const LATENCY_SMOOTHING: f64 = 0.1;
+
+/// Chooses the number of backup tasks which may run at once based on how quickly (and how
+/// reliably) recent tasks have completed, using an additive increase/multiplicative decrease
+/// approach similar to TCP's congestion control.
+///
+/// The limit grows by one after a full limit's worth of tasks complete successfully at their usual
+/// pace, shrinks by one whenever a task takes much longer than usual, and is halved whenever a task
+/// fails. It never leaves the range between the `min` and `max` it was created with.
+///
+/// Clones of an [`AutoConcurrency`] share the same state, allowing every backup policy to
+/// contribute to (and be limited by) the same controller.
+#[derive(Clone, Debug)]
+pub struct AutoConcurrency {
+    state: Arc<Mutex<Controller>>,
+}
+
+impl AutoConcurrency {
+    /// Creates a controller whose limits are derived from the parallelism available to this process.
+    pub fn detect() -> Self {
+        let cpus = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+
+        Self::new(1, cpus * TASKS_PER_CPU, cpus)
+    }
+
+    pub fn new(min: usize, max: usize, initial: usize) -> Self {
+        let min = min.max(1);
+        let max = max.max(min);
+
+        Self {
+            state: Arc::new(Mutex::new(Controller {
+                min,
+                max,
+                limit: initial.clamp(min, max),
+                latency: None,
+                successes: 0,
+            })),
+        }
+    }
+
+    /// The number of backup tasks which may currently run at once.
+    pub fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit
+    }
+
+    /// Records the outcome of a backup task which took `latency` to complete.
+    pub fn record(&self, latency: Duration, succeeded: bool) {
+        self.state.lock().unwrap().record(latency, succeeded)
+    }
+}
+
+#[derive(Debug)]
+struct Controller {
+    min: usize,
+    max: usize,
+    limit: usize,
+    /// The typical (exponentially smoothed) latency of a successful backup task.
+    latency: Option<Duration>,
+    /// The number of tasks which have completed successfully since the limit last changed.
+    successes: usize,
+}
+
+impl Controller {
+    fn record(&mut self, latency: Duration, succeeded: bool) {
+        if !succeeded {
+            self.set_limit(self.limit / 2);
+            return;
+        }
+
+        let typical = *self.latency.get_or_insert(latency);
+        self.latency =
+            Some(typical.mul_f64(1.0 - LATENCY_SMOOTHING) + latency.mul_f64(LATENCY_SMOOTHING));
+
+        if latency > typical * SLOW_FACTOR {
+            self.set_limit(self.limit.saturating_sub(1));
+            return;
+        }
+
+        self.successes += 1;
+        if self.successes >= self.limit {
+            self.set_limit(self.limit + 1);
+        }
+    }
+
+    fn set_limit(&mut self, limit: usize) {
+        self.limit = limit.clamp(self.min, self.max);
+        self.successes = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn run(controller: &AutoConcurrency, outcomes: &[(u64, bool)]) {
+        for (latency, succeeded) in outcomes {
+            controller.record(Duration::from_millis(*latency), *succeeded);
+        }
+    }
+
+    #[test]
+    fn test_detect() {
+        let controller = AutoConcurrency::detect();
+        assert!(controller.limit() >= 1);
+    }
+
+    #[rstest]
+    #[case(1, 8, 0, 1)]
+    #[case(1, 8, 20, 8)]
+    #[case(4, 2, 1, 4)]
+    #[case(0, 8, 3, 3)]
+    fn test_bounds(
+        #[case] min: usize,
+        #[case] max: usize,
+        #[case] initial: usize,
+        #[case] expected: usize,
+    ) {
+        assert_eq!(AutoConcurrency::new(min, max, initial).limit(), expected);
+    }
+
+    #[test]
+    fn test_grows_when_fast() {
+        let controller = AutoConcurrency::new(1, 10, 2);
+        run(&controller, &[(100, true); 2]);
+        assert_eq!(controller.limit(), 3);
+
+        run(&controller, &[(100, true); 3]);
+        assert_eq!(controller.limit(), 4);
+
+        run(&controller, &[(100, true); 100]);
+        assert_eq!(
+            controller.limit(),
+            10,
+            "the limit should never exceed the maximum"
+        );
+    }
+
+    #[test]
+    fn test_shrinks_on_errors() {
+        let controller = AutoConcurrency::new(1, 16, 16);
+        run(&controller, &[(100, false)]);
+        assert_eq!(controller.limit(), 8);
+
+        run(&controller, &[(100, false)]);
+        assert_eq!(controller.limit(), 4);
+
+        run(&controller, &[(100, false); 10]);
+        assert_eq!(
+            controller.limit(),
+            1,
+            "the limit should never drop below the minimum"
+        );
+    }
+
+    #[test]
+    fn test_shrinks_when_slow() {
+        let controller = AutoConcurrency::new(1, 16, 8);
+        run(&controller, &[(100, true); 4]);
+        assert_eq!(controller.limit(), 8);
+
+        run(&controller, &[(1000, true)]);
+        assert_eq!(controller.limit(), 7);
+
+        run(&controller, &[(1000, true)]);
+        assert_eq!(controller.limit(), 6);
+    }
+
+    #[test]
+    fn test_recovers() {
+        let controller = AutoConcurrency::new(1, 16, 8);
+        run(&controller, &[(100, true), (5000, false), (100, false)]);
+        assert_eq!(controller.limit(), 2);
+
+        run(&controller, &[(100, true); 5]);
+        assert!(
+            controller.limit() > 2,
+            "the limit should grow again once tasks complete successfully"
+        );
+    }
+
+    #[test]
+    fn test_clones_share_state() {
+        let controller = AutoConcurrency::new(1, 16, 8);
+        let clone = controller.clone();
+        run(&clone, &[(100, false)]);
+        assert_eq!(controller.limit(), 4);
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+};
+
+use tracing_batteries::prelude::*;
+
+use crate::{errors, helpers::hash::fnv1a, state::State, BackupPolicy};
+
+/// Detects entities whose names differ only by case, which would be written to the same path on
+/// case-insensitive filesystems (such as those used by macOS and Windows).
+///
+/// Collisions are always reported as warnings, however when `case_safe` is enabled, the later of
+/// the colliding entities is given a path with a short hash suffix, and this mapping is recorded in
+/// the policy's state file so that the entity keeps the same path on future runs.
+pub struct CaseSafePaths<'a> {
+    policy: &'a BackupPolicy,
+    case_safe: bool,
+    read_only: bool,
+    seen: HashMap<String, String>,
+    paths: BTreeMap<String, String>,
+}
+
+impl<'a> CaseSafePaths<'a> {
+    pub fn new(policy: &'a BackupPolicy, case_safe: bool) -> Result<Self, errors::Error> {
+        let paths = if case_safe {
+            State::load(&State::path_for(policy))?.policy(policy).paths
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self {
+            policy,
+            case_safe,
+            read_only: false,
+            seen: HashMap::new(),
+            paths,
+        })
+    }
+
+    /// Uses the mappings recorded in the policy's state file without ever recording new ones,
+    /// for use in dry runs.
+    pub fn with_read_only(self, read_only: bool) -> Self {
+        Self { read_only, ..self }
+    }
+
+    /// Determines the path at which the entity with the given name should be stored, returning
+    /// `None` if it should use the default path derived from its name.
+    pub async fn resolve(&mut self, name: &str) -> Result<Option<PathBuf>, errors::Error> {
+        if let Some(path) = self.paths.get(name) {
+            self.seen.insert(path.to_lowercase(), name.to_string());
+            return Ok(Some(path.into()));
+        }
+
+        let key = name.to_lowercase();
+        match self.seen.get(&key) {
+            Some(existing) if existing != name => {
+                warn!(
+                    "The names '{existing}' and '{name}' differ only by case, so their backups will overwrite one another on case-insensitive filesystems."
+                );
+
+                if !self.case_safe {
+                    return Ok(None);
+                }
+
+                let path = format!("{name}-{:08x}", fnv1a(name.as_bytes()) as u32);
+                if !self.read_only {
+                    State::update(self.policy, |s| {
+                        s.paths.insert(name.to_string(), path.clone());
+                    })
+                    .await?;
+                }
+
+                self.seen.insert(path.to_lowercase(), name.to_string());
+                self.paths.insert(name.to_string(), path.clone());
+                Ok(Some(path.into()))
+            }
+            _ => {
+                self.seen.insert(key, name.to_string());
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn policy(to: &std::path::Path) -> BackupPolicy {
+        serde_yaml::from_str(&format!(
+            r#"
+            kind: github/repo
+            from: users/notheotherben
+            to: {}
+            "#,
+            to.display()
+        ))
+        .unwrap()
+    }
+
+    #[rstest]
+    #[case(false)]
+    #[case(true)]
+    #[tokio::test]
+    async fn test_case_collisions(#[case] case_safe: bool) {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let policy = policy(temp_dir.path());
+
+        let mut paths = CaseSafePaths::new(&policy, case_safe).expect("paths to be created");
+        assert_eq!(paths.resolve("octocat/Hello-World").await.unwrap(), None);
+        assert_eq!(paths.resolve("octocat/Spoon-Knife").await.unwrap(), None);
+
+        let path = paths.resolve("octocat/hello-world").await.unwrap();
+        if !case_safe {
+            assert_eq!(path, None);
+            assert!(!State::path_for(&policy).exists());
+            return;
+        }
+
+        let path = path.expect("the colliding entity to be given a new path");
+        assert!(path.to_string_lossy().starts_with("octocat/hello-world-"));
+        assert_eq!(
+            State::load(&State::path_for(&policy))
+                .unwrap()
+                .policy(&policy)
+                .paths
+                .get("octocat/hello-world"),
+            Some(&path.to_string_lossy().to_string()),
+            "the mapping should be recorded in the state file"
+        );
+
+        // On the next run, the recorded mapping is used even if the entities are listed in a different order.
+        let mut paths = CaseSafePaths::new(&policy, case_safe).expect("paths to be created");
+        assert_eq!(
+            paths.resolve("octocat/hello-world").await.unwrap(),
+            Some(path)
+        );
+        assert_eq!(paths.resolve("octocat/Hello-World").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_case_collisions_read_only() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let policy = policy(temp_dir.path());
+
+        let mut paths = CaseSafePaths::new(&policy, true)
+            .expect("paths to be created")
+            .with_read_only(true);
+        assert_eq!(paths.resolve("octocat/Hello-World").await.unwrap(), None);
+        assert!(paths
+            .resolve("octocat/hello-world")
+            .await
+            .unwrap()
+            .is_some());
+
+        assert!(
+            !State::path_for(&policy).exists(),
+            "dry runs should not record new mappings in the state file"
+        );
+    }
+}
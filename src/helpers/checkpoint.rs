@@ -0,0 +1,294 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
+    sync::{Arc, Mutex, Weak},
+};
+
+use crate::errors;
+
+/// A file which records how far through a paginated listing we have progressed, so
+/// that an interrupted listing can be resumed rather than restarted from the first page.
+///
+/// A single checkpoint file may track several listings, each keyed by the URL of its
+/// first page.
+///
+/// The checkpoint only moves past a page once every item loaded from it has finished being
+/// processed (which is signalled by dropping the [`PageGuard`]s handed out with those items),
+/// so that items which were still being backed up when the listing was interrupted are listed
+/// again when it resumes.
+#[derive(Clone, Debug)]
+pub struct PaginationCheckpoint {
+    path: PathBuf,
+    read_only: bool,
+    pages: Arc<Mutex<VecDeque<TrackedPage>>>,
+}
+
+/// The URL which follows a page that is being processed, along with a handle which is alive
+/// until every item from that page has finished.
+type TrackedPage = (String, Weak<()>);
+
+/// Held by each item loaded from a page of a resumable listing, until that item has finished
+/// being processed.
+#[derive(Clone, Debug)]
+pub struct PageGuard {
+    _page: Arc<()>,
+}
+
+impl PaginationCheckpoint {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            read_only: false,
+            pages: Default::default(),
+        }
+    }
+
+    /// Resumes listings from the checkpoint without ever updating it, for use in dry runs.
+    pub fn with_read_only(self, read_only: bool) -> Self {
+        Self { read_only, ..self }
+    }
+
+    /// Retrieves the URL of the next page which should be fetched for the given listing.
+    pub async fn get(&self, base_url: &str) -> Result<Option<String>, errors::Error> {
+        Ok(self.load().await?.remove(base_url))
+    }
+
+    /// Records the URL of the next page which should be fetched for the given listing.
+    pub async fn set(&self, base_url: &str, next_url: &str) -> Result<(), errors::Error> {
+        if self.read_only {
+            return Ok(());
+        }
+
+        let mut checkpoints = self.load().await?;
+        checkpoints.insert(base_url.to_string(), next_url.to_string());
+        self.save(&checkpoints).await
+    }
+
+    /// Starts tracking a page of the listing whose items are about to be processed, returning the
+    /// guard which should be held by each of them. Once every guard has been dropped, the listing
+    /// may resume from `next_url`.
+    pub fn track(&self, next_url: &str) -> PageGuard {
+        let page = Arc::new(());
+        self.pages
+            .lock()
+            .unwrap()
+            .push_back((next_url.to_string(), Arc::downgrade(&page)));
+        PageGuard { _page: page }
+    }
+
+    /// Moves the checkpoint past the pages (in the order they were listed) whose items have all
+    /// finished being processed.
+    pub async fn commit(&self, base_url: &str) -> Result<(), errors::Error> {
+        let next_url = {
+            let mut pages = self.pages.lock().unwrap();
+            let mut next_url = None;
+            while let Some((url, page)) = pages.front() {
+                if page.strong_count() > 0 {
+                    break;
+                }
+
+                next_url = Some(url.clone());
+                pages.pop_front();
+            }
+
+            next_url
+        };
+
+        match next_url {
+            Some(next_url) => self.set(base_url, &next_url).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Removes the checkpoint for a listing once it has been fully processed.
+    pub async fn clear(&self, base_url: &str) -> Result<(), errors::Error> {
+        self.pages.lock().unwrap().clear();
+        if self.read_only {
+            return Ok(());
+        }
+
+        let mut checkpoints = self.load().await?;
+        if checkpoints.remove(base_url).is_none() {
+            return Ok(());
+        }
+
+        if checkpoints.is_empty() {
+            tokio::fs::remove_file(&self.path).await.map_err(|e| {
+                errors::user_with_internal(
+                    &format!(
+                        "Unable to remove the pagination checkpoint file '{}'.",
+                        self.path.display()
+                    ),
+                    "Make sure that you have permission to write to the backup directory.",
+                    e,
+                )
+            })
+        } else {
+            self.save(&checkpoints).await
+        }
+    }
+
+    async fn load(&self) -> Result<HashMap<String, String>, errors::Error> {
+        let content = match tokio::fs::read_to_string(&self.path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(e) => {
+                return Err(errors::user_with_internal(
+                    &format!(
+                        "Unable to read the pagination checkpoint file '{}'.",
+                        self.path.display()
+                    ),
+                    "Make sure that you have permission to read this file.",
+                    e,
+                ))
+            }
+        };
+
+        serde_json::from_str(&content).map_err(|e| {
+            errors::user_with_internal(
+                &format!(
+                    "The pagination checkpoint file '{}' could not be parsed.",
+                    self.path.display()
+                ),
+                "Remove the checkpoint file to restart the listing from its first page.",
+                e,
+            )
+        })
+    }
+
+    async fn save(&self, checkpoints: &HashMap<String, String>) -> Result<(), errors::Error> {
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(|e| {
+                errors::user_with_internal(
+                    &format!("Unable to create backup directory '{}'", parent.display()),
+                    "Make sure that you have permission to create the directory.",
+                    e,
+                )
+            })?;
+        }
+
+        let content = serde_json::to_string_pretty(checkpoints).map_err(|e| {
+            errors::system_with_internal(
+                "Unable to serialize the pagination checkpoint.",
+                "Please report this issue to us on GitHub.",
+                e,
+            )
+        })?;
+
+        let temp_path = self.path.with_extension("json.tmp");
+        let result = match tokio::fs::write(&temp_path, content).await {
+            Ok(()) => tokio::fs::rename(&temp_path, &self.path).await,
+            Err(e) => Err(e),
+        };
+
+        result.map_err(|e| {
+            errors::user_with_internal(
+                &format!(
+                    "Unable to write the pagination checkpoint file '{}'.",
+                    self.path.display()
+                ),
+                "Make sure that you have permission to write to the backup directory.",
+                e,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_checkpoint() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let path = temp_dir.path().join("checkpoint.json");
+        let checkpoint = PaginationCheckpoint::new(&path);
+
+        assert_eq!(checkpoint.get("https://a").await.unwrap(), None);
+
+        checkpoint
+            .set("https://a", "https://a?page=2")
+            .await
+            .unwrap();
+        checkpoint
+            .set("https://b", "https://b?page=5")
+            .await
+            .unwrap();
+        assert_eq!(
+            checkpoint.get("https://a").await.unwrap().as_deref(),
+            Some("https://a?page=2")
+        );
+
+        checkpoint.clear("https://a").await.unwrap();
+        assert_eq!(checkpoint.get("https://a").await.unwrap(), None);
+        assert!(path.exists());
+
+        checkpoint.clear("https://b").await.unwrap();
+        assert!(
+            !path.exists(),
+            "the checkpoint file should be removed once it is empty"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_commit() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let checkpoint = PaginationCheckpoint::new(temp_dir.path().join("checkpoint.json"));
+
+        let first = checkpoint.track("https://a?page=2");
+        let second = checkpoint.track("https://a?page=3");
+        let third = checkpoint.track("https://a?page=4");
+
+        checkpoint.commit("https://a").await.unwrap();
+        assert_eq!(checkpoint.get("https://a").await.unwrap(), None);
+
+        // Later pages can't be committed while an item from an earlier page is still in flight.
+        drop(second);
+        checkpoint.commit("https://a").await.unwrap();
+        assert_eq!(checkpoint.get("https://a").await.unwrap(), None);
+
+        let item = first.clone();
+        drop(first);
+        checkpoint.commit("https://a").await.unwrap();
+        assert_eq!(
+            checkpoint.get("https://a").await.unwrap(),
+            None,
+            "the page should be in flight until every item from it has finished"
+        );
+
+        drop(item);
+        checkpoint.commit("https://a").await.unwrap();
+        assert_eq!(
+            checkpoint.get("https://a").await.unwrap().as_deref(),
+            Some("https://a?page=3")
+        );
+
+        drop(third);
+        checkpoint.commit("https://a").await.unwrap();
+        assert_eq!(
+            checkpoint.get("https://a").await.unwrap().as_deref(),
+            Some("https://a?page=4")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_read_only() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let path = temp_dir.path().join("checkpoint.json");
+        PaginationCheckpoint::new(&path)
+            .set("https://a", "https://a?page=2")
+            .await
+            .unwrap();
+
+        let checkpoint = PaginationCheckpoint::new(&path).with_read_only(true);
+        drop(checkpoint.track("https://a?page=3"));
+        checkpoint.commit("https://a").await.unwrap();
+        checkpoint.clear("https://a").await.unwrap();
+
+        assert_eq!(
+            checkpoint.get("https://a").await.unwrap().as_deref(),
+            Some("https://a?page=2"),
+            "dry runs should resume from the checkpoint without changing it"
+        );
+    }
+}
@@ -0,0 +1,258 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::errors;
+
+/// The longest we will sleep before checking whether the backup has been cancelled.
+const CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The longest we will sleep before checking whether a scheduled time has been reached, so that
+/// changes to the system's clock (or time spent suspended) don't delay the next backup.
+const SCHEDULE_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Provides the current time and waits for delays to pass, allowing the timing of retries,
+/// backoffs and the scheduler to be tested without waiting on the real clock.
+#[async_trait::async_trait]
+pub trait Clock: Send + Sync {
+    /// The current (monotonic) time, used to measure delays.
+    fn now(&self) -> Instant;
+
+    /// The current time of day, used to schedule backups.
+    fn utc_now(&self) -> chrono::DateTime<chrono::Utc>;
+
+    /// Waits until the given duration has passed.
+    async fn sleep(&self, duration: Duration);
+
+    /// Waits until the given duration has passed, returning an error if the backup is cancelled
+    /// while we are waiting.
+    async fn sleep_cancellable(
+        &self,
+        duration: Duration,
+        cancel: &AtomicBool,
+    ) -> Result<(), errors::Error> {
+        let wake_at = self.now() + duration;
+        while let Some(remaining) = wake_at.checked_duration_since(self.now()) {
+            if remaining.is_zero() {
+                break;
+            }
+
+            if cancel.load(Ordering::Relaxed) {
+                return Err(cancelled());
+            }
+
+            self.sleep(remaining.min(CANCEL_CHECK_INTERVAL)).await;
+        }
+
+        Ok(())
+    }
+
+    /// Waits until the given time of day, or until the backup is cancelled.
+    async fn sleep_until(&self, time: chrono::DateTime<chrono::Utc>, cancel: &AtomicBool) {
+        while !cancel.load(Ordering::Relaxed) {
+            match (time - self.utc_now()).to_std() {
+                Ok(remaining) if !remaining.is_zero() => {
+                    self.sleep(remaining.min(SCHEDULE_CHECK_INTERVAL)).await
+                }
+                _ => break,
+            }
+        }
+    }
+}
+
+fn cancelled() -> errors::Error {
+    errors::user(
+        "The backup operation was cancelled by the user. Only partial data may have been backed up.",
+        "Allow the backup to complete fully before cancelling again.",
+    )
+}
+
+/// The real clock, which waits using tokio's timers.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+#[async_trait::async_trait]
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn utc_now(&self) -> chrono::DateTime<chrono::Utc> {
+        chrono::Utc::now()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+}
+
+/// A clock which records the delays it is asked to wait for (instead of waiting for them) and
+/// advances its own time by the same amount.
+#[cfg(test)]
+#[derive(Debug)]
+pub struct MockClock {
+    state: std::sync::Mutex<MockClockState>,
+}
+
+#[cfg(test)]
+#[derive(Debug)]
+struct MockClockState {
+    now: Instant,
+    utc_now: chrono::DateTime<chrono::Utc>,
+    slept: Vec<Duration>,
+}
+
+#[cfg(test)]
+impl MockClock {
+    pub fn new(utc_now: chrono::DateTime<chrono::Utc>) -> Self {
+        Self {
+            state: std::sync::Mutex::new(MockClockState {
+                now: Instant::now(),
+                utc_now,
+                slept: Vec::new(),
+            }),
+        }
+    }
+
+    /// The delays which this clock has been asked to wait for, in order.
+    pub fn slept(&self) -> Vec<Duration> {
+        self.state.lock().unwrap().slept.clone()
+    }
+
+    fn advance(&self, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.now += duration;
+        state.utc_now += duration;
+        state.slept.push(duration);
+    }
+}
+
+#[cfg(test)]
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new(chrono::Utc::now())
+    }
+}
+
+#[cfg(test)]
+#[async_trait::async_trait]
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.state.lock().unwrap().now
+    }
+
+    fn utc_now(&self) -> chrono::DateTime<chrono::Utc> {
+        self.state.lock().unwrap().utc_now
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+
+    /// Records the full delay at once (rather than the intervals between checks for
+    /// cancellation), so that tests can assert on the delays which were requested.
+    async fn sleep_cancellable(
+        &self,
+        duration: Duration,
+        cancel: &AtomicBool,
+    ) -> Result<(), errors::Error> {
+        if duration.is_zero() {
+            return Ok(());
+        }
+
+        if cancel.load(Ordering::Relaxed) {
+            return Err(cancelled());
+        }
+
+        self.advance(duration);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_system_clock() {
+        let clock = SystemClock;
+        let cancel = AtomicBool::new(false);
+
+        let start = clock.now();
+        clock
+            .sleep_cancellable(Duration::from_millis(150), &cancel)
+            .await
+            .expect("the sleep to complete");
+        assert!(clock.now() - start >= Duration::from_millis(150));
+
+        cancel.store(true, Ordering::Relaxed);
+        clock
+            .sleep_cancellable(Duration::from_secs(60), &cancel)
+            .await
+            .expect_err("the sleep to be cancelled");
+        assert!(clock.now() - start < Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn test_mock_clock() {
+        let start = chrono::Utc::now();
+        let clock = MockClock::new(start);
+        let cancel = AtomicBool::new(false);
+
+        let now = clock.now();
+        clock.sleep(Duration::from_secs(1)).await;
+        clock
+            .sleep_cancellable(Duration::from_secs(30), &cancel)
+            .await
+            .expect("the sleep to complete");
+
+        assert_eq!(
+            clock.slept(),
+            vec![Duration::from_secs(1), Duration::from_secs(30)]
+        );
+        assert_eq!(clock.now() - now, Duration::from_secs(31));
+        assert_eq!(clock.utc_now() - start, chrono::TimeDelta::seconds(31));
+
+        cancel.store(true, Ordering::Relaxed);
+        clock
+            .sleep_cancellable(Duration::from_secs(30), &cancel)
+            .await
+            .expect_err("the sleep to be cancelled");
+        assert_eq!(clock.slept().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_sleep_until() {
+        let start = chrono::Utc::now();
+        let clock = MockClock::new(start);
+        let cancel = AtomicBool::new(false);
+
+        clock
+            .sleep_until(start + chrono::TimeDelta::milliseconds(1200), &cancel)
+            .await;
+        assert_eq!(
+            clock.slept(),
+            vec![
+                Duration::from_millis(500),
+                Duration::from_millis(500),
+                Duration::from_millis(200)
+            ]
+        );
+
+        clock
+            .sleep_until(start - chrono::TimeDelta::seconds(1), &cancel)
+            .await;
+        assert_eq!(clock.slept().len(), 3, "past times shouldn't be waited for");
+
+        cancel.store(true, Ordering::Relaxed);
+        clock
+            .sleep_until(start + chrono::TimeDelta::hours(1), &cancel)
+            .await;
+        assert_eq!(
+            clock.slept().len(),
+            3,
+            "cancelled waits should end immediately"
+        );
+    }
+}
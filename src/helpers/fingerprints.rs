@@ -0,0 +1,94 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use crate::{errors, state::State, BackupEntity, BackupPolicy};
+
+/// Tracks the fingerprints of the entities which have been backed up by a policy, so that
+/// entities which haven't changed since their last successful backup can be skipped without
+/// contacting the remote.
+///
+/// Fingerprints are loaded from the policy's state file when the run starts, and any which were
+/// recorded during the run are written back to it once the run has completed.
+#[derive(Debug, Default)]
+pub struct Fingerprints {
+    previous: BTreeMap<String, String>,
+    recorded: Mutex<BTreeMap<String, String>>,
+}
+
+impl Fingerprints {
+    pub fn load(policy: &BackupPolicy) -> Result<Self, errors::Error> {
+        Ok(Self {
+            previous: State::load(&State::path_for(policy))?
+                .policy(policy)
+                .fingerprints,
+            recorded: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    /// Determines whether the entity has the same fingerprint as it did when it was last
+    /// successfully backed up, returning that fingerprint if it does.
+    pub fn unchanged<'e, E: BackupEntity>(&self, entity: &'e E) -> Option<&'e str> {
+        let fingerprint = entity.fingerprint()?;
+        match self.previous.get(entity.name()) {
+            Some(previous) if previous == fingerprint => Some(fingerprint),
+            _ => None,
+        }
+    }
+
+    /// Records the fingerprint of an entity which has been successfully backed up.
+    pub fn record<E: BackupEntity>(&self, entity: &E) {
+        if let Some(fingerprint) = entity.fingerprint() {
+            self.recorded
+                .lock()
+                .unwrap()
+                .insert(entity.name().to_string(), fingerprint.to_string());
+        }
+    }
+
+    /// Writes the fingerprints which were recorded during this run to the policy's state file.
+    pub async fn save(&self, policy: &BackupPolicy) -> Result<(), errors::Error> {
+        let recorded = std::mem::take(&mut *self.recorded.lock().unwrap());
+        let changed = recorded
+            .iter()
+            .any(|(name, fingerprint)| self.previous.get(name) != Some(fingerprint));
+        if !changed {
+            return Ok(());
+        }
+
+        State::update(policy, |s| s.fingerprints.extend(recorded)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::entities::HttpFile;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fingerprints() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let policy: BackupPolicy = serde_yaml::from_str(&format!(
+            "{{ kind: github/release, from: users/notheotherben, to: '{}' }}",
+            temp_dir.path().display()
+        ))
+        .unwrap();
+
+        let file = HttpFile::new("a/b.bin", "https://example.com/a/b.bin").with_fingerprint("v1");
+        let unknown = HttpFile::new("a/c.bin", "https://example.com/a/c.bin");
+
+        let fingerprints = Fingerprints::load(&policy).expect("fingerprints to load");
+        assert_eq!(fingerprints.unchanged(&file), None);
+
+        fingerprints.record(&file);
+        fingerprints.record(&unknown);
+        fingerprints
+            .save(&policy)
+            .await
+            .expect("fingerprints to be saved");
+
+        let fingerprints = Fingerprints::load(&policy).expect("fingerprints to load");
+        assert_eq!(fingerprints.unchanged(&file), Some("v1"));
+        assert_eq!(fingerprints.unchanged(&unknown), None);
+        assert_eq!(fingerprints.unchanged(&file.with_fingerprint("v2")), None);
+    }
+}
@@ -3,20 +3,68 @@ use std::{
     sync::{atomic::AtomicBool, Arc},
 };
 
-use reqwest::{header::LINK, Method, StatusCode, Url};
-use tokio_stream::Stream;
+use reqwest::{
+    header::{HeaderMap, HeaderValue, ACCEPT, LINK},
+    Method, StatusCode, Url,
+};
+use tokio_stream::{Stream, StreamExt};
 
 use crate::{
     entities::{Credentials, MetadataSource},
     errors::{self, ResponseError},
+    FilterValue,
+};
+
+use super::{
+    checkpoint::{PageGuard, PaginationCheckpoint},
+    Clock, RateLimit, SystemClock, Throttle,
 };
 
+/// The number of times a request which was rejected by one of GitHub's secondary rate limits is
+/// retried before the rejection is reported as an error.
+const SECONDARY_RATE_LIMIT_RETRIES: usize = 3;
+
+/// How long to wait before retrying a request which was rejected by one of GitHub's secondary rate
+/// limits, when GitHub doesn't tell us how long to wait (GitHub recommends at least a minute).
+const SECONDARY_RATE_LIMIT_BACKOFF: std::time::Duration = std::time::Duration::from_secs(60);
+
 #[derive(Clone)]
 pub struct GitHubClient {
     client: Arc<reqwest::Client>,
+    rate_limit: RateLimit,
+    throttle: Option<Throttle>,
+    clock: Arc<dyn Clock>,
 }
 
 impl GitHubClient {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self {
+            client: Arc::new(client),
+            rate_limit: RateLimit::default(),
+            throttle: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Uses the given clock to wait before retrying requests (and between throttled requests).
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            rate_limit: self.rate_limit.with_clock(clock.clone()),
+            clock,
+            ..self
+        }
+    }
+
+    /// Limits the rate at which this client (and all of its clones) send requests to GitHub.
+    pub fn with_throttle(self, throttle: Option<Throttle>) -> Self {
+        Self { throttle, ..self }
+    }
+
+    /// The rate limit reported by the GitHub API in response to this client's requests.
+    pub fn rate_limit(&self) -> RateLimit {
+        self.rate_limit.clone()
+    }
+
     #[allow(dead_code)]
     pub async fn get<T: serde::de::DeserializeOwned>(
         &self,
@@ -26,16 +74,138 @@ impl GitHubClient {
     ) -> Result<T, errors::Error> {
         let resp = self.call(Method::GET, &url, creds, |r| r, cancel).await?;
 
-        resp.json().await.map_err(|e| {
+        Self::parse_json(&url, resp).await
+    }
+
+    /// Fetches a resource which may not exist, returning `None` if GitHub
+    /// responds with a 404 Not Found status code.
+    pub async fn get_optional<T: serde::de::DeserializeOwned>(
+        &self,
+        url: String,
+        creds: &Credentials,
+        cancel: &AtomicBool,
+    ) -> Result<Option<T>, errors::Error> {
+        let resp = self.send(Method::GET, &url, creds, |r| r, cancel).await?;
+
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let resp = Self::check_status(resp, creds).await?;
+        Self::parse_json(&url, resp).await.map(Some)
+    }
+
+    /// Fetches the raw content of a resource which may not exist (using the given media type),
+    /// returning `None` if GitHub responds with a 404 Not Found status code.
+    pub async fn get_raw_optional(
+        &self,
+        url: String,
+        accept: &str,
+        creds: &Credentials,
+        cancel: &AtomicBool,
+    ) -> Result<Option<Vec<u8>>, errors::Error> {
+        let accept = HeaderValue::from_str(accept).map_err(|e| {
             errors::system_with_internal(
-                &format!(
-                    "Unable to parse GitHub's response for '{}' due to invalid JSON.",
-                    &url
-                ),
+                &format!("The media type '{accept}' is not a valid Accept header."),
                 "Please report this issue to us on GitHub.",
                 e,
             )
-        })
+        })?;
+
+        // Replacing the headers (rather than adding another) ensures that we don't also send the
+        // default JSON Accept header.
+        let headers = HeaderMap::from_iter([(ACCEPT, accept)]);
+        let resp = self
+            .send(
+                Method::GET,
+                &url,
+                creds,
+                |r| r.headers(headers.clone()),
+                cancel,
+            )
+            .await?;
+
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let resp = Self::check_status(resp, creds).await?;
+        let content = resp.bytes().await.map_err(|e| {
+            errors::system_with_internal(
+                &format!("Unable to read GitHub's response for '{url}'."),
+                "Make sure that your network connection is working correctly and try again.",
+                e,
+            )
+        })?;
+
+        Ok(Some(content.to_vec()))
+    }
+
+    /// Determines which of a chain of credentials is able to access the given resource, so that
+    /// they can be used by tools (like git) which can't fall back to the next credentials on their
+    /// own. Any other credentials are returned unchanged without making a request.
+    pub async fn resolve_credentials(
+        &self,
+        url: &str,
+        creds: &Credentials,
+        cancel: &AtomicBool,
+    ) -> Result<Credentials, errors::Error> {
+        // Per-host credentials are resolved when each request is made (since git clones from a
+        // different host to the one serving GitHub's API), so they're returned unchanged here.
+        let Credentials::Chain(chain) = creds else {
+            return Ok(creds.clone());
+        };
+
+        let mut status = None;
+        for creds in chain.iter() {
+            let resp = self
+                .send_with(Method::GET, url, creds, |r| r, cancel)
+                .await?;
+            if resp.status().is_success() {
+                return Ok(creds.clone());
+            }
+
+            status = Some(resp.status());
+        }
+
+        Err(Self::chain_rejected(url, creds, status))
+    }
+
+    /// Reports that every set of credentials in a chain was rejected, naming the credentials which
+    /// were tried (and where they were loaded from) so that the missing access can be granted.
+    fn chain_rejected(url: &str, creds: &Credentials, status: Option<StatusCode>) -> errors::Error {
+        let status = status
+            .map(|s| format!(" (the last responded with a {s} status code)"))
+            .unwrap_or_default();
+
+        errors::user(
+            &format!("None of your credentials were able to access '{url}'{status}, after trying: {creds}."),
+            "Make sure that at least one of the credentials in your list has been granted access to this resource.",
+        )
+    }
+
+    /// Retrieves the OAuth scopes which have been granted to a (classic) token, returning `None`
+    /// if GitHub does not report them (as is the case for fine-grained tokens).
+    pub async fn get_token_scopes(
+        &self,
+        api_url: &str,
+        creds: &Credentials,
+        cancel: &AtomicBool,
+    ) -> Result<Option<Vec<String>>, errors::Error> {
+        let url = format!("{}/", api_url.trim_end_matches('/'));
+        let resp = self.call(Method::GET, &url, creds, |r| r, cancel).await?;
+
+        Ok(resp
+            .headers()
+            .get("x-oauth-scopes")
+            .and_then(|h| h.to_str().ok())
+            .map(|scopes| {
+                scopes
+                    .split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect()
+            }))
     }
 
     pub fn get_paginated<'a, T: serde::de::DeserializeOwned + 'a>(
@@ -44,9 +214,45 @@ impl GitHubClient {
         creds: &'a Credentials,
         cancel: &'a AtomicBool,
     ) -> impl Stream<Item = Result<T, errors::Error>> + 'a {
+        self.paginate(page_url, None, creds, cancel)
+            .map(|result| result.map(|(item, _)| item))
+    }
+
+    /// Fetches a paginated listing, recording the next page to be fetched in the provided
+    /// checkpoint once every item from a page has finished being processed (which is signalled
+    /// by dropping the [`PageGuard`] returned with it). If a checkpoint already exists for this
+    /// listing, pagination resumes from it rather than from the first page.
+    ///
+    /// The checkpoint is only updated while the listing is being consumed, and is cleared once
+    /// the final page has been fetched.
+    pub fn get_paginated_resumable<'a, T: serde::de::DeserializeOwned + 'a>(
+        &'a self,
+        page_url: String,
+        checkpoint: &'a PaginationCheckpoint,
+        creds: &'a Credentials,
+        cancel: &'a AtomicBool,
+    ) -> impl Stream<Item = Result<(T, Option<PageGuard>), errors::Error>> + 'a {
+        self.paginate(page_url, Some(checkpoint), creds, cancel)
+    }
+
+    fn paginate<'a, T: serde::de::DeserializeOwned + 'a>(
+        &'a self,
+        page_url: String,
+        checkpoint: Option<&'a PaginationCheckpoint>,
+        creds: &'a Credentials,
+        cancel: &'a AtomicBool,
+    ) -> impl Stream<Item = Result<(T, Option<PageGuard>), errors::Error>> + 'a {
         async_stream::try_stream! {
+          let base_url = page_url.clone();
           let mut page_url = Some(page_url);
 
+          if let Some(checkpoint) = checkpoint {
+              if let Some(next_url) = checkpoint.get(&base_url).await? {
+                  tracing_batteries::prelude::info!("Resuming pagination of {} from {}", &base_url, &next_url);
+                  page_url = Some(next_url);
+              }
+          }
+
           while let Some(url) = page_url {
               if cancel.load(std::sync::atomic::Ordering::Relaxed) {
                   Err(errors::user(
@@ -54,6 +260,10 @@ impl GitHubClient {
                       "Allow the backup to complete fully before cancelling again."))?;
               }
 
+              if let Some(checkpoint) = checkpoint {
+                  checkpoint.commit(&base_url).await?;
+              }
+
               let resp = self.call(Method::GET, &url, creds, |r| r, cancel).await?;
 
               if let Some(link_header) = resp.headers().get(LINK) {
@@ -68,7 +278,7 @@ impl GitHubClient {
                       e))?;
 
                   if let Some(next_link) = links.get("next") {
-                      page_url = Some(next_link.raw_uri.clone());
+                      page_url = Some(resolve_next_link(&url, &base_url, &next_link.raw_uri)?);
                   } else {
                       page_url = None;
                   }
@@ -76,11 +286,22 @@ impl GitHubClient {
                   page_url = None;
               }
 
+              let guard = match (checkpoint, &page_url) {
+                  (Some(checkpoint), Some(next_url)) => Some(checkpoint.track(next_url)),
+                  _ => None,
+              };
+
               match resp.json::<Vec<T>>().await {
                 Ok(results) => {
                   for result in results.into_iter() {
-                      yield result;
+                      if let Some(checkpoint) = checkpoint {
+                          checkpoint.commit(&base_url).await?;
+                      }
+
+                      yield (result, guard.clone());
                   }
+
+                  drop(guard);
                 },
                 Err(err) => {
                   Err(errors::system_with_internal(
@@ -89,21 +310,119 @@ impl GitHubClient {
                       err))?;
                 }
               }
+
+              if let (Some(checkpoint), None) = (checkpoint, &page_url) {
+                  checkpoint.clear(&base_url).await?;
+              }
           }
         }
     }
 
-    async fn call<B>(
+    async fn send<B>(
+        &self,
+        method: Method,
+        url: &str,
+        creds: &Credentials,
+        builder: B,
+        cancel: &AtomicBool,
+    ) -> Result<reqwest::Response, errors::Error>
+    where
+        B: Fn(reqwest::RequestBuilder) -> reqwest::RequestBuilder,
+    {
+        let creds = &creds.for_url(url);
+        let Credentials::Chain(chain) = creds else {
+            return self.send_with(method, url, creds, builder, cancel).await;
+        };
+
+        // Each set of credentials in a chain is tried in turn until one of them is able to access
+        // the resource, however we don't fall back on network errors since the next set of
+        // credentials is unlikely to fare any better.
+        for (i, creds) in chain.iter().enumerate() {
+            let resp = self
+                .send_with(method.clone(), url, creds, &builder, cancel)
+                .await?;
+
+            if i + 1 < chain.len()
+                && matches!(resp.status(), StatusCode::FORBIDDEN | StatusCode::NOT_FOUND)
+            {
+                tracing_batteries::prelude::debug!(
+                    "Got an HTTP {} status code when requesting '{url}' with credentials #{}, falling back to the next credentials.",
+                    resp.status(),
+                    i + 1
+                );
+                continue;
+            }
+
+            return Ok(resp);
+        }
+
+        Err(Self::chain_rejected(url, creds, None))
+    }
+
+    async fn send_with<B>(
         &self,
         method: Method,
         url: &str,
         creds: &Credentials,
         builder: B,
-        _cancel: &AtomicBool,
+        cancel: &AtomicBool,
+    ) -> Result<reqwest::Response, errors::Error>
+    where
+        B: Fn(reqwest::RequestBuilder) -> reqwest::RequestBuilder,
+    {
+        let mut resp = self
+            .send_once(method.clone(), url, creds, &builder, cancel)
+            .await?;
+
+        // Secondary rate limits are applied when too many requests are made concurrently (or too
+        // quickly), and unlike authorization failures they are resolved by waiting a little while.
+        for attempt in 1..=SECONDARY_RATE_LIMIT_RETRIES {
+            let (backoff, checked) = Self::check_secondary_rate_limit(resp).await;
+            resp = checked;
+
+            let Some(backoff) = backoff else {
+                break;
+            };
+
+            tracing_batteries::prelude::warn!(
+                "GitHub's secondary rate limit was exceeded while requesting '{url}', waiting {:?} before retrying (attempt {attempt} of {SECONDARY_RATE_LIMIT_RETRIES}).",
+                backoff
+            );
+            self.clock.sleep_cancellable(backoff, cancel).await?;
+            resp = self
+                .send_once(method.clone(), url, creds, &builder, cancel)
+                .await?;
+        }
+
+        // Tokens which are rotated by another process may expire part way through a long backup
+        // run, so we try once more with the replacement token (if there is one).
+        if resp.status() == StatusCode::UNAUTHORIZED {
+            if let Some(creds) = creds.refresh() {
+                tracing_batteries::prelude::info!(
+                    "Retrying the request to '{url}' with refreshed credentials."
+                );
+                return self.send_once(method, url, &creds, &builder, cancel).await;
+            }
+        }
+
+        Ok(resp)
+    }
+
+    async fn send_once<B>(
+        &self,
+        method: Method,
+        url: &str,
+        creds: &Credentials,
+        builder: B,
+        cancel: &AtomicBool,
     ) -> Result<reqwest::Response, errors::Error>
     where
         B: FnOnce(reqwest::RequestBuilder) -> reqwest::RequestBuilder,
     {
+        if let Some(throttle) = &self.throttle {
+            throttle.wait(self.clock.as_ref(), cancel).await?;
+        }
+
         let parsed_url: Url = url.parse().map_err(|e| {
             errors::user_with_internal(
                 &format!("Unable to parse GitHub URL '{}' as a valid URL.", &url),
@@ -112,32 +431,116 @@ impl GitHubClient {
             )
         })?;
 
-        let mut req = self
+        let req = self
             .client
             .request(method, parsed_url)
             .header("Accept", "application/vnd.github.v3+json")
             .header("X-GitHub-Api-Version", "2022-11-28")
             .header("User-Agent", "SierraSoftworks/github-backup");
 
-        req = match creds {
+        let req = builder(Self::authenticate(req, creds));
+
+        let resp = req.send().await?;
+        self.rate_limit.observe(resp.headers());
+        Ok(resp)
+    }
+
+    /// Determines whether a response was rejected by one of GitHub's secondary rate limits,
+    /// returning how long we should wait before retrying along with the response itself (which
+    /// is rebuilt if its body had to be read to classify it).
+    async fn check_secondary_rate_limit(
+        resp: reqwest::Response,
+    ) -> (Option<std::time::Duration>, reqwest::Response) {
+        if !matches!(
+            resp.status(),
+            StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS
+        ) {
+            return (None, resp);
+        }
+
+        if let Some(backoff) = secondary_rate_limit_backoff(resp.status(), resp.headers(), None) {
+            return (Some(backoff), resp);
+        }
+
+        let status = resp.status();
+        let headers = resp.headers().clone();
+        let url = resp.url().clone();
+        let body = resp.bytes().await.unwrap_or_default();
+        let backoff =
+            secondary_rate_limit_backoff(status, &headers, std::str::from_utf8(&body).ok());
+
+        // The rebuilt response keeps the original URL, since it is used to select the credentials
+        // which were used for the request and to describe any failures.
+        use reqwest::ResponseBuilderExt;
+        let mut rebuilt = axum::http::Response::builder()
+            .url(url)
+            .body(body)
+            .unwrap_or_default();
+        *rebuilt.status_mut() = status;
+        *rebuilt.headers_mut() = headers;
+        (backoff, rebuilt.into())
+    }
+
+    fn authenticate(req: reqwest::RequestBuilder, creds: &Credentials) -> reqwest::RequestBuilder {
+        match creds {
             Credentials::None => req,
-            Credentials::Token(token) => req.bearer_auth(token),
-            Credentials::UsernamePassword { username, password } => {
-                req.basic_auth(username, Some(password))
+            Credentials::Token { value: token, .. } => req.bearer_auth(token),
+            Credentials::UsernamePassword {
+                username, password, ..
+            } => req.basic_auth(username, Some(password)),
+            // Chains are resolved by `send`, so this only happens if one is nested in another.
+            Credentials::Chain(chain) => {
+                Self::authenticate(req, chain.first().unwrap_or(&Credentials::None))
             }
-        };
-
-        let req = builder(req);
+            // Per-host credentials are resolved by `send` before any request is made.
+            Credentials::PerHost(_) => req,
+        }
+    }
 
-        let resp = req.send().await?;
+    async fn call<B>(
+        &self,
+        method: Method,
+        url: &str,
+        creds: &Credentials,
+        builder: B,
+        cancel: &AtomicBool,
+    ) -> Result<reqwest::Response, errors::Error>
+    where
+        B: Fn(reqwest::RequestBuilder) -> reqwest::RequestBuilder,
+    {
+        let resp = self.send(method, url, creds, builder, cancel).await?;
+        Self::check_status(resp, creds).await
+    }
 
+    async fn check_status(
+        resp: reqwest::Response,
+        creds: &Credentials,
+    ) -> Result<reqwest::Response, errors::Error> {
         if resp.status().is_success() {
             Ok(resp)
         } else if resp.status() == StatusCode::UNAUTHORIZED {
-            Err(errors::user(
-                "The access token you have provided was rejected by the GitHub API.",
+            Err(errors::authentication(
+                errors::AUTHENTICATION_FAILED,
                 "Make sure that your GitHub token is valid and has not expired.",
             ))
+        } else if creds.for_url(resp.url().as_str()) == Credentials::None
+            && is_rate_limited(resp.status(), resp.headers())
+        {
+            let resets_at = resp
+                .headers()
+                .get("x-ratelimit-reset")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<i64>().ok())
+                .and_then(|reset| chrono::DateTime::from_timestamp(reset, 0))
+                .map(|reset| format!(" (it resets at {})", reset.format("%Y-%m-%dT%H:%M:%SZ")))
+                .unwrap_or_default();
+            let err = ResponseError::with_body(resp).await;
+
+            Err(errors::user_with_internal(
+                &format!("GitHub's rate limit for unauthenticated requests has been exceeded{resets_at}, since no credentials have been configured for this backup policy."),
+                "Add a GitHub token to the 'credentials' of your backup policy (for example `credentials: !Token \"your_github_pat\"`), which raises the rate limit from 60 to 5,000 requests per hour.",
+                err,
+            ))
         } else {
             let err = ResponseError::with_body(resp).await;
             Err(errors::user_with_internal(
@@ -150,13 +553,130 @@ impl GitHubClient {
             ))
         }
     }
+
+    async fn parse_json<T: serde::de::DeserializeOwned>(
+        url: &str,
+        resp: reqwest::Response,
+    ) -> Result<T, errors::Error> {
+        resp.json().await.map_err(|e| {
+            errors::system_with_internal(
+                &format!(
+                    "Unable to parse GitHub's response for '{}' due to invalid JSON.",
+                    url
+                ),
+                "Please report this issue to us on GitHub.",
+                e,
+            )
+        })
+    }
+}
+
+/// Determines whether a response was rejected because GitHub's (primary) rate limit has been exhausted.
+fn is_rate_limited(status: StatusCode, headers: &HeaderMap) -> bool {
+    matches!(
+        status,
+        StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS
+    ) && headers
+        .get("x-ratelimit-remaining")
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        == Some("0")
+}
+
+/// Resolves the `next` link from a paginated response (which was returned when requesting `url`)
+/// into the URL of the next page to request.
+///
+/// GitHub Enterprise Server may return relative links, or links which refer to its own hostname
+/// when it sits behind a proxy, so relative links are resolved against the request URL and links
+/// to other hosts are rewritten to use the scheme, host and port of the listing's `base_url`
+/// (ensuring that we never send credentials to a host other than the one that was configured).
+fn resolve_next_link(url: &str, base_url: &str, next: &str) -> Result<String, errors::Error> {
+    let parse = |url: &str| {
+        reqwest::Url::parse(url).map_err(|e| {
+            errors::system_with_internal(
+                &format!(
+                    "Unable to parse the URL '{url}' while following GitHub's pagination links."
+                ),
+                "Please report this issue to us on GitHub.",
+                e,
+            )
+        })
+    };
+
+    let current = parse(url)?;
+    let base = parse(base_url)?;
+    let mut resolved = current.join(next).map_err(|e| {
+        errors::system_with_internal(
+            &format!("Unable to resolve the next page link '{next}' returned by GitHub when requesting '{url}'."),
+            "Please report this issue to us on GitHub.",
+            e,
+        )
+    })?;
+
+    if resolved.origin() != base.origin() {
+        tracing_batteries::prelude::debug!(
+            "Rewriting the next page link '{resolved}' to use '{}', since it refers to a different host",
+            base.origin().ascii_serialization()
+        );
+
+        // These can only fail for URLs which cannot have a host (like `mailto:`), which the
+        // join above would not have produced from an HTTP(S) request URL.
+        resolved
+            .set_scheme(base.scheme())
+            .and_then(|_| resolved.set_host(base.host_str()).map_err(|_| ()))
+            .and_then(|_| resolved.set_port(base.port()))
+            .map_err(|_| {
+                errors::system(
+                    &format!("Unable to rewrite the next page link '{next}' returned by GitHub to use '{base}'."),
+                    "Please report this issue to us on GitHub.",
+                )
+            })?;
+    }
+
+    Ok(resolved.to_string())
+}
+
+/// Classifies a `403 Forbidden` or `429 Too Many Requests` response as having been rejected by one of
+/// GitHub's secondary rate limits (rather than by its primary rate limit, or because our credentials
+/// don't have access to the resource), returning how long we should wait before retrying it.
+///
+/// The response's `body` is only needed when GitHub hasn't provided a `Retry-After` header.
+fn secondary_rate_limit_backoff(
+    status: StatusCode,
+    headers: &HeaderMap,
+    body: Option<&str>,
+) -> Option<std::time::Duration> {
+    if !matches!(
+        status,
+        StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS
+    ) {
+        return None;
+    }
+
+    // Once the primary rate limit is exhausted, retrying won't help until it resets (which may be
+    // up to an hour away), so we leave those responses to be reported as errors.
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+    };
+    if header("x-ratelimit-remaining") == Some("0") {
+        return None;
+    }
+
+    if let Some(retry_after) = header("retry-after").and_then(|v| v.parse::<u64>().ok()) {
+        return Some(std::time::Duration::from_secs(retry_after));
+    }
+
+    let body = body?.to_ascii_lowercase();
+    (body.contains("secondary rate limit") || body.contains("abuse detection"))
+        .then_some(SECONDARY_RATE_LIMIT_BACKOFF)
 }
 
 impl Default for GitHubClient {
     fn default() -> Self {
-        Self {
-            client: Arc::new(reqwest::Client::new()),
-        }
+        Self::new(reqwest::Client::new())
     }
 }
 
@@ -311,6 +831,8 @@ pub struct GitHubRepo {
     pub open_issues_count: u64,
     pub is_template: bool,
     pub topics: Vec<String>,
+    #[serde(default)]
+    pub visibility: Option<String>,
     pub has_issues: bool,
     pub has_projects: bool,
     pub has_wiki: bool,
@@ -319,12 +841,36 @@ pub struct GitHubRepo {
     pub has_discussions: bool,
     pub archived: bool,
     pub disabled: bool,
+    /// The permissions which the authenticated user has on this repository, which GitHub omits
+    /// for unauthenticated requests (in which case they are all treated as `false`).
+    #[serde(default)]
+    pub permissions: GitHubRepoPermissions,
+    /// The repository which this fork was created from, which GitHub only includes when a single
+    /// repository is fetched (and never when listing repositories).
+    #[serde(default)]
+    pub parent: Option<GitHubRepoRef>,
+    /// The root of the fork network that this fork belongs to, which is only included alongside
+    /// the `parent`.
+    #[serde(default)]
+    pub source: Option<GitHubRepoRef>,
 
     pub pushed_at: chrono::DateTime<chrono::Utc>,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub updated_at: chrono::DateTime<chrono::Utc>,
 }
 
+impl GitHubRepo {
+    /// Identifies the current version of the repository, which changes whenever it is pushed to
+    /// (or its settings are updated).
+    pub fn fingerprint(&self) -> String {
+        format!(
+            "pushed_at={},updated_at={}",
+            self.pushed_at.to_rfc3339(),
+            self.updated_at.to_rfc3339()
+        )
+    }
+}
+
 impl Display for GitHubRepo {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{}", self.full_name)
@@ -335,10 +881,13 @@ impl MetadataSource for GitHubRepo {
     fn inject_metadata(&self, metadata: &mut crate::entities::Metadata) {
         metadata.insert("repo.name", self.name.as_str());
         metadata.insert("repo.fullname", self.full_name.as_str());
+        metadata.insert("repo.owner", self.owner.login.as_str());
+        metadata.insert("repo.owner_type", self.owner.type_.as_str());
         metadata.insert("repo.private", self.private);
         metadata.insert("repo.public", !self.private);
         metadata.insert("repo.fork", self.fork);
-        metadata.insert("repo.size", self.size as u32);
+        // GitHub reports repository sizes in kilobytes, while every other size is in bytes.
+        metadata.insert("repo.size", self.size * 1024);
         metadata.insert("repo.archived", self.archived);
         metadata.insert("repo.disabled", self.disabled);
         metadata.insert("repo.default_branch", self.default_branch.as_str());
@@ -346,9 +895,56 @@ impl MetadataSource for GitHubRepo {
         metadata.insert("repo.template", self.is_template);
         metadata.insert("repo.forks", self.forks_count as u32);
         metadata.insert("repo.stargazers", self.stargazers_count as u32);
+        metadata.insert("repo.created_at", self.created_at);
+        metadata.insert("repo.updated_at", self.updated_at);
+        metadata.insert("repo.pushed_at", self.pushed_at);
+        metadata.insert(
+            "repo.topics",
+            self.topics
+                .iter()
+                .map(|t| t.as_str().into())
+                .collect::<Vec<FilterValue>>(),
+        );
+        metadata.insert(
+            "repo.visibility",
+            self.visibility
+                .as_deref()
+                .unwrap_or(if self.private { "private" } else { "public" }),
+        );
+        metadata.insert("repo.can_admin", self.permissions.admin);
+        metadata.insert("repo.can_push", self.permissions.push);
+        metadata.insert("repo.can_pull", self.permissions.pull);
+        metadata.insert(
+            "repo.parent",
+            self.parent.as_ref().map(|p| p.full_name.as_str()),
+        );
+        metadata.insert(
+            "repo.source",
+            self.source.as_ref().map(|s| s.full_name.as_str()),
+        );
     }
 }
 
+/// A reference to another repository, as included in the `parent` and `source` fields of a fork.
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq, serde::Deserialize)]
+pub struct GitHubRepoRef {
+    pub id: u64,
+    pub full_name: String,
+}
+
+/// The permissions which the authenticated user has on a repository.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Deserialize)]
+pub struct GitHubRepoPermissions {
+    #[serde(default)]
+    pub admin: bool,
+    #[serde(default)]
+    pub push: bool,
+    #[serde(default)]
+    pub pull: bool,
+}
+
 /// A user returned by the GitHub API.
 ///
 /// ```json
@@ -504,6 +1100,8 @@ impl MetadataSource for GitHubRelease {
         metadata.insert("release.draft", self.draft);
         metadata.insert("release.prerelease", self.prerelease);
         metadata.insert("release.published", self.published_at.is_some());
+        metadata.insert("release.asset_count", self.assets.len() as u32);
+        metadata.insert("release.has_assets", !self.assets.is_empty());
     }
 }
 
@@ -563,9 +1161,37 @@ pub struct GitHubReleaseAsset {
     pub uploader: GitHubUser,
 }
 
+impl GitHubReleaseAsset {
+    /// The (lowercase) file extension of the asset, treating compressed tarballs (like `.tar.gz`)
+    /// as a single extension.
+    pub fn extension(&self) -> Option<String> {
+        let name = self.name.to_lowercase();
+        let (stem, ext) = name.rsplit_once('.')?;
+        if stem.is_empty() || ext.is_empty() {
+            return None;
+        }
+
+        match stem.rsplit_once('.') {
+            Some((prefix, "tar")) if !prefix.is_empty() => Some(format!("tar.{ext}")),
+            _ => Some(ext.to_string()),
+        }
+    }
+
+    /// Identifies the current version of the asset, which changes whenever it is re-uploaded.
+    pub fn fingerprint(&self) -> String {
+        format!(
+            "updated_at={},size={}",
+            self.updated_at.to_rfc3339(),
+            self.size
+        )
+    }
+}
+
 impl MetadataSource for GitHubReleaseAsset {
     fn inject_metadata(&self, metadata: &mut crate::entities::Metadata) {
         metadata.insert("asset.name", self.name.as_str());
+        metadata.insert("asset.extension", self.extension());
+        metadata.insert("asset.content_type", self.content_type.as_str());
         metadata.insert("asset.size", self.size);
         metadata.insert("asset.downloaded", self.download_count > 0);
     }
@@ -617,7 +1243,9 @@ impl std::str::FromStr for GitHubRepoSourceKind {
 }
 
 #[allow(dead_code)]
-#[derive(PartialEq, Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(
+    PartialEq, Debug, Copy, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema,
+)]
 pub enum GitHubArtifactKind {
     #[serde(rename = "github/repo")]
     Repo,
@@ -625,6 +1253,8 @@ pub enum GitHubArtifactKind {
     Star,
     #[serde(rename = "github/release")]
     Release,
+    #[serde(rename = "github/pages")]
+    Pages,
 }
 
 impl GitHubArtifactKind {
@@ -633,6 +1263,7 @@ impl GitHubArtifactKind {
             GitHubArtifactKind::Repo => "github/repo",
             GitHubArtifactKind::Star => "github/star",
             GitHubArtifactKind::Release => "github/release",
+            GitHubArtifactKind::Pages => "github/pages",
         }
     }
 
@@ -641,6 +1272,7 @@ impl GitHubArtifactKind {
             GitHubArtifactKind::Repo => "repos",
             GitHubArtifactKind::Star => "starred",
             GitHubArtifactKind::Release => "repos",
+            GitHubArtifactKind::Pages => "repos",
         }
     }
 }
@@ -652,6 +1284,8 @@ mod tests {
     use rstest::rstest;
     use serde::de::DeserializeOwned;
 
+    use crate::helpers::clock::MockClock;
+
     use super::*;
 
     static CANCEL: AtomicBool = AtomicBool::new(false);
@@ -683,7 +1317,193 @@ mod tests {
             assert_eq!(metadata.get("repo.archived"), repo.archived.into());
             assert_eq!(metadata.get("repo.disabled"), repo.disabled.into());
             assert_eq!(metadata.get("repo.empty"), (repo.size == 0).into());
+            assert_eq!(metadata.get("repo.size"), (repo.size * 1024).into());
+            assert_eq!(metadata.get("repo.pushed_at"), repo.pushed_at.into());
+            assert_eq!(metadata.get("repo.pushed_at").type_name(), "date");
+            assert_eq!(
+                metadata.get("repo.topics"),
+                FilterValue::Tuple(repo.topics.iter().map(|t| t.as_str().into()).collect())
+            );
+            assert_eq!(
+                metadata.get("repo.visibility"),
+                repo.visibility.unwrap().into()
+            );
+            assert_eq!(metadata.get("repo.can_push"), repo.permissions.push.into());
+            assert_eq!(
+                metadata.get("repo.parent"),
+                FilterValue::Null,
+                "repository listings don't include the parent of forks"
+            );
+        }
+    }
+
+    #[rstest]
+    #[case(None, None)]
+    #[case(Some("upstream/thing"), Some("origin/thing"))]
+    fn test_repo_parent(#[case] parent: Option<&str>, #[case] source: Option<&str>) {
+        let mut repos: Vec<serde_json::Value> =
+            load_test_file("github.repos.0.json").expect("Failed to load test file");
+        let mut repo = repos.remove(0);
+        if let Some(parent) = parent {
+            repo["parent"] = serde_json::json!({ "id": 1, "full_name": parent, "fork": true });
+        }
+        if let Some(source) = source {
+            repo["source"] = serde_json::json!({ "id": 2, "full_name": source, "fork": false });
+        }
+
+        let repo: GitHubRepo = serde_json::from_value(repo).expect("Failed to parse repo");
+        assert_eq!(repo.parent.as_ref().map(|p| p.full_name.as_str()), parent);
+        assert_eq!(repo.source.as_ref().map(|s| s.full_name.as_str()), source);
+
+        let mut metadata = crate::entities::Metadata::default();
+        repo.inject_metadata(&mut metadata);
+        assert_eq!(metadata.get("repo.parent"), parent.into());
+        assert_eq!(metadata.get("repo.source"), source.into());
+
+        let entity =
+            crate::entities::GitRepo::new(repo.full_name.as_str(), repo.clone_url.as_str(), None)
+                .with_metadata_source(&repo);
+        let filter = crate::Filter::new(r#"repo.parent == "upstream/thing""#)
+            .expect("Failed to parse filter");
+        assert_eq!(
+            filter.matches(&entity).expect("Failed to evaluate filter"),
+            parent.is_some()
+        );
+    }
+
+    #[rstest]
+    #[case(
+        Some(r#"{"admin": true, "push": true, "pull": true}"#),
+        GitHubRepoPermissions { admin: true, push: true, pull: true }
+    )]
+    #[case(
+        Some(r#"{"admin": false, "push": false, "pull": true}"#),
+        GitHubRepoPermissions { admin: false, push: false, pull: true }
+    )]
+    #[case(Some(r#"{"pull": true}"#), GitHubRepoPermissions { admin: false, push: false, pull: true })]
+    #[case(None, GitHubRepoPermissions::default())]
+    fn test_repo_permissions(
+        #[case] permissions: Option<&str>,
+        #[case] expected: GitHubRepoPermissions,
+    ) {
+        let mut repos: Vec<serde_json::Value> =
+            load_test_file("github.repos.0.json").expect("Failed to load test file");
+        let mut repo = repos.remove(0);
+        match permissions {
+            Some(permissions) => repo["permissions"] = serde_json::from_str(permissions).unwrap(),
+            None => {
+                repo.as_object_mut().unwrap().remove("permissions");
+            }
         }
+
+        let repo: GitHubRepo = serde_json::from_value(repo).expect("Failed to parse repo");
+        assert_eq!(repo.permissions, expected);
+
+        let mut metadata = crate::entities::Metadata::default();
+        repo.inject_metadata(&mut metadata);
+        assert_eq!(metadata.get("repo.can_admin"), expected.admin.into());
+        assert_eq!(metadata.get("repo.can_push"), expected.push.into());
+        assert_eq!(metadata.get("repo.can_pull"), expected.pull.into());
+
+        let entity =
+            crate::entities::GitRepo::new(repo.full_name.as_str(), repo.clone_url.as_str(), None)
+                .with_metadata_source(&repo);
+        let filter = crate::Filter::new("repo.can_push").expect("Failed to parse filter");
+        assert_eq!(
+            filter.matches(&entity).expect("Failed to evaluate filter"),
+            expected.push
+        );
+    }
+
+    #[rstest]
+    #[case("octocat", "User", r#"repo.owner_type == "Organization""#, false)]
+    #[case("octocat", "User", r#"repo.owner == "octocat""#, true)]
+    #[case(
+        "SierraSoftworks",
+        "Organization",
+        r#"repo.owner_type == "Organization""#,
+        true
+    )]
+    #[case("SierraSoftworks", "Organization", r#"repo.owner == "octocat""#, false)]
+    fn test_repo_owner(
+        #[case] login: &str,
+        #[case] owner_type: &str,
+        #[case] filter: &str,
+        #[case] matches: bool,
+    ) {
+        let mut repos: Vec<serde_json::Value> =
+            load_test_file("github.repos.0.json").expect("Failed to load test file");
+        let mut repo = repos.remove(0);
+        repo["owner"]["login"] = login.into();
+        repo["owner"]["type"] = owner_type.into();
+
+        let repo: GitHubRepo = serde_json::from_value(repo).expect("Failed to parse repo");
+
+        let mut metadata = crate::entities::Metadata::default();
+        repo.inject_metadata(&mut metadata);
+        assert_eq!(metadata.get("repo.owner"), login.into());
+        assert_eq!(metadata.get("repo.owner_type"), owner_type.into());
+
+        let entity =
+            crate::entities::GitRepo::new(repo.full_name.as_str(), repo.clone_url.as_str(), None)
+                .with_metadata_source(&repo);
+        let filter = crate::Filter::new(filter).expect("Failed to parse filter");
+        assert_eq!(
+            filter.matches(&entity).expect("Failed to evaluate filter"),
+            matches
+        );
+    }
+
+    #[rstest]
+    #[case(
+        r#"["infra", "rust"]"#,
+        Some("internal"),
+        r#""infra" in repo.topics"#,
+        true
+    )]
+    #[case(
+        r#"["infra", "rust"]"#,
+        Some("internal"),
+        r#""web" in repo.topics"#,
+        false
+    )]
+    #[case(
+        r#"["infra", "rust"]"#,
+        Some("internal"),
+        r#"repo.visibility == "internal""#,
+        true
+    )]
+    #[case("[]", Some("public"), r#""infra" in repo.topics"#, false)]
+    #[case("[]", Some("public"), "repo.topics", false)]
+    #[case("[]", None, r#"repo.visibility == "private""#, true)]
+    fn test_repo_topics_and_visibility(
+        #[case] topics: &str,
+        #[case] visibility: Option<&str>,
+        #[case] filter: &str,
+        #[case] matches: bool,
+    ) {
+        let mut repos: Vec<serde_json::Value> =
+            load_test_file("github.repos.0.json").expect("Failed to load test file");
+        let mut repo = repos.remove(0);
+        repo["private"] = true.into();
+        repo["topics"] = serde_json::from_str(topics).unwrap();
+        match visibility {
+            Some(visibility) => repo["visibility"] = visibility.into(),
+            None => {
+                repo.as_object_mut().unwrap().remove("visibility");
+            }
+        }
+
+        let repo: GitHubRepo = serde_json::from_value(repo).expect("Failed to parse repo");
+        let entity =
+            crate::entities::GitRepo::new(repo.full_name.as_str(), repo.clone_url.as_str(), None)
+                .with_metadata_source(&repo);
+
+        let filter = crate::Filter::new(filter).expect("Failed to parse filter");
+        assert_eq!(
+            filter.matches(&entity).expect("Failed to evaluate filter"),
+            matches
+        );
     }
 
     #[rstest]
@@ -707,6 +1527,100 @@ mod tests {
         }
     }
 
+    #[rstest]
+    #[case(0, "!release.has_assets && release.asset_count == 0")]
+    #[case(1, "release.has_assets && release.asset_count == 1")]
+    #[case(3, "release.has_assets && release.asset_count == 3")]
+    fn test_release_asset_metadata(#[case] assets: usize, #[case] filter: &str) {
+        let releases: Vec<GitHubRelease> =
+            load_test_file("github.releases.1.json").expect("Failed to load test file");
+        let mut release = releases.into_iter().next().expect("a release");
+        release.assets.truncate(assets);
+
+        let entity = crate::entities::HttpFile::new("test", "https://example.com")
+            .with_metadata_source(&release);
+        assert_eq!(
+            entity.metadata.get("release.asset_count"),
+            (assets as u32).into()
+        );
+        assert_eq!(
+            entity.metadata.get("release.has_assets"),
+            (assets > 0).into()
+        );
+
+        let filter = crate::Filter::new(filter).expect("Failed to parse filter");
+        assert!(filter.matches(&entity).expect("Failed to evaluate filter"));
+    }
+
+    #[rstest]
+    #[case("github-backup-linux-amd64", None)]
+    #[case("github-backup.exe", Some("exe"))]
+    #[case("github-backup-v1.0.0.tar.gz", Some("tar.gz"))]
+    #[case("github-backup.TAR.XZ", Some("tar.xz"))]
+    #[case("github-backup.tar.gz.asc", Some("asc"))]
+    #[case("checksums.sha256.sig", Some("sig"))]
+    #[case("github-backup-1.2.3.zip", Some("zip"))]
+    #[case(".tar.gz", Some("gz"))]
+    #[case(".env", None)]
+    #[case("trailing.", None)]
+    fn test_asset_extension(#[case] name: &str, #[case] extension: Option<&str>) {
+        let releases: Vec<GitHubRelease> =
+            load_test_file("github.releases.0.json").expect("Failed to load test file");
+        let mut asset = releases
+            .into_iter()
+            .flat_map(|r| r.assets)
+            .next()
+            .expect("the test file to contain a release asset");
+        asset.name = name.to_string();
+
+        assert_eq!(asset.extension().as_deref(), extension);
+
+        let mut metadata = crate::entities::Metadata::default();
+        asset.inject_metadata(&mut metadata);
+        assert_eq!(metadata.get("asset.extension"), extension.into());
+        assert_eq!(
+            metadata.get("asset.content_type"),
+            asset.content_type.as_str().into()
+        );
+    }
+
+    #[rstest]
+    #[case("github-backup-v1.0.0.tar.gz", r#"asset.extension == "tar.gz""#, true)]
+    #[case("github-backup-v1.0.0.zip", r#"asset.extension == "tar.gz""#, false)]
+    #[case(
+        "github-backup-v1.0.0.tar.gz.asc",
+        r#"!(asset.name endswith ".asc")"#,
+        false
+    )]
+    #[case(
+        "github-backup-v1.0.0.tar.gz",
+        r#"!(asset.name endswith ".asc")"#,
+        true
+    )]
+    #[case(
+        "github-backup-v1.0.0.sig",
+        r#"asset.extension in ["asc", "sig"]"#,
+        true
+    )]
+    fn test_asset_filters(#[case] name: &str, #[case] filter: &str, #[case] matches: bool) {
+        let releases: Vec<GitHubRelease> =
+            load_test_file("github.releases.0.json").expect("Failed to load test file");
+        let mut asset = releases
+            .into_iter()
+            .flat_map(|r| r.assets)
+            .next()
+            .expect("the test file to contain a release asset");
+        asset.name = name.to_string();
+
+        let entity =
+            crate::entities::HttpFile::new(name, asset.url.as_str()).with_metadata_source(&asset);
+        let filter = crate::Filter::new(filter).expect("Failed to parse filter");
+        assert_eq!(
+            filter.matches(&entity).expect("Failed to evaluate filter"),
+            matches
+        );
+    }
+
     #[rstest]
     #[case("users/notheotherben")]
     #[case("orgs/sierrasoftworks")]
@@ -757,10 +1671,7 @@ mod tests {
 
     fn get_test_credentials() -> Credentials {
         std::env::var("GITHUB_TOKEN")
-            .map(|t| Credentials::UsernamePassword {
-                username: t,
-                password: String::new(),
-            })
+            .map(|t| Credentials::username_password(t, ""))
             .unwrap_or(Credentials::None)
     }
 
@@ -768,6 +1679,7 @@ mod tests {
     #[case("github/repo", GitHubArtifactKind::Repo, "repos")]
     #[case("github/star", GitHubArtifactKind::Star, "starred")]
     #[case("github/release", GitHubArtifactKind::Release, "repos")]
+    #[case("github/pages", GitHubArtifactKind::Pages, "repos")]
     fn test_deserialize_gh_repo_kind(
         #[case] kind_str: &str,
         #[case] expected_kind: GitHubArtifactKind,
@@ -792,4 +1704,553 @@ mod tests {
         let kind: GitHubRepoSourceKind = kind_str.parse().unwrap();
         assert_eq!(kind, expected_kind);
     }
+
+    #[rstest]
+    #[case(StatusCode::FORBIDDEN, &[("retry-after", "30")], None, Some(30))]
+    #[case(StatusCode::TOO_MANY_REQUESTS, &[("retry-after", "5")], None, Some(5))]
+    #[case(
+        StatusCode::FORBIDDEN,
+        &[],
+        Some(r#"{"message":"You have exceeded a secondary rate limit. Please wait a few minutes before you try again."}"#),
+        Some(60)
+    )]
+    #[case(
+        StatusCode::FORBIDDEN,
+        &[],
+        Some(r#"{"message":"You have triggered an abuse detection mechanism."}"#),
+        Some(60)
+    )]
+    #[case(
+        StatusCode::FORBIDDEN,
+        &[("x-ratelimit-remaining", "0"), ("retry-after", "30")],
+        Some(r#"{"message":"API rate limit exceeded for user ID 1."}"#),
+        None
+    )]
+    #[case(
+        StatusCode::FORBIDDEN,
+        &[("x-ratelimit-remaining", "4999")],
+        Some(r#"{"message":"Resource not accessible by integration"}"#),
+        None
+    )]
+    #[case(StatusCode::FORBIDDEN, &[], None, None)]
+    #[case(StatusCode::NOT_FOUND, &[("retry-after", "30")], None, None)]
+    fn test_secondary_rate_limit_backoff(
+        #[case] status: StatusCode,
+        #[case] headers: &[(&'static str, &'static str)],
+        #[case] body: Option<&str>,
+        #[case] expected: Option<u64>,
+    ) {
+        let headers = HeaderMap::from_iter(
+            headers
+                .iter()
+                .map(|(k, v)| (k.parse().unwrap(), HeaderValue::from_static(v))),
+        );
+
+        assert_eq!(
+            secondary_rate_limit_backoff(status, &headers, body),
+            expected.map(std::time::Duration::from_secs)
+        );
+    }
+
+    #[rstest]
+    #[case(
+        Credentials::None,
+        "unauthenticated requests has been exceeded (it resets at 2023-11-14T22:13:20Z)"
+    )]
+    #[case(Credentials::token("token"), "status code 403")]
+    #[case(
+        Credentials::PerHost(vec![("127.0.0.1".to_string(), Credentials::token("token"))]),
+        "status code 403"
+    )]
+    #[case(
+        Credentials::PerHost(vec![("github.com".to_string(), Credentials::token("token"))]),
+        "unauthenticated requests has been exceeded"
+    )]
+    #[tokio::test]
+    async fn test_unauthenticated_rate_limit(#[case] creds: Credentials, #[case] message: &str) {
+        use axum::{routing::get, Json, Router};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let url = format!(
+            "http://{}/users/octocat/repos",
+            listener.local_addr().unwrap()
+        );
+
+        let app = Router::new().route(
+            "/users/octocat/repos",
+            get(|| async {
+                (
+                    axum::http::StatusCode::FORBIDDEN,
+                    [
+                        ("x-ratelimit-limit", "60"),
+                        ("x-ratelimit-remaining", "0"),
+                        ("x-ratelimit-reset", "1700000000"),
+                    ],
+                    Json(serde_json::json!({
+                        "message": "API rate limit exceeded for 127.0.0.1. (But here's the good news: Authenticated requests get a higher rate limit.)"
+                    })),
+                )
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let err = GitHubClient::default()
+            .get::<serde_json::Value>(url, &creds, &CANCEL)
+            .await
+            .expect_err("the request to be rate limited");
+
+        assert!(err.is_user());
+        assert!(
+            err.description().contains(message),
+            "expected '{}' to contain '{message}'",
+            err.description()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_secondary_rate_limit_retry() {
+        use axum::{routing::get, Json, Router};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let url = format!("http://{}/user", listener.local_addr().unwrap());
+
+        let requests = Arc::new(AtomicUsize::new(0));
+        let app = Router::new().route(
+            "/user",
+            get({
+                let requests = requests.clone();
+                move || async move {
+                    match requests.fetch_add(1, Ordering::SeqCst) {
+                        0 => Err((
+                            axum::http::StatusCode::FORBIDDEN,
+                            [("retry-after", "0")],
+                            Json(serde_json::json!({
+                                "message": "You have exceeded a secondary rate limit. Please wait a few minutes before you try again."
+                            })),
+                        )),
+                        _ => Ok(Json(serde_json::json!({ "login": "octocat" }))),
+                    }
+                }
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = GitHubClient::default();
+        let cancel = AtomicBool::new(false);
+        let user: serde_json::Value = client
+            .get(url, &Credentials::None, &cancel)
+            .await
+            .expect("the request to be retried after the secondary rate limit");
+
+        assert_eq!(user["login"], "octocat");
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_secondary_rate_limit_retry_delays() {
+        use axum::{http::StatusCode, response::IntoResponse, routing::get, Json, Router};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let url = format!("http://{}/user", listener.local_addr().unwrap());
+
+        let requests = Arc::new(AtomicUsize::new(0));
+        let app = Router::new().route(
+            "/user",
+            get({
+                let requests = requests.clone();
+                move || async move {
+                    let limited = Json(serde_json::json!({
+                        "message": "You have exceeded a secondary rate limit. Please wait a few minutes before you try again."
+                    }));
+
+                    match requests.fetch_add(1, Ordering::SeqCst) {
+                        0 => (StatusCode::FORBIDDEN, [("retry-after", "5")], limited).into_response(),
+                        1 => (StatusCode::TOO_MANY_REQUESTS, limited).into_response(),
+                        2 => (StatusCode::FORBIDDEN, [("retry-after", "1")], limited).into_response(),
+                        _ => Json(serde_json::json!({ "login": "octocat" })).into_response(),
+                    }
+                }
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let clock = Arc::new(MockClock::default());
+        let client = GitHubClient::default().with_clock(clock.clone());
+        let user: serde_json::Value = client
+            .get(url, &Credentials::None, &CANCEL)
+            .await
+            .expect("the request to be retried after the secondary rate limits");
+
+        assert_eq!(user["login"], "octocat");
+        assert_eq!(
+            clock.slept(),
+            vec![
+                std::time::Duration::from_secs(5),
+                SECONDARY_RATE_LIMIT_BACKOFF,
+                std::time::Duration::from_secs(1)
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case("!TokenFile", true)]
+    #[case("!Token", false)]
+    #[tokio::test]
+    async fn test_refresh_credentials(#[case] tag: &str, #[case] succeeds: bool) {
+        use axum::{http::HeaderMap, http::StatusCode, routing::get, Json, Router};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let url = format!("http://{}/user", listener.local_addr().unwrap());
+
+        let app = Router::new().route(
+            "/user",
+            get(|headers: HeaderMap| async move {
+                match headers.get("Authorization").and_then(|h| h.to_str().ok()) {
+                    Some("Bearer refreshed_api_token") => {
+                        Ok(Json(serde_json::json!({ "login": "octocat" })))
+                    }
+                    _ => Err(StatusCode::UNAUTHORIZED),
+                }
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let token_path = temp_dir.path().join("token");
+        std::fs::write(&token_path, "expired_file_token").unwrap();
+
+        let creds: Credentials = match tag {
+            "!TokenFile" => serde_yaml::from_str(&format!("!TokenFile {}", token_path.display())),
+            _ => serde_yaml::from_str("!Token expired_static_token"),
+        }
+        .expect("the credentials to be loaded");
+
+        // The token is rotated part way through the run
+        std::fs::write(&token_path, "refreshed_api_token").unwrap();
+
+        let result = GitHubClient::default()
+            .get::<serde_json::Value>(url, &creds, &CANCEL)
+            .await;
+
+        if succeeds {
+            assert_eq!(result.expect("the request to succeed")["login"], "octocat");
+        } else {
+            let err = result.expect_err("the request to fail");
+            assert!(err.to_string().contains(errors::AUTHENTICATION_FAILED));
+        }
+    }
+
+    #[rstest]
+    #[case(403)]
+    #[case(404)]
+    #[tokio::test]
+    async fn test_credentials_chain(#[case] status: u16) {
+        use axum::{extract::State, http::HeaderMap, http::StatusCode, routing::get, Json, Router};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let url = format!("http://{}/user", listener.local_addr().unwrap());
+
+        let app = Router::new()
+            .route(
+                "/user",
+                get(|State(status): State<u16>, headers: HeaderMap| async move {
+                    match headers.get("Authorization").and_then(|h| h.to_str().ok()) {
+                        Some("Bearer second_token") => {
+                            Ok(Json(serde_json::json!({ "login": "octocat" })))
+                        }
+                        _ => Err(StatusCode::from_u16(status).unwrap()),
+                    }
+                }),
+            )
+            .with_state(status);
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let creds: Credentials = serde_yaml::from_str("[!Token first_token, !Token second_token]")
+            .expect("the credentials to be loaded");
+        let client = GitHubClient::default();
+
+        let result = client
+            .get::<serde_json::Value>(url.clone(), &creds, &CANCEL)
+            .await
+            .expect("the request to fall back to the second credentials");
+        assert_eq!(result["login"], "octocat");
+
+        assert_eq!(
+            client
+                .resolve_credentials(&url, &creds, &CANCEL)
+                .await
+                .expect("the credentials to be resolved"),
+            Credentials::token("second_token")
+        );
+
+        let err = client
+            .get::<serde_json::Value>(url.clone(), &Credentials::token("first_token"), &CANCEL)
+            .await
+            .expect_err("the first credentials alone should be rejected");
+        assert!(err.to_string().contains(&status.to_string()), "{err}");
+
+        let rejected: Credentials = serde_yaml::from_str("[!Token first_token, None]")
+            .expect("the credentials to be loaded");
+        let err = client
+            .resolve_credentials(&url, &rejected, &CANCEL)
+            .await
+            .expect_err("the chain should be rejected when none of its credentials have access");
+        assert!(
+            err.to_string().contains("Token, then No credentials"),
+            "the error should name the credentials which were tried: {err}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_credentials_per_host() {
+        use axum::{http::HeaderMap, routing::get, Json, Router};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let port = listener.local_addr().unwrap().port();
+
+        let app = Router::new().route(
+            "/user",
+            get(|headers: HeaderMap| async move {
+                let auth = headers
+                    .get("Authorization")
+                    .and_then(|h| h.to_str().ok())
+                    .map(|h| h.to_string());
+                Json(serde_json::json!({ "authorization": auth }))
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        // The same server is reachable using two different hosts, each of which gets its own token.
+        let creds: Credentials = serde_yaml::from_str(
+            r#"
+            !PerHost
+              - host: localhost
+                credentials: !Token localhost_token
+              - host: 127.0.0.1
+                credentials: !Token loopback_token
+            "#,
+        )
+        .expect("the credentials to be loaded");
+        let client = GitHubClient::default();
+
+        for (host, expected) in [
+            ("localhost", Some("Bearer localhost_token")),
+            ("127.0.0.1", Some("Bearer loopback_token")),
+        ] {
+            let result = client
+                .get::<serde_json::Value>(format!("http://{host}:{port}/user"), &creds, &CANCEL)
+                .await
+                .expect("the request to succeed");
+            assert_eq!(result["authorization"].as_str(), expected, "for {host}");
+        }
+
+        let creds = Credentials::PerHost(vec![(
+            "github.com".to_string(),
+            Credentials::token("github_token"),
+        )]);
+        let result = client
+            .get::<serde_json::Value>(format!("http://127.0.0.1:{port}/user"), &creds, &CANCEL)
+            .await
+            .expect("the request to succeed");
+        assert_eq!(
+            result["authorization"],
+            serde_json::Value::Null,
+            "hosts without matching credentials should not receive any"
+        );
+    }
+
+    #[rstest]
+    #[case(
+        "https://api.github.com/user/repos",
+        "https://api.github.com/user/repos?page=2",
+        "https://api.github.com/user/repos?page=2"
+    )]
+    #[case(
+        "https://ghes.example.com/api/v3/user/repos",
+        "/api/v3/user/repos?page=2",
+        "https://ghes.example.com/api/v3/user/repos?page=2"
+    )]
+    #[case(
+        "https://ghes.example.com/api/v3/user/repos?page=2",
+        "repos?page=3",
+        "https://ghes.example.com/api/v3/user/repos?page=3"
+    )]
+    #[case(
+        "https://ghes.example.com/api/v3/user/repos",
+        "http://ghes.internal:8080/api/v3/user/repos?page=2",
+        "https://ghes.example.com/api/v3/user/repos?page=2"
+    )]
+    #[case(
+        "http://localhost:3000/api/v3/user/repos",
+        "https://ghes.internal/api/v3/user/repos?page=2",
+        "http://localhost:3000/api/v3/user/repos?page=2"
+    )]
+    fn test_resolve_next_link(#[case] url: &str, #[case] next: &str, #[case] expected: &str) {
+        assert_eq!(
+            resolve_next_link(url, url, next).expect("the link to be resolved"),
+            expected
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_paginated_enterprise_links() {
+        use axum::{extract::Path, http::header, routing::get, Json, Router};
+        use tokio_stream::StreamExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let api_url = format!("http://{}/api/v3", listener.local_addr().unwrap());
+
+        // The first page links to the next using a relative URL, while the second links to the
+        // third using the (unreachable) hostname of a server behind a proxy.
+        let app = Router::new().route(
+            "/api/v3/items/:page",
+            get(|Path(page): Path<usize>| async move {
+                let link = match page {
+                    1 => "</api/v3/items/2>; rel=\"next\"",
+                    2 => "<https://ghes.invalid/api/v3/items/3>; rel=\"next\"",
+                    _ => "</api/v3/items/1>; rel=\"first\"",
+                };
+
+                ([(header::LINK, link)], Json(vec![page]))
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let items: Vec<usize> = GitHubClient::default()
+            .get_paginated(format!("{api_url}/items/1"), &Credentials::None, &CANCEL)
+            .collect::<Result<Vec<_>, _>>()
+            .await
+            .expect("every page to be retrieved");
+
+        assert_eq!(items, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_get_paginated_resumable() {
+        use axum::{
+            extract::{Path, State},
+            http::header,
+            response::IntoResponse,
+            routing::get,
+            Json, Router,
+        };
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio_stream::StreamExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let api_url = format!("http://{}", listener.local_addr().unwrap());
+
+        type MockState = State<(String, Arc<AtomicUsize>)>;
+
+        fn page(api_url: &str, requests: &AtomicUsize, page: usize) -> impl IntoResponse {
+            requests.fetch_add(1, Ordering::Relaxed);
+            let items: Vec<usize> = vec![page * 10 + 1, page * 10 + 2];
+            let link = if page < 3 {
+                format!("<{api_url}/items/{}>; rel=\"next\"", page + 1)
+            } else {
+                format!("<{api_url}/items>; rel=\"first\"")
+            };
+
+            ([(header::LINK, link)], Json(items))
+        }
+
+        let requests = Arc::new(AtomicUsize::new(0));
+        let app =
+            Router::new()
+                .route(
+                    "/items",
+                    get(|State((api_url, requests)): MockState| async move {
+                        page(&api_url, &requests, 1)
+                    }),
+                )
+                .route(
+                    "/items/:page",
+                    get(
+                        |State((api_url, requests)): MockState, Path(p): Path<usize>| async move {
+                            page(&api_url, &requests, p)
+                        },
+                    ),
+                )
+                .with_state((api_url.clone(), requests.clone()));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let checkpoint = PaginationCheckpoint::new(temp_dir.path().join("checkpoint.json"));
+        let client = GitHubClient::default();
+        let creds = Credentials::None;
+        let base_url = format!("{api_url}/items");
+
+        {
+            // Simulate the process being interrupted part way through the second page, while
+            // the first item on it is still being backed up.
+            let stream = client.get_paginated_resumable::<usize>(
+                base_url.clone(),
+                &checkpoint,
+                &creds,
+                &CANCEL,
+            );
+            tokio::pin!(stream);
+
+            let (first, first_guard) = stream.next().await.unwrap().expect("an item");
+            let (second, second_guard) = stream.next().await.unwrap().expect("an item");
+            assert_eq!((first, second), (11, 12));
+
+            drop(first_guard);
+            let (third, _third_guard) = stream.next().await.unwrap().expect("an item");
+            assert_eq!(third, 21);
+            assert_eq!(
+                checkpoint.get(&base_url).await.unwrap(),
+                None,
+                "the checkpoint should not move past a page while its items are in flight"
+            );
+
+            drop(second_guard);
+            let (fourth, _fourth_guard) = stream.next().await.unwrap().expect("an item");
+            assert_eq!(fourth, 22);
+        }
+
+        assert_eq!(
+            checkpoint.get(&base_url).await.unwrap(),
+            Some(format!("{api_url}/items/2")),
+            "the checkpoint should point at the first page which was not fully processed"
+        );
+
+        requests.store(0, Ordering::Relaxed);
+        let items: Vec<usize> = client
+            .get_paginated_resumable(base_url.clone(), &checkpoint, &creds, &CANCEL)
+            .map(|item| item.map(|(item, _)| item))
+            .collect::<Result<Vec<_>, _>>()
+            .await
+            .expect("the remaining items");
+
+        assert_eq!(items, vec![21, 22, 31, 32]);
+        assert_eq!(
+            requests.load(Ordering::Relaxed),
+            2,
+            "the first page should not be requested again"
+        );
+        assert_eq!(
+            checkpoint.get(&base_url).await.unwrap(),
+            None,
+            "the checkpoint should be cleared once pagination completes"
+        );
+    }
 }
@@ -0,0 +1,24 @@
+/// Computes the 64-bit FNV-1a hash of the provided data.
+///
+/// Unlike the standard library's hashers, this is guaranteed to produce the same value between
+/// runs (and releases), making it suitable for values which are persisted or shown to users.
+pub fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325u64, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("", 0xcbf29ce484222325)]
+    #[case("a", 0xaf63dc4c8601ec8c)]
+    #[case("foobar", 0x85944171f73967e8)]
+    fn test_fnv1a(#[case] data: &str, #[case] expected: u64) {
+        assert_eq!(fnv1a(data.as_bytes()), expected);
+    }
+}
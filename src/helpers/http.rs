@@ -0,0 +1,174 @@
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use crate::errors;
+
+/// Configures how GitHub Backup connects to GitHub, for environments where requests need to be
+/// made through a proxy or where TLS connections are inspected using a private CA.
+#[derive(Clone, Debug, Default, Deserialize, schemars::JsonSchema)]
+pub struct HttpConfig {
+    /// The URL of the proxy through which all HTTP(S) requests should be made.
+    #[serde(default)]
+    pub proxy: Option<String>,
+
+    /// The path to a PEM file containing additional CA certificates which should be trusted.
+    #[serde(default)]
+    pub ca_certificates: Option<PathBuf>,
+}
+
+/// The kinds of items which are downloaded from arbitrary HTTP(S) servers, rather than through
+/// the GitHub API.
+#[derive(
+    PartialEq, Debug, Copy, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema,
+)]
+pub enum HttpArtifactKind {
+    #[serde(rename = "http/file")]
+    File,
+}
+
+impl HttpArtifactKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HttpArtifactKind::File => "http/file",
+        }
+    }
+}
+
+impl HttpConfig {
+    /// Builds a [`reqwest::Client`] which uses the configured proxy and CA certificates.
+    pub fn client(&self) -> Result<reqwest::Client, errors::Error> {
+        let mut builder = reqwest::Client::builder();
+
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy).map_err(|e| {
+                errors::user_with_internal(
+                    &format!("The proxy '{proxy}' in your configuration file is not valid."),
+                    "Make sure that you have provided the full URL of your proxy, for example 'http://proxy.example.com:8080'.",
+                    e,
+                )
+            })?);
+        }
+
+        for certificate in self.certificates()? {
+            builder = builder.add_root_certificate(certificate);
+        }
+
+        builder.build().map_err(|e| {
+            errors::system_with_internal(
+                "Unable to configure the HTTP client used to connect to GitHub.",
+                "Please check your proxy and CA certificate configuration, and report this issue to us on GitHub if it persists.",
+                e,
+            )
+        })
+    }
+
+    /// The Git configuration which should be applied when cloning and fetching repositories, so
+    /// that they use the same proxy and CA certificates as our other requests.
+    pub fn git_config_overrides(&self) -> Vec<String> {
+        let mut overrides = Vec::new();
+
+        if let Some(proxy) = &self.proxy {
+            overrides.push(format!("http.proxy={proxy}"));
+        }
+
+        if let Some(path) = &self.ca_certificates {
+            overrides.push(format!("http.sslCAInfo={}", path.display()));
+        }
+
+        overrides
+    }
+
+    fn certificates(&self) -> Result<Vec<reqwest::Certificate>, errors::Error> {
+        let Some(path) = &self.ca_certificates else {
+            return Ok(Vec::new());
+        };
+
+        let content = std::fs::read(path).map_err(|e| {
+            errors::user_with_internal(
+                &format!(
+                    "Unable to read the CA certificates file '{}'.",
+                    path.display()
+                ),
+                "Make sure that the file exists and can be read by the process.",
+                e,
+            )
+        })?;
+
+        let certificates = reqwest::Certificate::from_pem_bundle(&content).map_err(|e| {
+            errors::user_with_internal(
+                &format!(
+                    "The CA certificates file '{}' could not be parsed.",
+                    path.display()
+                ),
+                "Make sure that the file contains one or more PEM encoded certificates.",
+                e,
+            )
+        })?;
+
+        if certificates.is_empty() {
+            return Err(errors::user(
+                &format!(
+                    "The CA certificates file '{}' does not contain any certificates.",
+                    path.display()
+                ),
+                "Make sure that the file contains one or more PEM encoded certificates.",
+            ));
+        }
+
+        Ok(certificates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_client() {
+        let config = HttpConfig::default();
+        config.client().expect("the client to be built");
+        assert!(config.git_config_overrides().is_empty());
+    }
+
+    #[test]
+    fn test_proxy() {
+        let config: HttpConfig =
+            serde_yaml::from_str("proxy: http://proxy.example.com:8080").unwrap();
+        config.client().expect("the client to be built");
+        assert_eq!(
+            config.git_config_overrides(),
+            vec!["http.proxy=http://proxy.example.com:8080".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_ca_certificates() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("data")
+            .join("ca.pem");
+
+        let config = HttpConfig {
+            ca_certificates: Some(path.clone()),
+            ..Default::default()
+        };
+
+        config
+            .client()
+            .expect("the client to accept the CA certificates");
+        assert_eq!(
+            config.git_config_overrides(),
+            vec![format!("http.sslCAInfo={}", path.display())]
+        );
+    }
+
+    #[test]
+    fn test_missing_ca_certificates() {
+        let config: HttpConfig =
+            serde_yaml::from_str("ca_certificates: /this/file/does/not/exist.pem").unwrap();
+        config
+            .client()
+            .expect_err("a missing CA file should be reported");
+    }
+}
@@ -1,3 +1,20 @@
+pub mod auto_concurrency;
+pub mod case_paths;
+pub mod checkpoint;
+pub mod clock;
+pub mod fingerprints;
 pub mod github;
+pub mod hash;
+pub mod http;
+pub mod path_locks;
+pub mod rate_limit;
+pub mod refspecs;
+pub mod safe_walk;
+pub mod throttle;
 
+pub use auto_concurrency::AutoConcurrency;
+pub use clock::{Clock, SystemClock};
 pub use github::GitHubClient;
+pub use path_locks::PathLocks;
+pub use rate_limit::RateLimit;
+pub use throttle::Throttle;
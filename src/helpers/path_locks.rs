@@ -0,0 +1,110 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, Weak},
+};
+
+use tokio::sync::OwnedMutexGuard;
+
+/// Ensures that only one backup task at a time writes to a given path, so that policies which
+/// back up the same items into the same directory don't race on their temporary, checksum and
+/// backup files.
+///
+/// Clones of a [`PathLocks`] share the same state, allowing every backup policy (regardless of its
+/// kind) to be serialized against the others.
+#[derive(Clone, Debug, Default)]
+pub struct PathLocks {
+    locks: Arc<Mutex<HashMap<PathBuf, Weak<tokio::sync::Mutex<()>>>>>,
+}
+
+/// Holds the lock on a path until it is dropped.
+pub type PathLock = OwnedMutexGuard<()>;
+
+impl PathLocks {
+    /// Waits until no other task holds the lock on `path`, then takes it.
+    pub async fn lock(&self, path: &Path) -> PathLock {
+        let key = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+
+        let lock = {
+            let mut locks = self.locks.lock().unwrap();
+
+            // Only paths which are currently locked (or being waited on) need to be tracked.
+            locks.retain(|_, lock| lock.strong_count() > 0);
+
+            match locks.get(&key).and_then(Weak::upgrade) {
+                Some(lock) => lock,
+                None => {
+                    let lock = Arc::new(tokio::sync::Mutex::new(()));
+                    locks.insert(key, Arc::downgrade(&lock));
+                    lock
+                }
+            }
+        };
+
+        lock.lock_owned().await
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.locks
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|lock| lock.strong_count() > 0)
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_serializes_same_path() {
+        let locks = PathLocks::default();
+        let first = locks.lock(Path::new("/backups/repo")).await;
+
+        let waiting = tokio::spawn({
+            let locks = locks.clone();
+            async move {
+                let _lock = locks.lock(Path::new("/backups/./repo")).await;
+            }
+        });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(
+            !waiting.is_finished(),
+            "the second lock should wait for the first to be released"
+        );
+
+        drop(first);
+        tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .expect("the second lock to be taken once the first is released")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_independent_paths() {
+        let locks = PathLocks::default();
+        let _first = locks.lock(Path::new("/backups/a")).await;
+
+        tokio::time::timeout(Duration::from_secs(1), locks.lock(Path::new("/backups/b")))
+            .await
+            .expect("locks on different paths should not wait for one another");
+    }
+
+    #[tokio::test]
+    async fn test_releases_unused_locks() {
+        let locks = PathLocks::default();
+        {
+            let _a = locks.lock(Path::new("/backups/a")).await;
+            let _b = locks.lock(Path::new("/backups/b")).await;
+            assert_eq!(locks.len(), 2);
+        }
+
+        assert_eq!(locks.len(), 0);
+    }
+}
@@ -0,0 +1,213 @@
+use std::sync::{Arc, Mutex};
+
+use reqwest::header::HeaderMap;
+
+use super::{Clock, SystemClock};
+
+/// The fraction of the rate limit window which may be used before we start reducing the
+/// number of backup tasks which run at once.
+const BACKOFF_THRESHOLD: f64 = 0.2;
+
+/// The number of backup tasks we back off from when there is no IO concurrency limit (which
+/// matches the default `--io-concurrency`), since a fraction of an unbounded limit is unbounded.
+const UNBOUNDED_BACKOFF_BASE: usize = 10;
+
+/// Tracks the GitHub API's rate limit (as reported by the `x-ratelimit-*` headers on its
+/// responses), so that the number of concurrent backup tasks can be reduced as we approach
+/// the limit and restored once the rate limit window resets.
+///
+/// Clones of a [`RateLimit`] share the same state, allowing the [`GitHubClient`](super::GitHubClient)
+/// to record the headers it observes while the backup pairings read them.
+#[derive(Clone)]
+pub struct RateLimit {
+    state: Arc<Mutex<Option<RateLimitWindow>>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            state: Default::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+impl std::fmt::Debug for RateLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RateLimit")
+            .field("state", &self.state)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct RateLimitWindow {
+    limit: u64,
+    remaining: u64,
+    reset: i64,
+}
+
+impl RateLimit {
+    /// Uses the given clock to determine whether the rate limit window has reset.
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+
+    /// Records the rate limit reported by the headers of a GitHub API response, if present.
+    pub fn observe(&self, headers: &HeaderMap) {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<i64>().ok())
+        };
+
+        if let (Some(limit), Some(remaining), Some(reset)) = (
+            header("x-ratelimit-limit"),
+            header("x-ratelimit-remaining"),
+            header("x-ratelimit-reset"),
+        ) {
+            self.record(limit.max(0) as u64, remaining.max(0) as u64, reset);
+        }
+    }
+
+    fn record(&self, limit: u64, remaining: u64, reset: i64) {
+        let mut state = self.state.lock().unwrap();
+        match state.as_mut() {
+            // Responses may arrive out of order, so within a window we only ever keep the lowest
+            // number of remaining requests we have seen.
+            Some(window) if window.reset == reset => {
+                window.remaining = window.remaining.min(remaining);
+            }
+            Some(window) if window.reset > reset => {}
+            _ => {
+                *state = Some(RateLimitWindow {
+                    limit,
+                    remaining,
+                    reset,
+                })
+            }
+        }
+    }
+
+    /// Determines how many backup tasks may run at once, given the configured `max`imum.
+    pub fn concurrency(&self, max: usize) -> usize {
+        self.concurrency_at(max, self.clock.utc_now().timestamp())
+    }
+
+    fn concurrency_at(&self, max: usize, now: i64) -> usize {
+        let state = self.state.lock().unwrap();
+        let Some(window) = *state else {
+            return max;
+        };
+
+        if now >= window.reset || window.limit == 0 {
+            return max;
+        }
+
+        let headroom = window.remaining as f64 / (window.limit as f64 * BACKOFF_THRESHOLD);
+        if headroom >= 1.0 {
+            return max;
+        }
+
+        let base = match max {
+            usize::MAX => UNBOUNDED_BACKOFF_BASE,
+            max => max,
+        };
+
+        ((base as f64 * headroom).floor() as usize).clamp(1, base.max(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn headers(limit: u64, remaining: u64, reset: i64) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-limit", limit.into());
+        headers.insert("x-ratelimit-remaining", remaining.into());
+        headers.insert("x-ratelimit-reset", reset.into());
+        headers
+    }
+
+    #[rstest]
+    #[case(5000, 5000, 10)]
+    #[case(5000, 1000, 10)]
+    #[case(5000, 500, 5)]
+    #[case(5000, 100, 1)]
+    #[case(5000, 0, 1)]
+    fn test_concurrency(#[case] limit: u64, #[case] remaining: u64, #[case] expected: usize) {
+        let rate_limit = RateLimit::default();
+        rate_limit.observe(&headers(limit, remaining, 1000));
+
+        assert_eq!(rate_limit.concurrency_at(10, 500), expected);
+    }
+
+    #[rstest]
+    #[case(5000, 5000, usize::MAX)]
+    #[case(5000, 500, 5)]
+    #[case(5000, 0, 1)]
+    fn test_concurrency_unbounded(
+        #[case] limit: u64,
+        #[case] remaining: u64,
+        #[case] expected: usize,
+    ) {
+        let rate_limit = RateLimit::default();
+        rate_limit.observe(&headers(limit, remaining, 1000));
+
+        assert_eq!(rate_limit.concurrency_at(usize::MAX, 500), expected);
+    }
+
+    #[test]
+    fn test_concurrency_without_headers() {
+        let rate_limit = RateLimit::default();
+        rate_limit.observe(&HeaderMap::new());
+
+        assert_eq!(rate_limit.concurrency_at(10, 500), 10);
+    }
+
+    #[test]
+    fn test_concurrency_after_reset() {
+        let rate_limit = RateLimit::default();
+        rate_limit.observe(&headers(5000, 0, 1000));
+        assert_eq!(rate_limit.concurrency_at(10, 999), 1);
+        assert_eq!(rate_limit.concurrency_at(10, 1000), 10);
+
+        rate_limit.observe(&headers(5000, 4999, 4600));
+        assert_eq!(rate_limit.concurrency_at(10, 1000), 10);
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_with_clock() {
+        let clock = Arc::new(crate::helpers::clock::MockClock::new(
+            chrono::DateTime::from_timestamp(500, 0).unwrap(),
+        ));
+        let rate_limit = RateLimit::default().with_clock(clock.clone());
+        rate_limit.observe(&headers(5000, 0, 1000));
+        assert_eq!(rate_limit.concurrency(10), 1);
+
+        clock.sleep(std::time::Duration::from_secs(500)).await;
+        assert_eq!(
+            rate_limit.concurrency(10),
+            10,
+            "the concurrency should be restored once the window resets"
+        );
+    }
+
+    #[test]
+    fn test_out_of_order_responses() {
+        let rate_limit = RateLimit::default();
+        rate_limit.observe(&headers(5000, 100, 1000));
+        rate_limit.observe(&headers(5000, 4000, 1000));
+        assert_eq!(rate_limit.concurrency_at(10, 500), 1);
+
+        // A response from the previous window shouldn't replace the current one.
+        rate_limit.observe(&headers(5000, 4000, 4600));
+        rate_limit.observe(&headers(5000, 0, 1000));
+        assert_eq!(rate_limit.concurrency_at(10, 2000), 10);
+    }
+}
@@ -0,0 +1,207 @@
+use crate::errors;
+
+/// Named sets of refspecs which may be used in place of an explicit `refspecs` list.
+const PROFILES: &[(&str, &[&str])] = &[
+    ("branches", &["+refs/heads/*:refs/remotes/origin/*"]),
+    ("tags-only", &["+refs/tags/*:refs/tags/*"]),
+    (
+        "branches-and-tags",
+        &[
+            "+refs/heads/*:refs/remotes/origin/*",
+            "+refs/tags/*:refs/tags/*",
+        ],
+    ),
+];
+
+/// Parses the value of a policy's `refspecs` property, which may either be the name of a
+/// known profile (e.g. `tags-only`) or a comma-separated list of git fetch refspecs.
+pub fn parse(value: &str) -> Result<Vec<String>, errors::Error> {
+    let value = value.trim();
+
+    if let Some((_, refspecs)) = PROFILES.iter().find(|(name, _)| *name == value) {
+        return Ok(refspecs.iter().map(|r| r.to_string()).collect());
+    }
+
+    value
+        .split(',')
+        .map(|spec| {
+            let spec = spec.trim();
+            validate(spec).map_err(|reason| {
+                errors::user(
+                    &format!("The refspec '{spec}' in your backup policy is not valid: {reason}."),
+                    &format!(
+                        "Make sure that your 'refspecs' property is a comma-separated list of valid git fetch refspecs (e.g. '+refs/heads/main:refs/remotes/origin/main'), or one of the supported profiles ({}).",
+                        PROFILES.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(", ")
+                    ),
+                )
+            })?;
+
+            Ok(spec.to_string())
+        })
+        .collect()
+}
+
+/// Converts the value of a policy's `branches` property, which is a comma-separated list of branch
+/// names or patterns (e.g. `main, release/*`), into the refspecs which fetch those branches.
+pub fn from_branches(value: &str) -> Result<Vec<String>, errors::Error> {
+    value
+        .split(',')
+        .map(|pattern| {
+            let pattern = pattern.trim();
+            let pattern = pattern.strip_prefix("refs/heads/").unwrap_or(pattern);
+            if pattern.is_empty() {
+                return Err("branch patterns cannot be empty");
+            }
+
+            validate_ref(&format!("refs/heads/{pattern}"))?;
+            Ok(format!("+refs/heads/{pattern}:refs/remotes/origin/{pattern}"))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|reason| {
+            errors::user(
+                &format!("The branches '{value}' in your backup policy are not valid: {reason}."),
+                "Make sure that your 'branches' property is a comma-separated list of branch names, which may each contain a single '*' wildcard (e.g. 'main, release/*').",
+            )
+        })
+}
+
+fn validate(spec: &str) -> Result<(), &'static str> {
+    if spec.is_empty() {
+        return Err("refspecs cannot be empty");
+    }
+
+    if let Some(negative) = spec.strip_prefix('^') {
+        if negative.contains(':') {
+            return Err("negative refspecs cannot specify a destination");
+        }
+
+        return validate_ref(negative);
+    }
+
+    let spec = spec.strip_prefix('+').unwrap_or(spec);
+    let (src, dst) = match spec.split_once(':') {
+        Some((_, dst)) if dst.contains(':') => {
+            return Err("refspecs may only contain a single ':' separator")
+        }
+        Some((src, dst)) => (src, Some(dst)),
+        None => (spec, None),
+    };
+
+    validate_ref(src)?;
+
+    if let Some(dst) = dst.filter(|d| !d.is_empty()) {
+        validate_ref(dst)?;
+
+        if src.contains('*') != dst.contains('*') {
+            return Err("wildcards must appear on both sides of the refspec, or on neither");
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_ref(name: &str) -> Result<(), &'static str> {
+    if name.is_empty() {
+        return Err("the source ref cannot be empty");
+    }
+
+    if name
+        .chars()
+        .any(|c| c.is_whitespace() || c.is_control() || "~^?[\\".contains(c))
+    {
+        return Err("ref names cannot contain whitespace or any of the characters ~^?[\\");
+    }
+
+    if name.contains("..") || name.contains("//") || name.contains("@{") {
+        return Err("ref names cannot contain '..', '//' or '@{'");
+    }
+
+    if name.starts_with('/') || name.ends_with('/') || name.ends_with('.') {
+        return Err("ref names cannot start or end with '/', or end with '.'");
+    }
+
+    if name.matches('*').count() > 1 {
+        return Err("ref names may contain at most one '*' wildcard");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("+refs/heads/*:refs/remotes/origin/*", &["+refs/heads/*:refs/remotes/origin/*"])]
+    #[case(
+        "+refs/heads/main:refs/remotes/origin/main, +refs/heads/develop:refs/remotes/origin/develop",
+        &["+refs/heads/main:refs/remotes/origin/main", "+refs/heads/develop:refs/remotes/origin/develop"]
+    )]
+    #[case("refs/heads/main", &["refs/heads/main"])]
+    #[case("+refs/heads/*:refs/remotes/origin/*,^refs/heads/dependabot/*", &["+refs/heads/*:refs/remotes/origin/*", "^refs/heads/dependabot/*"])]
+    #[case("tags-only", &["+refs/tags/*:refs/tags/*"])]
+    #[case(" branches ", &["+refs/heads/*:refs/remotes/origin/*"])]
+    #[case("branches-and-tags", &["+refs/heads/*:refs/remotes/origin/*", "+refs/tags/*:refs/tags/*"])]
+    fn test_parse_valid(#[case] value: &str, #[case] expected: &[&str]) {
+        assert_eq!(
+            parse(value).expect("refspecs to be valid"),
+            expected.iter().map(|s| s.to_string()).collect::<Vec<_>>()
+        );
+    }
+
+    #[rstest]
+    #[case("main", &["+refs/heads/main:refs/remotes/origin/main"])]
+    #[case("main, release/*", &["+refs/heads/main:refs/remotes/origin/main", "+refs/heads/release/*:refs/remotes/origin/release/*"])]
+    #[case("refs/heads/develop", &["+refs/heads/develop:refs/remotes/origin/develop"])]
+    #[case("feature-*", &["+refs/heads/feature-*:refs/remotes/origin/feature-*"])]
+    fn test_from_branches_valid(#[case] value: &str, #[case] expected: &[&str]) {
+        assert_eq!(
+            from_branches(value).expect("branches to be valid"),
+            expected.iter().map(|s| s.to_string()).collect::<Vec<_>>()
+        );
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("main,")]
+    #[case("my branch")]
+    #[case("release/*/*")]
+    #[case("release/")]
+    #[case("feature/[abc]")]
+    fn test_from_branches_invalid(#[case] value: &str) {
+        let err = from_branches(value).expect_err("branches to be rejected");
+        assert!(
+            err.description().contains(&format!("'{value}'")),
+            "the error should reference the branches property: {}",
+            err.description()
+        );
+    }
+
+    #[rstest]
+    #[case("", "''")]
+    #[case("+refs/heads/main:refs/remotes/origin/main,", "''")]
+    #[case("+refs/heads/main,,refs/heads/develop", "''")]
+    #[case(":refs/remotes/origin/main", "':refs/remotes/origin/main'")]
+    #[case(
+        "+refs/heads/*:refs/remotes/origin/main",
+        "'+refs/heads/*:refs/remotes/origin/main'"
+    )]
+    #[case("refs/heads/a:refs/b:refs/c", "'refs/heads/a:refs/b:refs/c'")]
+    #[case("refs/heads/my branch", "'refs/heads/my branch'")]
+    #[case("refs/heads/../main", "'refs/heads/../main'")]
+    #[case("refs/heads/*/*", "'refs/heads/*/*'")]
+    #[case(
+        "^refs/heads/main:refs/remotes/origin/main",
+        "'^refs/heads/main:refs/remotes/origin/main'"
+    )]
+    fn test_parse_invalid(#[case] value: &str, #[case] offending: &str) {
+        let err = parse(value).expect_err("refspecs to be rejected");
+        assert!(
+            err.description().contains(offending),
+            "the error should reference the offending refspec {offending}: {}",
+            err.description()
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use std::path::{Path, PathBuf};
+
+use tracing_batteries::prelude::*;
+
+/// The kind of an entry found while walking a directory tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EntryKind {
+    File,
+    Directory,
+    /// A symbolic link, which is never followed (so its target may be outside of the tree).
+    Symlink,
+}
+
+/// An entry found while walking a directory tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub path: PathBuf,
+    /// The path of the entry, relative to the root of the tree.
+    pub relative: PathBuf,
+    pub kind: EntryKind,
+    /// The size of the entry itself (not including its children, or the target of a symlink).
+    pub size: u64,
+}
+
+/// Walks the directory tree beneath `root`, returning every entry within it in depth-first
+/// order (so that a directory always appears before its children).
+///
+/// Since the results of a walk are used to delete files, it must never be possible for it to
+/// escape the tree: symlinks are returned as entries but never followed, every directory is
+/// confirmed to be within `root` before it is read, and special files (like FIFOs, sockets and
+/// devices) are skipped entirely. The `descend` predicate is called with the relative path of each
+/// directory and may return `false` to skip it (and everything within it).
+pub fn walk<F>(root: &Path, mut descend: F) -> std::io::Result<Vec<Entry>>
+where
+    F: FnMut(&Path) -> bool,
+{
+    let canonical_root = root.canonicalize()?;
+
+    let mut entries = Vec::new();
+    let mut directories = vec![root.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        if !is_within(&canonical_root, &directory)? {
+            warn!(
+                "Skipping '{}' since it is not within '{}'.",
+                directory.display(),
+                root.display()
+            );
+            continue;
+        }
+
+        let mut children = Vec::new();
+        for entry in std::fs::read_dir(&directory)? {
+            let entry = entry?;
+            let path = entry.path();
+            let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+
+            // Unlike std::fs::metadata, DirEntry::metadata does not follow symlinks.
+            let metadata = entry.metadata()?;
+            let kind = if metadata.is_symlink() {
+                EntryKind::Symlink
+            } else if metadata.is_dir() {
+                EntryKind::Directory
+            } else if metadata.is_file() {
+                EntryKind::File
+            } else {
+                trace!("Skipping special file '{}'", path.display());
+                continue;
+            };
+
+            children.push(Entry {
+                path,
+                relative,
+                kind,
+                size: metadata.len(),
+            });
+        }
+
+        // Sorting the children keeps the order of the walk predictable between runs.
+        children.sort_by(|a, b| a.path.cmp(&b.path));
+        for child in children {
+            if child.kind == EntryKind::Directory && descend(&child.relative) {
+                directories.push(child.path.clone());
+            }
+
+            entries.push(child);
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Determines whether a path is within the (canonical) root of a tree, once any symlinks in the
+/// path have been resolved.
+pub fn is_within(canonical_root: &Path, path: &Path) -> std::io::Result<bool> {
+    Ok(path.canonicalize()?.starts_with(canonical_root))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_walk() {
+        let root = tempfile::tempdir().expect("a temporary directory");
+        std::fs::create_dir_all(root.path().join("a").join("b")).unwrap();
+        std::fs::write(root.path().join("a").join("b").join("file.txt"), "abc").unwrap();
+        std::fs::write(root.path().join("top.txt"), "abcdef").unwrap();
+        std::fs::create_dir(root.path().join("skipped")).unwrap();
+        std::fs::write(root.path().join("skipped").join("file.txt"), "abc").unwrap();
+
+        let entries =
+            walk(root.path(), |p| p != Path::new("skipped")).expect("the walk to succeed");
+        let mut found = entries
+            .iter()
+            .map(|e| (e.relative.clone(), e.kind))
+            .collect::<Vec<_>>();
+        found.sort();
+
+        assert_eq!(
+            found,
+            vec![
+                (PathBuf::from("a"), EntryKind::Directory),
+                (PathBuf::from("a/b"), EntryKind::Directory),
+                (PathBuf::from("a/b/file.txt"), EntryKind::File),
+                (PathBuf::from("skipped"), EntryKind::Directory),
+                (PathBuf::from("top.txt"), EntryKind::File),
+            ]
+        );
+
+        let position = |p: &str| entries.iter().position(|e| e.relative == Path::new(p));
+        assert!(
+            position("a") < position("a/b"),
+            "directories should be listed before their children"
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_walk_symlinks_and_special_files() {
+        let root = tempfile::tempdir().expect("a temporary directory");
+        let outside = tempfile::tempdir().expect("a temporary directory");
+        std::fs::write(outside.path().join("secret.txt"), "secret").unwrap();
+
+        std::os::unix::fs::symlink(outside.path(), root.path().join("link")).unwrap();
+        std::os::unix::fs::symlink(
+            outside.path().join("secret.txt"),
+            root.path().join("file-link"),
+        )
+        .unwrap();
+        let _socket = std::os::unix::net::UnixListener::bind(root.path().join("socket")).unwrap();
+
+        let entries = walk(root.path(), |_| true).expect("the walk to succeed");
+        let mut found = entries
+            .iter()
+            .map(|e| (e.relative.clone(), e.kind))
+            .collect::<Vec<_>>();
+        found.sort();
+
+        assert_eq!(
+            found,
+            vec![
+                (PathBuf::from("file-link"), EntryKind::Symlink),
+                (PathBuf::from("link"), EntryKind::Symlink),
+            ],
+            "symlinks should never be followed, and special files should be skipped"
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_is_within() {
+        let root = tempfile::tempdir().expect("a temporary directory");
+        let outside = tempfile::tempdir().expect("a temporary directory");
+        std::fs::create_dir(root.path().join("inside")).unwrap();
+        std::os::unix::fs::symlink(outside.path(), root.path().join("link")).unwrap();
+
+        let canonical_root = root.path().canonicalize().unwrap();
+        assert!(is_within(&canonical_root, &root.path().join("inside")).unwrap());
+        assert!(!is_within(&canonical_root, &root.path().join("link")).unwrap());
+    }
+}
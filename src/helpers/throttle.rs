@@ -0,0 +1,157 @@
+use std::{
+    sync::{atomic::AtomicBool, Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::errors;
+
+use super::Clock;
+
+/// Limits the rate at which requests are sent to the GitHub API using a token bucket which holds
+/// a single token, so that requests are spread evenly over time rather than being sent in bursts.
+///
+/// Clones of a [`Throttle`] share the same bucket, allowing it to limit the requests made by every
+/// clone of a [`GitHubClient`](super::GitHubClient).
+#[derive(Clone, Debug)]
+pub struct Throttle {
+    requests_per_second: f64,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Throttle {
+    pub fn new(requests_per_second: f64) -> Result<Self, errors::Error> {
+        if !requests_per_second.is_finite() || requests_per_second <= 0.0 {
+            return Err(errors::user(
+                &format!(
+                    "The rate limit of {requests_per_second} requests per second is not valid."
+                ),
+                "Provide a rate limit which is greater than zero, for example '--rate-limit 10'.",
+            ));
+        }
+
+        Ok(Self {
+            requests_per_second,
+            bucket: Arc::new(Mutex::new(Bucket {
+                tokens: 1.0,
+                updated: Instant::now(),
+            })),
+        })
+    }
+
+    /// Waits until another request may be sent, returning an error if the backup is cancelled
+    /// while we are waiting.
+    pub async fn wait(&self, clock: &dyn Clock, cancel: &AtomicBool) -> Result<(), errors::Error> {
+        let now = clock.now();
+        let ready_at = self.reserve(now);
+
+        clock
+            .sleep_cancellable(ready_at.saturating_duration_since(now), cancel)
+            .await
+    }
+
+    /// Takes a token from the bucket, returning the time at which the request it was taken for
+    /// may be sent. The bucket may go into debt, which queues up requests behind one another.
+    fn reserve(&self, now: Instant) -> Instant {
+        let mut bucket = self.bucket.lock().unwrap();
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.requests_per_second).min(1.0) - 1.0;
+        bucket.updated = now;
+
+        if bucket.tokens >= 0.0 {
+            now
+        } else {
+            now + Duration::from_secs_f64(-bucket.tokens / self.requests_per_second)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use rstest::rstest;
+
+    use crate::helpers::{clock::MockClock, SystemClock};
+
+    use super::*;
+
+    #[rstest]
+    #[case(0.0)]
+    #[case(-1.0)]
+    #[case(f64::NAN)]
+    fn test_invalid_rate(#[case] requests_per_second: f64) {
+        assert!(Throttle::new(requests_per_second).is_err());
+    }
+
+    #[test]
+    fn test_reserve() {
+        let throttle = Throttle::new(4.0).unwrap();
+        let start = Instant::now();
+
+        assert_eq!(throttle.reserve(start), start);
+        assert_eq!(throttle.reserve(start), start + Duration::from_millis(250));
+        assert_eq!(throttle.reserve(start), start + Duration::from_millis(500));
+
+        // Time spent idle refills the bucket, but never beyond a single token.
+        let later = start + Duration::from_secs(10);
+        assert_eq!(throttle.reserve(later), later);
+        assert_eq!(throttle.reserve(later), later + Duration::from_millis(250));
+    }
+
+    #[tokio::test]
+    async fn test_wait() {
+        let throttle = Throttle::new(5.0).unwrap();
+        let cancel = AtomicBool::new(false);
+
+        let start = Instant::now();
+        for _ in 0..6 {
+            throttle
+                .wait(&SystemClock, &cancel)
+                .await
+                .expect("the wait to succeed");
+        }
+
+        assert!(
+            start.elapsed() >= Duration::from_millis(950),
+            "6 requests at 5 requests per second should take at least a second (took {:?})",
+            start.elapsed()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wait_delays() {
+        let clock = MockClock::default();
+        let throttle = Throttle::new(5.0).unwrap();
+        let cancel = AtomicBool::new(false);
+
+        for _ in 0..4 {
+            throttle
+                .wait(&clock, &cancel)
+                .await
+                .expect("the wait to succeed");
+        }
+
+        assert_eq!(clock.slept(), vec![Duration::from_millis(200); 3]);
+    }
+
+    #[tokio::test]
+    async fn test_wait_cancelled() {
+        let throttle = Throttle::new(0.1).unwrap();
+        let cancel = AtomicBool::new(false);
+        throttle
+            .wait(&SystemClock, &cancel)
+            .await
+            .expect("the first request to be sent immediately");
+
+        cancel.store(true, Ordering::Relaxed);
+        let start = Instant::now();
+        assert!(throttle.wait(&SystemClock, &cancel).await.is_err());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
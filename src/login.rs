@@ -0,0 +1,478 @@
+use std::{
+    path::PathBuf,
+    sync::{atomic::AtomicBool, Arc},
+    time::Duration,
+};
+
+use serde::Deserialize;
+use tracing_batteries::prelude::*;
+
+use crate::{
+    errors,
+    helpers::{Clock, SystemClock},
+};
+
+/// Sign in to GitHub using the device flow, and store the resulting access token so that it can be
+/// used by the `!TokenFile` or `!Keyring` credentials in your configuration file.
+#[derive(clap::Args, Debug)]
+pub struct LoginArgs {
+    /// The client ID of the GitHub OAuth App (or GitHub App) to sign in with, which must have the device flow enabled.
+    #[arg(long)]
+    pub client_id: String,
+
+    /// The scopes to request for the access token.
+    #[arg(long, value_delimiter = ',', default_value = "repo")]
+    pub scopes: Vec<String>,
+
+    /// Write the access token to this file (for use with `!TokenFile` credentials).
+    #[arg(long, value_name = "PATH", required_unless_present = "keyring_service")]
+    pub token_file: Option<PathBuf>,
+
+    /// Store the access token in your OS keyring under this service (for use with `!Keyring` credentials).
+    #[arg(
+        long,
+        value_name = "SERVICE",
+        requires = "keyring_account",
+        conflicts_with = "token_file"
+    )]
+    pub keyring_service: Option<String>,
+
+    /// The account to store the access token under in your OS keyring.
+    #[arg(long, value_name = "ACCOUNT", requires = "keyring_service")]
+    pub keyring_account: Option<String>,
+}
+
+impl LoginArgs {
+    /// Where the access token should be stored once the user has signed in.
+    pub fn store(&self) -> TokenStore {
+        match (
+            &self.token_file,
+            &self.keyring_service,
+            &self.keyring_account,
+        ) {
+            (_, Some(service), Some(account)) => TokenStore::Keyring {
+                service: service.clone(),
+                account: account.clone(),
+            },
+            (Some(path), _, _) => TokenStore::File(path.clone()),
+            _ => unreachable!("clap requires either a token file or keyring entry"),
+        }
+    }
+}
+
+/// A location where the access token obtained by [`DeviceFlow`] can be stored.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TokenStore {
+    File(PathBuf),
+    Keyring { service: String, account: String },
+}
+
+impl TokenStore {
+    pub fn save(&self, token: &str) -> Result<(), errors::Error> {
+        match self {
+            TokenStore::File(path) => write_token_file(path, token).map_err(|e| {
+                errors::user_with_internal(
+                    &format!("Unable to write your access token to '{}'.", path.display()),
+                    "Make sure that you have permission to write to this file/directory and try again.",
+                    e,
+                )
+            }),
+            TokenStore::Keyring { service, account } => keyring::Entry::new(service, account)
+                .and_then(|entry| entry.set_password(token))
+                .map_err(|e| {
+                    errors::user_with_internal(
+                        &format!(
+                            "Unable to store your access token for account '{account}' of service '{service}' in your OS keyring."
+                        ),
+                        "Make sure that your OS keyring is available and that GitHub Backup has permission to access it.",
+                        e,
+                    )
+                }),
+        }
+    }
+}
+
+impl std::fmt::Display for TokenStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenStore::File(path) => write!(f, "{}", path.display()),
+            TokenStore::Keyring { service, account } => {
+                write!(
+                    f,
+                    "your OS keyring (service '{service}', account '{account}')"
+                )
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn write_token_file(path: &std::path::Path, token: &str) -> std::io::Result<()> {
+    use std::{io::Write, os::unix::fs::OpenOptionsExt};
+
+    // The token grants access to your GitHub account, so it shouldn't be readable by other users.
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(token.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_token_file(path: &std::path::Path, token: &str) -> std::io::Result<()> {
+    std::fs::write(path, token)
+}
+
+/// The code which the user enters on GitHub to approve a device flow login.
+#[derive(Clone, Debug, Deserialize)]
+pub struct DeviceCode {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    pub expires_in: u64,
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+}
+
+fn default_interval() -> u64 {
+    5
+}
+
+/// A response from GitHub's access token endpoint while polling for a device flow login.
+#[derive(Clone, Debug, Deserialize)]
+struct TokenResponse {
+    access_token: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+    interval: Option<u64>,
+}
+
+/// The next step to take after polling GitHub for an access token.
+#[derive(Debug)]
+enum Poll {
+    /// The user hasn't approved the login yet, so we should poll again after the given interval.
+    Pending(Duration),
+    Complete(String),
+    Failed(errors::Error),
+}
+
+impl Poll {
+    fn next(response: TokenResponse, interval: Duration) -> Self {
+        if let Some(token) = response.access_token {
+            return Poll::Complete(token);
+        }
+
+        match response.error.as_deref() {
+            Some("authorization_pending") => Poll::Pending(interval),
+            // GitHub asks us to slow down by (at least) five seconds each time we poll too quickly.
+            Some("slow_down") => Poll::Pending(
+                response
+                    .interval
+                    .map(Duration::from_secs)
+                    .unwrap_or(interval + Duration::from_secs(5)),
+            ),
+            Some("expired_token") => Poll::Failed(errors::user(
+                "The code used to sign in to GitHub expired before the login was approved.",
+                "Run the login command again and enter the new code on GitHub before it expires.",
+            )),
+            Some("access_denied") => Poll::Failed(errors::user(
+                "The login was cancelled on GitHub.",
+                "Run the login command again and approve the request on GitHub to continue.",
+            )),
+            Some(error) => Poll::Failed(errors::user(
+                &format!(
+                    "GitHub rejected the login with the error '{}'{}.",
+                    error,
+                    response
+                        .error_description
+                        .map(|d| format!(" ({d})"))
+                        .unwrap_or_default()
+                ),
+                "Make sure that the client ID is correct and that the device flow is enabled for your GitHub App, then try again.",
+            )),
+            None => Poll::Failed(errors::system(
+                "GitHub responded to the login without an access token or an error.",
+                "Please try again, and report this issue to us on GitHub if it persists.",
+            )),
+        }
+    }
+}
+
+/// Obtains an access token using GitHub's OAuth device authorization flow, in which the user
+/// approves the login by entering a short code on GitHub.
+pub struct DeviceFlow {
+    client: reqwest::Client,
+    base_url: String,
+    client_id: String,
+    scopes: Vec<String>,
+    clock: Arc<dyn Clock>,
+}
+
+impl DeviceFlow {
+    pub fn new(client: reqwest::Client, client_id: &str, scopes: &[String]) -> Self {
+        Self {
+            client,
+            base_url: "https://github.com".to_string(),
+            client_id: client_id.to_string(),
+            scopes: scopes.to_vec(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Uses the given clock to wait between polls.
+    #[cfg(test)]
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+
+    #[allow(dead_code)]
+    pub fn with_base_url(self, base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            ..self
+        }
+    }
+
+    /// Requests a new code which the user must enter on GitHub to approve the login.
+    pub async fn start(&self) -> Result<DeviceCode, errors::Error> {
+        self.post(
+            "/login/device/code",
+            &[
+                ("client_id", self.client_id.as_str()),
+                ("scope", self.scopes.join(" ").as_str()),
+            ],
+        )
+        .await
+    }
+
+    /// Polls GitHub until the user has approved (or rejected) the login, returning the access token.
+    pub async fn poll(
+        &self,
+        code: &DeviceCode,
+        cancel: &AtomicBool,
+    ) -> Result<String, errors::Error> {
+        let expires_at = self.clock.now() + Duration::from_secs(code.expires_in);
+        let mut interval = Duration::from_secs(code.interval);
+
+        loop {
+            self.clock
+                .sleep_cancellable(interval, cancel)
+                .await
+                .map_err(|_| {
+                    errors::user(
+                        "The login was cancelled before it was approved on GitHub.",
+                        "Run the login command again and approve the request on GitHub to continue.",
+                    )
+                })?;
+
+            if self.clock.now() > expires_at {
+                return Err(errors::user(
+                    "The code used to sign in to GitHub expired before the login was approved.",
+                    "Run the login command again and enter the new code on GitHub before it expires.",
+                ));
+            }
+
+            let response: TokenResponse = self
+                .post(
+                    "/login/oauth/access_token",
+                    &[
+                        ("client_id", self.client_id.as_str()),
+                        ("device_code", code.device_code.as_str()),
+                        ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+                    ],
+                )
+                .await?;
+
+            match Poll::next(response, interval) {
+                Poll::Pending(next) => {
+                    trace!("Waiting {:?} for the login to be approved on GitHub", next);
+                    interval = next;
+                }
+                Poll::Complete(token) => return Ok(token),
+                Poll::Failed(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn post<T: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+        form: &[(&str, &str)],
+    ) -> Result<T, errors::Error> {
+        let url = format!("{}{}", self.base_url, path);
+        let resp = self
+            .client
+            .post(&url)
+            .header("Accept", "application/json")
+            .header("User-Agent", "SierraSoftworks/github-backup")
+            .form(form)
+            .send()
+            .await
+            .map_err(|e| {
+                errors::user_with_internal(
+                    &format!("Unable to make a request to '{url}' to sign in to GitHub."),
+                    "Make sure that you are connected to the internet and that GitHub is reachable, then try again.",
+                    e,
+                )
+            })?;
+
+        if !resp.status().is_success() {
+            return Err(errors::user(
+                &format!(
+                    "GitHub responded to the login request with a {} status code.",
+                    resp.status()
+                ),
+                "Make sure that the client ID is correct and that the device flow is enabled for your GitHub App, then try again.",
+            ));
+        }
+
+        resp.json().await.map_err(|e| {
+            errors::system_with_internal(
+                "Unable to parse GitHub's response to the login request.",
+                "Please try again, and report this issue to us on GitHub if it persists.",
+                e,
+            )
+        })
+    }
+}
+
+/// Runs the device flow login described by `args`, storing the resulting access token.
+///
+/// Requests to GitHub are made using the `http` settings (proxy, CA certificates, etc.) from
+/// the configuration file.
+pub async fn login(
+    args: &LoginArgs,
+    http: &crate::helpers::http::HttpConfig,
+    cancel: &AtomicBool,
+) -> Result<(), errors::Error> {
+    let flow = DeviceFlow::new(http.client()?, &args.client_id, &args.scopes);
+
+    let code = flow.start().await?;
+    println!(
+        "To sign in, open {} in your browser and enter the code: {}",
+        code.verification_uri, code.user_code
+    );
+
+    let token = flow.poll(&code, cancel).await?;
+    let store = args.store();
+    store.save(&token)?;
+    info!(
+        "Signed in to GitHub and stored your access token in {}",
+        store
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{routing::post, Json, Router};
+    use rstest::rstest;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::{errors::ErrorCategory, helpers::clock::MockClock};
+
+    fn response(body: serde_json::Value) -> TokenResponse {
+        serde_json::from_value(body).expect("a valid token response")
+    }
+
+    #[rstest]
+    #[case(serde_json::json!({ "error": "authorization_pending" }), Some(5))]
+    #[case(serde_json::json!({ "error": "slow_down" }), Some(10))]
+    #[case(serde_json::json!({ "error": "slow_down", "interval": 15 }), Some(15))]
+    #[case(serde_json::json!({ "access_token": "gho_test", "token_type": "bearer", "scope": "repo" }), None)]
+    fn test_poll_pending(#[case] body: serde_json::Value, #[case] interval: Option<u64>) {
+        match (Poll::next(response(body), Duration::from_secs(5)), interval) {
+            (Poll::Pending(next), Some(interval)) => {
+                assert_eq!(next, Duration::from_secs(interval))
+            }
+            (Poll::Complete(token), None) => assert_eq!(token, "gho_test"),
+            (poll, _) => panic!("unexpected poll result: {poll:?}"),
+        }
+    }
+
+    #[rstest]
+    #[case(serde_json::json!({ "error": "expired_token" }), ErrorCategory::User)]
+    #[case(serde_json::json!({ "error": "access_denied" }), ErrorCategory::User)]
+    #[case(serde_json::json!({ "error": "incorrect_client_credentials", "error_description": "The client_id is not valid." }), ErrorCategory::User)]
+    #[case(serde_json::json!({}), ErrorCategory::System)]
+    fn test_poll_failed(#[case] body: serde_json::Value, #[case] category: ErrorCategory) {
+        match Poll::next(response(body), Duration::from_secs(5)) {
+            Poll::Failed(e) => assert_eq!(ErrorCategory::of(&e), category),
+            poll => panic!("unexpected poll result: {poll:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_device_flow() {
+        let polls = Arc::new(AtomicUsize::new(0));
+        let app = Router::new()
+            .route(
+                "/login/device/code",
+                post(|| async {
+                    Json(serde_json::json!({
+                        "device_code": "device",
+                        "user_code": "ABCD-1234",
+                        "verification_uri": "https://github.com/login/device",
+                        "expires_in": 900,
+                        "interval": 5
+                    }))
+                }),
+            )
+            .route(
+                "/login/oauth/access_token",
+                post({
+                    let polls = polls.clone();
+                    move || async move {
+                        match polls.fetch_add(1, Ordering::Relaxed) {
+                            0 | 1 => Json(serde_json::json!({ "error": "authorization_pending" })),
+                            _ => Json(serde_json::json!({
+                                "access_token": "gho_test",
+                                "token_type": "bearer",
+                                "scope": "repo"
+                            })),
+                        }
+                    }
+                }),
+            );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let clock = Arc::new(MockClock::default());
+        let flow = DeviceFlow::new(reqwest::Client::new(), "client", &["repo".to_string()])
+            .with_base_url(&format!("http://{addr}"))
+            .with_clock(clock.clone());
+        let cancel = AtomicBool::new(false);
+
+        let code = flow.start().await.expect("a device code");
+        assert_eq!(code.user_code, "ABCD-1234");
+
+        let token = flow.poll(&code, &cancel).await.expect("an access token");
+        assert_eq!(token, "gho_test");
+        assert_eq!(polls.load(Ordering::Relaxed), 3);
+        assert_eq!(clock.slept(), vec![Duration::from_secs(5); 3]);
+
+        cancel.store(true, Ordering::Relaxed);
+        let err = flow
+            .poll(&code, &cancel)
+            .await
+            .expect_err("the login to be cancelled");
+        assert!(err.description().contains("cancelled"), "got {err}");
+    }
+
+    #[test]
+    fn test_save_token_file() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let path = temp_dir.path().join("token");
+
+        TokenStore::File(path.clone())
+            .save("gho_test")
+            .expect("the token to be saved");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "gho_test");
+    }
+}
@@ -1,9 +1,15 @@
-use clap::Parser;
+use clap::{CommandFactory, FromArgMatches, Parser};
 use engines::BackupState;
 use errors::Error;
+use exit_code::ExitCode;
 use pairing::PairingHandler;
+use statistics::SummaryStatistics;
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
 use std::time::Duration;
+use tokio_stream::StreamExt;
 use tracing_batteries::prelude::*;
 
 #[macro_use]
@@ -13,14 +19,22 @@ mod config;
 mod engines;
 mod entities;
 mod errors;
+mod exit_code;
 mod filter;
 pub(crate) mod helpers;
+mod login;
 mod pairing;
+mod plan;
 mod policy;
+mod preflight;
+#[cfg(feature = "progress")]
+mod progress;
 mod sources;
+mod state;
+mod statistics;
 mod telemetry;
 
-use crate::helpers::github::GitHubArtifactKind;
+use crate::helpers::{github::GitHubArtifactKind, http::HttpArtifactKind};
 pub use entities::BackupEntity;
 pub use filter::{Filter, FilterValue, Filterable};
 pub use policy::BackupPolicy;
@@ -28,6 +42,14 @@ pub use sources::BackupSource;
 
 static CANCEL: AtomicBool = AtomicBool::new(false);
 
+/// Set once a backup run has reached its maximum runtime, to stop any new backups from starting.
+static DRAIN: AtomicBool = AtomicBool::new(false);
+
+/// Set to cancel the backups in the current run, either when the process is interrupted or when
+/// the run overruns its maximum runtime's grace period. Unlike [`CANCEL`], it is reset at the start
+/// of each run, so that an overrun doesn't stop the next scheduled run (or the metrics servers).
+static RUN_CANCEL: AtomicBool = AtomicBool::new(false);
+
 /// Backup your GitHub repositories automatically.
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -40,66 +62,664 @@ pub struct Args {
     #[arg(short, long)]
     pub dry_run: bool,
 
-    /// The maximum number of concurrent backup tasks which are permitted to run at a given time.
-    #[arg(long, default_value = "10")]
-    pub concurrency: usize,
+    /// Write the plan produced by a dry run to this file (as JSON, or YAML if it has a .yaml extension) instead of logging each item.
+    #[arg(long, requires = "dry_run")]
+    pub plan_out: Option<PathBuf>,
+
+    /// During a dry run, print every metadata property (and its value) for each item, so that you can see which properties your filters can use.
+    #[arg(long, requires = "dry_run")]
+    pub dump_metadata: bool,
+
+    /// Run a scheduled backup as soon as the process starts, even if the last run already covered the most recent scheduled time.
+    #[arg(long)]
+    pub run_on_start: bool,
+
+    /// During a dry run, send the first request each backup policy would make (to check its credentials and network access) instead of planning the backup, then exit.
+    #[arg(long, requires = "dry_run")]
+    pub probe: bool,
+
+    /// Append each error encountered during the backup to this file as a line of JSON, in addition to logging it.
+    #[arg(long, value_name = "PATH")]
+    pub errors_json: Option<PathBuf>,
+
+    /// Only back up items which have been published since the last successful backup run (where supported).
+    #[arg(long)]
+    pub since: bool,
+
+    /// Only run the backup policies with this tag (may be repeated, and combined with --policy).
+    #[arg(long = "tag", value_name = "TAG")]
+    pub tags: Vec<String>,
+
+    /// Only run the backup policy with this name, for example github/repo/users/octocat (may be repeated, and combined with --tag).
+    #[arg(long = "policy", value_name = "POLICY")]
+    pub policies: Vec<String>,
+
+    /// Validate the configuration file and its backup policies, then exit.
+    #[arg(long)]
+    pub config_check: bool,
+
+    /// Write any missing SHA-256 checksum files alongside the release and GitHub Pages files in your backups, then exit.
+    #[arg(long)]
+    pub repair_sidecars: bool,
+
+    /// List every item covered by your backup policies (without backing them up) and remove the saved state of any which no longer exist, then exit.
+    #[arg(long)]
+    pub prune_state: bool,
+
+    /// Print a JSON Schema describing the configuration file (for use by your editor), then exit.
+    #[arg(long)]
+    pub print_config_schema: bool,
+
+    /// Print how the given filter expression is parsed and evaluated (to help debug your filters), then exit.
+    #[arg(long, value_name = "FILTER")]
+    pub dump_ast: Option<String>,
+
+    /// The maximum number of items which may be loaded from the GitHub API ahead of the backup tasks which will process them.
+    #[arg(long, default_value = "100")]
+    pub api_concurrency: usize,
+
+    /// The maximum number of concurrent backup tasks (clones, fetches and downloads) which are permitted to run at a given time, or 0 to run them all at once.
+    #[arg(long, alias = "concurrency", default_value = "10")]
+    pub io_concurrency: usize,
+
+    /// Choose the number of concurrent backup tasks based on the available CPUs, adjusting it as backups complete (an explicit --io-concurrency takes precedence).
+    #[arg(long)]
+    pub concurrency_auto: bool,
+
+    /// Reduce the number of concurrent backup tasks as the GitHub API rate limit is approached, restoring it once the rate limit resets.
+    #[arg(long)]
+    pub adaptive_concurrency: bool,
+
+    /// The maximum number of requests per second which may be sent to the GitHub API.
+    #[arg(long, value_name = "N")]
+    pub rate_limit: Option<f64>,
+
+    /// Report type mismatches in filter expressions as errors, rather than treating them as non-matches.
+    #[arg(long)]
+    pub strict_filters: bool,
+
+    /// Only back up the first N items (which match the filter) for each backup policy, overriding the policy's limit property.
+    #[arg(long)]
+    pub limit: Option<usize>,
+
+    /// The maximum number of backup policies which may run at the same time.
+    #[arg(long, default_value = "1")]
+    pub policy_concurrency: usize,
+
+    /// The longest a backup run may take (e.g. 30m or 1h30m), after which no new backups are started and the run ends once the running backups finish.
+    #[arg(long, value_name = "DURATION", value_parser = config::parse_duration)]
+    pub max_runtime: Option<Duration>,
+
+    /// How long backups which are still running once the maximum runtime is reached may continue for (e.g. 10m), after which they are cancelled.
+    #[arg(long, value_name = "DURATION", value_parser = config::parse_duration, requires = "max_runtime")]
+    pub max_runtime_grace: Option<Duration>,
+
+    /// Stop the whole run as soon as any backup fails, rather than continuing with the remaining backups.
+    #[arg(long)]
+    pub fail_fast: bool,
+
+    /// Fail, rather than warn, when the pre-flight checks find a problem with your credentials.
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Show a live progress display for each backup policy (when running in a terminal).
+    #[cfg(feature = "progress")]
+    #[arg(long)]
+    pub progress: bool,
+
+    /// The address on which to expose Prometheus metrics (e.g. 127.0.0.1:9100).
+    #[arg(long)]
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// The address on which to expose a health check endpoint for container orchestrators (e.g. 0.0.0.0:8080).
+    #[arg(long)]
+    pub health_addr: Option<SocketAddr>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
 }
 
-async fn run(args: Args) -> Result<(), Error> {
-    let config = config::Config::try_from(&args)?;
+#[derive(clap::Subcommand, Debug)]
+pub enum Command {
+    /// Sign in to GitHub using the device flow and store the resulting access token in a token file or your OS keyring.
+    Login(login::LoginArgs),
+}
+
+async fn run(args: Args) -> Result<Arc<SummaryStatistics>, Error> {
+    if let Some(Command::Login(login)) = &args.command {
+        // Signing in doesn't need a configuration file, but if there is one we respect its
+        // HTTP settings so that logins work behind the same proxy as the backups.
+        let http = if std::path::Path::new(&args.config).exists() {
+            config::Config::load(&args.config)?.http
+        } else {
+            Default::default()
+        };
+
+        login::login(login, &http, &CANCEL).await?;
+        return Ok(Arc::new(SummaryStatistics::default()));
+    }
+
+    let mut config = config::Config::try_from(&args)?;
 
-    let github_repo = pairing::Pairing::new(sources::GitHubRepoSource::repo(), engines::GitEngine)
-        .with_dry_run(args.dry_run)
-        .with_concurrency_limit(args.concurrency);
+    let stats = Arc::new(SummaryStatistics::default());
+    if let Some(addr) = args.metrics_addr {
+        let server = telemetry::MetricsServer::bind(addr).await?;
+        tokio::spawn(server.serve(stats.clone(), &CANCEL));
+    }
+
+    let health = telemetry::Health::default();
+    if let Some(addr) = args.health_addr {
+        let server = telemetry::HealthServer::bind(addr).await?;
+        tokio::spawn(server.serve(health.clone(), &CANCEL));
+    }
 
-    let github_star = pairing::Pairing::new(sources::GitHubRepoSource::star(), engines::GitEngine)
-        .with_dry_run(args.dry_run)
-        .with_concurrency_limit(args.concurrency);
+    let error_log = args
+        .errors_json
+        .as_deref()
+        .map(telemetry::ErrorLog::open)
+        .transpose()?;
+
+    let plan = args
+        .plan_out
+        .as_ref()
+        .map(|_| plan::PlanRecorder::default());
+
+    #[cfg(feature = "progress")]
+    let progress = if args.progress {
+        progress::Progress::detect()
+    } else {
+        None
+    };
+
+    if args.io_concurrency == 0 && !args.concurrency_auto {
+        warn!("The IO concurrency limit is disabled, so every backup task will be started at once. This may exhaust your system's memory, file handles or network connections if you are backing up many items.");
+    }
+
+    let http_client = config.http.client()?;
+    let mailer = config.smtp.as_ref().map(|smtp| smtp.mailer()).transpose()?;
+    let clock: Arc<dyn helpers::Clock> = Arc::new(helpers::SystemClock);
+    let github_client = helpers::GitHubClient::new(http_client.clone())
+        .with_throttle(args.rate_limit.map(helpers::Throttle::new).transpose()?)
+        .with_clock(clock.clone());
+    let rate_limit = args
+        .adaptive_concurrency
+        .then(|| github_client.rate_limit());
+    let auto_concurrency = args.concurrency_auto.then(helpers::AutoConcurrency::detect);
+    let path_locks = helpers::PathLocks::default();
+    let git_engine =
+        engines::GitEngine::default().with_config_overrides(config.http.git_config_overrides());
+
+    let github_repo = pairing::Pairing::new(
+        sources::GitHubRepoSource::with_client(github_client.clone(), GitHubArtifactKind::Repo)
+            .with_dry_run(args.dry_run),
+        git_engine.clone(),
+    )
+    .with_dry_run(args.dry_run)
+    .with_api_concurrency_limit(args.api_concurrency)
+    .with_io_concurrency_limit(args.io_concurrency)
+    .with_adaptive_concurrency(rate_limit.clone())
+    .with_auto_concurrency(auto_concurrency.clone())
+    .with_path_locks(path_locks.clone())
+    .with_strict_filters(args.strict_filters)
+    .with_fail_fast(args.fail_fast)
+    .with_limit(args.limit)
+    .with_drain(&DRAIN);
+
+    let github_star = pairing::Pairing::new(
+        sources::GitHubRepoSource::with_client(github_client.clone(), GitHubArtifactKind::Star)
+            .with_dry_run(args.dry_run),
+        git_engine,
+    )
+    .with_dry_run(args.dry_run)
+    .with_api_concurrency_limit(args.api_concurrency)
+    .with_io_concurrency_limit(args.io_concurrency)
+    .with_adaptive_concurrency(rate_limit.clone())
+    .with_auto_concurrency(auto_concurrency.clone())
+    .with_path_locks(path_locks.clone())
+    .with_strict_filters(args.strict_filters)
+    .with_fail_fast(args.fail_fast)
+    .with_limit(args.limit)
+    .with_drain(&DRAIN);
 
     let github_release = pairing::Pairing::new(
-        sources::GitHubReleasesSource::default(),
-        engines::HttpFileEngine::new(),
+        sources::GitHubReleasesSource::with_client(github_client.clone())
+            .with_incremental(args.since),
+        engines::HttpFileEngine::with_client(http_client.clone()),
+    )
+    .with_dry_run(args.dry_run)
+    .with_api_concurrency_limit(args.api_concurrency)
+    .with_io_concurrency_limit(args.io_concurrency)
+    .with_adaptive_concurrency(rate_limit.clone())
+    .with_auto_concurrency(auto_concurrency.clone())
+    .with_path_locks(path_locks.clone())
+    .with_strict_filters(args.strict_filters)
+    .with_fail_fast(args.fail_fast)
+    .with_limit(args.limit)
+    .with_drain(&DRAIN);
+
+    let github_pages = pairing::Pairing::new(
+        sources::GitHubPagesSource::with_client(github_client.clone()),
+        engines::HttpFileEngine::with_client(http_client.clone()),
+    )
+    .with_dry_run(args.dry_run)
+    .with_api_concurrency_limit(args.api_concurrency)
+    .with_io_concurrency_limit(args.io_concurrency)
+    .with_adaptive_concurrency(rate_limit.clone())
+    .with_auto_concurrency(auto_concurrency.clone())
+    .with_path_locks(path_locks.clone())
+    .with_strict_filters(args.strict_filters)
+    .with_fail_fast(args.fail_fast)
+    .with_limit(args.limit)
+    .with_drain(&DRAIN);
+
+    // Files which aren't hosted on GitHub don't use up its rate limit, so they aren't slowed down as it is approached.
+    let http_file = pairing::Pairing::new(
+        sources::StaticHttpSource,
+        engines::HttpFileEngine::with_client(http_client),
     )
     .with_dry_run(args.dry_run)
-    .with_concurrency_limit(args.concurrency);
+    .with_api_concurrency_limit(args.api_concurrency)
+    .with_io_concurrency_limit(args.io_concurrency)
+    .with_auto_concurrency(auto_concurrency.clone())
+    .with_path_locks(path_locks.clone())
+    .with_strict_filters(args.strict_filters)
+    .with_fail_fast(args.fail_fast)
+    .with_limit(args.limit)
+    .with_drain(&DRAIN);
+
+    if args.dry_run || args.config_check {
+        for policy in config.backups.iter() {
+            match policy.kind.as_str() {
+                k if k == GitHubArtifactKind::Repo.as_str() => github_repo.validate(policy)?,
+                k if k == GitHubArtifactKind::Star.as_str() => github_star.validate(policy)?,
+                k if k == GitHubArtifactKind::Release.as_str() => {
+                    github_release.validate(policy)?
+                }
+                k if k == GitHubArtifactKind::Pages.as_str() => github_pages.validate(policy)?,
+                k if k == HttpArtifactKind::File.as_str() => http_file.validate(policy)?,
+                _ => return Err(unknown_policy_kind(policy)),
+            }
+        }
+
+        if args.config_check {
+            info!("Your configuration file is valid.");
+            return Ok(stats);
+        }
+    }
+
+    if args.probe {
+        for policy in config.backups.iter() {
+            let result = match policy.kind.as_str() {
+                k if k == GitHubArtifactKind::Repo.as_str() => {
+                    github_repo.probe(policy, &CANCEL).await.map(|_| ())
+                }
+                k if k == GitHubArtifactKind::Star.as_str() => {
+                    github_star.probe(policy, &CANCEL).await.map(|_| ())
+                }
+                k if k == GitHubArtifactKind::Release.as_str() => {
+                    github_release.probe(policy, &CANCEL).await.map(|_| ())
+                }
+                k if k == GitHubArtifactKind::Pages.as_str() => {
+                    github_pages.probe(policy, &CANCEL).await.map(|_| ())
+                }
+                k if k == HttpArtifactKind::File.as_str() => {
+                    http_file.probe(policy, &CANCEL).await.map(|_| ())
+                }
+                _ => Err(unknown_policy_kind(policy)),
+            };
+
+            match result {
+                Ok(()) => info!("Successfully probed {}", policy),
+                Err(e) => {
+                    stats.record_error(&e);
+                    if let Some(error_log) = &error_log {
+                        error_log.record(Some(policy), None, &e);
+                    }
+                    error!("Unable to probe {}: {}", policy, e);
+                }
+            }
+        }
+
+        return Ok(stats);
+    }
+
+    if args.prune_state {
+        for policy in config.backups.iter() {
+            let result = match policy.kind.as_str() {
+                k if k == GitHubArtifactKind::Repo.as_str() => {
+                    github_repo.prune_state(policy, &CANCEL).await
+                }
+                k if k == GitHubArtifactKind::Star.as_str() => {
+                    github_star.prune_state(policy, &CANCEL).await
+                }
+                k if k == GitHubArtifactKind::Release.as_str() => {
+                    github_release.prune_state(policy, &CANCEL).await
+                }
+                k if k == GitHubArtifactKind::Pages.as_str() => {
+                    github_pages.prune_state(policy, &CANCEL).await
+                }
+                k if k == HttpArtifactKind::File.as_str() => {
+                    http_file.prune_state(policy, &CANCEL).await
+                }
+                _ => Err(unknown_policy_kind(policy)),
+            };
+
+            match result {
+                Ok(pruned) => info!(
+                    "Pruned the state of {} items which no longer exist from {}",
+                    pruned, policy
+                ),
+                Err(e) => {
+                    stats.record_error(&e);
+                    if let Some(error_log) = &error_log {
+                        error_log.record(Some(policy), None, &e);
+                    }
+                    error!("Unable to prune the state of {}: {}", policy, e);
+                }
+            }
+        }
+
+        return Ok(stats);
+    }
+
+    if args.repair_sidecars {
+        for policy in config.backups.iter().filter(|p| {
+            p.kind == GitHubArtifactKind::Release.as_str()
+                || p.kind == GitHubArtifactKind::Pages.as_str()
+                || p.kind == HttpArtifactKind::File.as_str()
+        }) {
+            let repair = engines::repair_sidecars(&policy.to, &CANCEL).await?;
+            info!(
+                "Wrote {} missing checksum files for {} ({} files already had a matching one).",
+                repair.created, policy, repair.valid
+            );
+            if repair.mismatched > 0 {
+                warn!(
+                    "Found {} files in {} whose content doesn't match their checksum file, which should be checked.",
+                    repair.mismatched, policy
+                );
+            }
+        }
+
+        return Ok(stats);
+    }
+
+    for warning in preflight::PreflightCheck::with_client(github_client)
+        .run(&config.backups, &CANCEL)
+        .await
+    {
+        if args.strict {
+            return Err(warning);
+        }
+
+        warn!("{}", warning);
+    }
+
+    if let (Some(schedule), false) = (&config.schedule, args.run_on_start || args.dry_run) {
+        // If the process was restarted (for example after a crash), we avoid running the backup
+        // again immediately when the last run already covered the most recent scheduled time.
+        if let Some(resume_at) = state::State::last_run(&config.backups)
+            .and_then(|last_run| state::resume_at(schedule, last_run, clock.utc_now()))
+        {
+            info!(
+                "The last backup ran recently, so the next backup is scheduled for: {} (use --run-on-start to run immediately)",
+                resume_at
+            );
+            clock.sleep_until(resume_at, &CANCEL).await;
+        }
+    }
+
+    // Each policy is backed up by its own task, so everything which those tasks use is shared.
+    let policies: Vec<Arc<BackupPolicy>> = std::mem::take(&mut config.backups)
+        .into_iter()
+        .map(Arc::new)
+        .collect();
+    let (github_repo, github_star, github_release, github_pages, http_file) = (
+        Arc::new(github_repo),
+        Arc::new(github_star),
+        Arc::new(github_release),
+        Arc::new(github_pages),
+        Arc::new(http_file),
+    );
+    let error_log = error_log.map(Arc::new);
+    #[cfg(feature = "progress")]
+    let progress = progress.map(Arc::new);
 
     while !CANCEL.load(std::sync::atomic::Ordering::Relaxed) {
         let next_run = config
             .schedule
             .as_ref()
-            .and_then(|s| s.find_next_occurrence(&chrono::Utc::now(), false).ok());
+            .and_then(|s| s.find_next_occurrence(&clock.utc_now(), false).ok());
 
         {
             let _span = tracing::info_span!("backup.all").entered();
+            let started_at = clock.now();
 
-            for policy in config.backups.iter() {
-                let _policy_span = tracing::info_span!("backup.policy", policy = %policy).entered();
+            DRAIN.store(false, std::sync::atomic::Ordering::Relaxed);
+            // The flag is cleared before checking for an interruption, so that one which arrives
+            // while we do so isn't lost.
+            RUN_CANCEL.store(false, std::sync::atomic::Ordering::Relaxed);
+            if CANCEL.load(std::sync::atomic::Ordering::Relaxed) {
+                RUN_CANCEL.store(true, std::sync::atomic::Ordering::Relaxed);
+            }
 
-                match policy.kind.as_str() {
-                    k if k == GitHubArtifactKind::Repo.as_str() => {
-                        info!("Backing up repositories for {}", &policy);
-                        github_repo
-                            .run(policy, &LoggingPairingHandler, &CANCEL)
-                            .await;
-                    }
-                    k if k == GitHubArtifactKind::Star.as_str() => {
-                        info!("Backing up starred repositories for {}", &policy);
-                        github_star
-                            .run(policy, &LoggingPairingHandler, &CANCEL)
-                            .await;
+            let grace = args.max_runtime_grace;
+            let watchdog = args.max_runtime.map(|max_runtime| {
+                let clock = clock.clone();
+                tokio::spawn(async move {
+                    if clock.sleep_cancellable(max_runtime, &RUN_CANCEL).await.is_err() {
+                        return;
                     }
-                    k if k == GitHubArtifactKind::Release.as_str() => {
-                        info!("Backing up release artifacts for {}", &policy);
-                        github_release
-                            .run(policy, &LoggingPairingHandler, &CANCEL)
-                            .await;
+
+                    warn!(
+                        "The backup run has reached its maximum runtime of {:?}, so no new backups will be started.",
+                        max_runtime
+                    );
+                    DRAIN.store(true, std::sync::atomic::Ordering::Relaxed);
+
+                    let Some(grace) = grace else {
+                        return;
+                    };
+
+                    if clock.sleep_cancellable(grace, &RUN_CANCEL).await.is_err() {
+                        return;
                     }
-                    _ => {
-                        error!("Unknown policy kind: {}", policy.kind);
+
+                    warn!(
+                        "The backup run has overrun its maximum runtime by {:?}, so the backups which are still running will be cancelled.",
+                        grace
+                    );
+                    RUN_CANCEL.store(true, std::sync::atomic::Ordering::Relaxed);
+                })
+            });
+
+            let runs = pairing::run_policies(
+                policies.iter().cloned().enumerate(),
+                args.policy_concurrency,
+                |(_, policy)| {
+                    // Each policy's plan is started before any of them run, so that the plan lists
+                    // the policies in the same order as the configuration file.
+                    let plan = plan.as_ref().map(|plan| plan.start(policy));
+                    let stats = stats.clone();
+                    let stagger = config.stagger;
+                    let dry_run = args.dry_run;
+                    let dump_metadata = args.dump_metadata;
+                    let span = tracing::info_span!("backup.policy", policy = %policy);
+                    let policy = policy.clone();
+                    let (github_repo, github_star, github_release, github_pages, http_file) = (
+                        github_repo.clone(),
+                        github_star.clone(),
+                        github_release.clone(),
+                        github_pages.clone(),
+                        http_file.clone(),
+                    );
+                    #[cfg(feature = "progress")]
+                    let progress = progress.clone();
+                    let error_log = error_log.clone();
+                    let clock = clock.clone();
+
+                    async move {
+                        if let Some(stagger) = stagger {
+                            let start_at = started_at + policy.stagger_offset(stagger);
+                            if start_at > clock.now() {
+                                debug!(
+                                    "Delaying the start of {} by up to {:?}",
+                                    policy,
+                                    start_at - clock.now()
+                                );
+                            }
+
+                            while clock.now() < start_at
+                                && !RUN_CANCEL.load(std::sync::atomic::Ordering::Relaxed)
+                                && !DRAIN.load(std::sync::atomic::Ordering::Relaxed)
+                            {
+                                clock
+                                    .sleep(Duration::from_millis(500).min(start_at - clock.now()))
+                                    .await;
+                            }
+                        }
+
+                        if RUN_CANCEL.load(std::sync::atomic::Ordering::Relaxed) {
+                            return None;
+                        }
+
+                        if DRAIN.load(std::sync::atomic::Ordering::Relaxed) {
+                            info!(
+                                "Skipping {} since the backup run has reached its maximum runtime",
+                                policy
+                            );
+                            return None;
+                        }
+
+                        if !policy.in_window(clock.utc_now()) {
+                            info!(
+                                "Skipping {} since this run falls outside of its backup window",
+                                policy
+                            );
+                            return None;
+                        }
+
+                        let policy_started_at = clock.utc_now();
+                        let handler = LoggingPairingHandler {
+                            stats,
+                            summary: telemetry::PolicySummary::new(&policy),
+                            plan,
+                            #[cfg(feature = "progress")]
+                            progress: progress.as_ref().map(|p| p.start(&policy)),
+                            policy: &policy,
+                            error_log: error_log.as_deref(),
+                            dump_metadata,
+                        };
+
+                        match policy.kind.as_str() {
+                            k if k == GitHubArtifactKind::Repo.as_str() => {
+                                info!("Backing up repositories for {}", &policy);
+                                github_repo.run(&policy, &handler, &RUN_CANCEL).await;
+                            }
+                            k if k == GitHubArtifactKind::Star.as_str() => {
+                                info!("Backing up starred repositories for {}", &policy);
+                                github_star.run(&policy, &handler, &RUN_CANCEL).await;
+                            }
+                            k if k == GitHubArtifactKind::Release.as_str() => {
+                                info!("Backing up release artifacts for {}", &policy);
+                                github_release.run(&policy, &handler, &RUN_CANCEL).await;
+                            }
+                            k if k == GitHubArtifactKind::Pages.as_str() => {
+                                info!("Backing up GitHub Pages sites for {}", &policy);
+                                github_pages.run(&policy, &handler, &RUN_CANCEL).await;
+                            }
+                            k if k == HttpArtifactKind::File.as_str() => {
+                                info!("Backing up files for {}", &policy);
+                                http_file.run(&policy, &handler, &RUN_CANCEL).await;
+                            }
+                            _ => {
+                                let err = unknown_policy_kind(&policy);
+                                handler.stats.record_error(&err);
+                                if let Some(error_log) = &error_log {
+                                    error_log.record(Some(&policy), None, &err);
+                                }
+                                handler.summary.record_error(&err);
+                                error!("{}", err);
+                            }
+                        }
+
+                        #[cfg(feature = "progress")]
+                        if let Some(progress) = &handler.progress {
+                            progress.finish();
+                        }
+
+                        if !dry_run && !RUN_CANCEL.load(std::sync::atomic::Ordering::Relaxed) {
+                            // A policy which was stopped part way through hasn't backed up everything,
+                            // so it shouldn't be used as the starting point for a --since backup.
+                            let succeeded = handler.summary.stats.errors() == 0
+                                && !DRAIN.load(std::sync::atomic::Ordering::Relaxed);
+                            if let Err(e) = state::State::update(&policy, |s| {
+                                s.last_run = Some(policy_started_at);
+                                if succeeded {
+                                    s.last_successful_run = Some(policy_started_at);
+                                }
+                            })
+                            .await
+                            {
+                                warn!("Unable to record the backup state for {}: {}", policy, e);
+                            }
+                        }
+
+                        Some(handler.summary)
                     }
-                }
+                    .instrument(span)
+                },
+            );
 
+            tokio::pin!(runs);
+            let mut summaries = Vec::new();
+            while let Some(((index, policy), summary)) = runs.next().await {
+                debug!("Finished backing up {}", policy);
                 println!();
+                summaries.extend(summary.map(|summary| (index, summary)));
+            }
+
+            // Policies may finish in any order, but they're reported in the order in which they
+            // were configured.
+            summaries.sort_by_key(|(index, _)| *index);
+            let summaries: Vec<_> = summaries.into_iter().map(|(_, summary)| summary).collect();
+
+            if let Some(watchdog) = watchdog {
+                watchdog.abort();
+            }
+
+            let cut_short = DRAIN.load(std::sync::atomic::Ordering::Relaxed);
+            if cut_short {
+                warn!("The backup run was stopped early because it reached its maximum runtime, so some items were not backed up.");
+            }
+
+            let completed_at = clock.utc_now();
+            stats.record_run(completed_at, clock.now() - started_at);
+            health.record_run(completed_at, &summaries, next_run);
+
+            if let Some(mailer) = &mailer {
+                let digest = telemetry::Digest {
+                    completed_at,
+                    duration: clock.now() - started_at,
+                    policies: summaries,
+                    cut_short,
+                };
+
+                // The digest is a convenience, so failing to send it shouldn't fail the backup run.
+                match mailer.send(&digest).await {
+                    Ok(()) => debug!("Sent the backup digest email"),
+                    Err(e) => warn!("Unable to send the backup digest email: {}", e),
+                }
+            }
+
+            if let (Some(plan), Some(path)) = (&plan, &args.plan_out) {
+                let plan = plan.write(path)?;
+                info!(
+                    "Wrote the plan to back up {} of {} items to {}",
+                    plan.totals.backup,
+                    plan.totals.entities,
+                    path.display()
+                );
             }
         }
 
@@ -109,52 +729,156 @@ async fn run(args: Args) -> Result<(), Error> {
 
         if let Some(next_run) = next_run {
             info!("Next backup scheduled for: {}", next_run);
-
-            while chrono::Utc::now() < next_run
-                && !CANCEL.load(std::sync::atomic::Ordering::Relaxed)
-            {
-                tokio::time::sleep(Duration::from_millis(500)).await;
-            }
+            clock.sleep_until(next_run, &CANCEL).await;
         } else {
             break;
         }
     }
 
-    Ok(())
+    Ok(stats)
 }
 
-pub struct LoggingPairingHandler;
+fn unknown_policy_kind(policy: &BackupPolicy) -> Error {
+    errors::user(
+        &format!(
+            "Your backup policy '{}' uses an unknown kind '{}'.",
+            policy, policy.kind
+        ),
+        "Make sure that you have specified a supported kind (github/repo, github/star, github/release, github/pages, or http/file) for this backup policy.",
+    )
+}
+
+/// Reports the outcome of a single backup policy's backups, while recording them in the
+/// overall summary statistics.
+pub struct LoggingPairingHandler<'a> {
+    stats: Arc<SummaryStatistics>,
+    summary: telemetry::PolicySummary,
+    plan: Option<plan::PolicyPlanRecorder>,
+    #[cfg(feature = "progress")]
+    progress: Option<progress::PolicyProgress>,
+    policy: &'a BackupPolicy,
+    error_log: Option<&'a telemetry::ErrorLog>,
+    dump_metadata: bool,
+}
 
-impl<E: BackupEntity> PairingHandler<E> for LoggingPairingHandler {
+impl<E: BackupEntity> PairingHandler<E> for LoggingPairingHandler<'_> {
     fn on_complete(&self, entity: E, state: BackupState) {
+        self.stats.record(&state);
+        self.summary.record(&state);
+
+        if self.dump_metadata {
+            println!("{} ({})\n{}", entity, state, entity.metadata().dump());
+        }
+
+        if let Some(plan) = &self.plan {
+            plan.on_complete(entity, state);
+            return;
+        }
+
+        #[cfg(feature = "progress")]
+        if let Some(progress) = &self.progress {
+            progress.inc();
+            return;
+        }
+
         info!(" - {} ({})", entity, state);
     }
 
-    fn on_error(&self, error: crate::Error) {
+    fn on_error(&self, entity: Option<&str>, error: crate::Error) {
+        self.stats.record_error(&error);
+        self.summary.record_error(&error);
+
+        if let Some(error_log) = self.error_log {
+            error_log.record(Some(self.policy), entity, &error);
+        }
+
+        if let Some(plan) = &self.plan {
+            plan.record_error(&error);
+        }
+
+        #[cfg(feature = "progress")]
+        if let Some(progress) = &self.progress {
+            progress.suspend(|| warn!("Error: {}", error));
+            return;
+        }
+
         warn!("Error: {}", error);
     }
+
+    #[cfg(feature = "progress")]
+    fn on_loaded(&self, total: usize) {
+        if let Some(progress) = &self.progress {
+            progress.set_total(total as u64);
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() {
     ctrlc::set_handler(|| {
         CANCEL.store(true, std::sync::atomic::Ordering::Relaxed);
+        RUN_CANCEL.store(true, std::sync::atomic::Ordering::Relaxed);
         info!("Received SIGINT, shutting down...");
     })
     .unwrap_or_default();
 
-    let args = Args::parse();
+    let (matches, mut args) = match Args::command()
+        .try_get_matches()
+        .and_then(|matches| Args::from_arg_matches(&matches).map(|args| (matches, args)))
+    {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            e.print().unwrap_or_default();
+            std::process::exit(ExitCode::from_usage_error(&e).code());
+        }
+    };
+
+    // An explicit IO concurrency limit always takes precedence over choosing one automatically.
+    if matches.value_source("io_concurrency") == Some(clap::parser::ValueSource::CommandLine) {
+        args.concurrency_auto = false;
+    }
+
+    if args.print_config_schema {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&config::Config::schema()).unwrap_or_default()
+        );
+        return;
+    }
+
+    if let Some(filter) = &args.dump_ast {
+        match Filter::new(filter.as_str()) {
+            Ok(filter) => print!("{}", filter.dump()),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(ExitCode::from_error(&e).code());
+            }
+        }
+
+        return;
+    }
 
     let session = telemetry::setup();
+    let errors_json = args.errors_json.clone();
 
-    let result = run(args).await;
+    let exit_code = match run(args).await {
+        Ok(stats) => ExitCode::from_statistics(&stats),
+        Err(e) => {
+            session.record_error(&e);
+            error!("{}", e);
+            if let Some(error_log) = errors_json
+                .as_deref()
+                .and_then(|path| telemetry::ErrorLog::open(path).ok())
+            {
+                error_log.record(None, None, &e);
+            }
+            ExitCode::from_error(&e)
+        }
+    };
 
-    if let Err(e) = result {
-        session.record_error(&e);
-        error!("{}", e);
-        session.shutdown();
-        std::process::exit(1);
-    } else {
-        session.shutdown();
+    session.shutdown();
+
+    if exit_code != ExitCode::Success {
+        std::process::exit(exit_code.code());
     }
 }
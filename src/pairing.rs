@@ -1,4 +1,8 @@
-use std::{marker::PhantomData, sync::atomic::AtomicBool};
+use std::{
+    collections::{BTreeSet, VecDeque},
+    marker::PhantomData,
+    sync::{atomic::AtomicBool, Arc},
+};
 
 use crate::telemetry::StreamExt;
 use tokio::task::JoinSet;
@@ -6,15 +10,30 @@ use tokio_stream::{Stream, StreamExt as _};
 use tracing_batteries::prelude::*;
 
 use crate::{
-    engines::{BackupEngine, BackupState},
+    engines::{
+        BackupDetails, BackupEngine, BackupState, BackupStore, FileSystemStore, RetryingStore,
+        SkipReason, DEFAULT_FS_RETRIES,
+    },
+    errors,
+    helpers::{fingerprints::Fingerprints, AutoConcurrency, PathLocks, RateLimit},
+    state::{State, PRUNE_AFTER_MISSED_RUNS},
     BackupEntity, BackupPolicy, BackupSource,
 };
 
 pub struct Pairing<E: BackupEntity, S: BackupSource<E>, T: BackupEngine<E>> {
     pub source: S,
     pub target: T,
+    pub store: Arc<dyn BackupStore>,
     pub dry_run: bool,
-    pub concurrency_limit: usize,
+    pub api_concurrency_limit: usize,
+    pub io_concurrency_limit: usize,
+    pub adaptive_concurrency: Option<RateLimit>,
+    pub auto_concurrency: Option<AutoConcurrency>,
+    pub path_locks: PathLocks,
+    pub strict_filters: bool,
+    pub fail_fast: bool,
+    pub limit: Option<usize>,
+    pub drain: Option<&'static AtomicBool>,
     _entity: PhantomData<E>,
 }
 
@@ -28,118 +47,622 @@ impl<
         Self {
             source,
             target,
+            store: Arc::new(FileSystemStore),
             dry_run: false,
-            concurrency_limit: 10,
+            api_concurrency_limit: 100,
+            io_concurrency_limit: 10,
+            adaptive_concurrency: None,
+            auto_concurrency: None,
+            path_locks: PathLocks::default(),
+            strict_filters: false,
+            fail_fast: false,
+            limit: None,
+            drain: None,
             _entity: Default::default(),
         }
     }
 
+    #[cfg(test)]
+    pub fn with_store<B: BackupStore + 'static>(self, store: B) -> Self {
+        Self {
+            store: Arc::new(store),
+            ..self
+        }
+    }
+
     pub fn with_dry_run(self, dry_run: bool) -> Self {
         Self { dry_run, ..self }
     }
 
-    pub fn with_concurrency_limit(self, concurrency_limit: usize) -> Self {
-        if concurrency_limit == 0 {
+    /// Sets the maximum number of entities which may be loaded from the source ahead of
+    /// the backup tasks which will process them.
+    pub fn with_api_concurrency_limit(self, api_concurrency_limit: usize) -> Self {
+        if api_concurrency_limit == 0 {
             self
         } else {
             Self {
-                concurrency_limit,
+                api_concurrency_limit,
                 ..self
             }
         }
     }
 
+    /// Sets the maximum number of backup tasks (clones, fetches and downloads) which may run at once.
+    ///
+    /// A limit of `0` (or `usize::MAX`) removes the limit entirely, starting a backup task for every
+    /// entity as soon as it has been loaded.
+    pub fn with_io_concurrency_limit(self, io_concurrency_limit: usize) -> Self {
+        Self {
+            io_concurrency_limit: match io_concurrency_limit {
+                0 => usize::MAX,
+                limit => limit,
+            },
+            ..self
+        }
+    }
+
+    /// Reduces the number of backup tasks which may run at once as the given GitHub API rate limit
+    /// is approached, restoring the IO concurrency limit once the rate limit window resets.
+    pub fn with_adaptive_concurrency(self, adaptive_concurrency: Option<RateLimit>) -> Self {
+        Self {
+            adaptive_concurrency,
+            ..self
+        }
+    }
+
+    /// Chooses the number of backup tasks which may run at once based on how quickly (and reliably)
+    /// recent backup tasks have completed, in place of the IO concurrency limit.
+    pub fn with_auto_concurrency(self, auto_concurrency: Option<AutoConcurrency>) -> Self {
+        Self {
+            auto_concurrency,
+            ..self
+        }
+    }
+
+    /// Shares the locks which prevent two backup tasks from writing to the same path at once with
+    /// other pairings, so that policies of different kinds which share a directory don't race.
+    pub fn with_path_locks(self, path_locks: PathLocks) -> Self {
+        Self { path_locks, ..self }
+    }
+
+    pub fn with_strict_filters(self, strict_filters: bool) -> Self {
+        Self {
+            strict_filters,
+            ..self
+        }
+    }
+
+    /// Stops the run (by setting the cancellation flag) as soon as any error is encountered,
+    /// rather than recording the error and continuing with the remaining entities.
+    pub fn with_fail_fast(self, fail_fast: bool) -> Self {
+        Self { fail_fast, ..self }
+    }
+
+    /// Limits the number of entities which match the policy's filter and will be backed up, overriding
+    /// the policy's `limit` property.
+    pub fn with_limit(self, limit: Option<usize>) -> Self {
+        Self { limit, ..self }
+    }
+
+    /// Stops starting new backups once the given flag has been set, while allowing the backups
+    /// which are already running to finish. Entities which were waiting to be backed up are
+    /// reported as cancelled.
+    pub fn with_drain(self, drain: &'static AtomicBool) -> Self {
+        Self {
+            drain: Some(drain),
+            ..self
+        }
+    }
+
     pub async fn run(
         &self,
         policy: &BackupPolicy,
         handler: &dyn PairingHandler<E>,
         cancel: &'static AtomicBool,
     ) {
-        let stream = self.run_all_backups(policy, cancel);
+        let stream = self.run_backups(policy, Some(handler), cancel);
         tokio::pin!(stream);
         while let Some(result) = stream.next().await {
             match result {
                 Ok((entity, state)) => handler.on_complete(entity, state),
-                Err(e) => handler.on_error(e),
+                Err((entity, e)) => handler.on_error(entity.as_deref(), e),
             }
         }
     }
 
+    #[allow(dead_code)]
     pub fn run_all_backups<'a>(
         &'a self,
         policy: &'a BackupPolicy,
         cancel: &'static AtomicBool,
     ) -> impl Stream<Item = Result<(E, BackupState), crate::Error>> + 'a {
+        self.run_backups(policy, None, cancel)
+            .map(|result| result.map_err(|(_, e)| e))
+    }
+
+    /// Checks that the policy is valid for this pairing, including both the properties understood
+    /// by its source and those (like `limit` and `fs_retries`) which control how backups are run.
+    pub fn validate(&self, policy: &BackupPolicy) -> Result<(), crate::Error> {
+        self.source.validate(policy)?;
+        policy.property::<usize>("limit")?;
+        policy.property::<usize>("fs_retries")?;
+        policy.property::<bool>("backup_empty")?;
+        policy.property::<bool>("skip_unchanged")?;
+        Ok(())
+    }
+
+    /// Sends the first request that a run of the policy would make to its source (by loading the
+    /// first entity it would back up), without backing anything up. This allows problems with
+    /// credentials or network access to be discovered before a real backup is run.
+    pub async fn probe(
+        &self,
+        policy: &BackupPolicy,
+        cancel: &AtomicBool,
+    ) -> Result<Option<E>, crate::Error> {
+        self.validate(policy)?;
+
+        let stream = self.source.load(policy, cancel);
+        tokio::pin!(stream);
+        stream.next().await.transpose()
+    }
+
+    /// Lists every entity covered by the policy (without backing any of them up) and immediately
+    /// prunes the state of any entity which wasn't listed, returning the number which were pruned.
+    pub async fn prune_state(
+        &self,
+        policy: &BackupPolicy,
+        cancel: &AtomicBool,
+    ) -> Result<usize, crate::Error> {
+        self.validate(policy)?;
+
+        if !self.source.is_exhaustive(policy) {
+            return Err(errors::user(
+                &format!("Unable to prune the state of {policy}, since it is configured to only list recently changed items."),
+                "Remove the 'since' property from this policy (and don't pass the --since flag) while pruning its state.",
+            ));
+        }
+
+        // A resumed listing would skip the entities before its checkpoint, so we always list them all.
+        let mut seen = BTreeSet::new();
+        let stream = self.source.load_all(policy, cancel);
+        tokio::pin!(stream);
+        while let Some(entity) = stream.next().await {
+            seen.insert(entity?.name().to_string());
+        }
+
+        if cancel.load(std::sync::atomic::Ordering::Relaxed) {
+            return Ok(0);
+        }
+
+        State::compact(policy, &seen, 1).await
+    }
+
+    fn run_backups<'a>(
+        &'a self,
+        policy: &'a BackupPolicy,
+        handler: Option<&'a dyn PairingHandler<E>>,
+        cancel: &'static AtomicBool,
+    ) -> impl Stream<Item = Outcome<E>> + 'a {
+        let policy_span =
+            tracing::info_span!("backup.policy", kind = self.source.kind(), policy = %policy);
+        let span = policy_span.clone();
+
         async_stream::stream! {
-          let span = tracing::info_span!("backup.policy", kind = self.source.kind(), policy = %policy).entered();
 
-          match self.source.validate(policy) {
+          match self.validate(policy) {
             Ok(_) => {},
             Err(e) => {
-              yield Err(e);
+              yield Err((None, e));
               return;
             }
           }
 
-          let mut join_set: JoinSet<Result<(E, BackupState), crate::Error>> = JoinSet::new();
+          // A policy whose filter is `false` can never back anything up, so we avoid spending any of
+          // the rate limit on loading its entities.
+          let never_matches = policy.filter.constant() == Some(false);
+          if never_matches {
+            info!("Skipping {policy}, since its filter will never match anything");
+          }
+
+          let limit = match self.limit {
+            Some(limit) => Some(limit),
+            None => match policy.property::<usize>("limit") {
+              Ok(limit) => limit,
+              Err(e) => {
+                yield Err((None, e));
+                return;
+              }
+            }
+          };
+
+          // Empty repositories have no commits to clone, so they are skipped unless the policy opts in.
+          let skip_empty = match policy.property::<bool>("backup_empty") {
+            Ok(backup_empty) => !backup_empty.unwrap_or_default(),
+            Err(e) => {
+              yield Err((None, e));
+              return;
+            }
+          };
 
-          for await entity in self.source.load(policy, cancel).trace(tracing::info_span!("backup.source.load")) {
-              while join_set.len() >= self.concurrency_limit {
-                debug!("Reached concurrency limit of {}, waiting for a task to complete", self.concurrency_limit);
-                yield join_set.join_next().await.unwrap().unwrap();
+          // Entities which haven't changed since their last successful backup are only skipped when
+          // the policy opts in, since their fingerprints don't reflect changes to the policy itself.
+          let fingerprints = match policy.property::<bool>("skip_unchanged") {
+            Ok(Some(true)) if !self.dry_run => match Fingerprints::load(policy) {
+              Ok(fingerprints) => Some(fingerprints),
+              Err(e) => {
+                yield Err((None, e));
+                return;
               }
+            },
+            Ok(_) => None,
+            Err(e) => {
+              yield Err((None, e));
+              return;
+            }
+          };
+
+          // Transient filesystem errors (which networked filesystems occasionally return) are retried
+          // a few times before the backup which encountered them is failed.
+          let store: Arc<dyn BackupStore> = match policy.property::<usize>("fs_retries") {
+            Ok(Some(0)) => self.store.clone(),
+            Ok(retries) => Arc::new(RetryingStore::new(self.store.clone(), retries.unwrap_or(DEFAULT_FS_RETRIES))),
+            Err(e) => {
+              yield Err((None, e));
+              return;
+            }
+          };
 
+          // Checking the size of an existing backup means walking the whole of its directory, so
+          // we only do so when the policy's filter actually refers to the backup.* properties.
+          let inspect_backups = policy
+            .filter
+            .properties()
+            .iter()
+            .any(|p| p.to_ascii_lowercase().starts_with("backup."));
+
+          // Once the source has listed every entity, the state of those which it no longer lists can be
+          // pruned, so we keep track of the entities which were listed during this run.
+          let mut seen = BTreeSet::new();
+          let mut exhausted = false;
+
+          let mut join_set: JoinSet<Outcome<E>> = JoinSet::new();
+          let mut pending: VecDeque<E> = VecDeque::new();
+          let mut loaded = 0;
+          let mut matched = 0;
+
+          let source = self.source.load(policy, cancel).trace(tracing::info_span!("backup.source.load"));
+          tokio::pin!(source);
+          // A limit of zero means that nothing should be backed up, so we don't load anything.
+          let mut source_done = limit == Some(0) || never_matches;
+          let mut draining = false;
+          let mut io_concurrency_limit = self.io_concurrency_limit;
+
+          loop {
               if cancel.load(std::sync::atomic::Ordering::Relaxed) {
                   break;
               }
 
-              let entity = entity?;
-              if self.dry_run {
-                  info!("Would backup {entity} to {}", &policy.to.display());
-                  yield Ok((entity, BackupState::Skipped));
-                  continue;
+              if !draining && self.drain.is_some_and(|d| d.load(std::sync::atomic::Ordering::Relaxed)) {
+                  debug!("Not starting any more backups for {policy}, since the run is being stopped");
+                  draining = true;
+                  source_done = true;
+                  for entity in pending.drain(..) {
+                    yield Ok((entity, BackupState::Skipped(SkipReason::Cancelled)));
+                  }
               }
 
-              match policy.filter.matches(&entity) {
-                Ok(true) => {},
-                Ok(false) => {
-                  yield Ok((entity, BackupState::Skipped));
-                  continue;
-                },
-                Err(e) => {
-                  yield Err(e);
-                  continue;
+              let max_concurrency = match &self.auto_concurrency {
+                Some(auto_concurrency) => auto_concurrency.limit(),
+                None => self.io_concurrency_limit,
+              };
+
+              if max_concurrency != io_concurrency_limit && self.adaptive_concurrency.is_none() {
+                debug!("Adjusting the IO concurrency limit from {io_concurrency_limit} to {max_concurrency} based on recent backup performance");
+                io_concurrency_limit = max_concurrency;
+              }
+
+              if let Some(rate_limit) = &self.adaptive_concurrency {
+                let limit = rate_limit.concurrency(max_concurrency);
+                if limit != io_concurrency_limit {
+                  debug!("Adjusting the IO concurrency limit from {io_concurrency_limit} to {limit} based on the GitHub API rate limit");
+                  io_concurrency_limit = limit;
                 }
               }
 
-              {
-                let span = tracing_batteries::prelude::info_span!(parent: &span, "backup.step", item=%entity);
+              while join_set.len() < io_concurrency_limit {
+                let Some(entity) = pending.pop_front() else {
+                  break;
+                };
+
+                let span = tracing_batteries::prelude::info_span!(
+                  parent: &span,
+                  "backup.step",
+                  item=%entity,
+                  entity.name=entity.name(),
+                  backup.state=tracing::field::Empty,
+                );
+                let step = span.clone();
                 let target = self.target.clone();
+                let store = store.clone();
                 let to = policy.to.clone();
+                let auto_concurrency = self.auto_concurrency.clone();
+                let path_locks = self.path_locks.clone();
                 join_set.spawn(async move {
+                    // Another policy may be backing up the same item into the same directory, so we wait
+                    // for it to finish rather than racing on its files. The lock is released when this
+                    // task completes, however it completes.
+                    let _lock = path_locks.lock(&target.backup_path(&entity, &to)).await;
+
                     debug!("Starting backup of {entity}");
-                    target.backup(&entity, to.as_path(), cancel).await.map(|state| (entity, state))
+                    let started_at = std::time::Instant::now();
+                    let result = target.backup(&entity, store.as_ref(), to.as_path(), cancel).await;
+                    if let Some(auto_concurrency) = auto_concurrency {
+                      auto_concurrency.record(started_at.elapsed(), result.is_ok());
+                    }
+                    record_outcome(&step, &entity, &result);
+                    match result {
+                      Ok(state) => Ok((entity, state)),
+                      Err(e) => Err((Some(entity.name().to_string()), e)),
+                    }
                 }.instrument(span));
               }
+
+              if source_done && pending.is_empty() {
+                  break;
+              }
+
+              if pending.len() >= self.api_concurrency_limit {
+                debug!("Reached API concurrency limit of {}, waiting for a task to complete", self.api_concurrency_limit);
+              }
+
+              // The source is allowed to run ahead of the backup tasks (which are bounded by the IO
+              // concurrency limit) until it has buffered up to the API concurrency limit.
+              let next = tokio::select! {
+                entity = source.next(), if !source_done && pending.len() < self.api_concurrency_limit => Next::Loaded(entity),
+                Some(result) = join_set.join_next(), if !join_set.is_empty() => Next::Completed(result.unwrap()),
+                else => break,
+              };
+
+              let entity = match next {
+                Next::Completed(result) => {
+                  // Skipped (e.g. cancelled) backups keep their previous fingerprint so that they are retried.
+                  if let (Some(fingerprints), Ok((entity, BackupState::New(..) | BackupState::Updated(..) | BackupState::Unchanged(..)))) = (&fingerprints, &result) {
+                    fingerprints.record(entity);
+                  }
+
+                  let failed = result.is_err();
+                  yield result;
+                  if failed && self.fail_fast {
+                    self.abort(cancel);
+                    break;
+                  }
+
+                  continue;
+                },
+                Next::Loaded(None) => {
+                  exhausted = true;
+                  source_done = true;
+                  continue;
+                },
+                Next::Loaded(Some(Ok(mut entity))) => {
+                  if inspect_backups {
+                    let size = store.size(&self.target.backup_path(&entity, &policy.to)).await;
+                    entity.metadata_mut().insert("backup.exists", size.is_some());
+                    entity.metadata_mut().insert("backup.size", size.unwrap_or_default());
+                  }
+
+                  entity
+                },
+                Next::Loaded(Some(Err(e))) => {
+                  yield Err((None, e));
+                  if self.fail_fast {
+                    self.abort(cancel);
+                    break;
+                  }
+
+                  // The source can't continue after an error, but the entities it has already loaded
+                  // are still backed up (and the target is finished) before the policy completes.
+                  source_done = true;
+                  continue;
+                },
+              };
+
+              loaded += 1;
+              seen.insert(entity.name().to_string());
+
+              let matches = match policy.filter.constant() {
+                Some(constant) => Ok(constant),
+                None if self.strict_filters => policy.filter.matches_strict(&entity),
+                None => policy.filter.matches(&entity),
+              };
+
+              // The limit is applied to the entities which match the filter, so that it always
+              // results in the requested number of entities being backed up.
+              if matches!(matches, Ok(true)) {
+                matched += 1;
+                if limit.is_some_and(|limit| matched >= limit) {
+                  debug!("Reached the limit of {} entities for {policy}, no more entities will be loaded", matched);
+                  source_done = true;
+                }
+              }
+
+              match matches {
+                Ok(true) if skip_empty && entity.get("repo.empty").is_truthy() => {
+                  debug!("Skipping the backup of {entity}, since it is an empty repository");
+                  yield Ok((entity, BackupState::Skipped(SkipReason::Empty)));
+                },
+                Ok(true) if self.dry_run => {
+                  debug!("Would backup {entity} to {}", &policy.to.display());
+                  yield Ok((entity, BackupState::Skipped(SkipReason::DryRun)));
+                },
+                Ok(true) => {
+                  let unchanged = match fingerprints.as_ref().and_then(|f| f.unchanged(&entity)) {
+                    Some(fingerprint) if store.exists(&self.target.backup_path(&entity, &policy.to)).await => Some(fingerprint.to_string()),
+                    _ => None,
+                  };
+
+                  match unchanged {
+                    Some(fingerprint) => {
+                      debug!("Skipping the backup of {entity}, since it hasn't changed since it was last backed up");
+                      yield Ok((entity, BackupState::Unchanged(BackupDetails::new(format!("at fingerprint {fingerprint}")))));
+                    },
+                    None => pending.push_back(entity),
+                  }
+                },
+                Ok(false) => {
+                  yield Ok((entity, BackupState::Skipped(SkipReason::Filtered)));
+                },
+                Err(e) => {
+                  yield Err((Some(entity.name().to_string()), e));
+                  if self.fail_fast {
+                    self.abort(cancel);
+                    break;
+                  }
+                }
+              }
+          }
+
+          if let Some(handler) = handler {
+            handler.on_loaded(loaded);
           }
 
+          // Any backups which are still running are allowed to finish (or observe the cancellation
+          // flag) so that they don't leave partially written backups behind.
           while let Some(fut) = join_set.join_next().await {
-            yield fut.unwrap();
+            let result = fut.unwrap();
+            if let (Some(fingerprints), Ok((entity, BackupState::New(..) | BackupState::Updated(..) | BackupState::Unchanged(..)))) = (&fingerprints, &result) {
+              fingerprints.record(entity);
+            }
+
+            yield result;
+          }
+
+          if let Some(fingerprints) = &fingerprints {
+            if let Err(e) = fingerprints.save(policy).await {
+              yield Err((None, e));
+            }
           }
+
+          if exhausted && !self.dry_run && !cancel.load(std::sync::atomic::Ordering::Relaxed) && self.source.is_exhaustive(policy) {
+            match State::compact(policy, &seen, PRUNE_AFTER_MISSED_RUNS).await {
+              Ok(0) => {},
+              Ok(pruned) => info!("Pruned the state of {pruned} entities which no longer exist from {policy}"),
+              Err(e) => yield Err((None, e)),
+            }
+          }
+
+          if let Err(e) = self.target.finish(store.as_ref(), &policy.to).await {
+            yield Err((None, e));
+          }
+        }
+        .trace(policy_span)
+    }
+
+    fn abort(&self, cancel: &AtomicBool) {
+        warn!(
+            "Stopping the backup run because an error was encountered and fail-fast mode is enabled."
+        );
+        cancel.store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+/// Runs `backup` for each of the given policies, allowing up to `concurrency` of them to run at
+/// once, and yields the policies (along with the result of their backups) as they complete.
+///
+/// Each policy's backup is spawned as its own task, so that policies which are running at the same
+/// time can make progress in parallel. Policies are started in the order in which they were provided.
+pub fn run_policies<P, F, Fut>(
+    policies: impl IntoIterator<Item = P>,
+    concurrency: usize,
+    mut backup: F,
+) -> impl Stream<Item = (P, Fut::Output)>
+where
+    P: Send + 'static,
+    F: FnMut(&P) -> Fut,
+    Fut: std::future::Future + Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    let permits = Arc::new(tokio::sync::Semaphore::new(concurrency.max(1)));
+    let mut runs = JoinSet::new();
+    for policy in policies {
+        let run = backup(&policy);
+        let permits = permits.clone();
+        runs.spawn(async move {
+            // Permits are handed out in the order they were requested, which is the order in
+            // which the policies were provided.
+            let _permit = permits
+                .acquire_owned()
+                .await
+                .expect("the semaphore should never be closed");
+            (policy, run.await)
+        });
+    }
+
+    async_stream::stream! {
+        while let Some(run) = runs.join_next().await {
+            yield run.unwrap();
+        }
+    }
+}
+
+/// Records the outcome of an entity's backup on its `backup.step` span, and emits it as an event
+/// so that each entity's backup can be inspected on its own in trace exporters.
+fn record_outcome<E: BackupEntity>(
+    span: &tracing::Span,
+    entity: &E,
+    result: &Result<BackupState, crate::Error>,
+) {
+    match result {
+        Ok(state) => {
+            span.record("backup.state", state.as_str());
+            info!(
+                entity.name = entity.name(),
+                backup.state = state.as_str(),
+                backup.detail = state.detail(),
+                backup.git_head = state.details().and_then(|d| d.git_head.as_deref()),
+                backup.bytes = state.details().and_then(|d| d.bytes),
+                "Finished backup of {entity} ({state})"
+            );
+        }
+        Err(error) => {
+            span.record("backup.state", "error");
+            info!(
+                entity.name = entity.name(),
+                backup.state = "error",
+                backup.detail = %error,
+                "Failed to back up {entity}"
+            );
         }
     }
 }
 
-pub trait PairingHandler<E: BackupEntity> {
+/// The outcome of an entity's backup, where errors carry the name of the entity they were
+/// encountered for (if they were encountered for a specific entity).
+type Outcome<E> = Result<(E, BackupState), (Option<String>, crate::Error)>;
+
+enum Next<E, R> {
+    Loaded(Option<Result<E, crate::Error>>),
+    Completed(R),
+}
+
+pub trait PairingHandler<E: BackupEntity>: Sync {
     fn on_complete(&self, entity: E, state: BackupState);
-    fn on_error(&self, error: crate::Error);
+    /// Called with each error encountered while running the policy, along with the name of the
+    /// entity it was encountered for (if it relates to a specific entity).
+    fn on_error(&self, entity: Option<&str>, error: crate::Error);
+
+    /// Called once the source has finished loading entities, with the total number
+    /// of entities which were loaded (and will eventually be passed to `on_complete`).
+    fn on_loaded(&self, _total: usize) {}
 }
 
 #[cfg(test)]
 mod tests {
     use std::path::Path;
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
     use rstest::rstest;
+    use tokio::sync::Semaphore;
 
     use crate::entities::GitRepo;
 
@@ -193,10 +716,39 @@ mod tests {
         async fn backup<P: AsRef<Path> + Send>(
             &self,
             entity: &GitRepo,
+            _store: &dyn BackupStore,
+            _target: P,
+            _cancel: &AtomicBool,
+        ) -> Result<BackupState, crate::Error> {
+            Ok(BackupState::New(BackupDetails::new(entity.name.clone())))
+        }
+    }
+
+    /// Backs up every entity, counting the number of times it has been finished.
+    #[derive(Clone)]
+    struct FinishingEngine {
+        finished: Arc<AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl BackupEngine<GitRepo> for FinishingEngine {
+        async fn backup<P: AsRef<Path> + Send>(
+            &self,
+            entity: &GitRepo,
+            _store: &dyn BackupStore,
             _target: P,
             _cancel: &AtomicBool,
         ) -> Result<BackupState, crate::Error> {
-            Ok(BackupState::New(Some(entity.name.clone())))
+            Ok(BackupState::New(BackupDetails::new(entity.name.clone())))
+        }
+
+        async fn finish(
+            &self,
+            _store: &dyn BackupStore,
+            _target: &Path,
+        ) -> Result<(), crate::Error> {
+            self.finished.fetch_add(1, Ordering::SeqCst);
+            Ok(())
         }
     }
 
@@ -239,7 +791,7 @@ mod tests {
         let source = MockRepoSource;
         let engine = MockEngine;
         let pairing = Pairing::new(source, engine)
-            .with_concurrency_limit(5)
+            .with_io_concurrency_limit(5)
             .with_dry_run(false);
 
         let stream = pairing.run_all_backups(&policy, &CANCEL);
@@ -250,14 +802,14 @@ mod tests {
         while let Some(result) = stream.next().await {
             let (entity, state) = result.unwrap();
             match state {
-                BackupState::New(name) if name == Some(entity.name.clone()) => {
+                BackupState::New(details) if details.description == Some(entity.name.clone()) => {
                     count += 1;
                     continue;
                 }
-                BackupState::New(name) => {
+                BackupState::New(details) => {
                     panic!(
-                        "Expected BackupState::New(Some({:?})) but got BackupState::New({:?})",
-                        entity.name, name
+                        "Expected a new backup described as {:?} but got {:?}",
+                        entity.name, details.description
                     );
                 }
                 _ => {}
@@ -269,4 +821,1382 @@ mod tests {
             MatchType::GreaterOrEqual => assert!(count >= matches),
         }
     }
+
+    #[tokio::test]
+    async fn records_backup_events() {
+        use std::collections::BTreeMap;
+        use std::sync::Mutex;
+        use tracing::{
+            field::{Field, Visit},
+            span, Event, Metadata, Subscriber,
+        };
+
+        #[derive(Default)]
+        struct Fields(BTreeMap<String, String>);
+
+        impl Visit for Fields {
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                self.0
+                    .insert(field.name().to_string(), format!("{value:?}"));
+            }
+
+            fn record_str(&mut self, field: &Field, value: &str) {
+                self.0.insert(field.name().to_string(), value.to_string());
+            }
+        }
+
+        /// Captures the fields of every span and event, so that we can check what would be
+        /// exported to OpenTelemetry.
+        #[derive(Clone, Default)]
+        struct CapturingSubscriber {
+            spans: Arc<Mutex<Vec<(&'static str, Fields)>>>,
+            events: Arc<Mutex<Vec<Fields>>>,
+        }
+
+        impl Subscriber for CapturingSubscriber {
+            fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+                // Exporters are usually configured to collect info-level events and above.
+                *metadata.level() <= tracing::Level::INFO
+            }
+
+            fn new_span(&self, attrs: &span::Attributes<'_>) -> span::Id {
+                let mut fields = Fields::default();
+                attrs.record(&mut fields);
+                let mut spans = self.spans.lock().unwrap();
+                spans.push((attrs.metadata().name(), fields));
+                span::Id::from_u64(spans.len() as u64)
+            }
+
+            fn record(&self, span: &span::Id, values: &span::Record<'_>) {
+                let mut spans = self.spans.lock().unwrap();
+                values.record(&mut spans[span.into_u64() as usize - 1].1);
+            }
+
+            fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+            fn event(&self, event: &Event<'_>) {
+                let mut fields = Fields::default();
+                event.record(&mut fields);
+                self.events.lock().unwrap().push(fields);
+            }
+
+            fn enter(&self, _span: &span::Id) {}
+
+            fn exit(&self, _span: &span::Id) {}
+        }
+
+        let policy: BackupPolicy = serde_yaml::from_str(
+            r#"
+            kind: mock
+            from: mock
+            to: /tmp
+            filter: '!repo.fork'
+            "#,
+        )
+        .unwrap();
+
+        let subscriber = CapturingSubscriber::default();
+        let _guard = tracing::subscriber::set_default(subscriber.clone());
+
+        let pairing = Pairing::new(MockRepoSource, MockEngine);
+        let names = pairing
+            .run_all_backups(&policy, &CANCEL)
+            .filter_map(|result| match result.unwrap() {
+                (entity, BackupState::New(_)) => Some(entity.name),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .await;
+        assert!(!names.is_empty());
+
+        let events = subscriber.events.lock().unwrap();
+        for name in names.iter() {
+            let event = events
+                .iter()
+                .find(|e| e.0.get("entity.name") == Some(name))
+                .unwrap_or_else(|| panic!("an event to be emitted for {name}"));
+            assert_eq!(event.0["backup.state"], "new");
+            assert_eq!(&event.0["backup.detail"], name);
+        }
+
+        let spans = subscriber.spans.lock().unwrap();
+        let steps = spans
+            .iter()
+            .filter(|(name, _)| *name == "backup.step")
+            .map(|(_, fields)| fields)
+            .collect::<Vec<_>>();
+        assert_eq!(steps.len(), names.len());
+        for step in steps {
+            assert!(names.contains(&step.0["entity.name"]));
+            assert_eq!(step.0["backup.state"], "new");
+        }
+    }
+
+    #[tokio::test]
+    async fn notifies_loaded_total() {
+        use std::sync::Mutex;
+
+        #[derive(Default)]
+        struct RecordingHandler {
+            completed: Mutex<usize>,
+            loaded: Mutex<Option<usize>>,
+        }
+
+        impl PairingHandler<GitRepo> for RecordingHandler {
+            fn on_complete(&self, _entity: GitRepo, _state: BackupState) {
+                *self.completed.lock().unwrap() += 1;
+            }
+
+            fn on_error(&self, _entity: Option<&str>, error: crate::Error) {
+                panic!("Unexpected error: {error}");
+            }
+
+            fn on_loaded(&self, total: usize) {
+                *self.loaded.lock().unwrap() = Some(total);
+            }
+        }
+
+        let policy: BackupPolicy = serde_yaml::from_str(
+            r#"
+            kind: mock
+            from: mock
+            to: /tmp
+            filter: '!repo.fork'
+            "#,
+        )
+        .unwrap();
+
+        let handler = RecordingHandler::default();
+        Pairing::new(MockRepoSource, MockEngine)
+            .run(&policy, &handler, &CANCEL)
+            .await;
+
+        let completed = *handler.completed.lock().unwrap();
+        assert!(completed > 0);
+        assert_eq!(
+            *handler.loaded.lock().unwrap(),
+            Some(completed),
+            "skipped entities should be included in the loaded total"
+        );
+    }
+
+    #[tokio::test]
+    async fn errors_name_their_entity() {
+        use std::sync::Mutex;
+
+        #[derive(Clone)]
+        struct FailingEngine;
+
+        #[async_trait::async_trait]
+        impl BackupEngine<GitRepo> for FailingEngine {
+            async fn backup<P: AsRef<Path> + Send>(
+                &self,
+                _entity: &GitRepo,
+                _store: &dyn BackupStore,
+                _target: P,
+                _cancel: &AtomicBool,
+            ) -> Result<BackupState, crate::Error> {
+                Err(errors::system("The backup failed.", "This is a test."))
+            }
+        }
+
+        #[derive(Default)]
+        struct RecordingHandler {
+            errors: Mutex<Vec<Option<String>>>,
+        }
+
+        impl PairingHandler<GitRepo> for RecordingHandler {
+            fn on_complete(&self, _entity: GitRepo, _state: BackupState) {}
+
+            fn on_error(&self, entity: Option<&str>, _error: crate::Error) {
+                self.errors
+                    .lock()
+                    .unwrap()
+                    .push(entity.map(|e| e.to_string()));
+            }
+        }
+
+        let policy: BackupPolicy = serde_yaml::from_str(
+            r#"
+            kind: mock
+            from: mock
+            to: /tmp
+            filter: repo.name == "aoc2020"
+            "#,
+        )
+        .unwrap();
+
+        let handler = RecordingHandler::default();
+        Pairing::new(MockRepoSource, FailingEngine)
+            .run(&policy, &handler, &CANCEL)
+            .await;
+
+        assert_eq!(
+            *handler.errors.lock().unwrap(),
+            vec![Some("octocat/aoc2020".to_string())],
+            "the error should name the entity whose backup failed"
+        );
+    }
+
+    /// Yields 20 repositories, counting how many have been loaded so far.
+    struct CountingSource {
+        loaded: Arc<AtomicUsize>,
+    }
+
+    impl BackupSource<GitRepo> for CountingSource {
+        fn kind(&self) -> &str {
+            "mock"
+        }
+
+        fn validate(&self, _policy: &BackupPolicy) -> Result<(), crate::Error> {
+            Ok(())
+        }
+
+        fn load<'a>(
+            &'a self,
+            _policy: &'a BackupPolicy,
+            _cancel: &'a AtomicBool,
+        ) -> impl Stream<Item = Result<GitRepo, crate::Error>> + 'a {
+            async_stream::stream! {
+              for i in 0..20 {
+                self.loaded.fetch_add(1, Ordering::SeqCst);
+                yield Ok(GitRepo::new(format!("mock/repo-{i}"), "https://example.com/repo.git", None));
+              }
+            }
+        }
+    }
+
+    #[rstest]
+    #[case("false", 0, 0)]
+    #[case("true", 20, 20)]
+    #[tokio::test]
+    async fn constant_filters(
+        #[case] filter: &str,
+        #[case] expected_loads: usize,
+        #[case] expected_backups: usize,
+    ) {
+        let policy: BackupPolicy = serde_yaml::from_str(&format!(
+            r#"
+            kind: mock
+            from: mock
+            to: /tmp
+            filter: '{filter}'
+            "#
+        ))
+        .unwrap();
+
+        let loaded = Arc::new(AtomicUsize::new(0));
+        let finished = Arc::new(AtomicUsize::new(0));
+        let pairing = Pairing::new(
+            CountingSource {
+                loaded: loaded.clone(),
+            },
+            FinishingEngine {
+                finished: finished.clone(),
+            },
+        );
+
+        let results = pairing
+            .run_all_backups(&policy, &CANCEL)
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(loaded.load(Ordering::SeqCst), expected_loads);
+        assert_eq!(
+            finished.load(Ordering::SeqCst),
+            1,
+            "the target should always be finished"
+        );
+        assert_eq!(
+            results
+                .iter()
+                .filter(|r| matches!(r, Ok((_, BackupState::New(..)))))
+                .count(),
+            expected_backups
+        );
+    }
+
+    /// Blocks every backup until a permit is added to its gate, tracking how many are running.
+    #[derive(Clone)]
+    struct BlockingEngine {
+        gate: Arc<Semaphore>,
+        running: Arc<AtomicUsize>,
+        max_running: Arc<AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl BackupEngine<GitRepo> for BlockingEngine {
+        async fn backup<P: AsRef<Path> + Send>(
+            &self,
+            entity: &GitRepo,
+            _store: &dyn BackupStore,
+            _target: P,
+            _cancel: &AtomicBool,
+        ) -> Result<BackupState, crate::Error> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+            self.gate.acquire().await.unwrap().forget();
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            Ok(BackupState::New(BackupDetails::new(entity.name.clone())))
+        }
+    }
+
+    #[tokio::test]
+    async fn respects_concurrency_limits() {
+        use std::time::Duration;
+
+        let policy: BackupPolicy = serde_yaml::from_str(
+            r#"
+            kind: mock
+            from: mock
+            to: /tmp
+            "#,
+        )
+        .unwrap();
+
+        let loaded = Arc::new(AtomicUsize::new(0));
+        let engine = BlockingEngine {
+            gate: Arc::new(Semaphore::new(0)),
+            running: Arc::new(AtomicUsize::new(0)),
+            max_running: Arc::new(AtomicUsize::new(0)),
+        };
+
+        let pairing = Pairing::new(
+            CountingSource {
+                loaded: loaded.clone(),
+            },
+            engine.clone(),
+        )
+        .with_api_concurrency_limit(3)
+        .with_io_concurrency_limit(2);
+
+        let stream = pairing.run_all_backups(&policy, &CANCEL);
+        tokio::pin!(stream);
+
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), stream.next())
+                .await
+                .is_err(),
+            "no backups should complete while the engine is blocked"
+        );
+        assert_eq!(
+            engine.running.load(Ordering::SeqCst),
+            2,
+            "the number of running backups should be bounded by the IO limit"
+        );
+        assert_eq!(
+            loaded.load(Ordering::SeqCst),
+            5,
+            "the source should run ahead of the running backups by the API limit"
+        );
+
+        engine.gate.add_permits(20);
+
+        let mut completed = 0;
+        while let Some(result) = stream.next().await {
+            result.expect("backup to succeed");
+            completed += 1;
+        }
+
+        assert_eq!(completed, 20);
+        assert_eq!(engine.max_running.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn unbounded_concurrency() {
+        use std::time::Duration;
+
+        let policy: BackupPolicy = serde_yaml::from_str(
+            r#"
+            kind: mock
+            from: mock
+            to: /tmp
+            "#,
+        )
+        .unwrap();
+
+        let engine = BlockingEngine {
+            gate: Arc::new(Semaphore::new(0)),
+            running: Arc::new(AtomicUsize::new(0)),
+            max_running: Arc::new(AtomicUsize::new(0)),
+        };
+
+        let pairing = Pairing::new(
+            CountingSource {
+                loaded: Arc::new(AtomicUsize::new(0)),
+            },
+            engine.clone(),
+        )
+        .with_io_concurrency_limit(0);
+        assert_eq!(pairing.io_concurrency_limit, usize::MAX);
+
+        let stream = pairing.run_all_backups(&policy, &CANCEL);
+        tokio::pin!(stream);
+
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), stream.next())
+                .await
+                .is_err(),
+            "no backups should complete while the engine is blocked"
+        );
+        assert_eq!(
+            engine.running.load(Ordering::SeqCst),
+            20,
+            "every backup should be started at once when the IO limit is unbounded"
+        );
+
+        engine.gate.add_permits(20);
+
+        let mut completed = 0;
+        while let Some(result) = stream.next().await {
+            result.expect("backup to succeed");
+            completed += 1;
+        }
+
+        assert_eq!(completed, 20);
+    }
+
+    #[rstest]
+    #[case(false, "!repo.fork", SkipReason::Filtered)]
+    #[case(true, "!repo.fork", SkipReason::Filtered)]
+    #[case(true, "true", SkipReason::DryRun)]
+    #[case(false, "true", SkipReason::Empty)]
+    #[case(true, "true", SkipReason::Empty)]
+    #[tokio::test]
+    async fn skip_reasons(#[case] dry_run: bool, #[case] filter: &str, #[case] reason: SkipReason) {
+        let policy: BackupPolicy = serde_yaml::from_str(&format!(
+            r#"
+            kind: mock
+            from: mock
+            to: /tmp
+            filter: '{filter}'
+            "#
+        ))
+        .unwrap();
+
+        let results: Vec<_> = Pairing::new(MockRepoSource, MockEngine)
+            .with_dry_run(dry_run)
+            .run_all_backups(&policy, &CANCEL)
+            .collect()
+            .await;
+
+        assert!(
+            results
+                .iter()
+                .any(|r| matches!(r, Ok((_, BackupState::Skipped(r))) if *r == reason)),
+            "at least one entity should be skipped because of {reason}"
+        );
+    }
+
+    #[rstest]
+    #[case("!backup.exists", 29)]
+    #[case("backup.exists", 2)]
+    #[case("backup.size > 0", 1)]
+    #[case("backup.size == 0", 30)]
+    #[tokio::test]
+    async fn existing_backups(#[case] filter: &str, #[case] expected: usize) {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let repos: Vec<crate::helpers::github::GitHubRepo> =
+            load_test_file("github.repos.0.json").unwrap();
+
+        // The first repository has a (non-empty) backup, while the second has an empty one
+        let first = temp_dir.path().join(&repos[0].full_name).join(".git");
+        std::fs::create_dir_all(&first).unwrap();
+        std::fs::write(first.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        std::fs::create_dir_all(temp_dir.path().join(&repos[1].full_name)).unwrap();
+
+        let policy: BackupPolicy = serde_yaml::from_str(&format!(
+            r#"
+            kind: mock
+            from: mock
+            to: {}
+            filter: '{filter}'
+            properties:
+              backup_empty: true
+            "#,
+            temp_dir.path().display()
+        ))
+        .unwrap();
+
+        let results: Vec<_> = Pairing::new(MockRepoSource, MockEngine)
+            .run_all_backups(&policy, &CANCEL)
+            .collect::<Result<Vec<_>, _>>()
+            .await
+            .expect("the backups to succeed");
+
+        assert_eq!(
+            results
+                .iter()
+                .filter(|(_, state)| matches!(state, BackupState::New(..)))
+                .count(),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case(1, 1)]
+    #[case(2, 2)]
+    #[case(5, 3)]
+    #[tokio::test]
+    async fn policy_concurrency(#[case] concurrency: usize, #[case] expected_overlap: usize) {
+        /// Records the largest number of backups which were running at the same time.
+        #[derive(Clone, Default)]
+        struct SlowEngine {
+            running: Arc<AtomicUsize>,
+            overlap: Arc<AtomicUsize>,
+        }
+
+        #[async_trait::async_trait]
+        impl BackupEngine<GitRepo> for SlowEngine {
+            async fn backup<P: AsRef<Path> + Send>(
+                &self,
+                _entity: &GitRepo,
+                _store: &dyn BackupStore,
+                _target: P,
+                _cancel: &AtomicBool,
+            ) -> Result<BackupState, crate::Error> {
+                let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+                self.overlap.fetch_max(running, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                self.running.fetch_sub(1, Ordering::SeqCst);
+                Ok(BackupState::New(BackupDetails::default()))
+            }
+        }
+
+        let policies: Vec<BackupPolicy> = serde_yaml::from_str(
+            r#"
+            - kind: mock
+              from: first
+              to: /tmp/first
+            - kind: mock
+              from: second
+              to: /tmp/second
+            - kind: mock
+              from: third
+              to: /tmp/third
+            "#,
+        )
+        .unwrap();
+
+        let engine = SlowEngine::default();
+        let pairing = Arc::new(Pairing::new(MockRepoSource, engine.clone()).with_limit(Some(1)));
+        let mut completed: Vec<_> =
+            run_policies(policies.into_iter().map(Arc::new), concurrency, |policy| {
+                let pairing = pairing.clone();
+                let policy = policy.clone();
+                async move {
+                    pairing
+                        .run_all_backups(&policy, &CANCEL)
+                        .collect::<Vec<_>>()
+                        .await
+                }
+            })
+            .map(|(policy, results)| (policy.from.clone(), results.len()))
+            .collect()
+            .await;
+        completed.sort();
+
+        assert_eq!(
+            completed,
+            vec![
+                ("first".to_string(), 1),
+                ("second".to_string(), 1),
+                ("third".to_string(), 1)
+            ]
+        );
+        assert_eq!(
+            engine.overlap.load(Ordering::SeqCst),
+            expected_overlap,
+            "only the permitted number of policies should run at the same time"
+        );
+    }
+
+    #[tokio::test]
+    async fn prunes_state_of_removed_entities() {
+        let repos: Vec<crate::helpers::github::GitHubRepo> =
+            load_test_file("github.repos.0.json").unwrap();
+        let skipped = repos[0].full_name.clone();
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let policy: BackupPolicy = serde_yaml::from_str(&format!(
+            r#"
+            kind: mock
+            from: mock
+            to: {}
+            filter: 'repo.fullname != "{skipped}"'
+            "#,
+            temp_dir.path().display()
+        ))
+        .unwrap();
+
+        State::update(&policy, |s| {
+            for name in ["removed/repo", skipped.as_str()] {
+                s.fingerprints.insert(name.to_string(), "v1".to_string());
+            }
+        })
+        .await
+        .expect("state to be updated");
+
+        let fingerprints = || {
+            State::load(&State::path_for(&policy))
+                .expect("state to load")
+                .policy(&policy)
+                .fingerprints
+                .into_keys()
+                .collect::<Vec<_>>()
+        };
+
+        // Runs which don't list every entity can't tell whether an entity has been removed.
+        let limited = Pairing::new(MockRepoSource, MockEngine).with_limit(Some(1));
+        for _ in 0..PRUNE_AFTER_MISSED_RUNS {
+            let _: Vec<_> = limited.run_all_backups(&policy, &CANCEL).collect().await;
+        }
+        assert_eq!(fingerprints().len(), 2);
+
+        let pairing = Pairing::new(MockRepoSource, MockEngine);
+        for run in 1..=PRUNE_AFTER_MISSED_RUNS {
+            let _: Vec<_> = pairing.run_all_backups(&policy, &CANCEL).collect().await;
+            assert_eq!(
+                fingerprints().contains(&"removed/repo".to_string()),
+                run < PRUNE_AFTER_MISSED_RUNS,
+                "the removed entity should only be pruned once it has been missing for several runs"
+            );
+        }
+
+        assert_eq!(
+            fingerprints(),
+            vec![skipped],
+            "entities which were skipped by the filter should be retained"
+        );
+    }
+
+    #[tokio::test]
+    async fn prune_state() {
+        let repos: Vec<crate::helpers::github::GitHubRepo> =
+            load_test_file("github.repos.0.json").unwrap();
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let policy: BackupPolicy = serde_yaml::from_str(&format!(
+            "{{ kind: mock, from: mock, to: '{}' }}",
+            temp_dir.path().display()
+        ))
+        .unwrap();
+
+        State::update(&policy, |s| {
+            for name in ["removed/repo", repos[0].full_name.as_str()] {
+                s.fingerprints.insert(name.to_string(), "v1".to_string());
+            }
+        })
+        .await
+        .expect("state to be updated");
+
+        let pruned = Pairing::new(MockRepoSource, MockEngine)
+            .prune_state(&policy, &CANCEL)
+            .await
+            .expect("the state to be pruned");
+        assert_eq!(pruned, 1);
+
+        let state = State::load(&State::path_for(&policy)).expect("state to load");
+        assert_eq!(
+            state
+                .policy(&policy)
+                .fingerprints
+                .into_keys()
+                .collect::<Vec<_>>(),
+            vec![repos[0].full_name.clone()]
+        );
+    }
+
+    #[tokio::test]
+    async fn shared_target_paths() {
+        /// Writes its backups in several steps, so that two tasks writing the same path at once
+        /// would interleave their content.
+        #[derive(Clone)]
+        struct NonAtomicEngine;
+
+        #[async_trait::async_trait]
+        impl BackupEngine<GitRepo> for NonAtomicEngine {
+            async fn backup<P: AsRef<Path> + Send>(
+                &self,
+                entity: &GitRepo,
+                _store: &dyn BackupStore,
+                target: P,
+                _cancel: &AtomicBool,
+            ) -> Result<BackupState, crate::Error> {
+                use tokio::io::AsyncWriteExt;
+
+                let path = self.backup_path(entity, target.as_ref());
+                tokio::fs::create_dir_all(path.parent().unwrap())
+                    .await
+                    .unwrap();
+
+                let mut file = tokio::fs::File::create(&path).await.unwrap();
+                file.write_all(b"header\n").await.unwrap();
+                file.flush().await.unwrap();
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+                let mut file = tokio::fs::OpenOptions::new()
+                    .append(true)
+                    .open(&path)
+                    .await
+                    .unwrap();
+                file.write_all(b"body\n").await.unwrap();
+                file.flush().await.unwrap();
+
+                Ok(BackupState::New(BackupDetails::default()))
+            }
+        }
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let policies: Vec<BackupPolicy> = serde_yaml::from_str(&format!(
+            r#"
+            - kind: mock
+              from: first
+              to: {0}
+            - kind: mock
+              from: second
+              to: {0}
+            "#,
+            temp_dir.path().display()
+        ))
+        .unwrap();
+
+        let pairing = Arc::new(Pairing::new(MockRepoSource, NonAtomicEngine).with_limit(Some(1)));
+        let started_at = std::time::Instant::now();
+        let completed: Vec<_> = run_policies(policies.into_iter().map(Arc::new), 2, |policy| {
+            let pairing = pairing.clone();
+            let policy = policy.clone();
+            async move {
+                pairing
+                    .run_all_backups(&policy, &CANCEL)
+                    .collect::<Vec<_>>()
+                    .await
+            }
+        })
+        .map(|(_, results)| results.len())
+        .collect()
+        .await;
+
+        assert_eq!(completed, vec![1, 1]);
+        assert!(
+            started_at.elapsed() >= std::time::Duration::from_millis(200),
+            "backups of the same path should not run at the same time"
+        );
+
+        let repos: Vec<crate::helpers::github::GitHubRepo> =
+            load_test_file("github.repos.0.json").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(temp_dir.path().join(&repos[0].full_name)).unwrap(),
+            "header\nbody\n"
+        );
+    }
+
+    #[rstest]
+    #[case(None, true, 2)]
+    #[case(Some("0"), false, 1)]
+    #[tokio::test]
+    async fn retries_store_failures(
+        #[case] fs_retries: Option<&str>,
+        #[case] succeeds: bool,
+        #[case] expected_attempts: usize,
+    ) {
+        /// Writes each entity's backup to a temporary file, then renames it into place.
+        #[derive(Clone)]
+        struct RenamingEngine;
+
+        #[async_trait::async_trait]
+        impl BackupEngine<GitRepo> for RenamingEngine {
+            async fn backup<P: AsRef<Path> + Send>(
+                &self,
+                entity: &GitRepo,
+                store: &dyn BackupStore,
+                target: P,
+                _cancel: &AtomicBool,
+            ) -> Result<BackupState, crate::Error> {
+                let temp_path = target.as_ref().join("backup.tmp");
+                store.write(&temp_path, b"backup").await.unwrap();
+                store
+                    .rename(&temp_path, &target.as_ref().join("backup"))
+                    .await
+                    .map_err(|e| {
+                        errors::user_with_internal(
+                            "Unable to rename the backup.",
+                            "Make sure that you have permission to write to the backup directory.",
+                            e,
+                        )
+                    })?;
+                Ok(BackupState::New(BackupDetails::new(entity.name.clone())))
+            }
+        }
+
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let store = crate::engines::FaultyStore::new({
+            let attempts = attempts.clone();
+            move |from, to| match attempts.fetch_add(1, Ordering::SeqCst) {
+                0 => Err(std::io::ErrorKind::Interrupted.into()),
+                _ => std::fs::rename(from, to),
+            }
+        });
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let mut policy: BackupPolicy = serde_yaml::from_str(&format!(
+            r#"
+            kind: mock
+            from: mock
+            to: {}
+            properties:
+              limit: "1"
+            "#,
+            temp_dir.path().display()
+        ))
+        .unwrap();
+        if let Some(fs_retries) = fs_retries {
+            policy
+                .properties
+                .insert("fs_retries".to_string(), fs_retries.to_string());
+        }
+
+        let results: Vec<_> = Pairing::new(MockRepoSource, RenamingEngine)
+            .with_store(store)
+            .run_all_backups(&policy, &CANCEL)
+            .collect()
+            .await;
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(
+            results[0].is_ok(),
+            succeeds,
+            "transient store errors should only be retried when fs_retries allows it"
+        );
+        assert_eq!(attempts.load(Ordering::SeqCst), expected_attempts);
+    }
+
+    #[rstest]
+    #[case("limit", "3", true)]
+    #[case("limit", "three", false)]
+    #[case("fs_retries", "-1", false)]
+    #[case("backup_empty", "yes", false)]
+    #[case("skip_unchanged", "true", true)]
+    #[case("skip_unchanged", "sometimes", false)]
+    fn validate(#[case] property: &str, #[case] value: &str, #[case] valid: bool) {
+        let policy: BackupPolicy = serde_yaml::from_str(&format!(
+            r#"
+            kind: mock
+            from: mock
+            to: /tmp
+            properties:
+              {property}: "{value}"
+            "#
+        ))
+        .unwrap();
+
+        let result = Pairing::new(MockRepoSource, MockEngine).validate(&policy);
+        assert_eq!(
+            result.is_ok(),
+            valid,
+            "the '{property}' property should be validated alongside the source's properties"
+        );
+    }
+
+    #[rstest]
+    #[case("true", None, 3)]
+    #[case("!repo.fork", None, 3)]
+    #[case("true", Some(5), 5)]
+    #[case("true", Some(0), 0)]
+    #[tokio::test]
+    async fn limit(
+        #[case] filter: &str,
+        #[case] override_limit: Option<usize>,
+        #[case] expected: usize,
+    ) {
+        let policy: BackupPolicy = serde_yaml::from_str(&format!(
+            r#"
+            kind: mock
+            from: mock
+            to: /tmp
+            filter: '{filter}'
+            properties:
+              limit: "3"
+            "#
+        ))
+        .unwrap();
+
+        let results: Vec<_> = Pairing::new(MockRepoSource, MockEngine)
+            .with_io_concurrency_limit(2)
+            .with_limit(override_limit)
+            .run_all_backups(&policy, &CANCEL)
+            .collect::<Result<Vec<_>, _>>()
+            .await
+            .expect("the backups to succeed");
+
+        assert_eq!(
+            results
+                .iter()
+                .filter(|(_, state)| matches!(state, BackupState::New(..)))
+                .count(),
+            expected,
+            "exactly the limited number of entities should be backed up"
+        );
+    }
+
+    #[tokio::test]
+    async fn fail_fast() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static FAIL_FAST_CANCEL: AtomicBool = AtomicBool::new(false);
+
+        struct FailingSource {
+            loaded: Arc<AtomicUsize>,
+        }
+
+        impl BackupSource<GitRepo> for FailingSource {
+            fn kind(&self) -> &str {
+                "mock"
+            }
+
+            fn validate(&self, _policy: &BackupPolicy) -> Result<(), crate::Error> {
+                Ok(())
+            }
+
+            fn load<'a>(
+                &'a self,
+                _policy: &'a BackupPolicy,
+                _cancel: &'a AtomicBool,
+            ) -> impl Stream<Item = Result<GitRepo, crate::Error>> + 'a {
+                async_stream::stream! {
+                  for i in 0..10 {
+                    self.loaded.fetch_add(1, Ordering::SeqCst);
+                    if i == 1 {
+                      yield Err(crate::errors::system("The source failed.", "This is a test."));
+                    } else {
+                      yield Ok(GitRepo::new(format!("mock/repo-{i}"), "https://example.com/repo.git", None));
+                    }
+                  }
+                }
+            }
+        }
+
+        #[derive(Clone)]
+        struct CountingEngine {
+            processed: Arc<AtomicUsize>,
+        }
+
+        #[async_trait::async_trait]
+        impl BackupEngine<GitRepo> for CountingEngine {
+            async fn backup<P: AsRef<Path> + Send>(
+                &self,
+                entity: &GitRepo,
+                _store: &dyn BackupStore,
+                _target: P,
+                _cancel: &AtomicBool,
+            ) -> Result<BackupState, crate::Error> {
+                self.processed.fetch_add(1, Ordering::SeqCst);
+                Ok(BackupState::New(BackupDetails::new(entity.name.clone())))
+            }
+        }
+
+        let policy: BackupPolicy = serde_yaml::from_str(
+            r#"
+            kind: mock
+            from: mock
+            to: /tmp
+            "#,
+        )
+        .unwrap();
+
+        let loaded = Arc::new(AtomicUsize::new(0));
+        let processed = Arc::new(AtomicUsize::new(0));
+
+        let pairing = Pairing::new(
+            FailingSource {
+                loaded: loaded.clone(),
+            },
+            CountingEngine {
+                processed: processed.clone(),
+            },
+        )
+        .with_io_concurrency_limit(1)
+        .with_fail_fast(true);
+
+        let results: Vec<_> = pairing
+            .run_all_backups(&policy, &FAIL_FAST_CANCEL)
+            .collect()
+            .await;
+
+        assert_eq!(
+            results.len(),
+            2,
+            "the run should stop after the first error"
+        );
+        assert!(
+            results.iter().any(|r| r.is_err()),
+            "the error should be reported"
+        );
+        assert_eq!(
+            loaded.load(Ordering::SeqCst),
+            2,
+            "no further entities should be loaded"
+        );
+        assert_eq!(
+            processed.load(Ordering::SeqCst),
+            1,
+            "no further entities should be backed up"
+        );
+        assert!(
+            FAIL_FAST_CANCEL.load(Ordering::Relaxed),
+            "the cancellation flag should be set"
+        );
+    }
+
+    #[tokio::test]
+    async fn finishes_after_source_error() {
+        struct FailingSource;
+
+        impl BackupSource<GitRepo> for FailingSource {
+            fn kind(&self) -> &str {
+                "mock"
+            }
+
+            fn validate(&self, _policy: &BackupPolicy) -> Result<(), crate::Error> {
+                Ok(())
+            }
+
+            fn load<'a>(
+                &'a self,
+                _policy: &'a BackupPolicy,
+                _cancel: &'a AtomicBool,
+            ) -> impl Stream<Item = Result<GitRepo, crate::Error>> + 'a {
+                async_stream::stream! {
+                  yield Ok(GitRepo::new("mock/repo-0", "https://example.com/repo.git", None));
+                  yield Err(crate::errors::system("The source failed.", "This is a test."));
+                }
+            }
+        }
+
+        let policy: BackupPolicy = serde_yaml::from_str(
+            r#"
+            kind: mock
+            from: mock
+            to: /tmp
+            "#,
+        )
+        .unwrap();
+
+        let finished = Arc::new(AtomicUsize::new(0));
+        let results: Vec<_> = Pairing::new(
+            FailingSource,
+            FinishingEngine {
+                finished: finished.clone(),
+            },
+        )
+        .run_all_backups(&policy, &CANCEL)
+        .collect()
+        .await;
+
+        assert_eq!(results.iter().filter(|r| r.is_err()).count(), 1);
+        assert!(
+            results
+                .iter()
+                .any(|r| matches!(r, Ok((_, BackupState::New(..))))),
+            "the entities loaded before the error should still be backed up"
+        );
+        assert_eq!(
+            finished.load(Ordering::SeqCst),
+            1,
+            "the target should be finished even though the source failed"
+        );
+    }
+
+    #[rstest]
+    #[case(axum::http::StatusCode::OK, None)]
+    #[case(
+        axum::http::StatusCode::UNAUTHORIZED,
+        Some(crate::errors::ErrorCategory::Authentication)
+    )]
+    #[tokio::test]
+    async fn probe(
+        #[case] status: axum::http::StatusCode,
+        #[case] expected: Option<crate::errors::ErrorCategory>,
+    ) {
+        use axum::{routing::get, Router};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let api_url = format!("http://{}", listener.local_addr().unwrap());
+
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let app = Router::new().route(
+            "/users/notheotherben/repos",
+            get(move || {
+                let counter = counter.clone();
+                async move {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                    (status, "[]")
+                }
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let policy: BackupPolicy = serde_yaml::from_str(&format!(
+            r#"
+            kind: github/repo
+            from: users/notheotherben
+            to: /tmp/probe
+            credentials: !Token test
+            properties:
+              api_url: {api_url}
+            "#
+        ))
+        .unwrap();
+
+        let pairing = Pairing::new(crate::sources::GitHubRepoSource::repo(), MockEngine);
+        let result = pairing.probe(&policy, &CANCEL).await;
+
+        assert_eq!(
+            result.as_ref().err().map(crate::errors::ErrorCategory::of),
+            expected,
+            "unexpected probe result: {:?}",
+            result.as_ref().err().map(|e| e.to_string())
+        );
+        assert_eq!(
+            requests.load(Ordering::Relaxed),
+            1,
+            "only the first request should be sent"
+        );
+    }
+
+    #[rstest]
+    #[case(None, true)]
+    #[case(Some(false), true)]
+    #[case(Some(true), false)]
+    #[tokio::test]
+    async fn empty_repositories(#[case] backup_empty: Option<bool>, #[case] skipped: bool) {
+        let policy: BackupPolicy = serde_yaml::from_str(&format!(
+            r#"
+            kind: mock
+            from: mock
+            to: /tmp
+            filter: repo.empty
+            {}
+            "#,
+            backup_empty
+                .map(|v| format!("properties: {{ backup_empty: {v} }}"))
+                .unwrap_or_default()
+        ))
+        .unwrap();
+
+        let results: Vec<_> = Pairing::new(MockRepoSource, MockEngine)
+            .run_all_backups(&policy, &CANCEL)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .map(|r| r.expect("no errors").1)
+            .collect();
+
+        assert_eq!(results.len(), 31);
+        let empty = results
+            .iter()
+            .filter(|s| !matches!(s, BackupState::Skipped(SkipReason::Filtered)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            empty.len(),
+            2,
+            "the test data contains two empty repositories"
+        );
+        for state in empty {
+            if skipped {
+                assert_eq!(*state, BackupState::Skipped(SkipReason::Empty));
+            } else {
+                assert!(matches!(state, BackupState::New(..)));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn drain() {
+        static DRAIN: AtomicBool = AtomicBool::new(false);
+
+        #[derive(Clone)]
+        struct SlowEngine;
+
+        #[async_trait::async_trait]
+        impl BackupEngine<GitRepo> for SlowEngine {
+            async fn backup<P: AsRef<Path> + Send>(
+                &self,
+                entity: &GitRepo,
+                _store: &dyn BackupStore,
+                _target: P,
+                _cancel: &AtomicBool,
+            ) -> Result<BackupState, crate::Error> {
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                Ok(BackupState::New(BackupDetails::new(entity.name.clone())))
+            }
+        }
+
+        let policy: BackupPolicy = serde_yaml::from_str(
+            r#"
+            kind: mock
+            from: mock
+            to: /tmp
+            "#,
+        )
+        .unwrap();
+
+        let pairing = Pairing::new(MockRepoSource, SlowEngine)
+            .with_io_concurrency_limit(2)
+            .with_drain(&DRAIN);
+
+        tokio::spawn(async {
+            tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+            DRAIN.store(true, Ordering::Relaxed);
+        });
+
+        let started_at = std::time::Instant::now();
+        let results: Vec<_> = pairing
+            .run_all_backups(&policy, &CANCEL)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .map(|r| r.expect("no errors").1)
+            .collect();
+
+        let backed_up = results
+            .iter()
+            .filter(|s| matches!(s, BackupState::New(..)))
+            .count();
+        assert!(
+            (2..=8).contains(&backed_up),
+            "only the backups started before the run was drained should complete (got {backed_up})"
+        );
+        assert!(
+            results.contains(&BackupState::Skipped(SkipReason::Cancelled)),
+            "the entities which were waiting should be reported as cancelled"
+        );
+        assert!(
+            started_at.elapsed() < std::time::Duration::from_millis(1000),
+            "the run should stop early (took {:?})",
+            started_at.elapsed()
+        );
+        assert!(
+            !CANCEL.load(Ordering::Relaxed),
+            "draining the run shouldn't cancel it"
+        );
+    }
+
+    #[tokio::test]
+    async fn skips_unchanged_fingerprints() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Mutex;
+
+        struct FingerprintSource {
+            versions: Mutex<Vec<&'static str>>,
+        }
+
+        impl BackupSource<GitRepo> for FingerprintSource {
+            fn kind(&self) -> &str {
+                "mock"
+            }
+
+            fn validate(&self, _policy: &BackupPolicy) -> Result<(), crate::Error> {
+                Ok(())
+            }
+
+            fn load<'a>(
+                &'a self,
+                _policy: &'a BackupPolicy,
+                _cancel: &'a AtomicBool,
+            ) -> impl Stream<Item = Result<GitRepo, crate::Error>> + 'a {
+                let versions = self.versions.lock().unwrap().clone();
+                async_stream::stream! {
+                  for (i, version) in versions.into_iter().enumerate() {
+                    yield Ok(GitRepo::new(format!("mock/repo-{i}"), "https://example.com/repo.git", None)
+                        .with_fingerprint(version));
+                  }
+                }
+            }
+        }
+
+        #[derive(Clone, Default)]
+        struct CountingEngine {
+            backups: Arc<AtomicUsize>,
+            cancelled: Arc<AtomicBool>,
+        }
+
+        #[async_trait::async_trait]
+        impl BackupEngine<GitRepo> for CountingEngine {
+            async fn backup<P: AsRef<Path> + Send>(
+                &self,
+                entity: &GitRepo,
+                store: &dyn BackupStore,
+                target: P,
+                _cancel: &AtomicBool,
+            ) -> Result<BackupState, crate::Error> {
+                self.backups.fetch_add(1, Ordering::SeqCst);
+                if self.cancelled.load(Ordering::SeqCst) {
+                    return Ok(BackupState::Skipped(SkipReason::Cancelled));
+                }
+
+                store
+                    .create_dir_all(&target.as_ref().join(entity.target_path()))
+                    .await
+                    .unwrap();
+                Ok(BackupState::New(BackupDetails::default()))
+            }
+        }
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let policy: BackupPolicy = serde_yaml::from_str(&format!(
+            r#"
+            kind: mock
+            from: mock
+            to: {}
+            properties:
+              skip_unchanged: true
+            "#,
+            temp_dir.path().display()
+        ))
+        .unwrap();
+
+        let engine = CountingEngine::default();
+        let pairing = Pairing::new(
+            FingerprintSource {
+                versions: Mutex::new(vec!["v1", "v1", "v1"]),
+            },
+            engine.clone(),
+        );
+
+        let run = || async {
+            engine.backups.store(0, Ordering::SeqCst);
+            let results: Vec<_> = pairing.run_all_backups(&policy, &CANCEL).collect().await;
+            let unchanged = results
+                .into_iter()
+                .map(|r| r.expect("backup to succeed"))
+                .filter(|(_, state)| matches!(state, BackupState::Unchanged(..)))
+                .count();
+            (engine.backups.load(Ordering::SeqCst), unchanged)
+        };
+
+        assert_eq!(run().await, (3, 0), "every entity should be backed up");
+        assert_eq!(run().await, (0, 3), "unchanged entities should be skipped");
+
+        *pairing.source.versions.lock().unwrap() = vec!["v1", "v2", "v1"];
+        assert_eq!(run().await, (1, 2), "changed entities should be backed up");
+
+        std::fs::remove_dir_all(temp_dir.path().join("mock/repo-2")).unwrap();
+        assert_eq!(run().await, (1, 2), "missing backups should be recreated");
+
+        *pairing.source.versions.lock().unwrap() = vec!["v1", "v3", "v1"];
+        engine.cancelled.store(true, Ordering::SeqCst);
+        assert_eq!(run().await, (1, 2), "changed entities should be backed up");
+
+        engine.cancelled.store(false, Ordering::SeqCst);
+        assert_eq!(
+            run().await,
+            (1, 2),
+            "cancelled backups should be retried on the next run"
+        );
+    }
 }
@@ -0,0 +1,301 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use serde::Serialize;
+
+use crate::{
+    engines::{BackupState, SkipReason},
+    errors,
+    pairing::PairingHandler,
+    BackupEntity, BackupPolicy,
+};
+
+/// A description of the work which a dry run found, suitable for being written to a file
+/// and reviewed (or processed by other tools) before any backups are run.
+#[derive(Debug, Default, Serialize)]
+pub struct Plan {
+    pub policies: Vec<PolicyPlan>,
+    pub totals: PlanTotals,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PolicyPlan {
+    pub kind: String,
+    pub from: String,
+    pub to: PathBuf,
+    pub entities: Vec<PlannedEntity>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+    pub totals: PlanTotals,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlannedEntity {
+    pub name: String,
+    pub action: PlannedAction,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlannedAction {
+    /// The entity would be backed up.
+    Backup,
+    /// The entity would be skipped (for example, because it does not match the policy's filter).
+    Skip,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct PlanTotals {
+    pub entities: usize,
+    pub backup: usize,
+    pub skip: usize,
+    pub errors: usize,
+}
+
+impl PlanTotals {
+    fn add(&mut self, other: &PlanTotals) {
+        self.entities += other.entities;
+        self.backup += other.backup;
+        self.skip += other.skip;
+        self.errors += other.errors;
+    }
+}
+
+/// Records the outcome of a dry run into a [`Plan`], instead of logging each entity. Clones of
+/// the recorder share the same plan.
+#[derive(Clone, Default)]
+pub struct PlanRecorder {
+    plan: Arc<Mutex<Plan>>,
+}
+
+impl PlanRecorder {
+    /// Starts recording the entities for a new backup policy, returning a handler which records
+    /// the outcome of that policy's backups (even while other policies are being recorded).
+    pub fn start(&self, policy: &BackupPolicy) -> PolicyPlanRecorder {
+        let mut plan = self.plan.lock().unwrap();
+        plan.policies.push(PolicyPlan {
+            kind: policy.kind.clone(),
+            from: policy.from.clone(),
+            to: policy.to.clone(),
+            entities: Vec::new(),
+            errors: Vec::new(),
+            totals: PlanTotals::default(),
+        });
+
+        PolicyPlanRecorder {
+            recorder: self.clone(),
+            index: plan.policies.len() - 1,
+        }
+    }
+
+    /// Completes the plan which has been recorded so far, leaving the recorder ready to
+    /// record a new plan.
+    pub fn finish(&self) -> Plan {
+        let mut plan = std::mem::take(&mut *self.plan.lock().unwrap());
+        plan.totals = PlanTotals::default();
+        for policy in plan.policies.iter() {
+            plan.totals.add(&policy.totals);
+        }
+
+        plan
+    }
+
+    /// Writes the plan which has been recorded so far to the given path, as YAML if the path
+    /// has a `.yaml` or `.yml` extension, or as JSON otherwise.
+    pub fn write(&self, path: &Path) -> Result<Plan, errors::Error> {
+        let plan = self.finish();
+
+        let content = match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml" | "yml") => serde_yaml::to_string(&plan).map_err(|e| {
+                errors::system_with_internal(
+                    "Unable to serialize the backup plan.",
+                    "Please report this issue to us on GitHub.",
+                    e,
+                )
+            })?,
+            _ => serde_json::to_string_pretty(&plan).map_err(|e| {
+                errors::system_with_internal(
+                    "Unable to serialize the backup plan.",
+                    "Please report this issue to us on GitHub.",
+                    e,
+                )
+            })?,
+        };
+
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+
+        std::fs::write(&temp_path, content)
+            .and_then(|_| std::fs::rename(&temp_path, path))
+            .map_err(|e| {
+                errors::user_with_internal(
+                    &format!("Unable to write the backup plan to '{}'.", path.display()),
+                    "Make sure that you have permission to write to this file.",
+                    e,
+                )
+            })?;
+
+        Ok(plan)
+    }
+}
+
+/// Records the outcome of a single backup policy's dry run into the plan.
+pub struct PolicyPlanRecorder {
+    recorder: PlanRecorder,
+    index: usize,
+}
+
+impl PolicyPlanRecorder {
+    pub fn record_error(&self, error: &crate::Error) {
+        if let Some(policy) = self
+            .recorder
+            .plan
+            .lock()
+            .unwrap()
+            .policies
+            .get_mut(self.index)
+        {
+            policy.totals.errors += 1;
+            policy.errors.push(error.to_string());
+        }
+    }
+}
+
+impl<E: BackupEntity> PairingHandler<E> for PolicyPlanRecorder {
+    fn on_complete(&self, entity: E, state: BackupState) {
+        let action = match state {
+            BackupState::Skipped(SkipReason::DryRun) => PlannedAction::Backup,
+            BackupState::Skipped(_) => PlannedAction::Skip,
+            _ => PlannedAction::Backup,
+        };
+
+        if let Some(policy) = self
+            .recorder
+            .plan
+            .lock()
+            .unwrap()
+            .policies
+            .get_mut(self.index)
+        {
+            policy.totals.entities += 1;
+            match action {
+                PlannedAction::Backup => policy.totals.backup += 1,
+                PlannedAction::Skip => policy.totals.skip += 1,
+            }
+
+            policy.entities.push(PlannedEntity {
+                name: entity.name().to_string(),
+                action,
+            });
+        }
+    }
+
+    fn on_error(&self, _entity: Option<&str>, error: crate::Error) {
+        self.record_error(&error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+
+    use rstest::rstest;
+    use tokio_stream::Stream;
+
+    use crate::{engines::GitEngine, entities::GitRepo, pairing::Pairing, BackupSource};
+
+    use super::*;
+
+    static CANCEL: AtomicBool = AtomicBool::new(false);
+
+    struct MockRepoSource;
+
+    impl BackupSource<GitRepo> for MockRepoSource {
+        fn kind(&self) -> &str {
+            "mock"
+        }
+
+        fn validate(&self, _policy: &BackupPolicy) -> Result<(), crate::Error> {
+            Ok(())
+        }
+
+        fn load<'a>(
+            &'a self,
+            _policy: &'a BackupPolicy,
+            _cancel: &'a AtomicBool,
+        ) -> impl Stream<Item = Result<GitRepo, crate::Error>> + 'a {
+            async_stream::stream! {
+              for i in 0..10 {
+                yield Ok(GitRepo::new(format!("mock/repo-{i}"), "https://example.com/repo.git", None)
+                    .with_metadata("repo.fork", i % 2 == 0));
+              }
+            }
+        }
+    }
+
+    #[rstest]
+    #[case("plan.json")]
+    #[case("plan.yaml")]
+    #[tokio::test]
+    async fn test_write_plan(#[case] file: &str) {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let path = temp_dir.path().join(file);
+
+        let policy: BackupPolicy = serde_yaml::from_str(
+            r#"
+            kind: mock
+            from: mock
+            to: /tmp
+            filter: '!repo.fork'
+            "#,
+        )
+        .unwrap();
+
+        let recorder = PlanRecorder::default();
+        let pairing = Pairing::new(MockRepoSource, GitEngine::default()).with_dry_run(true);
+        // The policies' plans should be kept apart, even when they are recorded at the same time
+        let first = recorder.start(&policy);
+        let second = recorder.start(&policy);
+        tokio::join!(
+            pairing.run(&policy, &first, &CANCEL),
+            pairing.run(&policy, &second, &CANCEL)
+        );
+
+        let plan = recorder.write(&path).expect("the plan to be written");
+        assert_eq!(
+            plan.totals,
+            PlanTotals {
+                entities: 20,
+                backup: 10,
+                skip: 10,
+                errors: 0,
+            }
+        );
+
+        let content = std::fs::read_to_string(&path).expect("the plan file to exist");
+        let written: serde_json::Value = if file.ends_with(".yaml") {
+            serde_yaml::from_str(&content).expect("the plan to be valid YAML")
+        } else {
+            serde_json::from_str(&content).expect("the plan to be valid JSON")
+        };
+
+        assert_eq!(written["policies"].as_array().unwrap().len(), 2);
+        assert_eq!(written["totals"]["backup"], 10);
+        assert_eq!(written["policies"][0]["totals"]["entities"], 10);
+        assert_eq!(written["policies"][0]["entities"][1]["name"], "mock/repo-1");
+        assert_eq!(written["policies"][0]["entities"][1]["action"], "backup");
+        assert_eq!(written["policies"][0]["entities"][0]["action"], "skip");
+
+        assert!(
+            recorder.finish().policies.is_empty(),
+            "the recorder should be reset once the plan has been written"
+        );
+        assert!(
+            !temp_dir.path().join(format!("{file}.tmp")).exists(),
+            "the temporary plan file should have been removed"
+        );
+    }
+}
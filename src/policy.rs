@@ -1,25 +1,111 @@
+use chrono::Timelike;
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::fmt::{Debug, Display, Formatter};
 use std::path::PathBuf;
+use std::str::FromStr;
 
 use crate::entities::Credentials;
-use crate::Filter;
+use crate::helpers::{github::GitHubArtifactKind, http::HttpArtifactKind};
+use crate::{errors, Filter};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, schemars::JsonSchema)]
 pub struct BackupPolicy {
+    /// The kind of items which this policy backs up.
+    #[schemars(with = "PolicyKind")]
     pub kind: String,
+    /// The collection of items to back up, for example `user`, `orgs/<name>` or `repos/<owner>/<name>`.
+    /// A list of collections may be provided, in which case a separate policy (sharing the rest of
+    /// these settings) is created for each of them when the configuration is loaded.
+    #[schemars(with = "PolicySources")]
     pub from: String,
+    /// The directory into which items should be backed up.
     #[serde(default = "default_backup_path")]
     pub to: PathBuf,
+    /// The credentials used to access the items being backed up.
     #[serde(default)]
     pub credentials: Credentials,
+    /// A filter expression which determines which items should be backed up.
     #[serde(default)]
     pub filter: Filter,
+    /// A cron expression (evaluated in UTC) describing when this policy is allowed to run, for example
+    /// `* * * * SAT,SUN` to only back it up on weekends. If omitted, the policy runs on every scheduled run.
+    #[serde(default, deserialize_with = "crate::config::deserialize_cron")]
+    #[schemars(with = "Option<String>")]
+    pub window: Option<croner::Cron>,
+    /// Labels which can be used to run a subset of your backup policies with the `--tag` option.
     #[serde(default)]
+    pub tags: Vec<String>,
+    /// Additional properties which control how this kind of item is backed up.
+    #[serde(default, deserialize_with = "deserialize_properties")]
+    #[schemars(schema_with = "properties_schema")]
     pub properties: HashMap<String, String>,
 }
 
+impl BackupPolicy {
+    /// Parses the value of the named policy property, returning `None` if it has not been set.
+    pub fn property<T>(&self, key: &str) -> Result<Option<T>, errors::Error>
+    where
+        T: FromStr,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        match self.properties.get(key) {
+            Some(value) => value.trim().parse().map(Some).map_err(|e| {
+                errors::user_with_internal(
+                    &format!(
+                        "The '{key}' property on your '{self}' backup policy has an invalid value '{value}'."
+                    ),
+                    &format!("Make sure that you have provided a valid value for the '{key}' property in your backup policy."),
+                    e,
+                )
+            }),
+            None => Ok(None),
+        }
+    }
+}
+
+impl BackupPolicy {
+    /// Determines whether this policy has been selected by the given `--tag` and `--policy` options,
+    /// matching either any of its tags or its name. Every policy is selected when neither is provided.
+    pub fn is_selected(&self, tags: &[String], names: &[String]) -> bool {
+        if tags.is_empty() && names.is_empty() {
+            return true;
+        }
+
+        self.tags.iter().any(|tag| tags.contains(tag))
+            || names.iter().any(|name| *name == self.to_string())
+    }
+
+    /// Determines whether this policy is allowed to run at the given time, based on its `window`.
+    pub fn in_window(&self, at: chrono::DateTime<chrono::Utc>) -> bool {
+        let Some(window) = &self.window else {
+            return true;
+        };
+
+        // Windows are written without a seconds field, which croner treats as matching only the
+        // first second of each minute.
+        let at = at
+            .with_second(0)
+            .and_then(|at| at.with_nanosecond(0))
+            .unwrap_or(at);
+        window.is_time_matching(&at).unwrap_or_default()
+    }
+
+    /// Determines how long the start of this policy should be delayed, within `[0, max]`.
+    ///
+    /// The offset is derived from a (FNV-1a) hash of the policy's name, so that it is stable
+    /// between runs and between restarts, while spreading different policies across the window.
+    pub fn stagger_offset(&self, max: std::time::Duration) -> std::time::Duration {
+        let max = max.as_millis() as u64;
+        if max == 0 {
+            return std::time::Duration::ZERO;
+        }
+
+        let hash = crate::helpers::hash::fnv1a(self.to_string().as_bytes());
+        std::time::Duration::from_millis(hash % (max + 1))
+    }
+}
+
 impl Display for BackupPolicy {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}/{}", self.kind, self.from)
@@ -32,12 +118,82 @@ impl Debug for BackupPolicy {
     }
 }
 
+/// The `from` field of a policy, as it may be written in the configuration file.
+#[derive(schemars::JsonSchema)]
+#[serde(untagged)]
+#[allow(dead_code)]
+enum PolicySources {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// Reads policy properties as strings, accepting any scalar value. This is needed when the policy
+/// is deserialized from an already-parsed YAML value (as happens when a config file includes other
+/// files), in which `true` and `3` are no longer strings.
+fn deserialize_properties<'de, D>(deserializer: D) -> Result<HashMap<String, String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    HashMap::<String, serde_yaml::Value>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(key, value)| match value {
+            serde_yaml::Value::String(value) => Ok((key, value)),
+            serde_yaml::Value::Bool(value) => Ok((key, value.to_string())),
+            serde_yaml::Value::Number(value) => Ok((key, value.to_string())),
+            _ => Err(serde::de::Error::custom(format!(
+                "the '{key}' property must be a string, boolean or number"
+            ))),
+        })
+        .collect()
+}
+
+/// Describes the `kind` of a backup policy in the configuration schema, which may be any of the
+/// kinds supported by our sources.
+#[allow(dead_code)]
+#[derive(schemars::JsonSchema)]
+#[schemars(untagged)]
+enum PolicyKind {
+    GitHub(GitHubArtifactKind),
+    Http(HttpArtifactKind),
+}
+
+/// Properties are read as strings, but YAML users will naturally write `true` or `3` for
+/// boolean and numeric properties, so the schema accepts any scalar value.
+fn properties_schema(_gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+    use schemars::schema::{InstanceType, SchemaObject};
+
+    SchemaObject {
+        instance_type: Some(InstanceType::Object.into()),
+        object: Some(Box::new(schemars::schema::ObjectValidation {
+            additional_properties: Some(Box::new(
+                SchemaObject {
+                    instance_type: Some(
+                        vec![
+                            InstanceType::String,
+                            InstanceType::Boolean,
+                            InstanceType::Number,
+                        ]
+                        .into(),
+                    ),
+                    ..Default::default()
+                }
+                .into(),
+            )),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .into()
+}
+
 fn default_backup_path() -> PathBuf {
     PathBuf::from("./backups")
 }
 
 #[cfg(test)]
 mod tests {
+    use rstest::rstest;
+
     use super::*;
 
     #[test]
@@ -57,10 +213,7 @@ mod tests {
         assert_eq!(policy.to, PathBuf::from("/tmp/backup"));
         assert_eq!(
             policy.credentials,
-            Credentials::UsernamePassword {
-                username: "admin".to_string(),
-                password: "pass".to_string(),
-            }
+            Credentials::username_password("admin", "pass")
         );
         assert_eq!(policy.filter.raw(), "repo.name == \"my-repo\"");
         assert_eq!(policy.properties, {
@@ -72,4 +225,87 @@ mod tests {
         assert_eq!(format!("{}", policy), "backup/source");
         assert_eq!(format!("{:?}", policy), "backup/source");
     }
+
+    #[test]
+    fn test_property() {
+        let policy = r#"
+          kind: backup
+          from: source
+          properties:
+            max_bytes: 1024
+            min_bytes: lots
+        "#;
+        let policy: BackupPolicy = serde_yaml::from_str(policy).unwrap();
+        assert_eq!(policy.property::<u64>("max_bytes").unwrap(), Some(1024));
+        assert_eq!(policy.property::<u64>("missing").unwrap(), None);
+        policy
+            .property::<u64>("min_bytes")
+            .expect_err("an invalid number should be rejected");
+    }
+
+    #[rstest]
+    #[case(None, "2024-06-03T12:30:45Z", true)]
+    #[case(Some("* * * * SAT,SUN"), "2024-06-01T12:30:45Z", true)]
+    #[case(Some("* * * * SAT,SUN"), "2024-06-02T23:59:59Z", true)]
+    #[case(Some("* * * * SAT,SUN"), "2024-06-03T00:00:00Z", false)]
+    #[case(Some("* * * * SAT,SUN"), "2024-06-05T12:30:45Z", false)]
+    #[case(Some("* 1-5 * * *"), "2024-06-03T03:15:30Z", true)]
+    #[case(Some("* 1-5 * * *"), "2024-06-03T06:00:00Z", false)]
+    #[case(Some("0-29 * * * *"), "2024-06-03T12:29:59Z", true)]
+    #[case(Some("0-29 * * * *"), "2024-06-03T12:30:00Z", false)]
+    fn test_in_window(#[case] window: Option<&str>, #[case] at: &str, #[case] allowed: bool) {
+        let policy: BackupPolicy = serde_yaml::from_str(&match window {
+            Some(window) => format!("kind: github/repo\nfrom: user\nwindow: '{window}'"),
+            None => "kind: github/repo\nfrom: user".to_string(),
+        })
+        .unwrap();
+
+        let at = chrono::DateTime::parse_from_rfc3339(at)
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        assert_eq!(policy.in_window(at), allowed);
+    }
+
+    #[test]
+    fn test_invalid_window() {
+        serde_yaml::from_str::<BackupPolicy>("kind: github/repo\nfrom: user\nwindow: weekends")
+            .expect_err("an invalid window should be rejected");
+    }
+
+    #[test]
+    fn test_stagger_offset() {
+        let max = std::time::Duration::from_secs(300);
+        let policies: Vec<BackupPolicy> = ["users/notheotherben", "orgs/SierraSoftworks", "user"]
+            .iter()
+            .map(|from| serde_yaml::from_str(&format!("kind: github/repo\nfrom: {from}")).unwrap())
+            .collect();
+
+        let offsets: Vec<_> = policies.iter().map(|p| p.stagger_offset(max)).collect();
+        for (policy, offset) in policies.iter().zip(offsets.iter()) {
+            assert!(
+                *offset <= max,
+                "the offset for {policy} should be within bounds"
+            );
+            assert_eq!(
+                policy.stagger_offset(max),
+                *offset,
+                "the offset for {policy} should be stable"
+            );
+        }
+
+        assert_ne!(
+            offsets[0], offsets[1],
+            "different policies should have different offsets"
+        );
+        assert_eq!(
+            policies[0].stagger_offset(std::time::Duration::ZERO),
+            std::time::Duration::ZERO
+        );
+        assert!(
+            policies[0]
+                .stagger_offset(std::time::Duration::from_millis(1))
+                .as_millis()
+                <= 1
+        );
+    }
 }
@@ -0,0 +1,378 @@
+use std::sync::atomic::AtomicBool;
+
+use tracing_batteries::prelude::*;
+
+use crate::{
+    entities::Credentials,
+    errors,
+    helpers::{
+        github::{GitHubArtifactKind, GitHubRepoSourceKind},
+        http::HttpArtifactKind,
+        GitHubClient,
+    },
+    BackupPolicy,
+};
+
+/// The number of requests per hour which GitHub allows clients to make without credentials.
+const UNAUTHENTICATED_RATE_LIMIT: usize = 60;
+
+/// Checks, before any backups are run, that the credentials used by each backup policy
+/// have been granted the access that the policy needs.
+///
+/// Classic tokens report their scopes through the `X-OAuth-Scopes` header, which is checked
+/// once for each distinct set of credentials. Fine-grained tokens don't report their
+/// permissions, so instead we probe the first resource each policy will list. Policies
+/// without any credentials are checked to see whether they are likely to exceed GitHub's
+/// (much lower) rate limit for unauthenticated requests.
+#[derive(Clone, Default)]
+pub struct PreflightCheck {
+    client: GitHubClient,
+}
+
+impl PreflightCheck {
+    pub fn with_client(client: GitHubClient) -> Self {
+        Self { client }
+    }
+
+    /// Runs the pre-flight checks for the given policies, returning a warning for each
+    /// problem which was found.
+    pub async fn run(&self, policies: &[BackupPolicy], cancel: &AtomicBool) -> Vec<errors::Error> {
+        let mut warnings = Vec::new();
+        warnings.extend(Self::check_unauthenticated(policies));
+
+        let mut checked: Vec<(&str, &Credentials, Option<Vec<String>>)> = Vec::new();
+
+        for policy in policies.iter() {
+            // The credentials for files which aren't hosted on GitHub must never be sent to it.
+            let api_url = Self::api_url(policy);
+            if policy.credentials.for_url(api_url) == Credentials::None
+                || policy.kind == HttpArtifactKind::File.as_str()
+            {
+                continue;
+            }
+            let scopes = match checked
+                .iter()
+                .find(|(url, creds, _)| *url == api_url && *creds == &policy.credentials)
+            {
+                Some((_, _, scopes)) => scopes.clone(),
+                None => {
+                    debug!(
+                        "Checking the scopes granted to the credentials for {}",
+                        policy
+                    );
+                    match self
+                        .client
+                        .get_token_scopes(api_url, &policy.credentials, cancel)
+                        .await
+                    {
+                        Ok(scopes) => {
+                            checked.push((api_url, &policy.credentials, scopes.clone()));
+                            scopes
+                        }
+                        Err(e) => {
+                            warnings.push(e);
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            match scopes {
+                Some(scopes) => {
+                    for scope in Self::required_scopes(policy) {
+                        if !scopes.iter().any(|s| s == scope) {
+                            warnings.push(errors::user(
+                                &format!(
+                                    "The GitHub token used by your backup policy '{}' has not been granted the '{}' scope (it has: {}), so some items may not be backed up.",
+                                    policy,
+                                    scope,
+                                    if scopes.is_empty() { "no scopes".to_string() } else { scopes.join(", ") }
+                                ),
+                                &format!("Generate a new token with the '{scope}' scope and update your configuration to use it."),
+                            ));
+                        }
+                    }
+                }
+                None => {
+                    if let Err(e) = self.probe(policy, cancel).await {
+                        warnings.push(errors::user_with_internal(
+                            &format!(
+                                "The GitHub token used by your backup policy '{}' was not able to access the resources it needs to back up.",
+                                policy
+                            ),
+                            "If you are using a fine-grained token, make sure that it has been granted read access to the repositories (and their contents) which you wish to back up.",
+                            e,
+                        ));
+                    }
+                }
+            }
+        }
+
+        warnings
+    }
+
+    /// Warns when the policies which don't have any credentials are likely to make more requests
+    /// than GitHub allows for unauthenticated clients.
+    fn check_unauthenticated(policies: &[BackupPolicy]) -> Option<errors::Error> {
+        let unauthenticated: Vec<&BackupPolicy> = policies
+            .iter()
+            .filter(|p| p.credentials.for_url(Self::api_url(p)) == Credentials::None)
+            .filter(|p| p.kind != HttpArtifactKind::File.as_str())
+            // GitHub Enterprise Server doesn't apply rate limits unless an administrator enables them.
+            .filter(|p| Self::api_url(p) == "https://api.github.com")
+            .collect();
+
+        let requests = unauthenticated
+            .iter()
+            .map(|p| Self::estimated_requests(p))
+            .try_fold(0, |total, requests| Some(total + requests?));
+
+        if unauthenticated.is_empty()
+            || requests.is_some_and(|requests| requests <= UNAUTHENTICATED_RATE_LIMIT)
+        {
+            return None;
+        }
+
+        Some(errors::user(
+            &format!(
+                "Your backup policies ({}) don't have any credentials, so GitHub will only allow them to make {UNAUTHENTICATED_RATE_LIMIT} requests per hour, which they are likely to exceed.",
+                unauthenticated
+                    .iter()
+                    .map(|p| p.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            "Add a GitHub token to the 'credentials' of these backup policies (for example `credentials: !Token \"your_github_pat\"`), which raises the rate limit to 5,000 requests per hour.",
+        ))
+    }
+
+    /// Estimates the number of GitHub API requests which a policy will make, or `None` if this
+    /// depends on the number of repositories it lists.
+    fn estimated_requests(policy: &BackupPolicy) -> Option<usize> {
+        match policy.from.parse::<GitHubRepoSourceKind>().ok()? {
+            GitHubRepoSourceKind::Repo(_) => match policy.kind.as_str() {
+                k if k == GitHubArtifactKind::Release.as_str() => Some(2),
+                k if k == GitHubArtifactKind::Pages.as_str() => Some(2),
+                _ => Some(1),
+            },
+            _ => None,
+        }
+    }
+
+    /// The scopes that a classic token must have been granted to fully back up a policy.
+    fn required_scopes(policy: &BackupPolicy) -> Vec<&'static str> {
+        match policy.kind.as_str() {
+            k if k == GitHubArtifactKind::Repo.as_str() => vec!["repo"],
+            k if k == GitHubArtifactKind::Release.as_str() => vec!["repo"],
+            k if k == GitHubArtifactKind::Pages.as_str() => vec!["repo"],
+            _ => vec![],
+        }
+    }
+
+    fn api_url(policy: &BackupPolicy) -> &str {
+        policy
+            .properties
+            .get("api_url")
+            .map(|u| u.as_str())
+            .unwrap_or("https://api.github.com")
+            .trim_end_matches('/')
+    }
+
+    /// Fetches the first item that the policy would back up, to confirm that its credentials
+    /// are able to access it.
+    async fn probe(&self, policy: &BackupPolicy, cancel: &AtomicBool) -> Result<(), errors::Error> {
+        let kind = match policy.kind.as_str() {
+            k if k == GitHubArtifactKind::Repo.as_str() => GitHubArtifactKind::Repo,
+            k if k == GitHubArtifactKind::Star.as_str() => GitHubArtifactKind::Star,
+            k if k == GitHubArtifactKind::Release.as_str() => GitHubArtifactKind::Release,
+            k if k == GitHubArtifactKind::Pages.as_str() => GitHubArtifactKind::Pages,
+            _ => return Ok(()),
+        };
+
+        let target: GitHubRepoSourceKind = policy.from.parse()?;
+        let url = match target {
+            GitHubRepoSourceKind::Repo(_) => {
+                format!("{}/{}", Self::api_url(policy), target.api_endpoint(kind))
+            }
+            _ => format!(
+                "{}/{}?per_page=1",
+                Self::api_url(policy),
+                target.api_endpoint(kind)
+            ),
+        };
+
+        self.client
+            .get::<serde_json::Value>(url, &policy.credentials, cancel)
+            .await
+            .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{http::StatusCode, routing::get, Router};
+    use rstest::rstest;
+
+    use super::*;
+
+    static CANCEL: AtomicBool = AtomicBool::new(false);
+
+    #[rstest]
+    #[case("github/repo", Some("repo, read:org"), StatusCode::OK, 0)]
+    #[case("github/repo", Some("read:user, public_repo"), StatusCode::OK, 1)]
+    #[case("github/release", Some(""), StatusCode::OK, 1)]
+    #[case("github/star", Some("read:user"), StatusCode::OK, 0)]
+    #[case("github/repo", None, StatusCode::OK, 0)]
+    #[case("github/repo", None, StatusCode::FORBIDDEN, 1)]
+    #[tokio::test]
+    async fn test_preflight(
+        #[case] kind: &str,
+        #[case] scopes: Option<&'static str>,
+        #[case] probe_status: StatusCode,
+        #[case] warnings: usize,
+    ) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let api_url = format!("http://{}", listener.local_addr().unwrap());
+
+        let app = Router::new()
+            .route(
+                "/",
+                get(move || async move {
+                    let mut headers = axum::http::HeaderMap::new();
+                    if let Some(scopes) = scopes {
+                        headers.insert("x-oauth-scopes", scopes.parse().unwrap());
+                    }
+                    headers
+                }),
+            )
+            .route(
+                "/users/notheotherben/repos",
+                get(move || async move { (probe_status, "[]") }),
+            )
+            .route(
+                "/users/notheotherben/starred",
+                get(move || async move { (probe_status, "[]") }),
+            );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let policies: Vec<BackupPolicy> = serde_yaml::from_str(&format!(
+            r#"
+            - kind: {kind}
+              from: users/notheotherben
+              to: /tmp
+              credentials: !Token test
+              properties:
+                api_url: {api_url}
+            - kind: {kind}
+              from: repos/notheotherben/github-backup
+              to: /tmp/unauthenticated
+            "#
+        ))
+        .expect("parse policies");
+
+        let found = PreflightCheck::default().run(&policies, &CANCEL).await;
+        assert_eq!(
+            found.len(),
+            warnings,
+            "unexpected warnings: {:?}",
+            found.iter().map(|w| w.to_string()).collect::<Vec<_>>()
+        );
+    }
+
+    #[rstest]
+    #[case(&[("github/repo", "repos/octocat/hello-world")], false)]
+    #[case(&[("github/release", "repos/octocat/hello-world"); 30], false)]
+    #[case(&[("github/release", "repos/octocat/hello-world"); 31], true)]
+    #[case(&[("github/repo", "users/octocat")], true)]
+    #[case(&[("github/star", "user")], true)]
+    #[case(&[("http/file", "files.yaml")], false)]
+    #[tokio::test]
+    async fn test_preflight_unauthenticated(
+        #[case] policies: &[(&str, &str)],
+        #[case] warns: bool,
+    ) {
+        let policies: Vec<BackupPolicy> = policies
+            .iter()
+            .map(|(kind, from)| {
+                serde_yaml::from_str(&format!("{{ kind: {kind}, from: {from}, to: /tmp }}"))
+                    .expect("parse policy")
+            })
+            .collect();
+
+        let found = PreflightCheck::default().run(&policies, &CANCEL).await;
+        assert_eq!(found.len(), usize::from(warns));
+        if warns {
+            assert!(found[0].to_string().contains("60 requests per hour"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_preflight_unauthenticated_enterprise() {
+        let policies: Vec<BackupPolicy> = serde_yaml::from_str(
+            r#"
+            - kind: github/repo
+              from: users/octocat
+              to: /tmp
+              properties:
+                api_url: https://github.example.com/api/v3
+            "#,
+        )
+        .expect("parse policies");
+
+        assert!(PreflightCheck::default()
+            .run(&policies, &CANCEL)
+            .await
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_preflight_checks_credentials_once() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let api_url = format!("http://{}", listener.local_addr().unwrap());
+
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let app = Router::new().route(
+            "/",
+            get(move || {
+                let counter = counter.clone();
+                async move {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                    [("x-oauth-scopes", "repo")]
+                }
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let policies: Vec<BackupPolicy> = serde_yaml::from_str(&format!(
+            r#"
+            - kind: github/repo
+              from: user
+              to: /tmp
+              credentials: !Token test
+              properties:
+                api_url: {api_url}
+            - kind: github/release
+              from: user
+              to: /tmp
+              credentials: !Token test
+              properties:
+                api_url: {api_url}/
+            "#
+        ))
+        .expect("parse policies");
+
+        let found = PreflightCheck::default().run(&policies, &CANCEL).await;
+        assert!(found.is_empty());
+        assert_eq!(requests.load(Ordering::Relaxed), 1);
+    }
+}
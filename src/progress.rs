@@ -0,0 +1,74 @@
+use std::{io::IsTerminal, time::Duration};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+
+use crate::BackupPolicy;
+
+/// Renders a live progress indicator for each backup policy as it runs.
+///
+/// Sources stream their entities, so the total number of entities isn't known
+/// until the source has finished loading. Until then, progress is shown as a
+/// spinner with a running count, which is converted into a progress bar once
+/// the total is known.
+pub struct Progress {
+    multi: MultiProgress,
+}
+
+impl Progress {
+    /// Creates a progress display if the terminal supports it, otherwise returns
+    /// [`None`] so that the standard logging output is used instead.
+    pub fn detect() -> Option<Self> {
+        if !std::io::stderr().is_terminal() {
+            return None;
+        }
+
+        Some(Self {
+            multi: MultiProgress::new(),
+        })
+    }
+
+    /// Adds a progress indicator for a backup policy which is starting, which is shown
+    /// alongside those of any other policies which are running at the same time.
+    pub fn start(&self, policy: &BackupPolicy) -> PolicyProgress {
+        let bar = self.multi.add(ProgressBar::new_spinner());
+        bar.set_style(
+            ProgressStyle::with_template("{spinner} {prefix} {pos} completed ({per_sec})")
+                .unwrap_or_else(|_| ProgressStyle::default_spinner()),
+        );
+        bar.set_prefix(policy.to_string());
+        bar.enable_steady_tick(Duration::from_millis(100));
+
+        PolicyProgress { bar }
+    }
+}
+
+/// The progress indicator for a single backup policy.
+pub struct PolicyProgress {
+    bar: ProgressBar,
+}
+
+impl PolicyProgress {
+    pub fn inc(&self) {
+        self.bar.inc(1);
+    }
+
+    pub fn set_total(&self, total: u64) {
+        self.bar.set_length(total);
+        self.bar.set_style(
+            ProgressStyle::with_template(
+                "{prefix} [{bar:40}] {pos}/{len} ({per_sec}, {eta} remaining)",
+            )
+            .unwrap_or_else(|_| ProgressStyle::default_bar())
+            .progress_chars("=> "),
+        );
+    }
+
+    pub fn finish(&self) {
+        self.bar.finish();
+    }
+
+    /// Hides the progress display while `f` runs, so that log output isn't interleaved with it.
+    pub fn suspend<F: FnOnce() -> R, R>(&self, f: F) -> R {
+        self.bar.suspend(f)
+    }
+}
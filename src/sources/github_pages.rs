@@ -0,0 +1,249 @@
+use std::{path::PathBuf, sync::atomic::AtomicBool};
+
+use tokio_stream::Stream;
+
+use crate::{
+    entities::HttpFile,
+    errors::{self},
+    helpers::{
+        github::{GitHubArtifactKind, GitHubRepo, GitHubRepoSourceKind},
+        GitHubClient,
+    },
+    policy::BackupPolicy,
+    BackupSource,
+};
+
+/// Backs up the GitHub Pages sites published by repositories.
+///
+/// A Pages site is built from many files (and GitHub doesn't provide an archive of the
+/// published site), so we currently back up the site's Pages configuration and the
+/// metadata describing its latest build, rather than the site's content.
+#[derive(Clone, Default)]
+pub struct GitHubPagesSource {
+    client: GitHubClient,
+}
+
+impl GitHubPagesSource {
+    pub fn with_client(client: GitHubClient) -> Self {
+        Self { client }
+    }
+
+    fn repos_url(policy: &BackupPolicy, target: &GitHubRepoSourceKind) -> String {
+        format!(
+            "{}/{}?{}",
+            policy
+                .properties
+                .get("api_url")
+                .unwrap_or(&"https://api.github.com".to_string())
+                .trim_end_matches('/'),
+            target.api_endpoint(GitHubArtifactKind::Pages),
+            policy.properties.get("query").unwrap_or(&"".to_string())
+        )
+        .trim_end_matches('?')
+        .to_string()
+    }
+
+    fn load_pages(policy: &BackupPolicy, repo: &GitHubRepo) -> Vec<HttpFile> {
+        if !repo.has_pages {
+            return Vec::new();
+        }
+
+        let temp_dir = policy.properties.get("temp_dir").map(PathBuf::from);
+        let verify_after_write = policy
+            .property::<bool>("verify_after_write")
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+
+        [
+            ("pages.json", format!("{}/pages", repo.url)),
+            (
+                "pages-build.json",
+                format!("{}/pages/builds/latest", repo.url),
+            ),
+        ]
+        .into_iter()
+        .map(|(name, url)| {
+            HttpFile::new(format!("{}/{}", &repo.full_name, name), url)
+                .with_content_type(Some("application/vnd.github+json".to_string()))
+                .with_credentials(policy.credentials.clone())
+                .with_temp_dir(temp_dir.clone())
+                .with_verify_after_write(verify_after_write)
+                .with_metadata_source(repo)
+        })
+        .collect()
+    }
+}
+
+impl BackupSource<HttpFile> for GitHubPagesSource {
+    fn kind(&self) -> &str {
+        GitHubArtifactKind::Pages.as_str()
+    }
+
+    fn validate(&self, policy: &BackupPolicy) -> Result<(), crate::Error> {
+        if policy.properties.contains_key("sparse") {
+            return Err(errors::user(
+                &format!("Your '{policy}' backup policy specifies the 'sparse' property, but sparse backups are only supported for Git repositories."),
+                "Remove the 'sparse' property from this policy, or use a filter to restrict which repositories' Pages sites are backed up.",
+            ));
+        }
+
+        policy.property::<bool>("verify_after_write")?;
+        super::validate_temp_dir(policy)?;
+
+        let target: GitHubRepoSourceKind = policy.from.as_str().parse()?;
+
+        match target {
+          GitHubRepoSourceKind::User(u) if u.is_empty() => Err(errors::user(
+              &format!(
+                  "Your 'from' target '{}' is not a valid GitHub username.",
+                  policy.from.as_str()
+              ),
+              "Make sure you provide a valid GitHub username in the 'from' field of your policy.",
+          )),
+          GitHubRepoSourceKind::Org(org) if org.is_empty() => Err(errors::user(
+              &format!(
+                  "Your 'from' target '{}' is not a valid GitHub organization name.",
+                  policy.from.as_str()
+              ),
+              "Make sure you provide a valid GitHub organization name in the 'from' field of your policy.",
+          )),
+          GitHubRepoSourceKind::Repo(repo) if repo.is_empty() => Err(errors::user(
+              &format!(
+                  "Your 'from' target '{}' is not a fully qualified GitHub repository name.",
+                  policy.from.as_str()
+              ),
+              "Make sure you provide a fully qualified GitHub repository name in the 'from' field of your policy.",
+          )),
+          _ => Ok(()),
+      }
+    }
+
+    fn load<'a>(
+        &'a self,
+        policy: &'a BackupPolicy,
+        cancel: &'a AtomicBool,
+    ) -> impl Stream<Item = Result<HttpFile, crate::Error>> + 'a {
+        let target: GitHubRepoSourceKind = policy.from.as_str().parse().unwrap();
+        let url = Self::repos_url(policy, &target);
+
+        async_stream::stream! {
+          if matches!(target, GitHubRepoSourceKind::Repo(_)) {
+            let repo: GitHubRepo = self.client.get(url, &policy.credentials, cancel).await?;
+
+            for file in Self::load_pages(policy, &repo) {
+              yield Ok(file);
+            }
+          } else {
+            for await repo in self.client.get_paginated::<GitHubRepo>(url, &policy.credentials, cancel) {
+              if let Err(e) = repo {
+                yield Err(e);
+                continue;
+              }
+
+              let repo: GitHubRepo = repo.unwrap();
+
+              for file in Self::load_pages(policy, &repo) {
+                yield Ok(file);
+              }
+            }
+          }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+
+    use rstest::rstest;
+
+    use crate::{BackupEntity, BackupPolicy, BackupSource, Filterable};
+
+    use super::GitHubPagesSource;
+
+    static CANCEL: AtomicBool = AtomicBool::new(false);
+
+    #[test]
+    fn check_name() {
+        assert_eq!(GitHubPagesSource::default().kind(), "github/pages");
+    }
+
+    #[rstest]
+    #[case("users/notheotherben", true)]
+    #[case("orgs/sierrasoftworks", true)]
+    #[case("repos/sierrasoftworks/github-backup", true)]
+    #[case("notheotherben", false)]
+    #[case("users/notheotherben/repos", false)]
+    fn validation(#[case] from: &str, #[case] success: bool) {
+        let source = GitHubPagesSource::default();
+
+        let policy = serde_yaml::from_str(&format!(
+            r#"
+            kind: github/pages
+            from: {}
+            to: /tmp
+            "#,
+            from
+        ))
+        .expect("parse policy");
+
+        if success {
+            source.validate(&policy).expect("validation to succeed");
+        } else {
+            source.validate(&policy).expect_err("validation to fail");
+        }
+    }
+
+    #[tokio::test]
+    async fn load_pages() {
+        use axum::{routing::get, Json, Router};
+        use tokio_stream::StreamExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let api_url = format!("http://{}", listener.local_addr().unwrap());
+
+        let mut repos: Vec<serde_json::Value> =
+            serde_json::from_str(include_str!("../../tests/data/github.repos.0.json")).unwrap();
+        for (i, repo) in repos.iter_mut().enumerate() {
+            repo["has_pages"] = (i < 3).into();
+        }
+
+        let app = Router::new().route(
+            "/users/octocat/repos",
+            get(move || async move { Json(repos) }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let policy: BackupPolicy = serde_yaml::from_str(&format!(
+            r#"
+            kind: github/pages
+            from: users/octocat
+            to: /tmp
+            properties:
+              api_url: {api_url}
+            "#
+        ))
+        .unwrap();
+
+        let source = GitHubPagesSource::default();
+        let files = source
+            .load(&policy, &CANCEL)
+            .collect::<Result<Vec<_>, _>>()
+            .await
+            .expect("the pages to be loaded");
+
+        assert_eq!(
+            files.len(),
+            6,
+            "only repositories with Pages enabled should be backed up"
+        );
+        assert!(files[0].name().ends_with("/pages.json"));
+        assert!(files[0].url.ends_with("/pages"));
+        assert!(files[1].name().ends_with("/pages-build.json"));
+        assert!(files[1].url.ends_with("/pages/builds/latest"));
+        assert_eq!(files[0].get("repo.name"), files[1].get("repo.name"));
+    }
+}
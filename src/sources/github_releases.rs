@@ -1,4 +1,4 @@
-use std::sync::atomic::AtomicBool;
+use std::{path::PathBuf, sync::atomic::AtomicBool};
 
 use tokio_stream::Stream;
 
@@ -10,26 +10,195 @@ use crate::{
         GitHubClient,
     },
     policy::BackupPolicy,
+    state::State,
     BackupSource,
 };
 
+/// The name given to a release's source code archives when no `source_archive_name` is configured.
+const DEFAULT_SOURCE_ARCHIVE_NAME: &str = "source";
+
+/// The formats in which the source code archive that GitHub generates for each release may be downloaded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SourceArchiveFormat {
+    TarGz,
+    Zip,
+}
+
+impl SourceArchiveFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            SourceArchiveFormat::TarGz => "tar.gz",
+            SourceArchiveFormat::Zip => "zip",
+        }
+    }
+
+    fn url<'a>(&self, release: &'a GitHubRelease) -> Option<&'a String> {
+        match self {
+            SourceArchiveFormat::TarGz => release.tarball_url.as_ref(),
+            SourceArchiveFormat::Zip => release.zipball_url.as_ref(),
+        }
+    }
+}
+
+impl std::str::FromStr for SourceArchiveFormat {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tar.gz" => Ok(SourceArchiveFormat::TarGz),
+            "zip" => Ok(SourceArchiveFormat::Zip),
+            _ => Err(errors::user(
+                &format!("The source archive format '{s}' is not supported."),
+                "Use 'tar.gz', 'zip' or both (separated by a comma) as the source archive formats.",
+            )),
+        }
+    }
+}
+
 #[derive(Clone, Default)]
 pub struct GitHubReleasesSource {
     client: GitHubClient,
+    incremental: bool,
 }
 
 impl GitHubReleasesSource {
-    #[allow(dead_code)]
     pub fn with_client(client: GitHubClient) -> Self {
-        Self { client }
+        Self {
+            client,
+            incremental: false,
+        }
+    }
+
+    /// Only backs up releases published since the last successful backup run, for all policies.
+    pub fn with_incremental(self, incremental: bool) -> Self {
+        Self {
+            incremental,
+            ..self
+        }
     }
 }
 
 impl GitHubReleasesSource {
+    /// Determines the cutoff before which releases should be skipped, if incremental mode is enabled.
+    fn since(&self, policy: &BackupPolicy) -> Option<chrono::DateTime<chrono::Utc>> {
+        let incremental = self.incremental
+            || policy
+                .property::<bool>("since")
+                .ok()
+                .flatten()
+                .unwrap_or_default();
+
+        if !incremental {
+            return None;
+        }
+
+        match State::load(&State::path_for(policy)) {
+            Ok(state) => state.policy(policy).last_successful_run,
+            Err(e) => {
+                tracing_batteries::prelude::warn!(
+                    "Unable to load the backup state for {policy}, all releases will be backed up: {e}"
+                );
+                None
+            }
+        }
+    }
+
+    fn releases_url(policy: &BackupPolicy, target: &GitHubRepoSourceKind) -> String {
+        let query = policy.properties.get("query").cloned().unwrap_or_default();
+
+        format!(
+            "{}/{}?{}",
+            policy
+                .properties
+                .get("api_url")
+                .unwrap_or(&"https://api.github.com".to_string())
+                .trim_end_matches('/'),
+            target.api_endpoint(GitHubArtifactKind::Release),
+            query
+        )
+        .trim_end_matches('?')
+        .to_string()
+    }
+
+    /// Release assets are downloaded using a token as the username for basic authentication,
+    /// rather than as a bearer token.
+    fn download_credentials(creds: &Credentials) -> Credentials {
+        match creds {
+            Credentials::Token { value, source } => Credentials::UsernamePassword {
+                username: value.clone(),
+                password: "".to_string(),
+                source: source.clone(),
+            },
+            Credentials::Chain(chain) => {
+                Credentials::Chain(chain.iter().map(Self::download_credentials).collect())
+            }
+            Credentials::PerHost(hosts) => Credentials::PerHost(
+                hosts
+                    .iter()
+                    .map(|(host, c)| (host.clone(), Self::download_credentials(c)))
+                    .collect(),
+            ),
+            creds => creds.clone(),
+        }
+    }
+
+    /// Determines which source code archives should be backed up for each release, defaulting to
+    /// only the tarball. An empty list disables the backup of source code archives entirely.
+    fn source_archives(policy: &BackupPolicy) -> Result<Vec<SourceArchiveFormat>, errors::Error> {
+        match policy.properties.get("source_archives") {
+            Some(formats) => formats
+                .split(',')
+                .map(str::trim)
+                .filter(|f| !f.is_empty())
+                .map(str::parse)
+                .collect(),
+            None => Ok(vec![SourceArchiveFormat::TarGz]),
+        }
+    }
+
+    /// Builds the file name of a release's source code archive from the policy's `source_archive_name`
+    /// template, in which `{owner}`, `{repo}` and `{tag}` are replaced with details of the release.
+    fn source_archive_name(
+        template: &str,
+        repo: &GitHubRepo,
+        release: &GitHubRelease,
+        format: SourceArchiveFormat,
+    ) -> String {
+        format!(
+            "{}.{}",
+            template
+                .replace("{owner}", &repo.owner.login)
+                .replace("{repo}", &repo.name)
+                .replace("{tag}", &release.tag_name),
+            format.extension()
+        )
+    }
+
+    /// GitHub's repository listings can't be limited to recently changed repositories, so we
+    /// skip those which haven't been pushed to (which includes publishing a release's tag) since
+    /// the given time ourselves.
+    fn is_pushed_since(repo: &GitHubRepo, since: Option<chrono::DateTime<chrono::Utc>>) -> bool {
+        match since {
+            Some(since) => repo.pushed_at >= since,
+            None => true,
+        }
+    }
+
+    fn is_published_since(
+        release: &GitHubRelease,
+        since: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> bool {
+        match (release.published_at, since) {
+            (Some(published_at), Some(since)) => published_at >= since,
+            _ => true,
+        }
+    }
+
     fn load_releases<'a>(
         &'a self,
         policy: &'a BackupPolicy,
         repo: &'a GitHubRepo,
+        since: Option<chrono::DateTime<chrono::Utc>>,
         cancel: &'a AtomicBool,
     ) -> impl Stream<Item = Result<HttpFile, crate::Error>> + 'a {
         async_stream::stream! {
@@ -38,6 +207,23 @@ impl GitHubReleasesSource {
           }
 
           let releases_url = format!("{}/releases", repo.url);
+          let min_bytes = policy.property::<u64>("min_bytes").ok().flatten();
+          let max_bytes = policy.property::<u64>("max_bytes").ok().flatten();
+          let sha_index = policy.property::<bool>("sha_index").ok().flatten().unwrap_or_default();
+          let verify_after_write = policy.property::<bool>("verify_after_write").ok().flatten().unwrap_or_default();
+          let temp_dir = policy.properties.get("temp_dir").map(PathBuf::from);
+          let ext_from_content_type = policy.property::<bool>("ext_from_content_type").ok().flatten().unwrap_or_default();
+          let accept = policy
+            .properties
+            .get("accept")
+            .cloned()
+            .unwrap_or_else(|| "application/octet-stream".to_string());
+          let source_archives = Self::source_archives(policy).unwrap_or_default();
+          let source_archive_name = policy
+            .properties
+            .get("source_archive_name")
+            .map(String::as_str)
+            .unwrap_or(DEFAULT_SOURCE_ARCHIVE_NAME);
 
           for await release in self.client.get_paginated::<GitHubRelease>(releases_url, &policy.credentials, cancel) {
             if let Err(e) = release {
@@ -47,19 +233,27 @@ impl GitHubReleasesSource {
 
             let release: GitHubRelease = release.unwrap();
 
-            if let Some(tarball_url) = &release.tarball_url {
-              yield Ok(HttpFile::new(format!("{}/{}/source.tar.gz", &repo.full_name, &release.tag_name), tarball_url)
+            if !Self::is_published_since(&release, since) {
+              continue;
+            }
+
+            for format in source_archives.iter() {
+              let Some(url) = format.url(&release) else {
+                continue;
+              };
+
+              let name = Self::source_archive_name(source_archive_name, repo, &release, *format);
+              yield Ok(HttpFile::new(format!("{}/{}/{}", &repo.full_name, &release.tag_name, name), url)
                   .with_metadata_source(repo)
                   .with_metadata_source(&release)
                   .with_metadata("asset.source-code", true)
-                  .with_credentials(match &policy.credentials {
-                    Credentials::Token(token) => Credentials::UsernamePassword {
-                      username: token.clone(),
-                      password: "".to_string(),
-                    },
-                    creds => creds.clone(),
-                  })
-                  .with_last_modified(release.published_at));
+                  .with_credentials(Self::download_credentials(&policy.credentials))
+                  .with_last_modified(release.published_at)
+                  .with_min_bytes(min_bytes)
+                  .with_max_bytes(max_bytes)
+                  .with_sha_index(sha_index)
+                  .with_verify_after_write(verify_after_write)
+                  .with_temp_dir(temp_dir.clone()));
             }
 
             for asset in release.assets.iter() {
@@ -74,15 +268,16 @@ impl GitHubReleasesSource {
               let asset_url = format!("{}/releases/assets/{}", repo.url, asset.id);
 
               yield Ok(HttpFile::new(format!("{}/{}/{}", &repo.full_name, &release.tag_name, &asset.name), asset_url)
-                  .with_content_type(Some("application/octet-stream".to_string()))
-                  .with_credentials(match &policy.credentials {
-                    Credentials::Token(token) => Credentials::UsernamePassword {
-                      username: token.clone(),
-                      password: "".to_string(),
-                    },
-                    creds => creds.clone(),
-                  })
+                  .with_content_type(Some(accept.clone()))
+                  .with_credentials(Self::download_credentials(&policy.credentials))
                   .with_last_modified(Some(asset.updated_at))
+                  .with_min_bytes(min_bytes)
+                  .with_max_bytes(max_bytes)
+                  .with_sha_index(sha_index)
+                  .with_verify_after_write(verify_after_write)
+                  .with_temp_dir(temp_dir.clone())
+                  .with_ext_from_content_type(ext_from_content_type)
+                  .with_fingerprint(asset.fingerprint())
                   .with_metadata_source(repo)
                   .with_metadata_source(&release)
                   .with_metadata_source(asset));
@@ -97,7 +292,37 @@ impl BackupSource<HttpFile> for GitHubReleasesSource {
         GitHubArtifactKind::Release.as_str()
     }
 
+    fn is_exhaustive(&self, policy: &BackupPolicy) -> bool {
+        // Incremental backups only list the releases published since the last successful run.
+        self.since(policy).is_none()
+    }
+
     fn validate(&self, policy: &BackupPolicy) -> Result<(), crate::Error> {
+        policy.property::<u64>("min_bytes")?;
+        policy.property::<u64>("max_bytes")?;
+        policy.property::<bool>("since")?;
+        policy.property::<bool>("sha_index")?;
+        policy.property::<bool>("verify_after_write")?;
+        policy.property::<bool>("ext_from_content_type")?;
+        super::validate_temp_dir(policy)?;
+        Self::source_archives(policy)?;
+
+        if let Some(name) = policy.properties.get("source_archive_name") {
+            if name.trim().is_empty() || name.contains(['/', '\\']) {
+                return Err(errors::user(
+                    &format!("Your '{policy}' backup policy specifies the 'source_archive_name' property as '{name}', which is not a valid file name."),
+                    "Provide a file name (without an extension) for the 'source_archive_name' property, for example '{repo}-{tag}'.",
+                ));
+            }
+        }
+
+        if policy.properties.contains_key("sparse") {
+            return Err(errors::user(
+                &format!("Your '{policy}' backup policy specifies the 'sparse' property, but sparse backups are only supported for Git repositories."),
+                "Remove the 'sparse' property from this policy, or use a filter to restrict which release artifacts are backed up.",
+            ));
+        }
+
         let target: GitHubRepoSourceKind = policy.from.as_str().parse()?;
 
         match target {
@@ -132,24 +357,14 @@ impl BackupSource<HttpFile> for GitHubReleasesSource {
         cancel: &'a AtomicBool,
     ) -> impl Stream<Item = Result<HttpFile, crate::Error>> + 'a {
         let target: GitHubRepoSourceKind = policy.from.as_str().parse().unwrap();
-        let url = format!(
-            "{}/{}?{}",
-            policy
-                .properties
-                .get("api_url")
-                .unwrap_or(&"https://api.github.com".to_string())
-                .trim_end_matches('/'),
-            target.api_endpoint(GitHubArtifactKind::Release),
-            policy.properties.get("query").unwrap_or(&"".to_string())
-        )
-        .trim_end_matches('?')
-        .to_string();
+        let since = self.since(policy);
+        let url = Self::releases_url(policy, &target);
 
         async_stream::stream! {
           if matches!(target, GitHubRepoSourceKind::Repo(_)) {
             let repo: GitHubRepo = self.client.get(url, &policy.credentials, cancel).await?;
 
-            for await file in self.load_releases(policy, &repo, cancel) {
+            for await file in self.load_releases(policy, &repo, since, cancel) {
               yield file;
             }
           } else {
@@ -160,8 +375,12 @@ impl BackupSource<HttpFile> for GitHubReleasesSource {
               }
 
               let repo: GitHubRepo = repo.unwrap();
+              if !Self::is_pushed_since(&repo, since) {
+                tracing_batteries::prelude::debug!("Skipping the releases of {}, since it hasn't been pushed to since the last successful backup", repo.full_name);
+                continue;
+              }
 
-              for await file in self.load_releases(policy, &repo, cancel) {
+              for await file in self.load_releases(policy, &repo, since, cancel) {
                 yield file;
               }
             }
@@ -176,7 +395,10 @@ mod tests {
 
     use rstest::rstest;
 
-    use crate::{BackupPolicy, BackupSource};
+    use crate::{
+        helpers::github::{GitHubRelease, GitHubRepo},
+        BackupPolicy, BackupSource, Filterable,
+    };
 
     use super::GitHubReleasesSource;
 
@@ -213,6 +435,229 @@ mod tests {
         }
     }
 
+    #[rstest]
+    #[case("max_bytes: 1024", true)]
+    #[case("min_bytes: 0", true)]
+    #[case("max_bytes: -1", false)]
+    #[case("min_bytes: 1kb", false)]
+    #[case("sparse: docs/*", false)]
+    #[case("verify_after_write: true", true)]
+    #[case("verify_after_write: sometimes", false)]
+    #[case("source_archives: zip", true)]
+    #[case("source_archives: \"tar.gz, zip\"", true)]
+    #[case("source_archives: \"\"", true)]
+    #[case("source_archives: tarball", false)]
+    #[case("source_archive_name: \"{repo}-{tag}\"", true)]
+    #[case("source_archive_name: \"../{tag}\"", false)]
+    #[case("source_archive_name: \"\"", false)]
+    fn validation_size_limits(#[case] properties: &str, #[case] success: bool) {
+        let source = GitHubReleasesSource::default();
+
+        let policy = serde_yaml::from_str(&format!(
+            r#"
+        kind: github/release
+        from: users/notheotherben
+        to: /tmp
+        properties:
+          {}
+        "#,
+            properties
+        ))
+        .expect("parse policy");
+
+        if success {
+            source.validate(&policy).expect("validation to succeed");
+        } else {
+            source.validate(&policy).expect_err("validation to fail");
+        }
+    }
+
+    #[rstest]
+    #[case("", "https://api.github.com/users/notheotherben/repos")]
+    #[case(
+        "query: type=owner",
+        "https://api.github.com/users/notheotherben/repos?type=owner"
+    )]
+    fn releases_url(#[case] properties: &str, #[case] expected: &str) {
+        let policy: BackupPolicy = serde_yaml::from_str(&format!(
+            r#"
+        kind: github/release
+        from: users/notheotherben
+        to: /tmp
+        properties:
+          {}
+        "#,
+            properties
+        ))
+        .expect("parse policy");
+
+        let target = policy.from.parse().unwrap();
+
+        assert_eq!(
+            GitHubReleasesSource::releases_url(&policy, &target),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case(None, 31)]
+    #[case(Some("2020-01-01T00:00:00Z"), 18)]
+    #[case(Some("2024-07-10T00:00:00Z"), 2)]
+    fn pushed_since(#[case] since: Option<&str>, #[case] expected: usize) {
+        let repos: Vec<GitHubRepo> =
+            serde_json::from_str(include_str!("../../tests/data/github.repos.0.json"))
+                .expect("parse repos");
+        let since = since.map(|s| s.parse().unwrap());
+
+        assert_eq!(
+            repos
+                .iter()
+                .filter(|r| GitHubReleasesSource::is_pushed_since(r, since))
+                .count(),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case(None, 8)]
+    #[case(Some("2018-12-01T00:00:00Z"), 5)]
+    #[case(Some("2020-01-01T00:00:00Z"), 1)]
+    fn published_since(#[case] since: Option<&str>, #[case] expected: usize) {
+        let releases: Vec<GitHubRelease> =
+            serde_json::from_str(include_str!("../../tests/data/github.releases.1.json"))
+                .expect("parse releases");
+        let since = since.map(|s| s.parse().unwrap());
+
+        assert_eq!(
+            releases
+                .iter()
+                .filter(|r| GitHubReleasesSource::is_published_since(r, since))
+                .count(),
+            expected,
+            "unpublished releases should always be included"
+        );
+    }
+
+    #[tokio::test]
+    async fn since_uses_state() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let policy: BackupPolicy = serde_yaml::from_str(&format!(
+            r#"
+        kind: github/release
+        from: users/notheotherben
+        to: {}
+        "#,
+            temp_dir.path().display()
+        ))
+        .expect("parse policy");
+
+        let timestamp = chrono::Utc::now();
+        crate::state::State::update(&policy, |s| s.last_successful_run = Some(timestamp))
+            .await
+            .expect("state to be updated");
+
+        assert_eq!(GitHubReleasesSource::default().since(&policy), None);
+        assert_eq!(
+            GitHubReleasesSource::default()
+                .with_incremental(true)
+                .since(&policy),
+            Some(timestamp)
+        );
+    }
+
+    #[rstest]
+    #[case("", &["source.tar.gz"])]
+    #[case("source_archives: zip", &["source.zip"])]
+    #[case("source_archives: \"tar.gz,zip\"", &["source.tar.gz", "source.zip"])]
+    #[case("source_archives: \"\"", &[])]
+    #[case("{ source_archives: zip, source_archive_name: \"{repo}-{tag}\" }", &["{repo}-{tag}.zip"])]
+    #[tokio::test]
+    async fn source_archives(#[case] properties: &str, #[case] expected: &[&str]) {
+        use axum::{routing::get, Router};
+        use tokio_stream::StreamExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let app = Router::new().route(
+            "/releases",
+            get(|| async {
+                (
+                    [("Content-Type", "application/json")],
+                    include_str!("../../tests/data/github.releases.1.json"),
+                )
+            }),
+        );
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let mut repo: serde_json::Value = serde_json::from_str::<Vec<serde_json::Value>>(
+            include_str!("../../tests/data/github.repos.0.json"),
+        )
+        .unwrap()
+        .remove(0);
+        repo["url"] = format!("http://{addr}").into();
+        repo["has_downloads"] = true.into();
+        let repo: GitHubRepo = serde_json::from_value(repo).unwrap();
+
+        let releases: Vec<GitHubRelease> =
+            serde_json::from_str(include_str!("../../tests/data/github.releases.1.json")).unwrap();
+
+        let policy: BackupPolicy = serde_yaml::from_str(&format!(
+            r#"
+        kind: github/release
+        from: users/notheotherben
+        to: /tmp
+        properties:
+          {}
+        "#,
+            properties
+        ))
+        .expect("parse policy");
+
+        let source = GitHubReleasesSource::default();
+        let stream = source.load_releases(&policy, &repo, None, &CANCEL);
+        tokio::pin!(stream);
+
+        let mut files = Vec::new();
+        while let Some(file) = stream.next().await {
+            let file = file.expect("the release to be loaded");
+            if file.get("asset.source-code").is_truthy() {
+                files.push(file);
+            }
+        }
+
+        let mut found = files
+            .iter()
+            .map(|f| (f.name.clone(), f.url.clone()))
+            .collect::<Vec<_>>();
+        found.sort();
+
+        let mut wanted = releases
+            .iter()
+            .flat_map(|release| {
+                expected.iter().filter_map(|name| {
+                    let url = if name.ends_with(".zip") {
+                        release.zipball_url.clone()?
+                    } else {
+                        release.tarball_url.clone()?
+                    };
+
+                    let name = name
+                        .replace("{repo}", &repo.name)
+                        .replace("{tag}", &release.tag_name);
+                    Some((
+                        format!("{}/{}/{}", repo.full_name, release.tag_name, name),
+                        url,
+                    ))
+                })
+            })
+            .collect::<Vec<_>>();
+        wanted.sort();
+
+        assert!(!files.is_empty() || expected.is_empty());
+        assert_eq!(found, wanted);
+        assert!(files.iter().all(|f| f.last_modified.is_some()));
+    }
+
     #[rstest]
     #[case("users/notheotherben")]
     #[tokio::test]
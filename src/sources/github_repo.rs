@@ -1,23 +1,81 @@
-use std::sync::atomic::AtomicBool;
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    pin::Pin,
+    sync::{atomic::AtomicBool, Arc, Mutex},
+};
 
-use tokio_stream::Stream;
+use tokio_stream::{Stream, StreamExt};
 
 use crate::{
-    entities::GitRepo,
+    engines::{BackupStore, FileSystemStore},
+    entities::{Attachment, Credentials, GitRepo, GitRepoFormat},
     errors::{self},
     helpers::{
+        case_paths::CaseSafePaths,
+        checkpoint::{PageGuard, PaginationCheckpoint},
         github::GitHubRepo,
         github::{GitHubArtifactKind, GitHubRepoSourceKind},
-        GitHubClient,
+        refspecs, GitHubClient,
     },
     policy::BackupPolicy,
     BackupSource,
 };
 
+/// The name of the file, within a policy's `to` directory, in which pagination progress is recorded.
+const CHECKPOINT_FILE_NAME: &str = ".github-backup-checkpoint.json";
+
+/// The name of the file, alongside a repository's backup, in which its README is stored.
+const README_FILE_NAME: &str = "README.backup.md";
+
+/// The names of the files which a repository's labels and milestones are written to.
+const LABELS_FILE_NAME: &str = "labels.json";
+const MILESTONES_FILE_NAME: &str = "milestones.json";
+
+/// How often repositories are repacked when the `repack` property is set without a `repack_interval`.
+const DEFAULT_REPACK_INTERVAL: std::time::Duration =
+    std::time::Duration::from_secs(7 * 24 * 60 * 60);
+
+/// The number of listed repositories whose attachments and credentials may be loaded at once.
+const PREPARE_CONCURRENCY: usize = 4;
+
+/// The additional files which should be stored alongside each repository's backup.
+#[derive(Clone, Copy, Debug, Default)]
+struct AttachmentOptions {
+    settings: bool,
+    readme: bool,
+    labels: bool,
+    milestones: bool,
+}
+
+impl AttachmentOptions {
+    fn from_policy(policy: &BackupPolicy) -> Self {
+        let include = |property: &str| {
+            policy
+                .property::<bool>(property)
+                .ok()
+                .flatten()
+                .unwrap_or_default()
+        };
+
+        Self {
+            settings: include("include_settings"),
+            readme: include("include_readme"),
+            labels: include("include_labels"),
+            milestones: include("include_milestones"),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct GitHubRepoSource {
     client: GitHubClient,
     artifact_kind: GitHubArtifactKind,
+    store: Arc<dyn BackupStore>,
+    /// The policies (identified by their `to` directory and name) whose most recent listing was
+    /// resumed from a pagination checkpoint, and so didn't include the repositories on earlier pages.
+    resumed: Arc<Mutex<HashSet<(PathBuf, String)>>>,
+    dry_run: bool,
 }
 
 impl BackupSource<GitRepo> for GitHubRepoSource {
@@ -26,6 +84,63 @@ impl BackupSource<GitRepo> for GitHubRepoSource {
     }
 
     fn validate(&self, policy: &BackupPolicy) -> Result<(), crate::Error> {
+        policy.property::<bool>("include_settings")?;
+        policy.property::<bool>("include_readme")?;
+        policy.property::<bool>("include_labels")?;
+        policy.property::<bool>("include_milestones")?;
+        policy.property::<bool>("resumable")?;
+        policy.property::<bool>("case_safe_paths")?;
+        policy.property::<bool>("use_git_credentials")?;
+        policy.property::<bool>("backup_empty")?;
+        let repack = Self::repack(policy)?;
+        let mirror = policy.property::<bool>("mirror")?.unwrap_or_default();
+        let format = policy
+            .property::<GitRepoFormat>("format")?
+            .unwrap_or_default();
+        let worktree = policy.property::<bool>("worktree")?.unwrap_or_default();
+
+        if worktree && format == GitRepoFormat::Bundle {
+            return Err(errors::user(
+                "Your backup policy specifies the 'worktree' property, but bundles are stored as a single file which cannot include a working tree.",
+                "Remove either the 'format' or 'worktree' property from your backup policy.",
+            ));
+        }
+
+        if worktree && policy.properties.contains_key("sparse") {
+            return Err(errors::user(
+                "Your backup policy specifies both the 'worktree' and 'sparse' properties, but the working tree always contains every file in the repository.",
+                "Remove either the 'worktree' or 'sparse' property from your backup policy.",
+            ));
+        }
+
+        if repack.is_some() && format == GitRepoFormat::Bundle {
+            return Err(errors::user(
+                "Your backup policy specifies the 'repack' property, but bundles are written from a fresh clone and never need to be repacked.",
+                "Remove either the 'format' or 'repack' property from your backup policy.",
+            ));
+        }
+
+        if let Some(property) = ["refspecs", "branches"]
+            .into_iter()
+            .find(|p| policy.properties.contains_key(*p))
+        {
+            Self::refspecs(policy)?;
+
+            if mirror {
+                return Err(errors::user(
+                    &format!("Your backup policy specifies both the 'mirror' and '{property}' properties, but mirrors always fetch every ref."),
+                    &format!("Remove either the 'mirror' or '{property}' property from your backup policy."),
+                ));
+            }
+
+            if format == GitRepoFormat::Bundle {
+                return Err(errors::user(
+                    &format!("Your backup policy specifies the '{property}' property, but bundles always include every branch and tag."),
+                    &format!("Remove either the 'format' or '{property}' property from your backup policy."),
+                ));
+            }
+        }
+
         let target: GitHubRepoSourceKind = policy.from.as_str().parse()?;
 
         match target {
@@ -62,10 +177,43 @@ impl BackupSource<GitRepo> for GitHubRepoSource {
         }
     }
 
+    fn is_exhaustive(&self, policy: &BackupPolicy) -> bool {
+        !self
+            .resumed
+            .lock()
+            .unwrap()
+            .contains(&(policy.to.clone(), policy.to_string()))
+    }
+
     fn load<'a>(
         &'a self,
         policy: &'a BackupPolicy,
         cancel: &'a AtomicBool,
+    ) -> impl Stream<Item = Result<GitRepo, errors::Error>> + 'a {
+        self.list(policy, true, cancel)
+    }
+
+    fn load_all<'a>(
+        &'a self,
+        policy: &'a BackupPolicy,
+        cancel: &'a AtomicBool,
+    ) -> impl Stream<Item = Result<GitRepo, errors::Error>> + 'a {
+        self.list(policy, false, cancel)
+    }
+}
+
+/// A repository from a listing, along with the guard which holds its page's checkpoint until
+/// it has been backed up.
+type ListedRepo = (GitHubRepo, Option<PageGuard>);
+
+impl GitHubRepoSource {
+    /// Lists the repositories covered by the policy, resuming from the policy's pagination
+    /// checkpoint (when it is `resumable`) only if `resume` is set.
+    fn list<'a>(
+        &'a self,
+        policy: &'a BackupPolicy,
+        resume: bool,
+        cancel: &'a AtomicBool,
     ) -> impl Stream<Item = Result<GitRepo, errors::Error>> + 'a {
         let target: GitHubRepoSourceKind = policy.from.as_str().parse().unwrap();
         let url = format!(
@@ -83,55 +231,398 @@ impl BackupSource<GitRepo> for GitHubRepoSource {
 
         tracing_batteries::prelude::debug!("Calling {} to fetch repos", &url);
 
-        let refspecs = policy
-            .properties
-            .get("refspecs")
-            .map(|r| r.split(',').map(|r| r.to_string()).collect::<Vec<String>>());
+        let refspecs = Self::refspecs(policy);
+        if let Ok(Some(refspecs)) = &refspecs {
+            tracing_batteries::prelude::debug!("Fetching {} for {}", refspecs.join(", "), policy);
+        }
+
+        let sparse = policy.properties.get("sparse").map(|r| {
+            r.split(',')
+                .map(|r| r.trim().to_string())
+                .collect::<Vec<String>>()
+        });
+
+        let mirror = policy
+            .property::<bool>("mirror")
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+
+        let format = policy
+            .property::<GitRepoFormat>("format")
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+
+        let worktree = policy
+            .property::<bool>("worktree")
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+
+        let repack = Self::repack(policy).ok().flatten();
+
+        // When the user already has git configured to authenticate with GitHub (for example using a
+        // credential helper), we leave it to gix to resolve credentials rather than providing our own.
+        let git_credentials = if policy
+            .property::<bool>("use_git_credentials")
+            .ok()
+            .flatten()
+            .unwrap_or_default()
+        {
+            Credentials::None
+        } else {
+            policy.credentials.clone()
+        };
+
+        let include = AttachmentOptions::from_policy(policy);
+
+        let case_safe_paths = policy
+            .property::<bool>("case_safe_paths")
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+
+        let checkpoint = policy
+            .property::<bool>("resumable")
+            .ok()
+            .flatten()
+            .unwrap_or_default()
+            .then(|| {
+                PaginationCheckpoint::new(policy.to.join(CHECKPOINT_FILE_NAME))
+                    .with_read_only(self.dry_run)
+            })
+            .filter(|_| resume);
 
         async_stream::try_stream! {
+          let refspecs = refspecs?;
+
+          // A resumed listing skips the repositories on the pages before its checkpoint, so it can't
+          // be used to determine which repositories no longer exist.
+          let resumed = match &checkpoint {
+            Some(checkpoint) => checkpoint.get(&url).await?.is_some(),
+            None => false,
+          };
+          {
+            let mut resumed_policies = self.resumed.lock().unwrap();
+            let key = (policy.to.clone(), policy.to_string());
+            if resumed {
+              resumed_policies.insert(key);
+            } else {
+              resumed_policies.remove(&key);
+            }
+          }
+
+          let entity = |repo: GitHubRepo, page_guard: Option<PageGuard>| {
+            let refspecs = refspecs.clone();
+            let sparse = sparse.clone();
+            let git_credentials = &git_credentials;
+
+            async move {
+              let entity = GitRepo::new(
+                repo.full_name.as_str(),
+                repo.clone_url.as_str(),
+                refspecs)
+                  .with_sparse(sparse)
+                  .with_mirror(mirror)
+                  .with_format(format)
+                  .with_worktree(worktree)
+                  .with_repack(repack)
+                  .with_artifact_kind(Some(self.artifact_kind.as_str().to_string()))
+                  .with_fingerprint(repo.fingerprint())
+                  .with_metadata_source(&repo)
+                  .with_metadata("repo.is_fork_of_backed_up", self.is_fork_of_backed_up(&repo, policy, format).await)
+                  .with_page_guard(page_guard);
+
+              let entity = self.prepare(entity, &repo, policy, include, git_credentials, cancel).await?;
+              Ok::<_, errors::Error>((repo, entity))
+            }
+          };
+
           if matches!(target, GitHubRepoSourceKind::Repo(_)) {
             let repo = self.client.get::<GitHubRepo>(url, &policy.credentials, cancel).await?;
-            yield GitRepo::new(
-              repo.full_name.as_str(),
-              repo.clone_url.as_str(),
-              refspecs.clone())
-                .with_credentials(policy.credentials.clone())
-                .with_metadata_source(&repo);
+            let (_, entity) = entity(repo, None).await?;
+            yield entity;
           } else {
-            for await repo in self.client.get_paginated::<GitHubRepo>(url, &policy.credentials, cancel) {
-              let repo = repo?;
-              yield GitRepo::new(
-                repo.full_name.as_str(),
-                repo.clone_url.as_str(),
-                refspecs.clone())
-                  .with_credentials(policy.credentials.clone())
-                  .with_metadata_source(&repo);
+            let repos: Pin<Box<dyn Stream<Item = Result<ListedRepo, errors::Error>> + Send + '_>> = match &checkpoint {
+              Some(checkpoint) => Box::pin(self.client.get_paginated_resumable(url, checkpoint, &policy.credentials, cancel)),
+              None => Box::pin(self.client.get_paginated(url, &policy.credentials, cancel).map(|repo| repo.map(|repo| (repo, None)))),
+            };
+
+            let mut paths = CaseSafePaths::new(policy, case_safe_paths)?.with_read_only(self.dry_run);
+
+            // Only the repositories which will actually be backed up need their attachments and
+            // credentials to be loaded, which we do for several repositories at a time.
+            let entities = futures::StreamExt::buffered(
+              repos.map(|repo| async {
+                let (repo, page_guard) = repo?;
+                entity(repo, page_guard).await
+              }),
+              PREPARE_CONCURRENCY,
+            );
+
+            for await entity in entities {
+              let (repo, entity) = entity?;
+              yield match paths.resolve(&repo.full_name).await? {
+                Some(path) => entity.with_path(path),
+                None => entity,
+              };
             }
           }
         }
     }
-}
 
-impl GitHubRepoSource {
-    #[allow(dead_code)]
+    /// Determines the refspecs which should be fetched for each repository, based on either the
+    /// `refspecs` or `branches` property of the backup policy.
+    fn refspecs(policy: &BackupPolicy) -> Result<Option<Vec<String>>, errors::Error> {
+        match (
+            policy.properties.get("refspecs"),
+            policy.properties.get("branches"),
+        ) {
+            (Some(_), Some(_)) => Err(errors::user(
+                "Your backup policy specifies both the 'refspecs' and 'branches' properties, but only one of them may be used.",
+                "Remove either the 'refspecs' or 'branches' property from your backup policy.",
+            )),
+            (Some(refspecs), None) => refspecs::parse(refspecs).map(Some),
+            (None, Some(branches)) => refspecs::from_branches(branches).map(Some),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Determines how often each repository should be repacked, or `None` if the policy has not
+    /// enabled the `repack` property.
+    fn repack(policy: &BackupPolicy) -> Result<Option<std::time::Duration>, errors::Error> {
+        if !policy.property::<bool>("repack")?.unwrap_or_default() {
+            return Ok(None);
+        }
+
+        match policy.properties.get("repack_interval") {
+            Some(interval) => crate::config::parse_duration(interval).map(Some).map_err(|e| {
+                errors::user(
+                    &format!("The 'repack_interval' property on your '{policy}' backup policy has an invalid value: {e}"),
+                    "Make sure that you have provided a valid duration (like '7d' or '12h') for the 'repack_interval' property in your backup policy.",
+                )
+            }),
+            None => Ok(Some(DEFAULT_REPACK_INTERVAL)),
+        }
+    }
+
+    /// Determines whether the repository that a fork was created from has already been backed up
+    /// to the policy's `to` directory, or `None` if the fork's parent is not known.
+    async fn is_fork_of_backed_up(
+        &self,
+        repo: &GitHubRepo,
+        policy: &BackupPolicy,
+        format: GitRepoFormat,
+    ) -> Option<bool> {
+        let parent = repo.parent.as_ref()?;
+        let mut path = policy.to.join(&parent.full_name).into_os_string();
+        if format == GitRepoFormat::Bundle {
+            path.push(".bundle");
+        }
+
+        Some(self.store.exists(std::path::Path::new(&path)).await)
+    }
+
+    /// Loads the attachments and git credentials which are needed to back up the repository. When
+    /// the repository will be skipped anyway (because this is a dry run, or it doesn't match the
+    /// policy's filter) we avoid spending the rate limit on them.
+    async fn prepare(
+        &self,
+        entity: GitRepo,
+        repo: &GitHubRepo,
+        policy: &BackupPolicy,
+        include: AttachmentOptions,
+        git_credentials: &Credentials,
+        cancel: &AtomicBool,
+    ) -> Result<GitRepo, errors::Error> {
+        // The backup.* properties are only added once the engine has inspected the existing
+        // backup, so a filter which refers to them can't be evaluated here.
+        let inspects_backups = policy
+            .filter
+            .properties()
+            .iter()
+            .any(|p| p.to_ascii_lowercase().starts_with("backup."));
+        if self.dry_run
+            || (!inspects_backups && matches!(policy.filter.matches(&entity), Ok(false)))
+        {
+            return Ok(entity);
+        }
+
+        let (attachments, credentials) = tokio::try_join!(
+            self.load_attachments(repo, include, &policy.credentials, cancel),
+            self.client
+                .resolve_credentials(&repo.url, git_credentials, cancel),
+        )?;
+
+        Ok(entity
+            .with_attachments(attachments)
+            .with_credentials(credentials))
+    }
+
+    /// Loads the additional files which should be stored alongside the repository's backup.
+    async fn load_attachments(
+        &self,
+        repo: &GitHubRepo,
+        include: AttachmentOptions,
+        creds: &Credentials,
+        cancel: &AtomicBool,
+    ) -> Result<Vec<Attachment>, errors::Error> {
+        let (settings, readme, labels, milestones) = tokio::try_join!(
+            async {
+                match include.settings {
+                    true => self.load_settings(repo, creds, cancel).await,
+                    false => Ok(Vec::new()),
+                }
+            },
+            async {
+                match include.readme {
+                    true => self.load_readme(repo, creds, cancel).await,
+                    false => Ok(None),
+                }
+            },
+            async {
+                match include.labels {
+                    true => self
+                        .load_listing(
+                            LABELS_FILE_NAME,
+                            format!("{}/labels", repo.url),
+                            creds,
+                            cancel,
+                        )
+                        .await
+                        .map(Some),
+                    false => Ok(None),
+                }
+            },
+            async {
+                match include.milestones {
+                    true => self
+                        .load_listing(
+                            MILESTONES_FILE_NAME,
+                            format!("{}/milestones?state=all", repo.url),
+                            creds,
+                            cancel,
+                        )
+                        .await
+                        .map(Some),
+                    false => Ok(None),
+                }
+            },
+        )?;
+
+        Ok(settings
+            .into_iter()
+            .chain(readme)
+            .chain(labels)
+            .chain(milestones)
+            .collect())
+    }
+
+    /// Fetches every page of a listing (like the repository's labels), storing the items in a
+    /// single JSON array so that they can be restored later. Empty listings are stored as `[]`.
+    async fn load_listing(
+        &self,
+        name: &str,
+        url: String,
+        creds: &Credentials,
+        cancel: &AtomicBool,
+    ) -> Result<Attachment, errors::Error> {
+        let items = self
+            .client
+            .get_paginated::<serde_json::Value>(url, creds, cancel)
+            .collect::<Result<Vec<_>, _>>()
+            .await?;
+
+        Attachment::json(name, &items)
+    }
+
+    /// Fetches the raw content of the repository's README, returning `None` if the repository
+    /// does not have one.
+    async fn load_readme(
+        &self,
+        repo: &GitHubRepo,
+        creds: &Credentials,
+        cancel: &AtomicBool,
+    ) -> Result<Option<Attachment>, errors::Error> {
+        let readme = self
+            .client
+            .get_raw_optional(
+                format!("{}/readme", repo.url),
+                "application/vnd.github.raw+json",
+                creds,
+                cancel,
+            )
+            .await?;
+
+        Ok(readme.map(|content| Attachment {
+            name: README_FILE_NAME.to_string(),
+            content,
+        }))
+    }
+
+    /// Fetches the repository's full settings and the protection rules for its default
+    /// branch, so that they can be stored alongside the repository's backup.
+    async fn load_settings(
+        &self,
+        repo: &GitHubRepo,
+        creds: &Credentials,
+        cancel: &AtomicBool,
+    ) -> Result<Vec<Attachment>, errors::Error> {
+        let settings: serde_json::Value = self.client.get(repo.url.clone(), creds, cancel).await?;
+
+        // Repositories without branch protection return a 404, which we record as `null`
+        // so that any previously backed up protection rules are cleared.
+        let protection: Option<serde_json::Value> = self
+            .client
+            .get_optional(
+                format!("{}/branches/{}/protection", repo.url, repo.default_branch),
+                creds,
+                cancel,
+            )
+            .await?;
+
+        Ok(vec![
+            Attachment::json("settings.json", &settings)?,
+            Attachment::json("protection.json", &protection)?,
+        ])
+    }
+
     pub fn with_client(client: GitHubClient, kind: GitHubArtifactKind) -> Self {
         GitHubRepoSource {
             client,
             artifact_kind: kind,
+            store: Arc::new(FileSystemStore),
+            resumed: Default::default(),
+            dry_run: false,
         }
     }
 
+    /// Resumes interrupted listings without recording any progress through them, so that a dry
+    /// run doesn't affect where the next real backup resumes from.
+    pub fn with_dry_run(self, dry_run: bool) -> Self {
+        Self { dry_run, ..self }
+    }
+
+    #[allow(dead_code)]
     pub fn repo() -> Self {
         GitHubRepoSource {
             client: GitHubClient::default(),
             artifact_kind: GitHubArtifactKind::Repo,
+            store: Arc::new(FileSystemStore),
+            resumed: Default::default(),
+            dry_run: false,
         }
     }
 
+    #[allow(dead_code)]
     pub fn star() -> Self {
         GitHubRepoSource {
             client: GitHubClient::default(),
             artifact_kind: GitHubArtifactKind::Star,
+            store: Arc::new(FileSystemStore),
+            resumed: Default::default(),
+            dry_run: false,
         }
     }
 }
@@ -142,9 +633,13 @@ mod tests {
 
     use rstest::rstest;
 
-    use crate::{helpers::github::GitHubArtifactKind, BackupPolicy, BackupSource};
+    use crate::{
+        entities::{Attachment, Credentials},
+        helpers::{checkpoint::PaginationCheckpoint, github::GitHubArtifactKind},
+        BackupPolicy, BackupSource, FilterValue,
+    };
 
-    use super::GitHubRepoSource;
+    use super::{GitHubRepoSource, CHECKPOINT_FILE_NAME};
 
     static CANCEL: AtomicBool = AtomicBool::new(false);
 
@@ -215,6 +710,658 @@ mod tests {
         }
     }
 
+    #[rstest]
+    #[case("include_settings: true", true)]
+    #[case("include_settings: false", true)]
+    #[case("include_settings: maybe", false)]
+    #[case("include_labels: true", true)]
+    #[case("include_labels: maybe", false)]
+    #[case("include_milestones: true", true)]
+    #[case("include_milestones: maybe", false)]
+    fn validation_include_settings(#[case] properties: &str, #[case] success: bool) {
+        let source = GitHubRepoSource::repo();
+
+        let policy = serde_yaml::from_str(&format!(
+            r#"
+            kind: github/repo
+            from: user
+            to: /tmp
+            properties:
+              {}
+            "#,
+            properties
+        ))
+        .expect("parse policy");
+
+        if success {
+            source.validate(&policy).expect("validation to succeed");
+        } else {
+            source.validate(&policy).expect_err("validation to fail");
+        }
+    }
+
+    #[rstest]
+    #[case("mirror: true", true)]
+    #[case("mirror: false", true)]
+    #[case("mirror: sometimes", false)]
+    #[case("{ mirror: true, refspecs: tags-only }", false)]
+    #[case("{ mirror: false, refspecs: tags-only }", true)]
+    #[case("worktree: true", true)]
+    #[case("worktree: sometimes", false)]
+    #[case("{ worktree: true, mirror: true }", true)]
+    #[case("{ worktree: true, format: bundle }", false)]
+    #[case("{ worktree: true, sparse: docs/ }", false)]
+    #[case("{ worktree: false, sparse: docs/ }", true)]
+    #[case("format: repository", true)]
+    #[case("format: bundle", true)]
+    #[case("format: tarball", false)]
+    #[case("{ format: bundle, mirror: true }", true)]
+    #[case("{ format: bundle, refspecs: tags-only }", false)]
+    #[case("repack: true", true)]
+    #[case("repack: sometimes", false)]
+    #[case("{ repack: true, repack_interval: 1d12h }", true)]
+    #[case("{ repack: true, repack_interval: weekly }", false)]
+    #[case("{ repack: false, repack_interval: weekly }", true)]
+    #[case("{ repack: true, mirror: true }", true)]
+    #[case("{ repack: true, format: bundle }", false)]
+    fn validation_mirror_and_format(#[case] properties: &str, #[case] success: bool) {
+        let source = GitHubRepoSource::repo();
+
+        let policy = serde_yaml::from_str(&format!(
+            r#"
+            kind: github/repo
+            from: user
+            to: /tmp
+            properties:
+              {}
+            "#,
+            properties
+        ))
+        .expect("parse policy");
+
+        if success {
+            source.validate(&policy).expect("validation to succeed");
+        } else {
+            source.validate(&policy).expect_err("validation to fail");
+        }
+    }
+
+    #[rstest]
+    #[case("refspecs: tags-only", true)]
+    #[case("refspecs: \"+refs/heads/main:refs/remotes/origin/main\"", true)]
+    #[case("refspecs: \"+refs/heads/main:refs/remotes/origin/main,\"", false)]
+    #[case("refspecs: \"refs/heads/my branch\"", false)]
+    #[case("branches: main", true)]
+    #[case("branches: \"main, release/*\"", true)]
+    #[case("branches: \"release/*/*\"", false)]
+    #[case("{ branches: main, refspecs: tags-only }", false)]
+    #[case("{ branches: main, mirror: true }", false)]
+    #[case("{ branches: main, format: bundle }", false)]
+    fn validation_refspecs(#[case] properties: &str, #[case] success: bool) {
+        let source = GitHubRepoSource::repo();
+
+        let policy = serde_yaml::from_str(&format!(
+            r#"
+            kind: github/repo
+            from: user
+            to: /tmp
+            properties:
+              {}
+            "#,
+            properties
+        ))
+        .expect("parse policy");
+
+        if success {
+            source.validate(&policy).expect("validation to succeed");
+        } else {
+            source.validate(&policy).expect_err("validation to fail");
+        }
+    }
+
+    #[rstest]
+    #[case("octocat/protected", Some(serde_json::json!({ "enforce_admins": { "enabled": true } })))]
+    #[case("octocat/unprotected", None)]
+    #[tokio::test]
+    async fn get_repo_settings(#[case] name: &str, #[case] protection: Option<serde_json::Value>) {
+        use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+        use tokio_stream::StreamExt;
+
+        #[derive(Clone)]
+        struct MockState {
+            repo: serde_json::Value,
+            protection: Option<serde_json::Value>,
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let api_url = format!("http://{}", listener.local_addr().unwrap());
+
+        let mut repo: serde_json::Value = serde_json::from_str::<Vec<serde_json::Value>>(
+            include_str!("../../tests/data/github.repos.0.json"),
+        )
+        .unwrap()
+        .remove(0);
+        repo["full_name"] = name.into();
+        repo["url"] = format!("{api_url}/repos/{name}").into();
+        repo["default_branch"] = "main".into();
+
+        let app = Router::new()
+            .route(
+                &format!("/repos/{name}"),
+                get(|State(state): State<MockState>| async move { Json(state.repo) }),
+            )
+            .route(
+                &format!("/repos/{name}/branches/main/protection"),
+                get(|State(state): State<MockState>| async move {
+                    match state.protection {
+                        Some(protection) => Ok(Json(protection)),
+                        None => Err(StatusCode::NOT_FOUND),
+                    }
+                }),
+            )
+            .with_state(MockState {
+                repo: repo.clone(),
+                protection: protection.clone(),
+            });
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let policy: BackupPolicy = serde_yaml::from_str(&format!(
+            r#"
+          kind: github/repo
+          from: repos/{name}
+          to: /tmp
+          properties:
+            api_url: {api_url}
+            include_settings: true
+        "#
+        ))
+        .unwrap();
+
+        let source = GitHubRepoSource::repo();
+        let stream = source.load(&policy, &CANCEL);
+        tokio::pin!(stream);
+
+        let entity = stream
+            .next()
+            .await
+            .expect("a repository to be returned")
+            .expect("the repository to be loaded");
+
+        assert_eq!(entity.attachments.len(), 2);
+        assert_eq!(entity.attachments[0].name, "settings.json");
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&entity.attachments[0].content).unwrap(),
+            repo
+        );
+        assert_eq!(entity.attachments[1].name, "protection.json");
+        assert_eq!(
+            serde_json::from_slice::<Option<serde_json::Value>>(&entity.attachments[1].content)
+                .unwrap(),
+            protection
+        );
+    }
+
+    #[rstest]
+    #[case(None, Credentials::token("token"))]
+    #[case(Some(false), Credentials::token("token"))]
+    #[case(Some(true), Credentials::None)]
+    #[tokio::test]
+    async fn use_git_credentials(#[case] property: Option<bool>, #[case] expected: Credentials) {
+        use axum::{routing::get, Json, Router};
+        use tokio_stream::StreamExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let api_url = format!("http://{}", listener.local_addr().unwrap());
+
+        let repo: serde_json::Value = serde_json::from_str::<Vec<serde_json::Value>>(include_str!(
+            "../../tests/data/github.repos.0.json"
+        ))
+        .unwrap()
+        .remove(0);
+
+        let app = Router::new().route(
+            "/repos/octocat/hello-world",
+            get(move || async move { Json(repo) }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let policy: BackupPolicy = serde_yaml::from_str(&format!(
+            r#"
+          kind: github/repo
+          from: repos/octocat/hello-world
+          to: /tmp
+          credentials: !Token token
+          properties:
+            api_url: {api_url}
+            {}
+        "#,
+            property
+                .map(|v| format!("use_git_credentials: {v}"))
+                .unwrap_or_default()
+        ))
+        .unwrap();
+
+        let source = GitHubRepoSource::repo();
+        let stream = source.load(&policy, &CANCEL);
+        tokio::pin!(stream);
+
+        let entity = stream
+            .next()
+            .await
+            .expect("a repository to be returned")
+            .expect("the repository to be loaded");
+
+        assert!(
+            entity.credentials == expected,
+            "the repository should be cloned with the expected credentials"
+        );
+    }
+
+    #[rstest]
+    #[case(None, false, FilterValue::Null)]
+    #[case(Some("upstream/thing"), false, FilterValue::Bool(false))]
+    #[case(Some("upstream/thing"), true, FilterValue::Bool(true))]
+    #[tokio::test]
+    async fn is_fork_of_backed_up(
+        #[case] parent: Option<&'static str>,
+        #[case] backed_up: bool,
+        #[case] expected: FilterValue,
+    ) {
+        use axum::{routing::get, Json, Router};
+        use tokio_stream::StreamExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let api_url = format!("http://{}", listener.local_addr().unwrap());
+
+        let mut repo: serde_json::Value = serde_json::from_str::<Vec<serde_json::Value>>(
+            include_str!("../../tests/data/github.repos.0.json"),
+        )
+        .unwrap()
+        .remove(0);
+        if let Some(parent) = parent {
+            repo["fork"] = true.into();
+            repo["parent"] = serde_json::json!({ "id": 1, "full_name": parent });
+            repo["source"] = serde_json::json!({ "id": 1, "full_name": parent });
+        }
+
+        let app = Router::new().route(
+            "/repos/octocat/hello-world",
+            get(move || async move { Json(repo) }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        if backed_up {
+            std::fs::create_dir_all(temp_dir.path().join("upstream").join("thing")).unwrap();
+        }
+
+        let policy: BackupPolicy = serde_yaml::from_str(&format!(
+            r#"
+          kind: github/repo
+          from: repos/octocat/hello-world
+          to: {}
+          properties:
+            api_url: {api_url}
+        "#,
+            temp_dir.path().display()
+        ))
+        .unwrap();
+
+        let source = GitHubRepoSource::repo();
+        let stream = source.load(&policy, &CANCEL);
+        tokio::pin!(stream);
+
+        let entity = stream
+            .next()
+            .await
+            .expect("a repository to be returned")
+            .expect("the repository to be loaded");
+
+        assert_eq!(entity.metadata.get("repo.parent"), parent.into());
+        assert_eq!(entity.metadata.get("repo.is_fork_of_backed_up"), expected);
+    }
+
+    #[tokio::test]
+    async fn resumed_listings_are_not_exhaustive() {
+        use axum::{routing::get, Json, Router};
+        use tokio_stream::StreamExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let api_url = format!("http://{}", listener.local_addr().unwrap());
+
+        let repos: Vec<serde_json::Value> =
+            serde_json::from_str(include_str!("../../tests/data/github.repos.0.json")).unwrap();
+        let app = Router::new().route(
+            "/users/octocat/repos",
+            get(move || async move { Json(repos) }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let policy: BackupPolicy = serde_yaml::from_str(&format!(
+            r#"
+          kind: github/repo
+          from: users/octocat
+          to: {}
+          properties:
+            api_url: {api_url}
+            resumable: true
+        "#,
+            temp_dir.path().display()
+        ))
+        .unwrap();
+
+        let checkpoint = PaginationCheckpoint::new(temp_dir.path().join(CHECKPOINT_FILE_NAME));
+        let base_url = format!("{api_url}/users/octocat/repos");
+        checkpoint
+            .set(&base_url, &format!("{base_url}?page=2"))
+            .await
+            .unwrap();
+
+        let source = GitHubRepoSource::repo();
+        source
+            .load_all(&policy, &CANCEL)
+            .collect::<Result<Vec<_>, _>>()
+            .await
+            .expect("the repositories to be listed");
+        assert!(source.is_exhaustive(&policy));
+        assert!(
+            checkpoint.get(&base_url).await.unwrap().is_some(),
+            "a full listing should leave the checkpoint alone"
+        );
+
+        source
+            .load(&policy, &CANCEL)
+            .collect::<Result<Vec<_>, _>>()
+            .await
+            .expect("the repositories to be listed");
+        assert!(
+            !source.is_exhaustive(&policy),
+            "a resumed listing doesn't include the repositories on earlier pages"
+        );
+
+        source
+            .load(&policy, &CANCEL)
+            .collect::<Result<Vec<_>, _>>()
+            .await
+            .expect("the repositories to be listed");
+        assert!(
+            source.is_exhaustive(&policy),
+            "the next listing starts from the first page"
+        );
+    }
+
+    #[rstest]
+    #[case(true)]
+    #[case(false)]
+    #[tokio::test]
+    async fn include_readme(#[case] has_readme: bool) {
+        use axum::{http::HeaderMap, http::StatusCode, routing::get, Json, Router};
+        use tokio_stream::StreamExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let api_url = format!("http://{}", listener.local_addr().unwrap());
+
+        let mut repo: serde_json::Value = serde_json::from_str::<Vec<serde_json::Value>>(
+            include_str!("../../tests/data/github.repos.0.json"),
+        )
+        .unwrap()
+        .remove(0);
+        repo["url"] = format!("{api_url}/repos/octocat/hello-world").into();
+
+        let app = Router::new()
+            .route(
+                "/repos/octocat/hello-world",
+                get(move || async move { Json(repo) }),
+            )
+            .route(
+                "/repos/octocat/hello-world/readme",
+                get(move |headers: HeaderMap| async move {
+                    assert_eq!(
+                        headers.get_all("accept").iter().collect::<Vec<_>>(),
+                        vec!["application/vnd.github.raw+json"],
+                        "the README should be requested as raw content"
+                    );
+
+                    if has_readme {
+                        (StatusCode::OK, "# Hello World\n")
+                    } else {
+                        (StatusCode::NOT_FOUND, "")
+                    }
+                }),
+            );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let policy: BackupPolicy = serde_yaml::from_str(&format!(
+            r#"
+          kind: github/repo
+          from: repos/octocat/hello-world
+          to: /tmp
+          properties:
+            api_url: {api_url}
+            include_readme: true
+        "#
+        ))
+        .unwrap();
+
+        let source = GitHubRepoSource::repo();
+        let stream = source.load(&policy, &CANCEL);
+        tokio::pin!(stream);
+
+        let entity = stream
+            .next()
+            .await
+            .expect("a repository to be returned")
+            .expect("the repository to be loaded");
+
+        if has_readme {
+            assert_eq!(
+                entity.attachments,
+                vec![Attachment {
+                    name: "README.backup.md".to_string(),
+                    content: b"# Hello World\n".to_vec(),
+                }]
+            );
+        } else {
+            assert!(entity.attachments.is_empty());
+        }
+    }
+
+    #[rstest]
+    #[case("true", false, 1)]
+    #[case("repo.name == \"someone-else\"", false, 0)]
+    #[case("backup.exists || true", false, 1)]
+    #[case("true", true, 0)]
+    #[tokio::test]
+    async fn attachments_are_only_loaded_for_backed_up_repos(
+        #[case] filter: &str,
+        #[case] dry_run: bool,
+        #[case] expected_requests: usize,
+    ) {
+        use axum::{routing::get, Json, Router};
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+        use tokio_stream::StreamExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let api_url = format!("http://{}", listener.local_addr().unwrap());
+
+        let mut repo: serde_json::Value = serde_json::from_str::<Vec<serde_json::Value>>(
+            include_str!("../../tests/data/github.repos.0.json"),
+        )
+        .unwrap()
+        .remove(0);
+        repo["url"] = format!("{api_url}/repos/octocat/hello-world").into();
+
+        let requests = Arc::new(AtomicUsize::new(0));
+        let readme_requests = requests.clone();
+        let app = Router::new()
+            .route(
+                "/repos/octocat/hello-world",
+                get(move || async move { Json(repo) }),
+            )
+            .route(
+                "/repos/octocat/hello-world/readme",
+                get(move || async move {
+                    readme_requests.fetch_add(1, Ordering::Relaxed);
+                    "# Hello World\n"
+                }),
+            );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let policy: BackupPolicy = serde_yaml::from_str(&format!(
+            r#"
+          kind: github/repo
+          from: repos/octocat/hello-world
+          to: /tmp
+          filter: '{filter}'
+          properties:
+            api_url: {api_url}
+            include_readme: true
+        "#
+        ))
+        .unwrap();
+
+        let source = GitHubRepoSource::repo().with_dry_run(dry_run);
+        let entities = source
+            .load(&policy, &CANCEL)
+            .collect::<Result<Vec<_>, _>>()
+            .await
+            .expect("the repository to be loaded");
+
+        assert_eq!(entities.len(), 1, "filtered repositories are still listed");
+        assert_eq!(requests.load(Ordering::Relaxed), expected_requests);
+    }
+
+    #[rstest]
+    #[case(true)]
+    #[case(false)]
+    #[tokio::test]
+    async fn include_labels_and_milestones(#[case] has_items: bool) {
+        use axum::{
+            extract::RawQuery, http::StatusCode, response::IntoResponse, routing::get, Json, Router,
+        };
+        use tokio_stream::StreamExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let api_url = format!("http://{}", listener.local_addr().unwrap());
+
+        let mut repo: serde_json::Value = serde_json::from_str::<Vec<serde_json::Value>>(
+            include_str!("../../tests/data/github.repos.0.json"),
+        )
+        .unwrap()
+        .remove(0);
+        repo["url"] = format!("{api_url}/repos/octocat/hello-world").into();
+
+        let labels_url = format!("{api_url}/repos/octocat/hello-world/labels");
+        let app = Router::new()
+            .route(
+                "/repos/octocat/hello-world",
+                get(move || async move { Json(repo) }),
+            )
+            .route(
+                "/repos/octocat/hello-world/labels",
+                get(move |RawQuery(query): RawQuery| async move {
+                    // Labels are split across two pages, to ensure that every page is included.
+                    match (has_items, query.as_deref()) {
+                        (false, _) => Json(serde_json::json!([])).into_response(),
+                        (true, None) => (
+                            [("link", format!("<{labels_url}?page=2>; rel=\"next\""))],
+                            Json(serde_json::json!([{ "name": "bug", "color": "d73a4a" }])),
+                        )
+                            .into_response(),
+                        (true, Some(_)) => {
+                            Json(serde_json::json!([{ "name": "enhancement", "color": "a2eeef" }]))
+                                .into_response()
+                        }
+                    }
+                }),
+            )
+            .route(
+                "/repos/octocat/hello-world/milestones",
+                get(move |RawQuery(query): RawQuery| async move {
+                    if query.as_deref() != Some("state=all") {
+                        return StatusCode::BAD_REQUEST.into_response();
+                    }
+
+                    match has_items {
+                        true => Json(serde_json::json!([
+                            { "number": 1, "title": "v1.0", "state": "closed" },
+                            { "number": 2, "title": "v2.0", "state": "open" }
+                        ]))
+                        .into_response(),
+                        false => Json(serde_json::json!([])).into_response(),
+                    }
+                }),
+            );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let policy: BackupPolicy = serde_yaml::from_str(&format!(
+            r#"
+          kind: github/repo
+          from: repos/octocat/hello-world
+          to: /tmp
+          properties:
+            api_url: {api_url}
+            include_labels: true
+            include_milestones: true
+        "#
+        ))
+        .unwrap();
+
+        let source = GitHubRepoSource::repo();
+        let stream = source.load(&policy, &CANCEL);
+        tokio::pin!(stream);
+
+        let entity = stream
+            .next()
+            .await
+            .expect("a repository to be returned")
+            .expect("the repository to be loaded");
+
+        assert_eq!(entity.attachments.len(), 2);
+        assert_eq!(entity.attachments[0].name, "labels.json");
+        assert_eq!(entity.attachments[1].name, "milestones.json");
+
+        let titles = |content: &[u8], field: &str| {
+            serde_json::from_slice::<Vec<serde_json::Value>>(content)
+                .expect("the attachment to be a JSON array")
+                .iter()
+                .map(|item| item[field].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        if has_items {
+            assert_eq!(
+                titles(&entity.attachments[0].content, "name"),
+                vec!["bug", "enhancement"]
+            );
+            assert_eq!(
+                titles(&entity.attachments[1].content, "title"),
+                vec!["v1.0", "v2.0"]
+            );
+        } else {
+            assert!(titles(&entity.attachments[0].content, "name").is_empty());
+            assert!(titles(&entity.attachments[1].content, "title").is_empty());
+        }
+    }
+
     #[rstest]
     #[case("users/notheotherben")]
     #[tokio::test]
@@ -1,12 +1,16 @@
+mod github_pages;
 mod github_releases;
 mod github_repo;
+mod static_http;
 
+pub use github_pages::GitHubPagesSource;
 pub use github_releases::GitHubReleasesSource;
 pub use github_repo::GitHubRepoSource;
+pub use static_http::StaticHttpSource;
 use tokio_stream::Stream;
 
-use crate::{BackupEntity, BackupPolicy};
-use std::sync::atomic::AtomicBool;
+use crate::{errors, BackupEntity, BackupPolicy};
+use std::{path::Path, sync::atomic::AtomicBool};
 
 pub trait BackupSource<T: BackupEntity> {
     fn kind(&self) -> &str;
@@ -16,4 +20,100 @@ pub trait BackupSource<T: BackupEntity> {
         policy: &'a BackupPolicy,
         cancel: &'a AtomicBool,
     ) -> impl Stream<Item = Result<T, crate::Error>> + 'a;
+
+    /// Whether [`load`](BackupSource::load) lists every entity covered by the policy (rather than, for
+    /// example, only those which have changed recently), so that any entity it doesn't list can be
+    /// assumed to no longer exist.
+    fn is_exhaustive(&self, _policy: &BackupPolicy) -> bool {
+        true
+    }
+
+    /// Lists every entity covered by the policy, like [`load`](BackupSource::load), but without
+    /// resuming from (or recording) any progress made by an earlier listing which was interrupted.
+    fn load_all<'a>(
+        &'a self,
+        policy: &'a BackupPolicy,
+        cancel: &'a AtomicBool,
+    ) -> impl Stream<Item = Result<T, crate::Error>> + 'a {
+        self.load(policy, cancel)
+    }
+}
+
+/// Checks that the policy's `temp_dir` property (if set) names an existing directory which we
+/// are able to write to, so that a misconfigured `temp_dir` is reported before any downloads fail.
+fn validate_temp_dir(policy: &BackupPolicy) -> Result<(), crate::Error> {
+    let Some(temp_dir) = policy.properties.get("temp_dir").map(Path::new) else {
+        return Ok(());
+    };
+
+    if !temp_dir.is_dir() {
+        return Err(errors::user(
+            &format!(
+                "The 'temp_dir' property on your '{policy}' backup policy refers to '{}', which is not a directory.",
+                temp_dir.display()
+            ),
+            "Make sure that the 'temp_dir' property refers to a directory which exists.",
+        ));
+    }
+
+    let probe = temp_dir.join(format!(".github-backup-{}.probe", std::process::id()));
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+        .and_then(|_| std::fs::remove_file(&probe))
+        .map_err(|e| {
+            errors::user_with_internal(
+                &format!(
+                    "Unable to write to the '{}' directory named by the 'temp_dir' property on your '{policy}' backup policy.",
+                    temp_dir.display()
+                ),
+                "Make sure that you have permission to write to the 'temp_dir' directory.",
+                e,
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(temp_dir: &Path) -> BackupPolicy {
+        serde_yaml::from_str(&format!(
+            r#"
+            kind: github/release
+            from: users/notheotherben
+            properties:
+              temp_dir: {}
+            "#,
+            temp_dir.display()
+        ))
+        .expect("parse policy")
+    }
+
+    #[test]
+    fn temp_dir_valid() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        validate_temp_dir(&policy(temp_dir.path())).expect("the temp_dir to be valid");
+        assert_eq!(
+            std::fs::read_dir(temp_dir.path()).unwrap().count(),
+            0,
+            "the write check shouldn't leave anything behind"
+        );
+    }
+
+    #[test]
+    fn temp_dir_missing() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        validate_temp_dir(&policy(&temp_dir.path().join("missing")))
+            .expect_err("a missing temp_dir to be rejected");
+    }
+
+    #[test]
+    fn temp_dir_file() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let file = temp_dir.path().join("file");
+        std::fs::write(&file, "").unwrap();
+        validate_temp_dir(&policy(&file)).expect_err("a file to be rejected as the temp_dir");
+    }
 }
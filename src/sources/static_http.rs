@@ -0,0 +1,395 @@
+use std::{
+    collections::HashSet,
+    path::{Component, Path, PathBuf},
+    sync::atomic::AtomicBool,
+};
+
+use tokio_stream::Stream;
+
+use crate::{
+    entities::{Credentials, HttpFile},
+    errors,
+    helpers::http::HttpArtifactKind,
+    policy::BackupPolicy,
+    BackupSource,
+};
+
+/// The policy property which may be used to list the files inline, in place of the file named
+/// by the policy's `from` field.
+const FILES_PROPERTY: &str = "files";
+
+/// Backs up an arbitrary list of files (which don't need to be hosted on GitHub), read from the
+/// YAML file named by the policy's `from` field or from its `files` property.
+///
+/// ```yaml
+/// - name: docs/handbook.pdf
+///   url: https://example.com/handbook.pdf
+/// - name: mirrors/tool-v1.0.0.tar.gz
+///   url: https://mirror.example.com/tool/v1.0.0.tar.gz
+///   credentials: !Token "your_token"
+///   content_type: application/gzip
+/// ```
+#[derive(Clone, Default)]
+pub struct StaticHttpSource;
+
+/// A file which should be backed up by a [`StaticHttpSource`].
+#[derive(Clone, Debug, serde::Deserialize)]
+struct StaticHttpEntry {
+    /// The path (relative to the policy's `to` directory) at which the file should be stored.
+    name: String,
+    url: String,
+    /// The credentials used to download this file, in place of the policy's credentials.
+    #[serde(default)]
+    credentials: Option<Credentials>,
+    /// The media type which the server is expected to respond with.
+    #[serde(default)]
+    content_type: Option<String>,
+}
+
+impl StaticHttpSource {
+    /// Reads the list of files for the policy, blocking until the file has been read. This is
+    /// used while validating the policy, which happens outside of the async runtime's tasks.
+    fn read_entries(policy: &BackupPolicy) -> Result<Vec<StaticHttpEntry>, crate::Error> {
+        match policy.properties.get(FILES_PROPERTY) {
+            Some(files) => Self::parse_entries(policy, files),
+            None => {
+                let content = std::fs::read_to_string(&policy.from)
+                    .map_err(|e| Self::read_error(policy, e))?;
+                Self::parse_entries(policy, &content)
+            }
+        }
+    }
+
+    async fn load_entries(policy: &BackupPolicy) -> Result<Vec<StaticHttpEntry>, crate::Error> {
+        match policy.properties.get(FILES_PROPERTY) {
+            Some(files) => Self::parse_entries(policy, files),
+            None => {
+                let content = tokio::fs::read_to_string(&policy.from)
+                    .await
+                    .map_err(|e| Self::read_error(policy, e))?;
+                Self::parse_entries(policy, &content)
+            }
+        }
+    }
+
+    fn read_error(policy: &BackupPolicy, e: std::io::Error) -> crate::Error {
+        errors::user_with_internal(
+            &format!(
+                "Unable to read the list of files for your '{policy}' backup policy from '{}'.",
+                Path::new(&policy.from).display()
+            ),
+            "Make sure that the 'from' field of your policy refers to a YAML file listing the files you wish to back up (or list them in its 'files' property), and that you have permission to read it.",
+            e,
+        )
+    }
+
+    fn parse_entries(
+        policy: &BackupPolicy,
+        content: &str,
+    ) -> Result<Vec<StaticHttpEntry>, crate::Error> {
+        serde_yaml::from_str(content).map_err(|e| {
+            let list = match policy.properties.contains_key(FILES_PROPERTY) {
+                true => format!("the '{FILES_PROPERTY}' property"),
+                false => format!("'{}'", Path::new(&policy.from).display()),
+            };
+
+            errors::user_with_internal(
+                &format!("The list of files for your '{policy}' backup policy in {list} is not valid."),
+                "Make sure that the list contains entries which each have a 'name' and 'url' (and optionally 'credentials' and 'content_type').",
+                e,
+            )
+        })
+    }
+
+    fn validate_entry(policy: &BackupPolicy, entry: &StaticHttpEntry) -> Result<(), crate::Error> {
+        match reqwest::Url::parse(&entry.url) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+            Ok(_) => {
+                return Err(errors::user(
+                    &format!("The URL '{}' for '{}' in your '{policy}' backup policy does not use HTTP or HTTPS.", entry.url, entry.name),
+                    "Make sure that each of the files in your list has a URL starting with 'http://' or 'https://'.",
+                ))
+            }
+            Err(e) => {
+                return Err(errors::user_with_internal(
+                    &format!("The URL '{}' for '{}' in your '{policy}' backup policy is not valid.", entry.url, entry.name),
+                    "Make sure that each of the files in your list has a valid URL.",
+                    e,
+                ))
+            }
+        }
+
+        let name = PathBuf::from(&entry.name);
+        if entry.name.trim().is_empty()
+            || !name
+                .components()
+                .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(errors::user(
+                &format!("The name '{}' for '{}' in your '{policy}' backup policy is not a valid relative path.", entry.name, entry.url),
+                "Make sure that each of the files in your list has a name which is a relative path within your backup directory, for example 'docs/handbook.pdf'.",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl BackupSource<HttpFile> for StaticHttpSource {
+    fn kind(&self) -> &str {
+        HttpArtifactKind::File.as_str()
+    }
+
+    fn validate(&self, policy: &BackupPolicy) -> Result<(), crate::Error> {
+        policy.property::<u64>("min_bytes")?;
+        policy.property::<u64>("max_bytes")?;
+        policy.property::<bool>("sha_index")?;
+        policy.property::<bool>("verify_after_write")?;
+        super::validate_temp_dir(policy)?;
+
+        let mut names = HashSet::new();
+        for entry in Self::read_entries(policy)? {
+            Self::validate_entry(policy, &entry)?;
+
+            if !names.insert(entry.name.clone()) {
+                return Err(errors::user(
+                    &format!("The name '{}' is used by more than one of the files in your '{policy}' backup policy.", entry.name),
+                    "Make sure that each of the files in your list has a unique name, so that they don't overwrite one another.",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn load<'a>(
+        &'a self,
+        policy: &'a BackupPolicy,
+        cancel: &'a AtomicBool,
+    ) -> impl Stream<Item = Result<HttpFile, crate::Error>> + 'a {
+        async_stream::stream! {
+          let entries = match Self::load_entries(policy).await {
+            Ok(entries) => entries,
+            Err(e) => {
+              yield Err(e);
+              return;
+            }
+          };
+
+          let min_bytes = policy.property::<u64>("min_bytes").ok().flatten();
+          let max_bytes = policy.property::<u64>("max_bytes").ok().flatten();
+          let sha_index = policy.property::<bool>("sha_index").ok().flatten().unwrap_or_default();
+          let verify_after_write = policy.property::<bool>("verify_after_write").ok().flatten().unwrap_or_default();
+          let temp_dir = policy.properties.get("temp_dir").map(PathBuf::from);
+
+          for entry in entries {
+            if cancel.load(std::sync::atomic::Ordering::Relaxed) {
+              return;
+            }
+
+            if let Err(e) = Self::validate_entry(policy, &entry) {
+              yield Err(e);
+              continue;
+            }
+
+            yield Ok(HttpFile::new(entry.name.as_str(), entry.url.as_str())
+                .with_metadata("file.name", entry.name.as_str())
+                .with_metadata("file.url", entry.url.as_str())
+                .with_credentials(entry.credentials.unwrap_or_else(|| policy.credentials.clone()))
+                .with_content_type(entry.content_type)
+                .with_min_bytes(min_bytes)
+                .with_max_bytes(max_bytes)
+                .with_sha_index(sha_index)
+                .with_verify_after_write(verify_after_write)
+                .with_temp_dir(temp_dir.clone()));
+          }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, sync::atomic::AtomicBool};
+
+    use rstest::rstest;
+
+    use crate::{
+        engines::{BackupEngine, BackupState, FileSystemStore, HttpFileEngine},
+        entities::Credentials,
+        BackupPolicy, BackupSource, Filterable,
+    };
+
+    use super::StaticHttpSource;
+
+    static CANCEL: AtomicBool = AtomicBool::new(false);
+
+    fn policy(list: &Path, to: &Path) -> BackupPolicy {
+        serde_yaml::from_str(&format!(
+            r#"
+            kind: http/file
+            from: {}
+            to: {}
+            credentials: !Token policy_token
+            "#,
+            list.display(),
+            to.display()
+        ))
+        .expect("parse policy")
+    }
+
+    #[test]
+    fn check_name() {
+        assert_eq!(StaticHttpSource.kind(), "http/file");
+    }
+
+    #[rstest]
+    #[case("- { name: file.txt, url: 'https://example.com/file.txt' }", true)]
+    #[case(
+        "- { name: a/file.txt, url: 'https://example.com/a' }\n- { name: b/file.txt, url: 'http://example.com/b' }",
+        true
+    )]
+    #[case("[]", true)]
+    #[case("- { name: file.txt, url: 'not a url' }", false)]
+    #[case("- { name: file.txt, url: 'ftp://example.com/file.txt' }", false)]
+    #[case("- { name: '', url: 'https://example.com/file.txt' }", false)]
+    #[case("- { name: ../file.txt, url: 'https://example.com/file.txt' }", false)]
+    #[case("- { name: /etc/passwd, url: 'https://example.com/file.txt' }", false)]
+    #[case(
+        "- { name: file.txt, url: 'https://example.com/a' }\n- { name: file.txt, url: 'https://example.com/b' }",
+        false
+    )]
+    #[case("- { url: 'https://example.com/file.txt' }", false)]
+    #[case("name: file.txt", false)]
+    fn validation(#[case] list: &str, #[case] success: bool) {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let list_path = temp_dir.path().join("files.yaml");
+        std::fs::write(&list_path, list).unwrap();
+
+        let policy = policy(&list_path, temp_dir.path());
+        if success {
+            StaticHttpSource
+                .validate(&policy)
+                .expect("validation to succeed");
+        } else {
+            StaticHttpSource
+                .validate(&policy)
+                .expect_err("validation to fail");
+        }
+    }
+
+    #[rstest]
+    #[case("- { name: file.txt, url: 'https://example.com/file.txt' }", true)]
+    #[case("- { name: ../file.txt, url: 'https://example.com/file.txt' }", false)]
+    #[case("name: file.txt", false)]
+    fn validation_inline(#[case] list: &str, #[case] success: bool) {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+
+        // The 'from' field doesn't need to name a file when the files are listed inline.
+        let mut policy = policy(&temp_dir.path().join("missing.yaml"), temp_dir.path());
+        policy
+            .properties
+            .insert("files".to_string(), list.to_string());
+
+        assert_eq!(StaticHttpSource.validate(&policy).is_ok(), success);
+    }
+
+    #[test]
+    fn validation_missing_list() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let policy = policy(&temp_dir.path().join("missing.yaml"), temp_dir.path());
+        StaticHttpSource
+            .validate(&policy)
+            .expect_err("validation to fail");
+    }
+
+    #[tokio::test]
+    async fn load_files() {
+        use axum::{routing::get, Router};
+        use tokio_stream::StreamExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("a local port to bind");
+        let server = format!("http://{}", listener.local_addr().unwrap());
+
+        let app = Router::new()
+            .route("/handbook.pdf", get(|| async { "handbook" }))
+            .route("/tool/v1.0.0.tar.gz", get(|| async { "tool" }));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let list_path = temp_dir.path().join("files.yaml");
+        std::fs::write(
+            &list_path,
+            format!(
+                r#"
+                - name: docs/handbook.pdf
+                  url: {server}/handbook.pdf
+                - name: mirrors/tool-v1.0.0.tar.gz
+                  url: {server}/tool/v1.0.0.tar.gz
+                  credentials: !Token file_token
+                  content_type: text/plain
+                "#
+            ),
+        )
+        .unwrap();
+
+        let backups = temp_dir.path().join("backups");
+        let mut inline = policy(&temp_dir.path().join("missing.yaml"), &backups);
+        inline.properties.insert(
+            "files".to_string(),
+            std::fs::read_to_string(&list_path).unwrap(),
+        );
+
+        let policy = policy(&list_path, &backups);
+        StaticHttpSource
+            .validate(&policy)
+            .expect("validation to succeed");
+
+        let files = StaticHttpSource
+            .load(&policy, &CANCEL)
+            .collect::<Result<Vec<_>, _>>()
+            .await
+            .expect("the files to be loaded");
+
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].name, "docs/handbook.pdf");
+        assert_eq!(files[0].url, format!("{server}/handbook.pdf"));
+        assert_eq!(files[0].credentials, Credentials::token("policy_token"));
+        assert_eq!(files[0].content_type, None);
+        assert_eq!(files[0].get("file.name"), "docs/handbook.pdf".into());
+
+        assert_eq!(files[1].name, "mirrors/tool-v1.0.0.tar.gz");
+        assert_eq!(files[1].credentials, Credentials::token("file_token"));
+        assert_eq!(files[1].content_type.as_deref(), Some("text/plain"));
+
+        // The same list may be provided inline, in the policy's 'files' property.
+        let inline_files = StaticHttpSource
+            .load(&inline, &CANCEL)
+            .collect::<Result<Vec<_>, _>>()
+            .await
+            .expect("the inline files to be loaded");
+        assert_eq!(
+            inline_files.iter().map(|f| &f.url).collect::<Vec<_>>(),
+            files.iter().map(|f| &f.url).collect::<Vec<_>>()
+        );
+
+        let engine = HttpFileEngine::new();
+        for file in files.iter() {
+            let state = engine
+                .backup(file, &FileSystemStore, &backups, &CANCEL)
+                .await
+                .expect("the backup to succeed");
+            assert!(matches!(state, BackupState::New(_)), "got {state}");
+        }
+
+        assert_eq!(
+            std::fs::read_to_string(backups.join("docs").join("handbook.pdf")).unwrap(),
+            "handbook"
+        );
+        assert_eq!(
+            std::fs::read_to_string(backups.join("mirrors").join("tool-v1.0.0.tar.gz")).unwrap(),
+            "tool"
+        );
+    }
+}
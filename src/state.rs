@@ -0,0 +1,439 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{errors, BackupPolicy};
+
+/// The name of the file, within a policy's `to` directory, in which state is persisted.
+pub const STATE_FILE_NAME: &str = ".github-backup-state.json";
+
+/// The number of consecutive runs, each of which listed every entity covered by a policy, from which
+/// an entity must be missing before its state is pruned.
+pub const PRUNE_AFTER_MISSED_RUNS: u32 = 3;
+
+/// Policies which share a `to` directory also share a state file, so updates are made one at a time
+/// to prevent concurrently running policies from overwriting each other's state.
+static UPDATE_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// State which is persisted between backup runs, stored alongside the backups it describes.
+///
+/// A single state file may be shared by several policies which back up to the same
+/// directory, so each policy's state is keyed by its `kind/from` identifier.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    #[serde(default)]
+    policies: HashMap<String, PolicyState>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PolicyState {
+    /// The time at which the most recent backup run, which was not cancelled, started.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_run: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// The time at which the most recent backup run, which completed without errors, started.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_successful_run: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// The paths (relative to the policy's `to` directory) at which entities have been stored when
+    /// their name could not be used, keyed by the entity's name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub paths: BTreeMap<String, String>,
+
+    /// The fingerprints of the entities which have been successfully backed up, keyed by the
+    /// entity's name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fingerprints: BTreeMap<String, String>,
+
+    /// The number of consecutive runs from which entities with recorded state have been missing,
+    /// keyed by the entity's name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub missing: BTreeMap<String, u32>,
+}
+
+impl PolicyState {
+    /// Removes the state of entities which have been missing from `after` consecutive listings of
+    /// the policy's source (including this one, whose entities are given by `seen`), returning the
+    /// number of entities which were pruned.
+    ///
+    /// This must only be called with the result of a listing which completed, since any entity
+    /// which wasn't listed is assumed to no longer exist.
+    pub fn compact(&mut self, seen: &BTreeSet<String>, after: u32) -> usize {
+        let tracked: BTreeSet<String> = self
+            .paths
+            .keys()
+            .chain(self.fingerprints.keys())
+            .filter(|name| !seen.contains(*name))
+            .cloned()
+            .collect();
+
+        self.missing.retain(|name, _| tracked.contains(name));
+
+        let mut pruned = 0;
+        for name in tracked {
+            let missed = self.missing.entry(name.clone()).or_default();
+            *missed += 1;
+
+            if *missed >= after {
+                self.missing.remove(&name);
+                self.paths.remove(&name);
+                self.fingerprints.remove(&name);
+                pruned += 1;
+            }
+        }
+
+        pruned
+    }
+}
+
+impl State {
+    pub fn path_for(policy: &BackupPolicy) -> PathBuf {
+        policy.to.join(STATE_FILE_NAME)
+    }
+
+    /// Loads the state file at the given path, returning an empty state if it does not exist.
+    pub fn load(path: &Path) -> Result<Self, errors::Error> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(errors::user_with_internal(
+                    &format!("Unable to read the backup state file '{}'.", path.display()),
+                    "Make sure that you have permission to read this file.",
+                    e,
+                ))
+            }
+        };
+
+        serde_json::from_str(&content).map_err(|e| {
+            errors::user_with_internal(
+                &format!(
+                    "The backup state file '{}' could not be parsed.",
+                    path.display()
+                ),
+                "Remove the state file to have it recreated on the next successful backup run.",
+                e,
+            )
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), errors::Error> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| {
+                errors::user_with_internal(
+                    &format!("Unable to create backup directory '{}'", parent.display()),
+                    "Make sure that you have permission to create the directory.",
+                    e,
+                )
+            })?;
+        }
+
+        let content = serde_json::to_string_pretty(self).map_err(|e| {
+            errors::system_with_internal(
+                "Unable to serialize the backup state.",
+                "Please report this issue to us on GitHub.",
+                e,
+            )
+        })?;
+
+        let temp_path = path.with_extension("json.tmp");
+        std::fs::write(&temp_path, content)
+            .and_then(|_| std::fs::rename(&temp_path, path))
+            .map_err(|e| {
+                errors::user_with_internal(
+                    &format!(
+                        "Unable to write the backup state file '{}'.",
+                        path.display()
+                    ),
+                    "Make sure that you have permission to write to the backup directory.",
+                    e,
+                )
+            })
+    }
+
+    /// Loads the state for a policy, applies an update to it, and saves the result.
+    pub async fn update<U>(policy: &BackupPolicy, update: U) -> Result<(), errors::Error>
+    where
+        U: FnOnce(&mut PolicyState),
+    {
+        let _lock = UPDATE_LOCK.lock().await;
+
+        let path = Self::path_for(policy);
+        let mut state = Self::load_blocking(path.clone()).await?;
+        update(state.policies.entry(policy.to_string()).or_default());
+        state.save_blocking(path).await
+    }
+
+    /// Compacts the state of a policy (see [`PolicyState::compact`]) after a complete listing of its
+    /// source, returning the number of entities which were pruned. The state file is only rewritten
+    /// (atomically) if it has changed.
+    pub async fn compact(
+        policy: &BackupPolicy,
+        seen: &BTreeSet<String>,
+        after: u32,
+    ) -> Result<usize, errors::Error> {
+        let _lock = UPDATE_LOCK.lock().await;
+
+        let path = Self::path_for(policy);
+        let mut state = Self::load_blocking(path.clone()).await?;
+        let Some(policy_state) = state.policies.get_mut(&policy.to_string()) else {
+            return Ok(0);
+        };
+
+        let missing = policy_state.missing.clone();
+        let pruned = policy_state.compact(seen, after);
+        if pruned > 0 || policy_state.missing != missing {
+            state.save_blocking(path).await?;
+        }
+
+        Ok(pruned)
+    }
+
+    /// Loads the state file at the given path on a blocking thread, so that updates (which hold
+    /// the update lock while doing so) don't block the async runtime.
+    async fn load_blocking(path: PathBuf) -> Result<Self, errors::Error> {
+        tokio::task::spawn_blocking(move || Self::load(&path))
+            .await
+            .map_err(Self::blocking_error)?
+    }
+
+    /// Saves the state to the given path on a blocking thread (see [`State::load_blocking`]).
+    async fn save_blocking(self, path: PathBuf) -> Result<(), errors::Error> {
+        tokio::task::spawn_blocking(move || self.save(&path))
+            .await
+            .map_err(Self::blocking_error)?
+    }
+
+    fn blocking_error(e: tokio::task::JoinError) -> errors::Error {
+        errors::system_with_internal(
+            "Unable to access the backup state file.",
+            "Please report this issue to us on GitHub.",
+            e,
+        )
+    }
+
+    pub fn policy(&self, policy: &BackupPolicy) -> PolicyState {
+        self.policies
+            .get(&policy.to_string())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// The time at which the least recently run of the given policies last ran, or `None` if any
+    /// of them has never run (or its state could not be read).
+    pub fn last_run(policies: &[BackupPolicy]) -> Option<chrono::DateTime<chrono::Utc>> {
+        let mut earliest: Option<chrono::DateTime<chrono::Utc>> = None;
+        for policy in policies {
+            let last_run = Self::load(&Self::path_for(policy))
+                .ok()?
+                .policy(policy)
+                .last_run?;
+            earliest = Some(earliest.map_or(last_run, |e| e.min(last_run)));
+        }
+
+        earliest
+    }
+}
+
+/// Determines when a scheduled backup should resume after being restarted, returning the next
+/// scheduled occurrence if the last run already covered the most recent one, or `None` if the
+/// backup should run immediately.
+pub fn resume_at(
+    schedule: &croner::Cron,
+    last_run: chrono::DateTime<chrono::Utc>,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Option<chrono::DateTime<chrono::Utc>> {
+    schedule
+        .find_next_occurrence(&last_run, false)
+        .ok()
+        .filter(|next_run| *next_run > now)
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn policy(kind: &str, to: &Path) -> BackupPolicy {
+        serde_yaml::from_str(&format!(
+            r#"
+            kind: {kind}
+            from: users/notheotherben
+            to: {}
+            "#,
+            to.display()
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_load_missing() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let state = State::load(&temp_dir.path().join(STATE_FILE_NAME)).expect("state to load");
+        assert!(state.policies.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_update() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let releases = policy("github/release", temp_dir.path());
+        let repos = policy("github/repo", temp_dir.path());
+
+        let timestamp = chrono::Utc::now();
+        State::update(&releases, |s| s.last_successful_run = Some(timestamp))
+            .await
+            .expect("state to be updated");
+
+        let state = State::load(&State::path_for(&releases)).expect("state to load");
+        assert_eq!(state.policy(&releases).last_successful_run, Some(timestamp));
+        assert_eq!(state.policy(&repos).last_successful_run, None);
+    }
+
+    #[test]
+    fn test_compact() {
+        let mut state = PolicyState {
+            paths: [("a/renamed".to_string(), "a/renamed-1".to_string())].into(),
+            fingerprints: [
+                ("a/present".to_string(), "v1".to_string()),
+                ("a/renamed".to_string(), "v1".to_string()),
+                ("a/removed".to_string(), "v1".to_string()),
+            ]
+            .into(),
+            ..Default::default()
+        };
+
+        let all: BTreeSet<String> = ["a/present", "a/renamed", "a/removed"]
+            .map(String::from)
+            .into();
+        let some: BTreeSet<String> = ["a/present"].map(String::from).into();
+
+        assert_eq!(state.compact(&some, 3), 0);
+        assert_eq!(state.compact(&some, 3), 0);
+        assert_eq!(state.missing.get("a/removed"), Some(&2));
+
+        // An entity which reappears starts counting again from scratch.
+        assert_eq!(state.compact(&all, 3), 0);
+        assert!(state.missing.is_empty());
+
+        for _ in 0..2 {
+            assert_eq!(state.compact(&some, 3), 0);
+        }
+        assert_eq!(state.compact(&some, 3), 2);
+
+        assert_eq!(state.fingerprints.keys().collect::<Vec<_>>(), ["a/present"]);
+        assert!(state.paths.is_empty());
+        assert!(state.missing.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_compact_file() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let releases = policy("github/release", temp_dir.path());
+        let repos = policy("github/repo", temp_dir.path());
+
+        State::update(&releases, |s| {
+            s.fingerprints
+                .insert("a/removed".to_string(), "v1".to_string());
+        })
+        .await
+        .expect("state to be updated");
+        State::update(&repos, |s| {
+            s.fingerprints
+                .insert("a/removed".to_string(), "v1".to_string());
+        })
+        .await
+        .expect("state to be updated");
+
+        assert_eq!(
+            State::compact(&releases, &BTreeSet::new(), 1)
+                .await
+                .expect("state to be compacted"),
+            1
+        );
+
+        let state = State::load(&State::path_for(&releases)).expect("state to load");
+        assert!(state.policy(&releases).fingerprints.is_empty());
+        assert_eq!(
+            state.policy(&repos).fingerprints.len(),
+            1,
+            "other policies sharing the state file should not be compacted"
+        );
+
+        let empty = tempfile::tempdir().expect("a temporary directory");
+        assert_eq!(
+            State::compact(&policy("github/repo", empty.path()), &BTreeSet::new(), 1)
+                .await
+                .expect("state to be compacted"),
+            0
+        );
+        assert!(
+            !empty.path().join(STATE_FILE_NAME).exists(),
+            "no state file should be created for a policy without any state"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_last_run() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let policies = [
+            policy("github/release", temp_dir.path()),
+            policy("github/repo", temp_dir.path()),
+        ];
+
+        let timestamp = chrono::Utc::now();
+        State::update(&policies[0], |s| s.last_run = Some(timestamp))
+            .await
+            .expect("state to be updated");
+        assert_eq!(
+            State::last_run(&policies),
+            None,
+            "a policy which has never run should cause the backup to run immediately"
+        );
+
+        State::update(&policies[1], |s| {
+            s.last_run = Some(timestamp - chrono::Duration::hours(1))
+        })
+        .await
+        .expect("state to be updated");
+        assert_eq!(
+            State::last_run(&policies),
+            Some(timestamp - chrono::Duration::hours(1))
+        );
+    }
+
+    #[rstest]
+    #[case(
+        "2024-01-01T10:05:00Z",
+        "2024-01-01T10:30:00Z",
+        Some("2024-01-01T11:00:00Z")
+    )]
+    #[case("2024-01-01T09:55:00Z", "2024-01-01T10:30:00Z", None)]
+    #[case("2024-01-01T08:05:00Z", "2024-01-01T10:30:00Z", None)]
+    #[case(
+        "2024-01-01T10:00:00Z",
+        "2024-01-01T10:00:00Z",
+        Some("2024-01-01T11:00:00Z")
+    )]
+    fn test_resume_at(#[case] last_run: &str, #[case] now: &str, #[case] expected: Option<&str>) {
+        let schedule = croner::Cron::new("0 * * * *").parse().unwrap();
+        let parse = |t: &str| t.parse::<chrono::DateTime<chrono::Utc>>().unwrap();
+
+        assert_eq!(
+            resume_at(&schedule, parse(last_run), parse(now)),
+            expected.map(parse)
+        );
+    }
+
+    #[test]
+    fn test_load_invalid() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let path = temp_dir.path().join(STATE_FILE_NAME);
+        std::fs::write(&path, "not json").unwrap();
+
+        State::load(&path).expect_err("an invalid state file should be reported");
+    }
+}
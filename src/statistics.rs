@@ -0,0 +1,191 @@
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+use crate::{
+    engines::{BackupState, SkipReason},
+    errors::{Error, ErrorCategory},
+};
+
+/// Keeps track of the outcome of backups over the lifetime of the process, so that
+/// they can be reported on (for example, through the metrics endpoint).
+#[derive(Default)]
+pub struct SummaryStatistics {
+    skipped: AtomicU64,
+    skipped_filtered: AtomicU64,
+    skipped_dry_run: AtomicU64,
+    skipped_cancelled: AtomicU64,
+    skipped_empty: AtomicU64,
+    skipped_size_limit: AtomicU64,
+    new: AtomicU64,
+    updated: AtomicU64,
+    unchanged: AtomicU64,
+    errors: AtomicU64,
+    authentication_errors: AtomicU64,
+    network_errors: AtomicU64,
+    last_run_timestamp: AtomicI64,
+    last_run_duration_ms: AtomicU64,
+}
+
+impl SummaryStatistics {
+    pub fn record(&self, state: &BackupState) {
+        if let BackupState::Skipped(reason) = state {
+            let counter = match reason {
+                SkipReason::Filtered => &self.skipped_filtered,
+                SkipReason::DryRun => &self.skipped_dry_run,
+                SkipReason::Cancelled => &self.skipped_cancelled,
+                SkipReason::Empty => &self.skipped_empty,
+                SkipReason::SizeLimit => &self.skipped_size_limit,
+            };
+
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let counter = match state {
+            BackupState::Skipped(..) => &self.skipped,
+            BackupState::New(..) => &self.new,
+            BackupState::Updated(..) => &self.updated,
+            BackupState::Unchanged(..) => &self.unchanged,
+        };
+
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_error(&self, error: &Error) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+
+        match ErrorCategory::of(error) {
+            ErrorCategory::Authentication => {
+                self.authentication_errors.fetch_add(1, Ordering::Relaxed);
+            }
+            ErrorCategory::Network => {
+                self.network_errors.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn record_run(
+        &self,
+        completed_at: chrono::DateTime<chrono::Utc>,
+        duration: std::time::Duration,
+    ) {
+        self.last_run_timestamp
+            .store(completed_at.timestamp(), Ordering::Relaxed);
+        self.last_run_duration_ms
+            .store(duration.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// The number of entities which have been processed, grouped by the state they finished in.
+    pub fn entities(&self) -> [(&'static str, u64); 4] {
+        [
+            ("skipped", self.skipped.load(Ordering::Relaxed)),
+            ("new", self.new.load(Ordering::Relaxed)),
+            ("updated", self.updated.load(Ordering::Relaxed)),
+            ("unchanged", self.unchanged.load(Ordering::Relaxed)),
+        ]
+    }
+
+    /// The number of entities which have been skipped, grouped by the reason they were skipped.
+    pub fn skipped(&self) -> [(SkipReason, u64); 5] {
+        [
+            (
+                SkipReason::Filtered,
+                self.skipped_filtered.load(Ordering::Relaxed),
+            ),
+            (
+                SkipReason::DryRun,
+                self.skipped_dry_run.load(Ordering::Relaxed),
+            ),
+            (
+                SkipReason::Cancelled,
+                self.skipped_cancelled.load(Ordering::Relaxed),
+            ),
+            (
+                SkipReason::Empty,
+                self.skipped_empty.load(Ordering::Relaxed),
+            ),
+            (
+                SkipReason::SizeLimit,
+                self.skipped_size_limit.load(Ordering::Relaxed),
+            ),
+        ]
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    pub fn authentication_errors(&self) -> u64 {
+        self.authentication_errors.load(Ordering::Relaxed)
+    }
+
+    pub fn network_errors(&self) -> u64 {
+        self.network_errors.load(Ordering::Relaxed)
+    }
+
+    /// The UNIX timestamp (in seconds) at which the most recent backup run completed, if any.
+    pub fn last_run_timestamp(&self) -> Option<i64> {
+        match self.last_run_timestamp.load(Ordering::Relaxed) {
+            0 => None,
+            ts => Some(ts),
+        }
+    }
+
+    pub fn last_run_duration(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.last_run_duration_ms.load(Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engines::BackupDetails;
+
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let stats = SummaryStatistics::default();
+        stats.record(&BackupState::New(BackupDetails::default()));
+        stats.record(&BackupState::New(BackupDetails::new("at abc")));
+        stats.record(&BackupState::Unchanged(BackupDetails::default()));
+        stats.record(&BackupState::Skipped(SkipReason::Filtered));
+        stats.record(&BackupState::Skipped(SkipReason::DryRun));
+        stats.record(&BackupState::Skipped(SkipReason::Empty));
+        stats.record_error(&crate::errors::user("Something failed.", "Try again."));
+        stats.record_error(&crate::errors::authentication(
+            crate::errors::AUTHENTICATION_FAILED,
+            "Check your token.",
+        ));
+
+        assert_eq!(
+            stats.entities(),
+            [("skipped", 3), ("new", 2), ("updated", 0), ("unchanged", 1)]
+        );
+        assert_eq!(
+            stats.skipped(),
+            [
+                (SkipReason::Filtered, 1),
+                (SkipReason::DryRun, 1),
+                (SkipReason::Cancelled, 0),
+                (SkipReason::Empty, 1),
+                (SkipReason::SizeLimit, 0)
+            ]
+        );
+        assert_eq!(stats.errors(), 2);
+        assert_eq!(stats.authentication_errors(), 1);
+        assert_eq!(stats.network_errors(), 0);
+        assert_eq!(stats.last_run_timestamp(), None);
+    }
+
+    #[test]
+    fn test_record_run() {
+        let stats = SummaryStatistics::default();
+        let now = chrono::Utc::now();
+        stats.record_run(now, std::time::Duration::from_millis(1500));
+
+        assert_eq!(stats.last_run_timestamp(), Some(now.timestamp()));
+        assert_eq!(
+            stats.last_run_duration(),
+            std::time::Duration::from_millis(1500)
+        );
+    }
+}
@@ -0,0 +1,410 @@
+use std::{fmt::Write, sync::Mutex, time::Duration};
+
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials as SmtpCredentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use serde::Deserialize;
+
+use crate::{
+    engines::BackupState, entities::Credentials, errors, statistics::SummaryStatistics,
+    BackupPolicy,
+};
+
+/// The maximum number of errors which are listed for each policy in a digest, so that a
+/// policy which fails for every item doesn't produce an enormous email.
+const MAX_ERRORS_PER_POLICY: usize = 20;
+
+/// Configures the SMTP server through which a digest email is sent after each backup run.
+#[derive(Clone, Deserialize, schemars::JsonSchema)]
+pub struct SmtpConfig {
+    /// The hostname of the SMTP server.
+    pub host: String,
+
+    /// The port on which the SMTP server is listening (defaults to 587, or 25 when `tls` is disabled).
+    #[serde(default)]
+    pub port: Option<u16>,
+
+    /// Whether the connection to the SMTP server should be encrypted. Port 465 uses implicit TLS,
+    /// while all other ports use STARTTLS.
+    #[serde(default = "default_tls")]
+    pub tls: bool,
+
+    /// The address from which the digest is sent.
+    pub from: String,
+
+    /// The addresses to which the digest is sent.
+    pub to: Vec<String>,
+
+    /// The username and password used to authenticate with the SMTP server.
+    #[serde(default)]
+    pub auth: Credentials,
+}
+
+fn default_tls() -> bool {
+    true
+}
+
+impl SmtpConfig {
+    /// Prepares a [`Mailer`] for this configuration, reporting any problems with it before
+    /// backups are run rather than when the first digest is sent.
+    pub fn mailer(&self) -> Result<Mailer, errors::Error> {
+        let from = parse_mailbox(&self.from)?;
+        let to = self
+            .to
+            .iter()
+            .map(|to| parse_mailbox(to))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if to.is_empty() {
+            return Err(errors::user(
+                "Your SMTP configuration does not include any recipients for the backup digest.",
+                "Add at least one email address to the 'to' list in your 'smtp' configuration.",
+            ));
+        }
+
+        let builder = match (self.tls, self.port) {
+            (true, Some(465)) => AsyncSmtpTransport::<Tokio1Executor>::relay(&self.host),
+            (true, _) => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.host),
+            (false, _) => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+                &self.host,
+            )),
+        }
+        .map_err(|e| {
+            errors::user_with_internal(
+                &format!("Unable to configure the SMTP server '{}'.", self.host),
+                "Make sure that you have provided a valid hostname for your SMTP server.",
+                e,
+            )
+        })?
+        .port(self.port.unwrap_or(if self.tls { 587 } else { 25 }));
+
+        let builder = match &self.auth {
+            Credentials::None => builder,
+            Credentials::UsernamePassword {
+                username, password, ..
+            } => {
+                builder.credentials(SmtpCredentials::new(username.clone(), password.clone()))
+            }
+            Credentials::Token { .. } => {
+                return Err(errors::user(
+                    "Your SMTP configuration uses a token to authenticate, but SMTP servers require a username and password.",
+                    "Use '!UsernamePassword' credentials for the 'auth' property in your 'smtp' configuration.",
+                ))
+            }
+            Credentials::PerHost(_) => {
+                return Err(errors::user(
+                    "Your SMTP configuration provides per-host credentials, but only a single username and password can be used to authenticate with an SMTP server.",
+                    "Use a single set of '!UsernamePassword' credentials for the 'auth' property in your 'smtp' configuration.",
+                ))
+            }
+            Credentials::Chain(_) => {
+                return Err(errors::user(
+                    "Your SMTP configuration provides a list of credentials, but only a single username and password can be used to authenticate with an SMTP server.",
+                    "Use a single set of '!UsernamePassword' credentials for the 'auth' property in your 'smtp' configuration.",
+                ))
+            }
+        };
+
+        Ok(Mailer {
+            transport: builder.build(),
+            from,
+            to,
+        })
+    }
+}
+
+fn parse_mailbox(address: &str) -> Result<Mailbox, errors::Error> {
+    address.parse().map_err(|e| {
+        errors::user_with_internal(
+            &format!("The email address '{address}' in your SMTP configuration is not valid."),
+            "Make sure that you have provided a valid email address, for example 'backups@example.com' or 'Backups <backups@example.com>'.",
+            e,
+        )
+    })
+}
+
+/// Sends backup digests to the recipients in the user's [`SmtpConfig`].
+pub struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl Mailer {
+    pub fn message(&self, digest: &Digest) -> Result<Message, errors::Error> {
+        let builder = self
+            .to
+            .iter()
+            .fold(Message::builder().from(self.from.clone()), |b, to| {
+                b.to(to.clone())
+            });
+
+        builder
+            .subject(digest.subject())
+            .header(ContentType::TEXT_PLAIN)
+            .body(digest.body())
+            .map_err(|e| {
+                errors::system_with_internal(
+                    "Unable to prepare the backup digest email.",
+                    "Please report this issue to us on GitHub.",
+                    e,
+                )
+            })
+    }
+
+    pub async fn send(&self, digest: &Digest) -> Result<(), errors::Error> {
+        let message = self.message(digest)?;
+        self.transport.send(message).await.map_err(|e| {
+            errors::system_with_internal(
+                "Unable to send the backup digest email.",
+                "Make sure that your SMTP server is reachable and that your 'smtp' configuration is correct.",
+                e,
+            )
+        })?;
+
+        Ok(())
+    }
+}
+
+/// The outcome of a single backup policy during a backup run.
+pub struct PolicySummary {
+    pub policy: String,
+    pub stats: SummaryStatistics,
+    errors: Mutex<Vec<String>>,
+}
+
+impl PolicySummary {
+    pub fn new(policy: &BackupPolicy) -> Self {
+        Self {
+            policy: policy.to_string(),
+            stats: SummaryStatistics::default(),
+            errors: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn record(&self, state: &BackupState) {
+        self.stats.record(state);
+    }
+
+    pub fn record_error(&self, error: &errors::Error) {
+        self.stats.record_error(error);
+
+        let mut errors = self.errors.lock().unwrap();
+        if errors.len() < MAX_ERRORS_PER_POLICY {
+            errors.push(error.to_string());
+        }
+    }
+}
+
+/// A summary of a backup run, which is emailed to the user once the run has completed.
+pub struct Digest {
+    pub completed_at: chrono::DateTime<chrono::Utc>,
+    pub duration: Duration,
+    pub policies: Vec<PolicySummary>,
+    /// Whether the run was stopped early because it reached its maximum runtime.
+    pub cut_short: bool,
+}
+
+impl Digest {
+    pub fn errors(&self) -> u64 {
+        self.policies.iter().map(|p| p.stats.errors()).sum()
+    }
+
+    pub fn subject(&self) -> String {
+        match self.errors() {
+            0 => "GitHub Backup completed successfully".to_string(),
+            1 => "GitHub Backup completed with 1 error".to_string(),
+            errors => format!("GitHub Backup completed with {errors} errors"),
+        }
+    }
+
+    pub fn body(&self) -> String {
+        let mut out = String::new();
+
+        writeln!(
+            out,
+            "GitHub Backup completed a backup run at {} (taking {}s).",
+            self.completed_at.format("%Y-%m-%dT%H:%M:%SZ"),
+            self.duration.as_secs()
+        )
+        .unwrap();
+
+        if self.cut_short {
+            writeln!(
+                out,
+                "The run reached its maximum runtime, so some items were not backed up."
+            )
+            .unwrap();
+        }
+
+        for policy in self.policies.iter() {
+            writeln!(out).unwrap();
+            writeln!(out, "{}", policy.policy).unwrap();
+
+            let entities = policy
+                .stats
+                .entities()
+                .iter()
+                .map(|(state, count)| format!("{count} {state}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            writeln!(out, "  {}, {} errors", entities, policy.stats.errors()).unwrap();
+
+            let errors = policy.errors.lock().unwrap();
+            for error in errors.iter() {
+                writeln!(out, "  - {}", error.replace('\n', "\n    ")).unwrap();
+            }
+
+            let unlisted = policy.stats.errors().saturating_sub(errors.len() as u64);
+            if unlisted > 0 {
+                writeln!(
+                    out,
+                    "  - ...and {unlisted} more errors (see the logs for details)"
+                )
+                .unwrap();
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engines::{BackupDetails, SkipReason};
+
+    use super::*;
+
+    fn digest() -> Digest {
+        let repos = PolicySummary::new(
+            &serde_yaml::from_str("{ kind: github/repo, from: user, to: /backups/repos }").unwrap(),
+        );
+        repos.record(&BackupState::New(BackupDetails::default()));
+        repos.record(&BackupState::Unchanged(BackupDetails::default()));
+        repos.record(&BackupState::Unchanged(BackupDetails::default()));
+        repos.record(&BackupState::Skipped(SkipReason::Filtered));
+        repos.record_error(&errors::user(
+            "Failed to clone octocat/hello-world.",
+            "Check your network connection.",
+        ));
+
+        let releases = PolicySummary::new(
+            &serde_yaml::from_str("{ kind: github/release, from: user, to: /backups/releases }")
+                .unwrap(),
+        );
+        releases.record(&BackupState::Updated(BackupDetails::default()));
+
+        Digest {
+            completed_at: chrono::DateTime::parse_from_rfc3339("2024-01-02T03:04:05Z")
+                .unwrap()
+                .into(),
+            duration: Duration::from_secs(42),
+            policies: vec![repos, releases],
+            cut_short: false,
+        }
+    }
+
+    #[test]
+    fn test_body() {
+        let digest = digest();
+        assert_eq!(digest.subject(), "GitHub Backup completed with 1 error");
+
+        let body = digest.body();
+        assert!(
+            body.starts_with(
+                "GitHub Backup completed a backup run at 2024-01-02T03:04:05Z (taking 42s).\n"
+            ),
+            "{body}"
+        );
+        assert!(
+            body.contains("  1 skipped, 1 new, 0 updated, 2 unchanged, 1 errors\n"),
+            "{body}"
+        );
+        assert!(
+            body.contains("Failed to clone octocat/hello-world."),
+            "{body}"
+        );
+        assert!(
+            body.contains("  0 skipped, 0 new, 1 updated, 0 unchanged, 0 errors\n"),
+            "{body}"
+        );
+        assert!(!body.contains("maximum runtime"), "{body}");
+    }
+
+    #[test]
+    fn test_body_cut_short() {
+        let digest = Digest {
+            cut_short: true,
+            ..digest()
+        };
+
+        assert!(
+            digest.body().contains(
+                "The run reached its maximum runtime, so some items were not backed up.\n"
+            ),
+            "{}",
+            digest.body()
+        );
+    }
+
+    #[test]
+    fn test_truncated_errors() {
+        let summary = PolicySummary::new(
+            &serde_yaml::from_str("{ kind: github/repo, from: user, to: /backups }").unwrap(),
+        );
+        for _ in 0..MAX_ERRORS_PER_POLICY + 5 {
+            summary.record_error(&errors::user("Something went wrong.", "Try again."));
+        }
+
+        let digest = Digest {
+            completed_at: chrono::Utc::now(),
+            duration: Duration::from_secs(1),
+            policies: vec![summary],
+            cut_short: false,
+        };
+
+        assert_eq!(
+            digest.subject(),
+            format!(
+                "GitHub Backup completed with {} errors",
+                MAX_ERRORS_PER_POLICY + 5
+            )
+        );
+        assert!(digest.body().contains("...and 5 more errors"));
+    }
+
+    #[test]
+    fn test_message() {
+        let config: SmtpConfig = serde_yaml::from_str(
+            r#"
+            host: smtp.example.com
+            from: "GitHub Backup <backups@example.com>"
+            to:
+              - admin@example.com
+              - ops@example.com
+            auth: !UsernamePassword { username: backups, password: secret }
+            "#,
+        )
+        .unwrap();
+
+        let mailer = config.mailer().expect("the mailer to be configured");
+        let message = String::from_utf8(mailer.message(&digest()).unwrap().formatted()).unwrap();
+
+        assert!(message.contains("From: \"GitHub Backup\" <backups@example.com>"));
+        assert!(message.contains("To: admin@example.com, ops@example.com"));
+        assert!(message.contains("Subject: GitHub Backup completed with 1 error"));
+        assert!(message.contains("Failed to clone octocat/hello-world."));
+    }
+
+    #[rstest::rstest]
+    #[case("{ host: smtp.example.com, from: not-an-address, to: [admin@example.com] }")]
+    #[case("{ host: smtp.example.com, from: backups@example.com, to: [] }")]
+    #[case("{ host: smtp.example.com, from: backups@example.com, to: [admin@example.com], auth: !Token abc }")]
+    #[case("{ host: smtp.example.com, from: backups@example.com, to: [admin@example.com], auth: [!Token abc] }")]
+    fn test_invalid_config(#[case] config: &str) {
+        let config: SmtpConfig = serde_yaml::from_str(config).unwrap();
+        assert!(config.mailer().is_err());
+    }
+}
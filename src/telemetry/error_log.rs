@@ -0,0 +1,128 @@
+use std::{io::Write, path::Path, sync::Mutex};
+
+use serde::Serialize;
+use tracing_batteries::prelude::*;
+
+use crate::{
+    errors::{self, ErrorCategory},
+    BackupPolicy,
+};
+
+/// Writes each error encountered while running backups to a file as a line of JSON, so that
+/// errors can be collected by automation without being interleaved with the human-readable logs.
+///
+/// The file is always appended to, so that errors from previous runs are retained until the
+/// file is rotated by another process.
+pub struct ErrorLog {
+    file: Mutex<std::fs::File>,
+}
+
+#[derive(Serialize)]
+struct ErrorRecord<'a> {
+    timestamp: chrono::DateTime<chrono::Utc>,
+    policy: Option<String>,
+    entity: Option<&'a str>,
+    category: ErrorCategory,
+    message: String,
+}
+
+impl ErrorLog {
+    pub fn open(path: &Path) -> Result<Self, errors::Error> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| {
+                errors::user_with_internal(
+                    &format!("Unable to open the error log file '{}'.", path.display()),
+                    "Make sure that you have permission to write to this file and that its directory exists.",
+                    e,
+                )
+            })?;
+
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Records an error, along with the policy and entity it was encountered for (if known).
+    pub fn record(
+        &self,
+        policy: Option<&BackupPolicy>,
+        entity: Option<&str>,
+        error: &errors::Error,
+    ) {
+        let record = ErrorRecord {
+            timestamp: chrono::Utc::now(),
+            policy: policy.map(|p| p.to_string()),
+            entity,
+            category: ErrorCategory::of(error),
+            message: error.to_string(),
+        };
+
+        let mut line = match serde_json::to_string(&record) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("Unable to serialize an error for the error log: {}", e);
+                return;
+            }
+        };
+        line.push('\n');
+
+        // Each record is written in a single call so that concurrently recorded errors never
+        // interleave within a line.
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            warn!("Unable to write to the error log: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let temp_dir = tempfile::tempdir().expect("a temporary directory");
+        let path = temp_dir.path().join("errors.jsonl");
+        let policy: BackupPolicy =
+            serde_yaml::from_str("{ kind: github/repo, from: users/notheotherben, to: /backups }")
+                .unwrap();
+
+        let log = ErrorLog::open(&path).expect("the error log to open");
+        log.record(
+            Some(&policy),
+            Some("notheotherben/repo"),
+            &errors::user("Something went wrong.", "Try again."),
+        );
+
+        // Re-opening the log should append to it, rather than replacing it.
+        let log = ErrorLog::open(&path).expect("the error log to open");
+        log.record(
+            None,
+            None,
+            &errors::system("Something else went wrong.", "Report it."),
+        );
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines = content
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).expect("valid JSON"))
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+
+        assert_eq!(lines[0]["policy"], "github/repo/users/notheotherben");
+        assert_eq!(lines[0]["entity"], "notheotherben/repo");
+        assert_eq!(lines[0]["category"], "user");
+        assert!(lines[0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("Something went wrong."));
+        assert!(lines[0]["timestamp"].is_string());
+
+        assert!(lines[1]["policy"].is_null());
+        assert!(lines[1]["entity"].is_null());
+        assert_eq!(lines[1]["category"], "system");
+    }
+}
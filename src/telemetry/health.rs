@@ -0,0 +1,227 @@
+use std::{
+    net::SocketAddr,
+    sync::{atomic::AtomicBool, Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, routing::get, Json, Router};
+use chrono::{DateTime, Utc};
+use tracing_batteries::prelude::*;
+
+use crate::errors;
+
+use super::PolicySummary;
+
+/// Tracks the outcome of the most recent backup run, so that it can be reported to container
+/// orchestrators (like Kubernetes) through the [`HealthServer`].
+///
+/// Clones of a [`Health`] share the same state, allowing the scheduler loop to record each run
+/// while the server reports on it.
+#[derive(Clone, Debug, Default)]
+pub struct Health {
+    state: Arc<Mutex<HealthState>>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct HealthState {
+    last_run: Option<DateTime<Utc>>,
+    last_run_failed: bool,
+    last_successful_run: Option<DateTime<Utc>>,
+    next_run: Option<DateTime<Utc>>,
+}
+
+impl Health {
+    /// Records the completion of a backup run. A run is considered to have failed only if it
+    /// encountered errors without backing anything up successfully.
+    pub fn record_run(
+        &self,
+        completed_at: DateTime<Utc>,
+        summaries: &[PolicySummary],
+        next_run: Option<DateTime<Utc>>,
+    ) {
+        let errors: u64 = summaries.iter().map(|s| s.stats.errors()).sum();
+        let backed_up: u64 = summaries
+            .iter()
+            .flat_map(|s| s.stats.entities())
+            .filter(|(state, _)| *state != "skipped")
+            .map(|(_, count)| count)
+            .sum();
+        let failed = errors > 0 && backed_up == 0;
+
+        let mut state = self.state.lock().unwrap();
+        state.last_run = Some(completed_at);
+        state.last_run_failed = failed;
+        state.next_run = next_run;
+        if !failed {
+            state.last_successful_run = Some(completed_at);
+        }
+    }
+
+    fn status(&self) -> (StatusCode, Json<serde_json::Value>) {
+        let state = self.state.lock().unwrap().clone();
+        let (code, status) = match (state.last_run, state.last_run_failed) {
+            (None, _) => (StatusCode::SERVICE_UNAVAILABLE, "starting"),
+            (Some(_), true) => (StatusCode::SERVICE_UNAVAILABLE, "failed"),
+            (Some(_), false) => (StatusCode::OK, "ok"),
+        };
+
+        (
+            code,
+            Json(serde_json::json!({
+                "status": status,
+                "last_run": state.last_run,
+                "last_successful_run": state.last_successful_run,
+                "next_run": state.next_run,
+            })),
+        )
+    }
+}
+
+/// A minimal HTTP server which reports the [`Health`] of the backup scheduler on `/health`, for
+/// use as a liveness or readiness probe.
+pub struct HealthServer {
+    listener: tokio::net::TcpListener,
+}
+
+impl HealthServer {
+    pub async fn bind(addr: SocketAddr) -> Result<Self, errors::Error> {
+        let listener = tokio::net::TcpListener::bind(addr).await.map_err(|e| {
+            errors::user_with_internal(
+                &format!("Unable to start the health check server on '{addr}'."),
+                "Make sure that the address is valid and that no other process is listening on this port.",
+                e,
+            )
+        })?;
+
+        Ok(Self { listener })
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.listener.local_addr().ok()
+    }
+
+    pub async fn serve(self, health: Health, cancel: &'static AtomicBool) {
+        if let Some(addr) = self.local_addr() {
+            info!("Serving health checks on http://{addr}/health");
+        }
+
+        let app = Router::new()
+            .route("/health", get(health_check))
+            .with_state(health);
+
+        axum::serve(self.listener, app)
+            .with_graceful_shutdown(async move {
+                while !cancel.load(std::sync::atomic::Ordering::Relaxed) {
+                    tokio::time::sleep(Duration::from_millis(500)).await;
+                }
+            })
+            .await
+            .unwrap_or_else(|e| error!("The health check server failed unexpectedly: {e}"));
+    }
+}
+
+async fn health_check(State(health): State<Health>) -> impl IntoResponse {
+    health.status()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        engines::{BackupDetails, BackupState},
+        BackupPolicy,
+    };
+
+    use super::*;
+
+    static CANCEL: AtomicBool = AtomicBool::new(false);
+
+    fn summary(states: &[BackupState], errors: usize) -> PolicySummary {
+        let policy: BackupPolicy =
+            serde_yaml::from_str("{ kind: github/repo, from: user, to: /backups }").unwrap();
+        let summary = PolicySummary::new(&policy);
+        for state in states {
+            summary.record(state);
+        }
+
+        for _ in 0..errors {
+            summary.record_error(&errors::user("Something went wrong.", "Try again."));
+        }
+
+        summary
+    }
+
+    #[test]
+    fn test_status() {
+        let health = Health::default();
+        let (code, Json(body)) = health.status();
+        assert_eq!(code, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "starting");
+        assert!(body["last_successful_run"].is_null());
+
+        let first_run = chrono::Utc::now();
+        let next_run = first_run + chrono::Duration::hours(1);
+        health.record_run(
+            first_run,
+            &[summary(&[BackupState::New(BackupDetails::default())], 1)],
+            Some(next_run),
+        );
+        let (code, Json(body)) = health.status();
+        assert_eq!(
+            code,
+            StatusCode::OK,
+            "partial failures should still be healthy"
+        );
+        assert_eq!(body["status"], "ok");
+        assert_eq!(body["last_successful_run"], serde_json::json!(first_run));
+        assert_eq!(body["next_run"], serde_json::json!(next_run));
+
+        let second_run = next_run;
+        health.record_run(
+            second_run,
+            &[
+                summary(&[], 2),
+                summary(&[BackupState::Unchanged(BackupDetails::default())], 0),
+            ],
+            None,
+        );
+        let (code, _) = health.status();
+        assert_eq!(code, StatusCode::OK);
+
+        let third_run = second_run + chrono::Duration::hours(1);
+        health.record_run(third_run, &[summary(&[], 1), summary(&[], 3)], None);
+        let (code, Json(body)) = health.status();
+        assert_eq!(code, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "failed");
+        assert_eq!(body["last_run"], serde_json::json!(third_run));
+        assert_eq!(
+            body["last_successful_run"],
+            serde_json::json!(second_run),
+            "the last successful run should be retained when a run fails"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_serve() {
+        let server = HealthServer::bind("127.0.0.1:0".parse().unwrap())
+            .await
+            .expect("the server to bind");
+        let addr = server.local_addr().expect("a local address");
+
+        let health = Health::default();
+        tokio::spawn(server.serve(health.clone(), &CANCEL));
+
+        let resp = reqwest::get(format!("http://{addr}/health"))
+            .await
+            .expect("the request to succeed");
+        assert_eq!(resp.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+
+        health.record_run(chrono::Utc::now(), &[], None);
+        let resp = reqwest::get(format!("http://{addr}/health"))
+            .await
+            .expect("the request to succeed");
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+
+        let body: serde_json::Value = resp.json().await.expect("a JSON response body");
+        assert_eq!(body["status"], "ok");
+    }
+}
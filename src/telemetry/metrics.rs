@@ -0,0 +1,159 @@
+use std::{
+    fmt::Write,
+    net::SocketAddr,
+    sync::{atomic::AtomicBool, Arc},
+    time::Duration,
+};
+
+use axum::{extract::State, http::header, response::IntoResponse, routing::get, Router};
+use tracing_batteries::prelude::*;
+
+use crate::{errors, statistics::SummaryStatistics};
+
+/// A minimal HTTP server which exposes the current [`SummaryStatistics`] in the
+/// Prometheus text exposition format on `/metrics`.
+pub struct MetricsServer {
+    listener: tokio::net::TcpListener,
+}
+
+impl MetricsServer {
+    pub async fn bind(addr: SocketAddr) -> Result<Self, errors::Error> {
+        let listener = tokio::net::TcpListener::bind(addr).await.map_err(|e| {
+            errors::user_with_internal(
+                &format!("Unable to start the metrics server on '{addr}'."),
+                "Make sure that the address is valid and that no other process is listening on this port.",
+                e,
+            )
+        })?;
+
+        Ok(Self { listener })
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.listener.local_addr().ok()
+    }
+
+    pub async fn serve(self, stats: Arc<SummaryStatistics>, cancel: &'static AtomicBool) {
+        if let Some(addr) = self.local_addr() {
+            info!("Serving metrics on http://{addr}/metrics");
+        }
+
+        let app = Router::new()
+            .route("/metrics", get(metrics))
+            .with_state(stats);
+
+        axum::serve(self.listener, app)
+            .with_graceful_shutdown(async move {
+                while !cancel.load(std::sync::atomic::Ordering::Relaxed) {
+                    tokio::time::sleep(Duration::from_millis(500)).await;
+                }
+            })
+            .await
+            .unwrap_or_else(|e| error!("The metrics server failed unexpectedly: {e}"));
+    }
+}
+
+async fn metrics(State(stats): State<Arc<SummaryStatistics>>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        render(&stats),
+    )
+}
+
+fn render(stats: &SummaryStatistics) -> String {
+    let mut out = String::new();
+
+    writeln!(out, "# HELP github_backup_entities_total The number of entities which have been processed, by the state they finished in.").unwrap();
+    writeln!(out, "# TYPE github_backup_entities_total counter").unwrap();
+    for (state, count) in stats.entities() {
+        writeln!(
+            out,
+            "github_backup_entities_total{{state=\"{state}\"}} {count}"
+        )
+        .unwrap();
+    }
+
+    writeln!(out, "# HELP github_backup_skipped_total The number of entities which have been skipped, by the reason they were skipped.").unwrap();
+    writeln!(out, "# TYPE github_backup_skipped_total counter").unwrap();
+    for (reason, count) in stats.skipped() {
+        writeln!(
+            out,
+            "github_backup_skipped_total{{reason=\"{}\"}} {count}",
+            reason.as_str()
+        )
+        .unwrap();
+    }
+
+    writeln!(out, "# HELP github_backup_errors_total The number of errors which have been encountered while running backups.").unwrap();
+    writeln!(out, "# TYPE github_backup_errors_total counter").unwrap();
+    writeln!(out, "github_backup_errors_total {}", stats.errors()).unwrap();
+
+    if let Some(timestamp) = stats.last_run_timestamp() {
+        writeln!(out, "# HELP github_backup_last_run_timestamp The UNIX timestamp at which the most recent backup run completed.").unwrap();
+        writeln!(out, "# TYPE github_backup_last_run_timestamp gauge").unwrap();
+        writeln!(out, "github_backup_last_run_timestamp {timestamp}").unwrap();
+
+        writeln!(out, "# HELP github_backup_run_duration_seconds The time taken to complete the most recent backup run.").unwrap();
+        writeln!(out, "# TYPE github_backup_run_duration_seconds gauge").unwrap();
+        writeln!(
+            out,
+            "github_backup_run_duration_seconds {}",
+            stats.last_run_duration().as_secs_f64()
+        )
+        .unwrap();
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engines::{BackupDetails, BackupState};
+
+    use super::*;
+
+    static CANCEL: AtomicBool = AtomicBool::new(false);
+
+    #[test]
+    fn test_render() {
+        let stats = SummaryStatistics::default();
+        stats.record(&BackupState::New(BackupDetails::default()));
+        stats.record_error(&crate::errors::user("Something failed.", "Try again."));
+
+        let output = render(&stats);
+        assert!(output.contains("github_backup_entities_total{state=\"new\"} 1\n"));
+        assert!(output.contains("github_backup_entities_total{state=\"updated\"} 0\n"));
+        assert!(output.contains("github_backup_skipped_total{reason=\"dry_run\"} 0\n"));
+        assert!(output.contains("github_backup_errors_total 1\n"));
+        assert!(
+            !output.contains("github_backup_last_run_timestamp"),
+            "the last run should not be reported before a run has completed"
+        );
+
+        stats.record_run(chrono::Utc::now(), Duration::from_millis(2500));
+        let output = render(&stats);
+        assert!(output.contains("github_backup_last_run_timestamp "));
+        assert!(output.contains("github_backup_run_duration_seconds 2.5\n"));
+    }
+
+    #[tokio::test]
+    async fn test_serve() {
+        let server = MetricsServer::bind("127.0.0.1:0".parse().unwrap())
+            .await
+            .expect("the server to bind");
+        let addr = server.local_addr().expect("a local address");
+
+        let stats = Arc::new(SummaryStatistics::default());
+        stats.record(&BackupState::Unchanged(BackupDetails::default()));
+        tokio::spawn(server.serve(stats, &CANCEL));
+
+        let body = reqwest::get(format!("http://{addr}/metrics"))
+            .await
+            .expect("the request to succeed")
+            .text()
+            .await
+            .expect("a response body");
+
+        assert!(body.contains("github_backup_entities_total{state=\"unchanged\"} 1\n"));
+    }
+}
@@ -1,5 +1,13 @@
+mod digest;
+mod error_log;
+mod health;
+mod metrics;
 mod traced_stream;
 
+pub use digest::{Digest, PolicySummary, SmtpConfig};
+pub use error_log::ErrorLog;
+pub use health::{Health, HealthServer};
+pub use metrics::MetricsServer;
 pub use traced_stream::*;
 
 use tracing_batteries::*;